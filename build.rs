@@ -0,0 +1,23 @@
+//! With `ffi`, writes include/atomics_and_locks.h from src/ffi.rs, as
+//! `cbindgen.toml` says. The header is checked in, for C code built without
+//! cargo; tests/ffi.rs compiles against it.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+#[cfg(feature = "ffi")]
+fn header() {
+    let root = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/ffi.rs"))
+        .generate()
+        .expect("generating the C header")
+        .write_to_file(root.join("include/atomics_and_locks.h"));
+}
@@ -0,0 +1,149 @@
+/*
+ * The spin lock, futex mutex and SPSC byte ring of atomics_and_locks, for C.
+ * tests/ffi.rs compiles against it.
+ *
+ * Build the library with
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type staticlib
+ *
+ * and link target/release/libatomics_and_locks.a, plus -lpthread -ldl -lm
+ * on Linux.
+ */
+
+#ifndef ATOMICS_AND_LOCKS_H
+#define ATOMICS_AND_LOCKS_H
+
+/* Written by build.rs from src/ffi.rs, don't edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * How many bytes an `AalSpsc` holds. `AAL_SPSC_CAPACITY` in C.
+ */
+#define AAL_SPSC_CAPACITY 4096
+
+typedef struct AalMutex AalMutex;
+
+typedef struct AalSpinLock AalSpinLock;
+
+typedef struct AalSpsc AalSpsc;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Spins until it gets the lock. Unlock from the thread that holds it.
+ */
+AalSpinLock *aal_spin_lock_new(void);
+
+/**
+ * # Safety
+ *
+ * `lock` is from `aal_spin_lock_new`, unlocked, and not used after this.
+ */
+void aal_spin_lock_free(AalSpinLock *lock);
+
+/**
+ * # Safety
+ *
+ * `lock` is from `aal_spin_lock_new`, and not freed yet.
+ */
+void aal_spin_lock_lock(const AalSpinLock *lock);
+
+/**
+ * # Safety
+ *
+ * As for `aal_spin_lock_lock`.
+ */
+bool aal_spin_lock_try_lock(const AalSpinLock *lock);
+
+/**
+ * # Safety
+ *
+ * As for `aal_spin_lock_lock`, and the lock is held, by the caller.
+ */
+void aal_spin_lock_unlock(const AalSpinLock *lock);
+
+/**
+ * Sleeps in the OS (a futex on Linux) until it gets the lock.
+ */
+AalMutex *aal_mutex_new(void);
+
+/**
+ * # Safety
+ *
+ * `mutex` is from `aal_mutex_new`, unlocked, and not used after this.
+ */
+void aal_mutex_free(AalMutex *mutex);
+
+/**
+ * # Safety
+ *
+ * `mutex` is from `aal_mutex_new`, and not freed yet.
+ */
+void aal_mutex_lock(const AalMutex *mutex);
+
+/**
+ * # Safety
+ *
+ * As for `aal_mutex_lock`.
+ */
+bool aal_mutex_try_lock(const AalMutex *mutex);
+
+/**
+ * # Safety
+ *
+ * As for `aal_mutex_lock`, and the lock is held, by the caller.
+ */
+void aal_mutex_unlock(const AalMutex *mutex);
+
+/**
+ * One thread writes, one thread reads. Neither blocks: they return how
+ * many bytes they wrote or read, which may be fewer than asked for, or none.
+ */
+AalSpsc *aal_spsc_new(void);
+
+/**
+ * # Safety
+ *
+ * `spsc` is from `aal_spsc_new`, and not used after this.
+ */
+void aal_spsc_free(AalSpsc *spsc);
+
+/**
+ * Writes as much of `buf` as fits, and returns how many bytes that was.
+ *
+ * # Safety
+ *
+ * `spsc` is from `aal_spsc_new`, and not freed yet. `buf` points to `len`
+ * bytes. Only one thread at a time writes.
+ */
+size_t aal_spsc_write(const AalSpsc *spsc, const uint8_t *buf, size_t len);
+
+/**
+ * Reads up to `len` bytes into `buf`, and returns how many there were.
+ *
+ * # Safety
+ *
+ * `spsc` is from `aal_spsc_new`, and not freed yet. `buf` points to room
+ * for `len` bytes. Only one thread at a time reads.
+ */
+size_t aal_spsc_read(const AalSpsc *spsc, uint8_t *buf, size_t len);
+
+/**
+ * How many bytes are waiting to be read.
+ *
+ * # Safety
+ *
+ * `spsc` is from `aal_spsc_new`, and not freed yet.
+ */
+size_t aal_spsc_len(const AalSpsc *spsc);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ATOMICS_AND_LOCKS_H */
@@ -0,0 +1,152 @@
+//! Actors: state owned by one thread, only reachable by sending it messages.
+//!
+//! Every actor gets its own thread and a mutex channel from cap_5 as mailbox.
+//! `Addr` is the sending end; clone it to give more threads access.
+
+use crate::mutex_channel::Channel;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+pub trait Actor: Send + 'static {
+    type Msg: Send + 'static;
+
+    fn handle(&mut self, msg: Self::Msg);
+
+    /// Called on the actor's thread before the first message,
+    /// and again after every restart.
+    fn started(&mut self) {}
+
+    /// Called once the mailbox is closed and empty.
+    fn stopped(&mut self) {}
+}
+
+pub struct Addr<A: Actor> {
+    mailbox: Arc<Channel<A::Msg>>,
+}
+
+impl<A: Actor> Addr<A> {
+    /// Gives the message back if the actor is stopped.
+    pub fn send(&self, msg: A::Msg) -> Result<(), A::Msg> {
+        self.mailbox.try_send(msg)
+    }
+
+    /// The actor handles what's already in its mailbox, then stops.
+    pub fn stop(&self) {
+        self.mailbox.close();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.mailbox.is_closed()
+    }
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Self {
+        Self {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+}
+
+/// Runs the actor until it's stopped.
+/// A panic in `handle` stops the actor for good; see `supervise`.
+pub fn spawn<A: Actor>(actor: A) -> (Addr<A>, JoinHandle<()>) {
+    let mailbox = Arc::new(Channel::new());
+    let addr = Addr {
+        mailbox: mailbox.clone(),
+    };
+    let mut actor = Some(actor);
+    let handle = thread::spawn(move || {
+        run(&mailbox, || actor.take().unwrap(), 0);
+    });
+    (addr, handle)
+}
+
+/// Runs an actor made by `factory`, and replaces it with a fresh one
+/// whenever it panics, at most `max_restarts` times.
+/// After that the mailbox is closed and senders get their messages back.
+///
+/// The message being handled during the panic is lost, the rest of the
+/// mailbox is kept for the new actor.
+/// The join handle returns how many restarts happened.
+pub fn supervise<A, F>(mut factory: F, max_restarts: usize) -> (Addr<A>, JoinHandle<usize>)
+where
+    A: Actor,
+    F: FnMut() -> A + Send + 'static,
+{
+    let mailbox = Arc::new(Channel::new());
+    let addr = Addr {
+        mailbox: mailbox.clone(),
+    };
+    let handle = thread::spawn(move || run(&mailbox, &mut factory, max_restarts));
+    (addr, handle)
+}
+
+fn run<A: Actor>(
+    mailbox: &Channel<A::Msg>,
+    mut factory: impl FnMut() -> A,
+    max_restarts: usize,
+) -> usize {
+    let mut restarts = 0;
+    loop {
+        let mut actor = factory();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            actor.started();
+            while let Some(msg) = mailbox.receive() {
+                actor.handle(msg);
+            }
+            actor.stopped();
+        }));
+        match result {
+            Ok(()) => return restarts,
+            Err(_) if restarts < max_restarts => restarts += 1,
+            Err(_) => {
+                mailbox.close();
+                return restarts;
+            }
+        }
+    }
+}
+
+pub fn main() {
+    use std::sync::mpsc;
+
+    enum Msg {
+        Add(i64),
+        Get(mpsc::Sender<i64>),
+        Crash,
+    }
+
+    struct Counter {
+        total: i64,
+    }
+
+    impl Actor for Counter {
+        type Msg = Msg;
+
+        fn handle(&mut self, msg: Msg) {
+            match msg {
+                Msg::Add(n) => self.total += n,
+                Msg::Get(reply) => reply.send(self.total).unwrap(),
+                Msg::Crash => panic!("counter crashed at {}", self.total),
+            }
+        }
+
+        fn started(&mut self) {
+            println!("counter (re)started");
+        }
+    }
+
+    let (addr, handle) = supervise(|| Counter { total: 0 }, 3);
+    let (tx, rx) = mpsc::channel();
+    addr.send(Msg::Add(5)).ok();
+    addr.send(Msg::Crash).ok();
+    addr.send(Msg::Add(2)).ok();
+    addr.send(Msg::Get(tx)).ok();
+    println!("total after a restart: {}", rx.recv().unwrap());
+    addr.stop();
+    println!("restarts: {}", handle.join().unwrap());
+}
@@ -0,0 +1,201 @@
+//! Pinning threads to cores.
+//!
+//! Whether two threads share a core, a cache, or nothing at all changes what
+//! the ordering experiments of cap_3 show and what any contention benchmark
+//! measures. These pin the current thread, as far as the platform lets us:
+//! Linux and Windows honor the request, macOS only takes it as a hint
+//! (and Apple Silicon ignores it), anything else reports `Unsupported`.
+
+use std::{io, thread};
+
+/// Pins the current thread to logical core `core`.
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    imp::pin_to_core(core)
+}
+
+/// One logical core per physical core, so pinned threads don't end up on
+/// two hyperthreads of the same core.
+///
+/// Where the topology isn't known this is every logical core.
+pub fn physical_cores() -> Vec<usize> {
+    imp::physical_cores().unwrap_or_else(|| {
+        let n = thread::available_parallelism().map_or(1, |n| n.get());
+        (0..n).collect()
+    })
+}
+
+/// The logical core the current thread is running on, as of the call: it
+/// may have moved by the time it's used. `None` where we can't tell.
+pub fn current_cpu() -> Option<usize> {
+    imp::current_cpu()
+}
+
+/// Pins the `index`th benchmark thread: every thread on its own physical
+/// core while there are enough of them, then round-robin.
+/// Returns the core it went to.
+pub fn pin_spread(index: usize) -> io::Result<usize> {
+    let cores = physical_cores();
+    let core = cores[index % cores.len()];
+    pin_to_core(core).map(|()| core)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::{collections::BTreeSet, fs, io, mem};
+
+    pub fn pin_to_core(core: usize) -> io::Result<()> {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+        // SAFETY: cpu_set_t is plain data, all zeroes is the empty set,
+        // and `core` is within its bounds.
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            // 0 is the calling thread.
+            if libc::sched_setaffinity(0, mem::size_of_val(&set), &set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        // SAFETY: no arguments, and nothing to clean up.
+        usize::try_from(unsafe { libc::sched_getcpu() }).ok()
+    }
+
+    /// The lowest-numbered logical core of each (package, core) pair in sysfs.
+    pub fn physical_cores() -> Option<Vec<usize>> {
+        let read = |cpu: usize, file: &str| -> Option<u32> {
+            let path = format!("/sys/devices/system/cpu/cpu{cpu}/topology/{file}");
+            fs::read_to_string(path).ok()?.trim().parse().ok()
+        };
+        let mut seen = BTreeSet::new();
+        let mut cores = Vec::new();
+        for entry in fs::read_dir("/sys/devices/system/cpu").ok()? {
+            let name = entry.ok()?.file_name();
+            let Some(cpu) = name.to_str()?.strip_prefix("cpu") else {
+                continue;
+            };
+            let Ok(cpu) = cpu.parse::<usize>() else {
+                continue; // cpufreq, cpuidle, ...
+            };
+            cores.push((
+                read(cpu, "physical_package_id")?,
+                read(cpu, "core_id")?,
+                cpu,
+            ));
+        }
+        cores.sort_unstable();
+        let cores: Vec<usize> = cores
+            .into_iter()
+            .filter(|&(package, core, _)| seen.insert((package, core)))
+            .map(|(_, _, cpu)| cpu)
+            .collect();
+        (!cores.is_empty()).then_some(cores)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{ffi::c_void, io};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadAffinityMask(thread: *mut c_void, mask: usize) -> usize;
+        fn GetCurrentProcessorNumber() -> u32;
+    }
+
+    pub fn pin_to_core(core: usize) -> io::Result<()> {
+        // Without processor groups we only reach the first 64 cores.
+        if core >= usize::BITS as usize {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+        // SAFETY: the pseudo handle of the current thread is always valid.
+        if unsafe { SetThreadAffinityMask(GetCurrentThread(), 1 << core) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        // SAFETY: no arguments. Within the thread's processor group.
+        Some(unsafe { GetCurrentProcessorNumber() } as usize)
+    }
+
+    pub fn physical_cores() -> Option<Vec<usize>> {
+        None
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+mod imp {
+    use std::io;
+
+    /// Threads with the same tag are scheduled to share an L2 cache,
+    /// threads with different tags not to. It's a hint, not a pin.
+    pub fn pin_to_core(core: usize) -> io::Result<()> {
+        let mut policy = libc::thread_affinity_policy {
+            // 0 means "no affinity".
+            affinity_tag: core as libc::integer_t + 1,
+        };
+        // SAFETY: `policy` is a valid thread_affinity_policy of the given
+        // count, and the port is the current thread's.
+        let result = unsafe {
+            libc::thread_policy_set(
+                libc::pthread_mach_thread_np(libc::pthread_self()),
+                libc::THREAD_AFFINITY_POLICY as _,
+                &mut policy as *mut _ as libc::thread_policy_t,
+                libc::THREAD_AFFINITY_POLICY_COUNT,
+            )
+        };
+        match result {
+            libc::KERN_SUCCESS => Ok(()),
+            _ => Err(io::Error::from(io::ErrorKind::Unsupported)),
+        }
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        None
+    }
+
+    pub fn physical_cores() -> Option<Vec<usize>> {
+        None
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    windows,
+    target_vendor = "apple"
+)))]
+mod imp {
+    use std::io;
+
+    pub fn pin_to_core(_core: usize) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        None
+    }
+
+    pub fn physical_cores() -> Option<Vec<usize>> {
+        None
+    }
+}
+
+pub fn main() {
+    println!("physical cores: {:?}", physical_cores());
+    thread::scope(|s| {
+        for i in 0..4 {
+            s.spawn(move || match pin_spread(i) {
+                Ok(core) => println!("thread {i} pinned to core {core}"),
+                Err(e) => println!("thread {i} not pinned: {e}"),
+            });
+        }
+    });
+}
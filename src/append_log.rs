@@ -0,0 +1,298 @@
+//! A log that only grows: writers append to it from any number of threads,
+//! and readers go through everything appended so far, without either ever
+//! waiting for the other.
+//!
+//! Entries live in chunks that double in size, 32, 64, 128, ..., allocated
+//! the first time an entry falls in one and never moved after that, so a
+//! reference to an entry stays good for as long as the log. A writer
+//! reserves an index with one `fetch_add`, writes its entry, and marks it
+//! ready. What readers see is `len`: entries below it are all written. It's
+//! moved past each ready entry in turn, by whichever writer finds it ready,
+//! so one slow writer holds back what readers see, but no other writer.
+//!
+//! A `Snapshot` is `len` at one moment: iterating it goes through the same
+//! entries however much is appended meanwhile.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release, SeqCst},
+    },
+};
+
+/// Entries in the first chunk; each one after is twice the one before.
+const FIRST: usize = 32;
+const FIRST_BITS: u32 = FIRST.trailing_zeros();
+/// Enough for every index a `usize` can hold.
+const CHUNKS: usize = (usize::BITS - FIRST_BITS) as usize;
+
+struct Slot<T> {
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct AppendLog<T> {
+    /// The first slot of each chunk, or null until it's needed.
+    chunks: [AtomicPtr<Slot<T>>; CHUNKS],
+    /// Indices handed out to writers.
+    reserved: AtomicUsize,
+    /// Entries that are written, all of them below it.
+    len: AtomicUsize,
+}
+
+// Entries are sent in by writers and shared with every reader.
+unsafe impl<T: Send> Send for AppendLog<T> {}
+unsafe impl<T: Send + Sync> Sync for AppendLog<T> {}
+
+impl<T> AppendLog<T> {
+    pub const fn new() -> Self {
+        Self {
+            chunks: [const { AtomicPtr::new(ptr::null_mut()) }; CHUNKS],
+            reserved: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// The chunk that entry `i` is in, and where in it.
+    fn locate(i: usize) -> (usize, usize) {
+        let n = i + FIRST;
+        let chunk = (usize::BITS - 1 - n.leading_zeros() - FIRST_BITS) as usize;
+        (chunk, n - (FIRST << chunk))
+    }
+
+    /// The first slot of chunk `c`, allocating it if it isn't yet.
+    fn chunk(&self, c: usize) -> *mut Slot<T> {
+        let p = self.chunks[c].load(Acquire);
+        if !p.is_null() {
+            return p;
+        }
+        let new: Box<[Slot<T>]> = (0..FIRST << c)
+            .map(|_| Slot {
+                ready: AtomicBool::new(false),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        let new = Box::into_raw(new).cast::<Slot<T>>();
+        match self.chunks[c].compare_exchange(ptr::null_mut(), new, Release, Acquire) {
+            Ok(_) => new,
+            Err(p) => {
+                // Someone else's got there first. Ours was never shared.
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(new, FIRST << c)) });
+                p
+            }
+        }
+    }
+
+    /// Slot `i`, if its chunk is allocated.
+    fn slot(&self, i: usize) -> Option<&Slot<T>> {
+        let (c, offset) = Self::locate(i);
+        let p = self.chunks[c].load(Acquire);
+        // Safety: a chunk stays in place until `drop`, and has `FIRST << c` slots.
+        (!p.is_null()).then(|| unsafe { &*p.add(offset) })
+    }
+
+    /// Appends `value`, and returns its index. Readers see it once every
+    /// entry before it is written too.
+    pub fn append(&self, value: T) -> usize {
+        let i = self.reserved.fetch_add(1, Relaxed);
+        let (c, offset) = Self::locate(i);
+        // Safety: index `i` is ours alone, and its chunk is in place.
+        let slot = unsafe { &*self.chunk(c).add(offset) };
+        unsafe { (*slot.value.get()).write(value) };
+        // `SeqCst`, with the load of `len` in `publish`: either we see that
+        // `len` got to us, or whoever moved it there sees us ready.
+        slot.ready.store(true, SeqCst);
+        self.publish();
+        i
+    }
+
+    /// Moves `len` past every entry that's ready, from where it is.
+    fn publish(&self) {
+        let mut len = self.len.load(SeqCst);
+        while let Some(slot) = self.slot(len) {
+            if !slot.ready.load(SeqCst) {
+                break;
+            }
+            // Releases the entry, for whoever sees the new `len`.
+            match self.len.compare_exchange(len, len + 1, SeqCst, SeqCst) {
+                Ok(_) => len += 1,
+                Err(l) => len = l,
+            }
+        }
+    }
+
+    /// How many entries readers can see.
+    pub fn len(&self) -> usize {
+        self.len.load(Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entry `i`, if readers can see it yet.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        (i < self.len()).then(|| unsafe { self.get_unchecked(i) })
+    }
+
+    /// # Safety
+    ///
+    /// `i` is below a `len` this thread has loaded.
+    unsafe fn get_unchecked(&self, i: usize) -> &T {
+        let slot = self.slot(i).unwrap();
+        unsafe { (*slot.value.get()).assume_init_ref() }
+    }
+
+    /// The entries there are now, to go through as many times as needed.
+    pub fn snapshot(&self) -> Snapshot<'_, T> {
+        Snapshot {
+            log: self,
+            len: self.len(),
+        }
+    }
+
+    /// Goes through the entries there are now.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.snapshot().iter()
+    }
+}
+
+impl<T> Default for AppendLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AppendLog<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Drop for AppendLog<T> {
+    fn drop(&mut self) {
+        for (c, chunk) in self.chunks.iter_mut().enumerate() {
+            let p = *chunk.get_mut();
+            if p.is_null() {
+                continue;
+            }
+            // Safety: no one else has the log anymore, and the chunk is
+            // the box it was allocated as.
+            let mut slots = unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(p, FIRST << c)) };
+            for slot in slots.iter_mut() {
+                if *slot.ready.get_mut() {
+                    unsafe { slot.value.get_mut().assume_init_drop() };
+                }
+            }
+        }
+    }
+}
+
+/// The entries of an `AppendLog` as of when it was taken.
+pub struct Snapshot<'a, T> {
+    log: &'a AppendLog<T>,
+    len: usize,
+}
+
+impl<T> Clone for Snapshot<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Snapshot<'_, T> {}
+
+impl<'a, T> Snapshot<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'a T> {
+        // Safety: `len` was loaded by `snapshot`.
+        (i < self.len).then(|| unsafe { self.log.get_unchecked(i) })
+    }
+
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter {
+            snapshot: *self,
+            next: 0,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for Snapshot<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    snapshot: Snapshot<'a, T>,
+    next: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let entry = self.snapshot.get(self.next)?;
+        self.next += 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.snapshot.len - self.next;
+        (left, Some(left))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub fn main() {
+    use std::thread;
+
+    // Four writers appending while a reader keeps taking snapshots.
+    const N: usize = 100_000;
+    let log = AppendLog::new();
+    thread::scope(|s| {
+        s.spawn(|| {
+            let mut snapshots = 0;
+            while log.len() < 4 * N {
+                let snapshot = log.snapshot();
+                // The same entries, both times, whatever's appended between.
+                assert_eq!(snapshot.iter().count(), snapshot.len());
+                assert!(snapshot.iter().eq(snapshot.iter()));
+                snapshots += 1;
+            }
+            println!("{snapshots} snapshots while they wrote");
+        });
+        for w in 0..4 {
+            let log = &log;
+            s.spawn(move || {
+                for i in 0..N {
+                    log.append((w, i));
+                }
+            });
+        }
+    });
+    // Each writer's own entries are in the order it appended them.
+    let mut next = [0; 4];
+    for &(w, i) in &log {
+        assert_eq!(next[w], i);
+        next[w] += 1;
+    }
+    println!("{} entries, in {:?} per writer", log.len(), next);
+}
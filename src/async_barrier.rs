@@ -0,0 +1,158 @@
+//! Lets `n` tasks wait for each other: `wait().await` resolves for all of
+//! them once the `n`th one arrives, and the barrier is ready for the next phase.
+//!
+//! The generation counter is what makes reuse work: a waiter remembers the
+//! generation it arrived in and is released as soon as that generation is over,
+//! even if faster tasks already started arriving for the next one.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+pub struct Barrier {
+    n: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    arrived: usize,
+    generation: u64,
+    /// One per waiter of this generation, in the order they arrived.
+    wakers: Vec<Waker>,
+}
+
+impl Barrier {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "a barrier needs at least one party");
+        Self {
+            n,
+            state: Mutex::new(State {
+                arrived: 0,
+                generation: 0,
+                wakers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Dropping the future after its first poll still counts as arrived.
+    pub fn wait(&self) -> Wait<'_> {
+        Wait {
+            barrier: self,
+            arrival: None,
+        }
+    }
+}
+
+/// Exactly one waiter per generation gets `is_leader() == true`.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitResult {
+    leader: bool,
+}
+
+impl WaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+pub struct Wait<'a> {
+    barrier: &'a Barrier,
+    /// The generation we arrived in, and our waker's index in `wakers`,
+    /// once we did.
+    arrival: Option<(u64, usize)>,
+}
+
+impl Future for Wait<'_> {
+    type Output = WaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WaitResult> {
+        let mut state = self.barrier.state.lock().unwrap();
+        if let Some((generation, index)) = self.arrival {
+            if state.generation != generation {
+                return Poll::Ready(WaitResult { leader: false });
+            }
+            let waker = &mut state.wakers[index];
+            if !waker.will_wake(cx.waker()) {
+                waker.clone_from(cx.waker());
+            }
+            return Poll::Pending;
+        }
+        state.arrived += 1;
+        if state.arrived == self.barrier.n {
+            state.arrived = 0;
+            state.generation += 1;
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+            return Poll::Ready(WaitResult { leader: true });
+        }
+        let arrival = (state.generation, state.wakers.len());
+        state.wakers.push(cx.waker().clone());
+        drop(state);
+        self.arrival = Some(arrival);
+        Poll::Pending
+    }
+}
+
+pub fn main() {
+    use crate::executor::Executor;
+    use std::sync::Arc;
+
+    let barrier = Arc::new(Barrier::new(3));
+    let executor = Executor::new();
+    for t in 0..3 {
+        let barrier = barrier.clone();
+        executor.spawn(async move {
+            for phase in 0..3 {
+                println!("task {t} finished phase {phase}");
+                if barrier.wait().await.is_leader() {
+                    println!("-- everyone finished phase {phase}");
+                }
+            }
+        });
+    }
+    executor.run_on(3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Barrier;
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering::Relaxed},
+            Arc,
+        },
+        task::{Context, Wake, Waker},
+    };
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Relaxed);
+        }
+    }
+
+    #[test]
+    fn repolling_keeps_one_waker() {
+        let barrier = Barrier::new(2);
+        let count = Arc::new(Count(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut first = pin!(barrier.wait());
+        for _ in 0..5 {
+            assert!(first.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(barrier.state.lock().unwrap().wakers.len(), 1);
+
+        let last = pin!(barrier.wait());
+        assert!(last.poll(&mut cx).is_ready());
+        assert_eq!(count.0.load(Relaxed), 1);
+        assert!(first.poll(&mut cx).is_ready());
+    }
+}
@@ -0,0 +1,259 @@
+//! A bounded multi-producer, single-consumer channel for async code.
+//!
+//! Same shape as the mutex channel in cap_5: a `VecDeque` behind a `Mutex`,
+//! except that instead of a `Condvar` the blocked side leaves a `Waker` behind.
+//! A full queue makes `send().await` wait, which is the backpressure.
+//!
+//! Watermarks warn before that: a callback when the queue fills up to a
+//! high mark, and again when it's drained back down to a low one, so
+//! producers can slow down, or batch more, before they have to wait.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    marker,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be at least 1");
+    let channel = Arc::new(Channel {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            senders: 1,
+            receiver_alive: true,
+            send_wakers: Vec::new(),
+            receive_waker: None,
+            watermarks: None,
+        }),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+struct Channel<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+    send_wakers: Vec<Waker>,
+    receive_waker: Option<Waker>,
+    watermarks: Option<Watermarks>,
+}
+
+/// Which way the queue's length crossed a watermark.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watermark {
+    /// Up to the high mark, from below the low one.
+    High,
+    /// Back down to the low mark.
+    Low,
+}
+
+struct Watermarks {
+    low: usize,
+    high: usize,
+    /// Whether the last one crossed was `High`.
+    above: bool,
+    on_cross: Box<dyn Fn(Watermark) + marker::Send>,
+}
+
+impl<T> State<T> {
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.receive_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// After every push and pop: calls `on_cross` if that crossed a mark.
+    fn check_watermarks(&mut self) {
+        let len = self.queue.len();
+        if let Some(w) = &mut self.watermarks {
+            let crossed = match w.above {
+                false if len >= w.high => Watermark::High,
+                true if len <= w.low => Watermark::Low,
+                _ => return,
+            };
+            w.above = crossed == Watermark::High;
+            (w.on_cross)(crossed);
+        }
+    }
+
+    /// All of them, not just one: a waker may belong to a `Send` future that
+    /// was dropped in the meantime, and waking only that one would lose the slot.
+    fn wake_senders(&mut self) {
+        for waker in self.send_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Waits for room in the queue.
+    /// Gives the message back as `Err` if the receiver is gone.
+    pub fn send(&self, message: T) -> Send<'_, T> {
+        Send {
+            sender: self,
+            message: Some(message),
+        }
+    }
+
+    /// Gives the message back if the queue is full or the receiver is gone.
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        let mut state = self.channel.state.lock().unwrap();
+        if !state.receiver_alive || state.queue.len() == state.capacity {
+            return Err(message);
+        }
+        state.queue.push_back(message);
+        state.check_watermarks();
+        state.wake_receiver();
+        Ok(())
+    }
+
+    /// Calls `on_cross` with `Watermark::High` when the queue fills up to
+    /// `high` messages, and with `Watermark::Low` once it's back down to
+    /// `low`, alternating: nothing in between, however the length goes up
+    /// and down. Replaces the watermarks set before.
+    ///
+    /// It's called with the channel locked, by whoever sent or received the
+    /// message that crossed the mark. So it mustn't use the channel, only
+    /// tell someone who will, e.g. with a `Notify` or an atomic.
+    pub fn set_watermarks(
+        &self,
+        low: usize,
+        high: usize,
+        on_cross: impl Fn(Watermark) + marker::Send + 'static,
+    ) {
+        let mut state = self.channel.state.lock().unwrap();
+        assert!(
+            low < high && high <= state.capacity,
+            "watermarks must be low < high <= capacity"
+        );
+        let above = state.queue.len() >= high;
+        state.watermarks = Some(Watermarks {
+            low,
+            high,
+            above,
+            on_cross: Box::new(on_cross),
+        });
+    }
+
+    pub fn remove_watermarks(&self) {
+        self.channel.state.lock().unwrap().watermarks = None;
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.state.lock().unwrap().senders += 1;
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake_receiver();
+        }
+    }
+}
+
+pub struct Send<'a, T> {
+    sender: &'a Sender<T>,
+    message: Option<T>,
+}
+
+// The message is never pinned, we only ever move it out.
+impl<T> Unpin for Send<'_, T> {}
+
+impl<T> Future for Send<'_, T> {
+    type Output = Result<(), T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let message = self.message.take().expect("polled after completion");
+        let mut state = self.sender.channel.state.lock().unwrap();
+        if !state.receiver_alive {
+            return Poll::Ready(Err(message));
+        }
+        if state.queue.len() < state.capacity {
+            state.queue.push_back(message);
+            state.check_watermarks();
+            state.wake_receiver();
+            return Poll::Ready(Ok(()));
+        }
+        state.send_wakers.push(cx.waker().clone());
+        drop(state);
+        self.message = Some(message);
+        Poll::Pending
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Resolves to `None` once every sender is gone and the queue is drained.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        let mut state = self.channel.state.lock().unwrap();
+        let message = state.queue.pop_front();
+        if message.is_some() {
+            state.check_watermarks();
+            state.wake_senders();
+        }
+        message
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.receiver_alive = false;
+        state.wake_senders();
+    }
+}
+
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.receiver.channel.state.lock().unwrap();
+        if let Some(message) = state.queue.pop_front() {
+            state.check_watermarks();
+            state.wake_senders();
+            return Poll::Ready(Some(message));
+        }
+        if state.senders == 0 {
+            return Poll::Ready(None);
+        }
+        state.receive_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,168 @@
+//! Wakes up tasks waiting in `notified().await`.
+//!
+//! `notify_one` with nobody waiting isn't lost: it's kept as a single permit
+//! that the next `notified()` consumes right away, so the usual
+//! "check condition, then wait" sequence can't miss a wakeup.
+//! `notify_waiters` only wakes the tasks already waiting and leaves no permit.
+//!
+//! The number of tasks waiting is kept in an atomic next to the lock, so
+//! `waiters` doesn't have to take it.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+pub struct Notify {
+    state: Mutex<State>,
+    /// `state.waiters.len()`, changed under the lock.
+    waiting: AtomicUsize,
+}
+
+struct State {
+    permit: bool,
+    next_id: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    id: u64,
+    waker: Waker,
+    notified: Option<Wakeup>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Wakeup {
+    One,
+    All,
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        match self.waiters.iter_mut().find(|w| w.notified.is_none()) {
+            Some(waiter) => {
+                waiter.notified = Some(Wakeup::One);
+                waiter.waker.wake_by_ref();
+            }
+            None => self.permit = true,
+        }
+    }
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                permit: false,
+                next_id: 0,
+                waiters: VecDeque::new(),
+            }),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wakes the longest waiting task, or stores a permit if there is none.
+    pub fn notify_one(&self) {
+        self.state.lock().unwrap().notify_one();
+    }
+
+    /// Wakes every task currently waiting. Doesn't store a permit.
+    pub fn notify_waiters(&self) {
+        let mut state = self.state.lock().unwrap();
+        for waiter in state.waiters.iter_mut() {
+            if waiter.notified.is_none() {
+                waiter.notified = Some(Wakeup::All);
+                waiter.waker.wake_by_ref();
+            }
+        }
+    }
+
+    /// Tasks waiting in `notified().await`, counting ones notified that
+    /// haven't run since.
+    pub fn waiters(&self) -> usize {
+        self.waiting.load(Relaxed)
+    }
+
+    pub fn has_waiters(&self) -> bool {
+        self.waiters() > 0
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            id: None,
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// Set once we're in the waiters queue.
+    id: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.notify.state.lock().unwrap();
+        let Some(id) = self.id else {
+            if state.permit {
+                state.permit = false;
+                return Poll::Ready(());
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            state.waiters.push_back(Waiter {
+                id,
+                waker: cx.waker().clone(),
+                notified: None,
+            });
+            self.notify.waiting.fetch_add(1, Relaxed);
+            drop(state);
+            self.id = Some(id);
+            return Poll::Pending;
+        };
+        let i = state
+            .waiters
+            .iter()
+            .position(|w| w.id == id)
+            .expect("waiter is queued until it completes");
+        if state.waiters[i].notified.is_some() {
+            state.waiters.remove(i);
+            self.notify.waiting.fetch_sub(1, Relaxed);
+            drop(state);
+            self.id = None;
+            return Poll::Ready(());
+        }
+        state.waiters[i].waker.clone_from(cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else { return };
+        let mut state = self.notify.state.lock().unwrap();
+        let i = state.waiters.iter().position(|w| w.id == id).unwrap();
+        let waiter = state.waiters.remove(i).unwrap();
+        self.notify.waiting.fetch_sub(1, Relaxed);
+        // We were picked by `notify_one` but never got to see it:
+        // hand the wakeup on so it isn't lost.
+        if waiter.notified == Some(Wakeup::One) {
+            state.notify_one();
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! An atomic anything: `AtomicCell<T>` is one of `core`'s atomic integers
+//! when `T` has the size of one and at least its alignment, and a value
+//! behind a spin lock otherwise. No more transmuting a pair of `u16`s to an
+//! `AtomicU32` by hand.
+//!
+//! Only a `T` with no padding bytes can go through an integer: reading
+//! padding as part of one is undefined behavior, and it has no value to
+//! compare anyway. That's `NoPadding`, which `new` asks for. `with_lock`
+//! takes any `T`, and always locks.
+//!
+//! The integer is compared bit for bit, so `compare_exchange` on a `T`
+//! whose `Eq` ignores some of its bits retries with the bits it found.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::{self, ManuallyDrop},
+    sync::atomic::Ordering::{AcqRel, Acquire},
+};
+
+use crate::{cache_padded::CachePadded, spin_lock::SpinLock};
+
+/// Loads are `Acquire`, stores `Release`, and the rest `AcqRel`.
+pub struct AtomicCell<T> {
+    v: UnsafeCell<T>,
+    /// Made by `new`, so `T: NoPadding`.
+    no_padding: bool,
+}
+
+/// Types whose every byte is part of a value, so they can be read as an
+/// integer of their size.
+///
+/// # Safety
+///
+/// `Self` mustn't have padding bytes, anywhere, including inside its
+/// fields. A `#[repr(C)]` struct whose fields are `NoPadding` and add up
+/// to its size is; most enums and structs of mixed sizes aren't.
+pub unsafe trait NoPadding: Copy {}
+
+macro_rules! no_padding {
+    ($($t:ty),*) => {
+        $(unsafe impl NoPadding for $t {})*
+    };
+}
+
+no_padding!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char);
+
+unsafe impl<T: NoPadding, const N: usize> NoPadding for [T; N] {}
+
+// Values only move in and out whole, as with a `Mutex`.
+unsafe impl<T: Send> Sync for AtomicCell<T> {}
+
+/// Whether a `T` can be an `I` in place, padding aside.
+const fn fits<T, I>() -> bool {
+    mem::size_of::<T>() == mem::size_of::<I>() && mem::align_of::<T>() >= mem::size_of::<I>()
+}
+
+/// Reinterprets `v` as a `U` of the same size.
+unsafe fn cast<T, U>(v: T) -> U {
+    mem::transmute_copy(&ManuallyDrop::new(v))
+}
+
+/// Runs `$native` with `$a` the cell as the atomic integer `$int` it fits,
+/// if there is one and `T` has no padding, and `$fallback` with its stripe
+/// locked otherwise.
+macro_rules! dispatch {
+    ($cell:expr, |$a:ident: $int:ident| $native:expr, $fallback:expr) => {
+        'native: {
+            if !$cell.no_padding {
+                let _guard = stripe($cell.v.get()).lock();
+                break 'native $fallback;
+            }
+            #[cfg(target_has_atomic = "8")]
+            if fits::<T, u8>() {
+                type $int = u8;
+                // Safety: same size, aligned enough, and no padding.
+                let $a = unsafe { &*$cell.v.get().cast::<core::sync::atomic::AtomicU8>() };
+                break 'native $native;
+            }
+            #[cfg(target_has_atomic = "16")]
+            if fits::<T, u16>() {
+                type $int = u16;
+                // Safety: as above.
+                let $a = unsafe { &*$cell.v.get().cast::<core::sync::atomic::AtomicU16>() };
+                break 'native $native;
+            }
+            #[cfg(target_has_atomic = "32")]
+            if fits::<T, u32>() {
+                type $int = u32;
+                // Safety: as above.
+                let $a = unsafe { &*$cell.v.get().cast::<core::sync::atomic::AtomicU32>() };
+                break 'native $native;
+            }
+            #[cfg(target_has_atomic = "64")]
+            if fits::<T, u64>() {
+                type $int = u64;
+                // Safety: as above.
+                let $a = unsafe { &*$cell.v.get().cast::<core::sync::atomic::AtomicU64>() };
+                break 'native $native;
+            }
+            let _guard = stripe($cell.v.get()).lock();
+            $fallback
+        }
+    };
+}
+
+/// Like `AtomicU128`'s: cells share a lock only if their addresses collide.
+static STRIPES: [CachePadded<SpinLock<()>>; 64] =
+    [const { CachePadded::new(SpinLock::new(())) }; 64];
+
+fn stripe<T>(address: *mut T) -> &'static SpinLock<()> {
+    &STRIPES[(address as usize >> 3) % STRIPES.len()]
+}
+
+impl<T: NoPadding> AtomicCell<T> {
+    pub const fn new(v: T) -> Self {
+        Self {
+            v: UnsafeCell::new(v),
+            no_padding: true,
+        }
+    }
+}
+
+impl<T> AtomicCell<T> {
+    /// For a `T` that may have padding: every operation takes a lock.
+    pub const fn with_lock(v: T) -> Self {
+        Self {
+            v: UnsafeCell::new(v),
+            no_padding: false,
+        }
+    }
+
+    /// Whether this cell goes through an atomic integer on this target,
+    /// rather than a lock.
+    pub const fn is_lock_free(&self) -> bool {
+        self.no_padding
+            && ((cfg!(target_has_atomic = "8") && fits::<T, u8>())
+                || (cfg!(target_has_atomic = "16") && fits::<T, u16>())
+                || (cfg!(target_has_atomic = "32") && fits::<T, u32>())
+                || (cfg!(target_has_atomic = "64") && fits::<T, u64>()))
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.v.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.v.into_inner()
+    }
+
+    pub fn store(&self, v: T) {
+        drop(self.swap(v));
+    }
+
+    pub fn swap(&self, v: T) -> T {
+        dispatch!(
+            self,
+            |a: Int| unsafe { cast(a.swap(cast::<T, Int>(v), AcqRel)) },
+            // Safety: the stripe is locked.
+            unsafe { mem::replace(&mut *self.v.get(), v) }
+        )
+    }
+}
+
+impl<T: Copy> AtomicCell<T> {
+    pub fn load(&self) -> T {
+        dispatch!(
+            self,
+            |a: Int| unsafe { cast::<Int, T>(a.load(Acquire)) },
+            // Safety: the stripe is locked.
+            unsafe { *self.v.get() }
+        )
+    }
+}
+
+impl<T: Copy + Eq> AtomicCell<T> {
+    /// Stores `new` if the value is equal to `current`. Returns the
+    /// previous value, as `Ok` if it was stored.
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T> {
+        dispatch!(
+            self,
+            |a: Int| unsafe {
+                let mut expected = cast::<T, Int>(current);
+                loop {
+                    match a.compare_exchange(expected, cast(new), AcqRel, Acquire) {
+                        Ok(previous) => break Ok(cast::<Int, T>(previous)),
+                        // Equal, but not in its bits.
+                        Err(previous) if cast::<Int, T>(previous) == current => expected = previous,
+                        Err(previous) => break Err(cast(previous)),
+                    }
+                }
+            },
+            // Safety: the stripe is locked.
+            unsafe {
+                let v = &mut *self.v.get();
+                if *v == current {
+                    Ok(mem::replace(v, new))
+                } else {
+                    Err(*v)
+                }
+            }
+        )
+    }
+
+    pub fn fetch_update(&self, mut f: impl FnMut(T) -> Option<T>) -> Result<T, T> {
+        let mut previous = self.load();
+        while let Some(next) = f(previous) {
+            match self.compare_exchange(previous, next) {
+                Ok(x) => return Ok(x),
+                Err(x) => previous = x,
+            }
+        }
+        Err(previous)
+    }
+}
+
+impl<T: NoPadding + Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: NoPadding> From<T> for AtomicCell<T> {
+    fn from(v: T) -> Self {
+        Self::new(v)
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for AtomicCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AtomicCell, NoPadding};
+
+    #[test]
+    fn native_and_locked() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        #[repr(C, align(4))]
+        struct Pair(u16, u16);
+        unsafe impl NoPadding for Pair {}
+        assert!(AtomicCell::new(Pair(1, 2)).is_lock_free());
+        // Only aligned to 2, three bytes, and padded: all behind the lock.
+        assert!(!AtomicCell::new([0u16; 2]).is_lock_free());
+        assert!(!AtomicCell::new([0u8; 3]).is_lock_free());
+        assert!(!AtomicCell::with_lock((0u8, 0u16)).is_lock_free());
+
+        let a = AtomicCell::new(Pair(1, 2));
+        assert_eq!(a.swap(Pair(3, 4)), Pair(1, 2));
+        assert_eq!(a.compare_exchange(Pair(1, 2), Pair(5, 6)), Err(Pair(3, 4)));
+        assert_eq!(a.compare_exchange(Pair(3, 4), Pair(5, 6)), Ok(Pair(3, 4)));
+        assert_eq!(a.load(), Pair(5, 6));
+
+        let b = AtomicCell::new([1u8, 2, 3]);
+        assert_eq!(b.fetch_update(|[x, y, z]| Some([z, y, x])), Ok([1, 2, 3]));
+        assert_eq!(b.compare_exchange([1, 2, 3], [0; 3]), Err([3, 2, 1]));
+        assert_eq!(b.load(), [3, 2, 1]);
+
+        let c = AtomicCell::with_lock((1u8, 2u16));
+        assert_eq!(c.compare_exchange((1, 2), (3, 4)), Ok((1, 2)));
+        assert_eq!(c.swap((5, 6)), (3, 4));
+        assert_eq!(c.load(), (5, 6));
+    }
+}
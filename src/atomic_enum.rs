@@ -0,0 +1,185 @@
+//! A typed atomic for `#[repr(u8)]` state machines, so states are enum
+//! variants instead of loose `u8` constants.
+//!
+//! `state_machine!` declares such an enum along with the transitions it
+//! allows, and `transition` is the `compare_exchange` that debug builds
+//! check against them, so a new state can't quietly be skipped over or
+//! moved out of the wrong way.
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The transitions a state machine allows, as `state_machine!` declares them.
+pub trait Transitions: Copy + Eq {
+    fn allows(from: Self, to: Self) -> bool;
+}
+
+pub struct AtomicEnum<E> {
+    value: AtomicU8,
+    _enum: PhantomData<E>,
+}
+
+impl<E> AtomicEnum<E>
+where
+    E: Copy + Into<u8> + TryFrom<u8>,
+{
+    pub fn new(value: E) -> Self {
+        Self::from_raw(value.into())
+    }
+
+    /// `const` version of `new`, for use in `const fn` constructors,
+    /// e.g. `AtomicEnum::from_raw(State::Empty as u8)`.
+    /// An invalid `raw` is caught (with a panic) on the first load.
+    pub const fn from_raw(raw: u8) -> Self {
+        Self {
+            value: AtomicU8::new(raw),
+            _enum: PhantomData,
+        }
+    }
+
+    pub fn load(&self, order: Ordering) -> E {
+        Self::decode(self.value.load(order))
+    }
+
+    pub fn store(&self, value: E, order: Ordering) {
+        self.value.store(value.into(), order);
+    }
+
+    pub fn swap(&self, value: E, order: Ordering) -> E {
+        Self::decode(self.value.swap(value.into(), order))
+    }
+
+    pub fn compare_exchange(
+        &self,
+        current: E,
+        new: E,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<E, E> {
+        self.value
+            .compare_exchange(current.into(), new.into(), success, failure)
+            .map(Self::decode)
+            .map_err(Self::decode)
+    }
+
+    /// Reads the value without an atomic operation;
+    /// `&mut self` already proves no other thread can be touching it.
+    pub fn load_mut(&mut self) -> E {
+        Self::decode(*self.value.get_mut())
+    }
+
+    fn decode(raw: u8) -> E {
+        E::try_from(raw).unwrap_or_else(|_| panic!("invalid state {raw} in AtomicEnum"))
+    }
+}
+
+impl<E> AtomicEnum<E>
+where
+    E: Transitions + Into<u8> + TryFrom<u8> + fmt::Debug,
+{
+    /// `compare_exchange` from `from` to `to`, with the state it was in
+    /// instead when it fails. Debug builds panic if `E` doesn't allow it,
+    /// whether or not it would have succeeded.
+    pub fn transition(
+        &self,
+        from: E,
+        to: E,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<(), E> {
+        Self::check(from, to);
+        self.compare_exchange(from, to, success, failure).map(drop)
+    }
+
+    /// A `store` of `to`, when it's in `from` and only this thread may
+    /// move it on from there. Debug builds also check it was in `from`.
+    pub fn advance(&self, from: E, to: E, order: Ordering) {
+        Self::check(from, to);
+        if cfg!(debug_assertions) {
+            let was = self.swap(to, order);
+            assert!(was == from, "advancing from {from:?}, but it was {was:?}");
+        } else {
+            self.store(to, order);
+        }
+    }
+
+    fn check(from: E, to: E) {
+        debug_assert!(E::allows(from, to), "no transition from {from:?} to {to:?}");
+    }
+}
+
+impl<E> fmt::Debug for AtomicEnum<E>
+where
+    E: Copy + Into<u8> + TryFrom<u8> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicEnum")
+            .field(&self.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Declares a `#[repr(u8)]` enum for `AtomicEnum`, each variant with the
+/// ones it may go to next, and implements the conversions and
+/// `Transitions` for it:
+///
+/// ```
+/// atomics_and_locks::state_machine! {
+///     #[derive(Debug)]
+///     enum State {
+///         Empty => [Writing],
+///         Writing => [Ready],
+///         Ready => [Reading],
+///         Reading => [],
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! state_machine {
+    (
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident {
+            $($variant:ident => [$($next:ident),* $(,)?]),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl ::core::convert::From<$name> for u8 {
+            fn from(state: $name) -> u8 {
+                state as u8
+            }
+        }
+
+        impl ::core::convert::TryFrom<u8> for $name {
+            type Error = u8;
+
+            fn try_from(raw: u8) -> ::core::result::Result<Self, u8> {
+                $(
+                    if raw == $name::$variant as u8 {
+                        return ::core::result::Result::Ok($name::$variant);
+                    }
+                )*
+                ::core::result::Result::Err(raw)
+            }
+        }
+
+        impl $crate::atomic::Transitions for $name {
+            fn allows(from: Self, to: Self) -> bool {
+                match from {
+                    $($name::$variant => {
+                        let next: &[Self] = &[$($name::$next),*];
+                        next.contains(&to)
+                    })*
+                }
+            }
+        }
+    };
+}
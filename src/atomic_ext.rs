@@ -0,0 +1,125 @@
+//! Combinators for the compare-and-exchange loops that keep showing up
+//! (see `get_key` in cap_2).
+//!
+//! `fetch_min`/`fetch_max` are left out on purpose: std atomics already have them.
+
+// The polyfill where there's no 64-bit atomic, so `get_key` keeps working.
+use crate::atomic_u64::AtomicU64;
+use std::sync::atomic::{
+    AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU8,
+    AtomicUsize,
+    Ordering::{self, AcqRel, Acquire, Relaxed, Release, SeqCst},
+};
+
+pub trait AtomicExt {
+    type Value: Copy;
+
+    /// Replaces the value with `f(value)` and returns the previous one.
+    /// `f` may run more than once if other threads race with us.
+    ///
+    /// Not called `update`: newer std atomics have an inherent method by that
+    /// name (taking two orderings), which would shadow this one.
+    fn update_with(
+        &self,
+        order: Ordering,
+        f: impl FnMut(Self::Value) -> Self::Value,
+    ) -> Self::Value;
+
+    /// Like `update_with`, but only while `pred` holds for the current value.
+    /// Returns `Err(current)` without storing anything once it doesn't.
+    fn try_update_if(
+        &self,
+        order: Ordering,
+        pred: impl FnMut(Self::Value) -> bool,
+        f: impl FnMut(Self::Value) -> Self::Value,
+    ) -> Result<Self::Value, Self::Value>;
+
+    /// Returns the stored value, storing `init()` first if it is still zero.
+    /// Racing threads may all run `init`, but they all get the same winner back.
+    fn get_or_init(&self, order: Ordering, init: impl FnOnce() -> Self::Value) -> Self::Value;
+
+    /// Adds `v`, sticking at the maximum instead of wrapping around.
+    fn fetch_saturating_add(&self, v: Self::Value, order: Ordering) -> Self::Value;
+
+    /// Subtracts `v`, sticking at the minimum instead of wrapping around.
+    fn fetch_saturating_sub(&self, v: Self::Value, order: Ordering) -> Self::Value;
+
+    /// Adds `v` unless it would overflow, in which case nothing is stored.
+    fn fetch_checked_add(&self, v: Self::Value, order: Ordering) -> Option<Self::Value>;
+
+    /// Subtracts `v` unless it would overflow, in which case nothing is stored.
+    fn fetch_checked_sub(&self, v: Self::Value, order: Ordering) -> Option<Self::Value>;
+}
+
+/// The ordering to use for the load half of a read-modify-write.
+fn load_ordering(order: Ordering) -> Ordering {
+    match order {
+        Release | Relaxed => Relaxed,
+        AcqRel | Acquire => Acquire,
+        _ => SeqCst,
+    }
+}
+
+macro_rules! impl_atomic_ext {
+    ($($atomic:ty => $int:ty),* $(,)?) => {$(
+        impl AtomicExt for $atomic {
+            type Value = $int;
+
+            fn update_with(&self, order: Ordering, mut f: impl FnMut($int) -> $int) -> $int {
+                match self.fetch_update(order, load_ordering(order), |v| Some(f(v))) {
+                    Ok(v) | Err(v) => v,
+                }
+            }
+
+            fn try_update_if(
+                &self,
+                order: Ordering,
+                mut pred: impl FnMut($int) -> bool,
+                mut f: impl FnMut($int) -> $int,
+            ) -> Result<$int, $int> {
+                self.fetch_update(order, load_ordering(order), |v| pred(v).then(|| f(v)))
+            }
+
+            fn get_or_init(&self, order: Ordering, init: impl FnOnce() -> $int) -> $int {
+                let v = self.load(load_ordering(order));
+                if v != 0 {
+                    return v;
+                }
+                let new = init();
+                match self.compare_exchange(0, new, order, load_ordering(order)) {
+                    Ok(_) => new,
+                    Err(v) => v,
+                }
+            }
+
+            fn fetch_saturating_add(&self, v: $int, order: Ordering) -> $int {
+                self.update_with(order, |x| x.saturating_add(v))
+            }
+
+            fn fetch_saturating_sub(&self, v: $int, order: Ordering) -> $int {
+                self.update_with(order, |x| x.saturating_sub(v))
+            }
+
+            fn fetch_checked_add(&self, v: $int, order: Ordering) -> Option<$int> {
+                self.fetch_update(order, load_ordering(order), |x| x.checked_add(v)).ok()
+            }
+
+            fn fetch_checked_sub(&self, v: $int, order: Ordering) -> Option<$int> {
+                self.fetch_update(order, load_ordering(order), |x| x.checked_sub(v)).ok()
+            }
+        }
+    )*};
+}
+
+impl_atomic_ext! {
+    AtomicU8 => u8,
+    AtomicU16 => u16,
+    AtomicU32 => u32,
+    AtomicU64 => u64,
+    AtomicUsize => usize,
+    AtomicI8 => i8,
+    AtomicI16 => i16,
+    AtomicI32 => i32,
+    AtomicI64 => i64,
+    AtomicIsize => isize,
+}
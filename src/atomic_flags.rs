@@ -0,0 +1,117 @@
+//! A typed atomic for sets of flags, so a handful of conditions (ready,
+//! closed, poisoned) can live in one `AtomicU32` and change together, without
+//! loose `u32` masks at every use.
+//!
+//! Like `AtomicEnum`, it converts through the integer: any `F` with
+//! `Into<u32>` and `From<u32>` works, e.g. a `bitflags!` type with those two
+//! impls forwarding to `bits` and `from_bits_retain`.
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+pub struct AtomicFlags<F> {
+    bits: AtomicU32,
+    _flags: PhantomData<F>,
+}
+
+impl<F> AtomicFlags<F>
+where
+    F: Copy + Into<u32> + From<u32>,
+{
+    pub fn new(flags: F) -> Self {
+        Self::from_bits(flags.into())
+    }
+
+    /// `const` version of `new`, e.g. `AtomicFlags::from_bits(0)`.
+    pub const fn from_bits(bits: u32) -> Self {
+        Self {
+            bits: AtomicU32::new(bits),
+            _flags: PhantomData,
+        }
+    }
+
+    pub fn load(&self, order: Ordering) -> F {
+        F::from(self.bits.load(order))
+    }
+
+    pub fn store(&self, flags: F, order: Ordering) {
+        self.bits.store(flags.into(), order);
+    }
+
+    pub fn swap(&self, flags: F, order: Ordering) -> F {
+        F::from(self.bits.swap(flags.into(), order))
+    }
+
+    /// Whether all of `flags` are set.
+    pub fn contains(&self, flags: F, order: Ordering) -> bool {
+        let bits = flags.into();
+        self.bits.load(order) & bits == bits
+    }
+
+    /// Whether any of `flags` is set.
+    pub fn intersects(&self, flags: F, order: Ordering) -> bool {
+        self.bits.load(order) & flags.into() != 0
+    }
+
+    /// Sets `flags`, and returns the ones that were set before.
+    pub fn insert(&self, flags: F, order: Ordering) -> F {
+        F::from(self.bits.fetch_or(flags.into(), order))
+    }
+
+    /// Clears `flags`, and returns the ones that were set before.
+    pub fn remove(&self, flags: F, order: Ordering) -> F {
+        F::from(self.bits.fetch_and(!flags.into(), order))
+    }
+
+    /// Flips `flags`, and returns the ones that were set before.
+    pub fn toggle(&self, flags: F, order: Ordering) -> F {
+        F::from(self.bits.fetch_xor(flags.into(), order))
+    }
+
+    pub fn compare_exchange(
+        &self,
+        current: F,
+        new: F,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<F, F> {
+        self.bits
+            .compare_exchange(current.into(), new.into(), success, failure)
+            .map(F::from)
+            .map_err(F::from)
+    }
+
+    /// For changes that depend on more than one flag, e.g. set `CLOSED`
+    /// unless `POISONED` is.
+    pub fn fetch_update(
+        &self,
+        set_order: Ordering,
+        fetch_order: Ordering,
+        mut f: impl FnMut(F) -> Option<F>,
+    ) -> Result<F, F> {
+        self.bits
+            .fetch_update(set_order, fetch_order, |b| f(F::from(b)).map(Into::into))
+            .map(F::from)
+            .map_err(F::from)
+    }
+
+    /// Reads the flags without an atomic operation;
+    /// `&mut self` already proves no other thread can be touching them.
+    pub fn load_mut(&mut self) -> F {
+        F::from(*self.bits.get_mut())
+    }
+}
+
+impl<F> fmt::Debug for AtomicFlags<F>
+where
+    F: Copy + Into<u32> + From<u32> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicFlags")
+            .field(&self.load(Ordering::Relaxed))
+            .finish()
+    }
+}
@@ -0,0 +1,122 @@
+//! `AtomicF32` and `AtomicF64`: the bits of a float in an `AtomicU32` or
+//! `AtomicU64`. There's no hardware float add on memory, so the `fetch_*`
+//! arithmetic is a compare-and-swap loop, through `fetch_update`.
+//!
+//! `compare_exchange` compares bits, not values: `-0.0` isn't `0.0` there,
+//! and a NaN is equal to itself, if it's the same NaN.
+
+use core::{fmt, sync::atomic::AtomicU32, sync::atomic::Ordering};
+
+use crate::atomic_u64::AtomicU64;
+
+macro_rules! atomic_float {
+    ($name:ident, $float:ty, $atomic:ty) => {
+        #[repr(transparent)]
+        pub struct $name {
+            bits: $atomic,
+        }
+
+        impl $name {
+            pub const fn new(v: $float) -> Self {
+                Self {
+                    bits: <$atomic>::new(v.to_bits()),
+                }
+            }
+
+            pub fn into_inner(self) -> $float {
+                <$float>::from_bits(self.bits.into_inner())
+            }
+
+            pub fn load(&self, order: Ordering) -> $float {
+                <$float>::from_bits(self.bits.load(order))
+            }
+
+            pub fn store(&self, v: $float, order: Ordering) {
+                self.bits.store(v.to_bits(), order);
+            }
+
+            pub fn swap(&self, v: $float, order: Ordering) -> $float {
+                <$float>::from_bits(self.bits.swap(v.to_bits(), order))
+            }
+
+            pub fn compare_exchange(
+                &self,
+                current: $float,
+                new: $float,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<$float, $float> {
+                self.bits
+                    .compare_exchange(current.to_bits(), new.to_bits(), success, failure)
+                    .map(<$float>::from_bits)
+                    .map_err(<$float>::from_bits)
+            }
+
+            pub fn fetch_update(
+                &self,
+                set_order: Ordering,
+                fetch_order: Ordering,
+                mut f: impl FnMut($float) -> Option<$float>,
+            ) -> Result<$float, $float> {
+                self.bits
+                    .fetch_update(set_order, fetch_order, |b| {
+                        f(<$float>::from_bits(b)).map(<$float>::to_bits)
+                    })
+                    .map(<$float>::from_bits)
+                    .map_err(<$float>::from_bits)
+            }
+
+            /// Adds `v`, and returns the previous value.
+            pub fn fetch_add(&self, v: $float, order: Ordering) -> $float {
+                self.update(order, |x| x + v)
+            }
+
+            pub fn fetch_sub(&self, v: $float, order: Ordering) -> $float {
+                self.update(order, |x| x - v)
+            }
+
+            /// Like `f64::max`, a NaN on either side gives the other one.
+            pub fn fetch_max(&self, v: $float, order: Ordering) -> $float {
+                self.update(order, |x| x.max(v))
+            }
+
+            pub fn fetch_min(&self, v: $float, order: Ordering) -> $float {
+                self.update(order, |x| x.min(v))
+            }
+
+            /// `fetch_update` with an `f` that always gives a value. Loads
+            /// are `Relaxed`, or `Acquire` if `order` includes it.
+            fn update(&self, order: Ordering, mut f: impl FnMut($float) -> $float) -> $float {
+                let fetch_order = match order {
+                    Ordering::AcqRel | Ordering::SeqCst => Ordering::Acquire,
+                    Ordering::Release => Ordering::Relaxed,
+                    order => order,
+                };
+                match self.fetch_update(order, fetch_order, |x| Some(f(x))) {
+                    Ok(x) | Err(x) => x,
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new(0.0)
+            }
+        }
+
+        impl From<$float> for $name {
+            fn from(v: $float) -> Self {
+                Self::new(v)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+            }
+        }
+    };
+}
+
+atomic_float!(AtomicF32, f32, AtomicU32);
+atomic_float!(AtomicF64, f64, AtomicU64);
@@ -0,0 +1,162 @@
+//! `RefCell`'s borrow rules, checked with an atomic, so it can be shared
+//! between threads: any number of `borrow`s, or one `borrow_mut`. A borrow
+//! is one `fetch_add`, and never waits. Where two threads do want the value
+//! at the same time that's a bug, and the borrow fails instead of blocking;
+//! for that, use a `Mutex`.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::error::{Error, Result};
+
+/// Set while borrowed mutably. The rest of the bits count the `borrow`s.
+const WRITER: usize = 1 << (usize::BITS - 1);
+/// Far more than any program holds, far enough from `WRITER` that
+/// readers racing past it can't reach it.
+const MAX_READERS: usize = WRITER >> 1;
+
+pub struct AtomicRefCell<T: ?Sized> {
+    borrows: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+// Like `RwLock`: shared borrows hand out `&T` to many threads, a mutable
+// one `&mut T` to any of them.
+unsafe impl<T: ?Sized + Send + Sync> Sync for AtomicRefCell<T> {}
+
+impl<T> AtomicRefCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            borrows: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> AtomicRefCell<T> {
+    /// Fails if it's borrowed mutably.
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>> {
+        let previous = self.borrows.fetch_add(1, Acquire);
+        if previous & WRITER != 0 {
+            // The writer only clears its own bit, so this is ours to undo.
+            self.borrows.fetch_sub(1, Relaxed);
+            return Err(Error::Borrowed);
+        }
+        if previous >= MAX_READERS {
+            self.borrows.fetch_sub(1, Relaxed);
+            panic!("too many borrows");
+        }
+        Ok(AtomicRef { cell: self })
+    }
+
+    /// Fails if it's borrowed at all.
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>> {
+        match self.borrows.compare_exchange(0, WRITER, Acquire, Relaxed) {
+            Ok(_) => Ok(AtomicRefMut { cell: self }),
+            Err(_) => Err(Error::Borrowed),
+        }
+    }
+
+    /// Panics if it's borrowed mutably.
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        self.try_borrow().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Panics if it's borrowed at all.
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        self.try_borrow_mut().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// No borrow needed, `&mut self` already proves there's no other.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for AtomicRefCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("AtomicRefCell");
+        match self.try_borrow() {
+            Ok(value) => d.field("value", &&*value),
+            Err(_) => d.field("value", &format_args!("<borrowed>")),
+        };
+        d.finish()
+    }
+}
+
+pub struct AtomicRef<'a, T: ?Sized> {
+    cell: &'a AtomicRefCell<T>,
+}
+
+impl<T: ?Sized> Deref for AtomicRef<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this AtomicRef
+        // guarantees there's no mutable borrow.
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AtomicRef<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrows.fetch_sub(1, Release);
+    }
+}
+
+pub struct AtomicRefMut<'a, T: ?Sized> {
+    cell: &'a AtomicRefCell<T>,
+}
+
+impl<T: ?Sized> Deref for AtomicRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this AtomicRefMut
+        // guarantees it's the only borrow.
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AtomicRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this AtomicRefMut
+        // guarantees it's the only borrow.
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AtomicRefMut<'_, T> {
+    fn drop(&mut self) {
+        // Not a plain store of 0: readers that failed may not have undone
+        // their increment yet.
+        self.cell.borrows.fetch_and(!WRITER, Release);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
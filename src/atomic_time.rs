@@ -0,0 +1,202 @@
+//! `AtomicDuration` and `AtomicInstant`: nanoseconds in an `AtomicU64`,
+//! so code timing things doesn't have to pick a unit, convert to it and
+//! back, and remember which one it was.
+//!
+//! 64 bits of nanoseconds is 584 years, past which durations saturate on
+//! the way in, and wrap on `fetch_add`.
+
+use core::{fmt, sync::atomic::Ordering, time::Duration};
+
+use crate::atomic_u64::AtomicU64;
+
+const fn nanos(d: Duration) -> u64 {
+    let n = d.as_nanos();
+    if n > u64::MAX as u128 {
+        u64::MAX
+    } else {
+        n as u64
+    }
+}
+
+#[repr(transparent)]
+pub struct AtomicDuration {
+    nanos: AtomicU64,
+}
+
+impl AtomicDuration {
+    pub const fn new(d: Duration) -> Self {
+        Self {
+            nanos: AtomicU64::new(nanos(d)),
+        }
+    }
+
+    pub fn into_inner(self) -> Duration {
+        Duration::from_nanos(self.nanos.into_inner())
+    }
+
+    pub fn load(&self, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.load(order))
+    }
+
+    pub fn store(&self, d: Duration, order: Ordering) {
+        self.nanos.store(nanos(d), order);
+    }
+
+    pub fn swap(&self, d: Duration, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.swap(nanos(d), order))
+    }
+
+    pub fn compare_exchange(
+        &self,
+        current: Duration,
+        new: Duration,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Duration, Duration> {
+        self.nanos
+            .compare_exchange(nanos(current), nanos(new), success, failure)
+            .map(Duration::from_nanos)
+            .map_err(Duration::from_nanos)
+    }
+
+    pub fn fetch_add(&self, d: Duration, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.fetch_add(nanos(d), order))
+    }
+
+    pub fn fetch_sub(&self, d: Duration, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.fetch_sub(nanos(d), order))
+    }
+
+    pub fn fetch_max(&self, d: Duration, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.fetch_max(nanos(d), order))
+    }
+
+    pub fn fetch_min(&self, d: Duration, order: Ordering) -> Duration {
+        Duration::from_nanos(self.nanos.fetch_min(nanos(d), order))
+    }
+}
+
+impl Default for AtomicDuration {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+impl From<Duration> for AtomicDuration {
+    fn from(d: Duration) -> Self {
+        Self::new(d)
+    }
+}
+
+impl fmt::Debug for AtomicDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+    }
+}
+
+#[cfg(feature = "std")]
+pub use instant::AtomicInstant;
+
+#[cfg(feature = "std")]
+mod instant {
+    use core::{fmt, sync::atomic::Ordering, time::Duration};
+    use std::{sync::OnceLock, time::Instant};
+
+    use super::nanos;
+    use crate::atomic_u64::AtomicU64;
+
+    /// What every `AtomicInstant` counts from: the first time one is used.
+    fn anchor() -> Instant {
+        static ANCHOR: OnceLock<Instant> = OnceLock::new();
+        *ANCHOR.get_or_init(Instant::now)
+    }
+
+    /// Half of the range, for the instants before the anchor.
+    const BIAS: u64 = 1 << 63;
+
+    /// An `Instant`, as nanoseconds from an anchor taken the first time any
+    /// of them is used, either way: 292 years each, plenty for a process.
+    /// The anchor is in the middle of the range, so later instants are
+    /// still larger numbers, for `fetch_max`.
+    pub struct AtomicInstant {
+        bits: AtomicU64,
+    }
+
+    fn to_bits(t: Instant) -> u64 {
+        let anchor = anchor();
+        if t >= anchor {
+            BIAS.saturating_add(nanos(t - anchor))
+        } else {
+            BIAS.saturating_sub(nanos(anchor - t))
+        }
+    }
+
+    fn from_bits(b: u64) -> Instant {
+        if b >= BIAS {
+            anchor() + Duration::from_nanos(b - BIAS)
+        } else {
+            anchor() - Duration::from_nanos(BIAS - b)
+        }
+    }
+
+    impl AtomicInstant {
+        pub fn new(t: Instant) -> Self {
+            Self {
+                bits: AtomicU64::new(to_bits(t)),
+            }
+        }
+
+        pub fn now() -> Self {
+            Self::new(Instant::now())
+        }
+
+        pub fn load(&self, order: Ordering) -> Instant {
+            from_bits(self.bits.load(order))
+        }
+
+        pub fn store(&self, t: Instant, order: Ordering) {
+            self.bits.store(to_bits(t), order);
+        }
+
+        pub fn store_now(&self, order: Ordering) {
+            self.store(Instant::now(), order);
+        }
+
+        pub fn swap(&self, t: Instant, order: Ordering) -> Instant {
+            from_bits(self.bits.swap(to_bits(t), order))
+        }
+
+        /// Keeps the later of the two, like a "last seen" that can be
+        /// updated out of order. Returns the previous one.
+        pub fn fetch_max(&self, t: Instant, order: Ordering) -> Instant {
+            from_bits(self.bits.fetch_max(to_bits(t), order))
+        }
+
+        pub fn fetch_min(&self, t: Instant, order: Ordering) -> Instant {
+            from_bits(self.bits.fetch_min(to_bits(t), order))
+        }
+
+        /// How long ago the stored instant is, zero if it's in the future.
+        pub fn elapsed_since_store(&self, order: Ordering) -> Duration {
+            Instant::now().saturating_duration_since(self.load(order))
+        }
+    }
+
+    impl Default for AtomicInstant {
+        fn default() -> Self {
+            Self::now()
+        }
+    }
+
+    impl From<Instant> for AtomicInstant {
+        fn from(t: Instant) -> Self {
+            Self::new(t)
+        }
+    }
+
+    impl fmt::Debug for AtomicInstant {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+        }
+    }
+}
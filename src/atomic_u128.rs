@@ -0,0 +1,344 @@
+//! A 128-bit atomic, for compare-and-swapping two words at once: a pointer
+//! and a version counter against ABA, or a packed state too big for 64 bits.
+//!
+//! Native where the hardware has a double-word compare-and-swap:
+//! `cmpxchg16b` on x86_64 (when built with that target feature, e.g.
+//! `-C target-cpu=native`), `casp` on aarch64 with LSE, and an exclusive
+//! load/store pair on any other aarch64. Elsewhere every value hashes its
+//! address to one of a few spin locks, see `is_lock_free`.
+//!
+//! The tests build the lock-based version, and `cmpxchg16b` on x86_64,
+//! whatever this target uses, to check both.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::Ordering::{self, Relaxed},
+};
+
+/// Every native operation is `SeqCst`-like, whatever ordering is asked for.
+#[repr(C, align(16))]
+pub struct AtomicU128 {
+    v: UnsafeCell<u128>,
+}
+
+// Only ever accessed through `imp`.
+unsafe impl Sync for AtomicU128 {}
+
+impl AtomicU128 {
+    pub const fn new(v: u128) -> Self {
+        Self {
+            v: UnsafeCell::new(v),
+        }
+    }
+
+    /// Whether this target does it without taking a lock.
+    pub const fn is_lock_free() -> bool {
+        imp::LOCK_FREE
+    }
+
+    pub fn get_mut(&mut self) -> &mut u128 {
+        self.v.get_mut()
+    }
+
+    pub fn into_inner(self) -> u128 {
+        self.v.into_inner()
+    }
+
+    pub fn load(&self, _: Ordering) -> u128 {
+        // Safety: aligned, and only ever accessed atomically.
+        unsafe { imp::load(self.v.get()) }
+    }
+
+    pub fn store(&self, v: u128, order: Ordering) {
+        self.swap(v, order);
+    }
+
+    pub fn swap(&self, v: u128, order: Ordering) -> u128 {
+        self.fetch_update(order, Relaxed, |_| Some(v)).unwrap()
+    }
+
+    pub fn compare_exchange(
+        &self,
+        current: u128,
+        new: u128,
+        _: Ordering,
+        _: Ordering,
+    ) -> Result<u128, u128> {
+        // Safety: aligned, and only ever accessed atomically.
+        match unsafe { imp::cas(self.v.get(), current, new) } {
+            (previous, true) => Ok(previous),
+            (previous, false) => Err(previous),
+        }
+    }
+
+    pub fn compare_exchange_weak(
+        &self,
+        current: u128,
+        new: u128,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<u128, u128> {
+        self.compare_exchange(current, new, success, failure)
+    }
+
+    pub fn fetch_update(
+        &self,
+        set_order: Ordering,
+        fetch_order: Ordering,
+        mut f: impl FnMut(u128) -> Option<u128>,
+    ) -> Result<u128, u128> {
+        let mut previous = self.load(fetch_order);
+        while let Some(next) = f(previous) {
+            match self.compare_exchange(previous, next, set_order, fetch_order) {
+                Ok(x) => return Ok(x),
+                Err(x) => previous = x,
+            }
+        }
+        Err(previous)
+    }
+}
+
+impl Default for AtomicU128 {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl fmt::Debug for AtomicU128 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(Relaxed), f)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "cmpxchg16b"))]
+use cmpxchg16b as imp;
+
+#[cfg(all(target_arch = "x86_64", any(target_feature = "cmpxchg16b", test)))]
+#[cfg_attr(test, allow(dead_code))]
+mod cmpxchg16b {
+    use core::arch::asm;
+
+    pub const LOCK_FREE: bool = true;
+
+    /// Swaps in `new` if `*dst` is `old`. Returns what was there, and whether it was `old`.
+    pub unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+        let (lo, hi, ok): (u64, u64, u64);
+        // `rbx` belongs to LLVM, so `new`'s low half goes through another
+        // register and `rbx` is put back afterwards.
+        asm!(
+            "xchg {new_lo}, rbx",
+            "lock cmpxchg16b xmmword ptr [{dst}]",
+            "sete cl",
+            "mov rbx, {new_lo}",
+            dst = in(reg) dst,
+            new_lo = inout(reg) new as u64 => _,
+            inout("rcx") (new >> 64) as u64 => ok,
+            inout("rax") old as u64 => lo,
+            inout("rdx") (old >> 64) as u64 => hi,
+            options(nostack),
+        );
+        (((hi as u128) << 64) | lo as u128, ok as u8 != 0)
+    }
+
+    /// A failing (or idempotent) compare-and-swap: there's no 16-byte load.
+    pub unsafe fn load(src: *mut u128) -> u128 {
+        cas(src, 0, 0).0
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+mod imp {
+    use core::arch::asm;
+
+    pub const LOCK_FREE: bool = true;
+
+    /// Swaps in `new` if `*dst` is `old`. Returns what was there, and whether it was `old`.
+    #[cfg(target_feature = "lse")]
+    pub unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+        let (lo, hi): (u64, u64);
+        asm!(
+            "caspal x4, x5, x6, x7, [{dst}]",
+            dst = in(reg) dst,
+            inout("x4") old as u64 => lo,
+            inout("x5") (old >> 64) as u64 => hi,
+            in("x6") new as u64,
+            in("x7") (new >> 64) as u64,
+            options(nostack, preserves_flags),
+        );
+        let previous = ((hi as u128) << 64) | lo as u128;
+        (previous, previous == old)
+    }
+
+    /// Without LSE: an exclusive load, and an exclusive store of either the
+    /// new value or, on a mismatch, the old one, since only a successful
+    /// store makes the load a single 16-byte read.
+    #[cfg(not(target_feature = "lse"))]
+    pub unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+        let (lo, hi): (u64, u64);
+        asm!(
+            "2:",
+            "ldaxp {lo}, {hi}, [{dst}]",
+            "cmp {lo}, {old_lo}",
+            "ccmp {hi}, {old_hi}, #0, eq",
+            "b.ne 3f",
+            "stlxp {failed:w}, {new_lo}, {new_hi}, [{dst}]",
+            "cbnz {failed:w}, 2b",
+            "b 4f",
+            "3:",
+            "stlxp {failed:w}, {lo}, {hi}, [{dst}]",
+            "cbnz {failed:w}, 2b",
+            "4:",
+            dst = in(reg) dst,
+            old_lo = in(reg) old as u64,
+            old_hi = in(reg) (old >> 64) as u64,
+            new_lo = in(reg) new as u64,
+            new_hi = in(reg) (new >> 64) as u64,
+            lo = out(reg) lo,
+            hi = out(reg) hi,
+            failed = out(reg) _,
+            options(nostack),
+        );
+        let previous = ((hi as u128) << 64) | lo as u128;
+        (previous, previous == old)
+    }
+
+    pub unsafe fn load(src: *mut u128) -> u128 {
+        cas(src, 0, 0).0
+    }
+}
+
+#[cfg(not(any(
+    all(target_arch = "x86_64", target_feature = "cmpxchg16b"),
+    all(target_arch = "aarch64", target_endian = "little"),
+)))]
+use locked as imp;
+
+#[cfg(any(
+    test,
+    not(any(
+        all(target_arch = "x86_64", target_feature = "cmpxchg16b"),
+        all(target_arch = "aarch64", target_endian = "little"),
+    ))
+))]
+#[cfg_attr(test, allow(dead_code))]
+mod locked {
+    use crate::{cache_padded::CachePadded, spin_lock::SpinLock};
+
+    pub const LOCK_FREE: bool = false;
+
+    /// Values share a lock only if their addresses collide, so unrelated
+    /// ones rarely wait on each other. Nor on their neighbours' cache lines.
+    static STRIPES: [CachePadded<SpinLock<()>>; 64] =
+        [const { CachePadded::new(SpinLock::new(())) }; 64];
+
+    fn stripe(address: *mut u128) -> &'static SpinLock<()> {
+        // The low four bits are always zero.
+        &STRIPES[(address as usize >> 4) % STRIPES.len()]
+    }
+
+    pub unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+        let _guard = stripe(dst).lock();
+        let previous = *dst;
+        if previous == old {
+            *dst = new;
+        }
+        (previous, previous == old)
+    }
+
+    pub unsafe fn load(src: *mut u128) -> u128 {
+        let _guard = stripe(src).lock();
+        *src
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AtomicU128;
+    use core::sync::atomic::Ordering::{Relaxed, SeqCst};
+    use std::thread;
+
+    /// One in each half, so an operation that only did one would show.
+    const ONE: u128 = 1 << 64 | 1;
+    const X: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+    const THREADS: u128 = 4;
+    const ADDS: u128 = 1000;
+
+    type Cas = unsafe fn(*mut u128, u128, u128) -> (u128, bool);
+    type Load = unsafe fn(*mut u128) -> u128;
+
+    /// A version's own `cas` and `load`, on an `AtomicU128` for the
+    /// alignment.
+    fn check(cas: Cas, load: Load) {
+        let a = AtomicU128::new(0);
+        let p = a.v.get();
+        // Safety: aligned, and only ever accessed through this version.
+        unsafe {
+            assert_eq!(cas(p, 0, X), (0, true));
+            assert_eq!(load(p), X);
+            assert_eq!(cas(p, 0, ONE), (X, false));
+            assert_eq!(load(p), X);
+        }
+
+        let counter = &AtomicU128::new(0);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(move || {
+                    let p = counter.v.get();
+                    for _ in 0..ADDS {
+                        // Safety: as above.
+                        let mut v = unsafe { load(p) };
+                        loop {
+                            match unsafe { cas(p, v, v + ONE) } {
+                                (_, true) => break,
+                                (previous, false) => v = previous,
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(SeqCst), THREADS * ADDS * ONE);
+    }
+
+    #[test]
+    fn locked() {
+        check(super::locked::cas, super::locked::load);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn cmpxchg16b() {
+        if std::arch::is_x86_feature_detected!("cmpxchg16b") {
+            check(super::cmpxchg16b::cas, super::cmpxchg16b::load);
+        }
+    }
+
+    /// Whichever version this target uses, through the type.
+    #[test]
+    fn api() {
+        let a = AtomicU128::new(0);
+        a.store(X, SeqCst);
+        assert_eq!(a.load(SeqCst), X);
+        assert_eq!(a.swap(ONE, SeqCst), X);
+        assert_eq!(a.compare_exchange(X, 0, SeqCst, Relaxed), Err(ONE));
+        assert_eq!(a.compare_exchange(ONE, X, SeqCst, Relaxed), Ok(ONE));
+        assert_eq!(a.fetch_update(SeqCst, Relaxed, |v| Some(!v)), Ok(X));
+        assert_eq!(a.fetch_update(SeqCst, Relaxed, |_| None), Err(!X));
+        assert_eq!(a.into_inner(), !X);
+
+        let counter = AtomicU128::new(0);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for _ in 0..ADDS {
+                        counter
+                            .fetch_update(SeqCst, Relaxed, |v| Some(v + ONE))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(SeqCst), THREADS * ADDS * ONE);
+    }
+}
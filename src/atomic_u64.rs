@@ -0,0 +1,211 @@
+//! `AtomicU64` everywhere, including 32-bit targets that don't have one.
+//!
+//! Where the hardware does 64-bit atomics this is just `core`'s type.
+//! Elsewhere it's two `AtomicU32` halves behind a sequence lock: readers
+//! retry until they saw both halves without a writer in between, writers
+//! take turns by making the sequence number odd.
+//!
+//! Taking turns needs a compare-and-swap. Targets without any (thumbv6m)
+//! get a critical section with interrupts disabled instead, which is only
+//! enough on a single core, which is what those chips have. That's done
+//! for Cortex-M (ARM) only; other targets without a compare-and-swap, like
+//! riscv32i or AVR, don't build.
+
+#[cfg(target_has_atomic = "64")]
+pub use core::sync::atomic::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+pub use polyfill::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+mod polyfill {
+    use core::{
+        fmt,
+        sync::atomic::{
+            fence, AtomicU32,
+            Ordering::{self, Acquire, Relaxed},
+        },
+    };
+
+    /// Every operation is at least as strong as the ordering asked for:
+    /// the sequence lock makes them all `SeqCst`-like for this one value.
+    #[repr(C, align(8))]
+    pub struct AtomicU64 {
+        /// Odd while a writer is busy.
+        seq: AtomicU32,
+        lo: AtomicU32,
+        hi: AtomicU32,
+    }
+
+    impl AtomicU64 {
+        pub const fn new(v: u64) -> Self {
+            Self {
+                seq: AtomicU32::new(0),
+                lo: AtomicU32::new(v as u32),
+                hi: AtomicU32::new((v >> 32) as u32),
+            }
+        }
+
+        pub fn into_inner(self) -> u64 {
+            self.load(Relaxed)
+        }
+
+        pub fn load(&self, _: Ordering) -> u64 {
+            loop {
+                let seq = self.seq.load(Acquire);
+                if seq & 1 == 1 {
+                    core::hint::spin_loop();
+                    continue;
+                }
+                let lo = self.lo.load(Acquire);
+                let hi = self.hi.load(Acquire);
+                // Keep the loads above from moving below the check.
+                fence(Acquire);
+                if self.seq.load(Relaxed) == seq {
+                    return ((hi as u64) << 32) | lo as u64;
+                }
+            }
+        }
+
+        /// Runs `f` on the value with every other writer locked out, and stores
+        /// what it returns, if anything. Returns the previous value.
+        fn write(&self, f: impl FnOnce(u64) -> Option<u64>) -> Result<u64, u64> {
+            critical::with(&self.seq, || {
+                let old = ((self.hi.load(Relaxed) as u64) << 32) | self.lo.load(Relaxed) as u64;
+                let new = f(old).ok_or(old)?;
+                self.lo.store(new as u32, Relaxed);
+                self.hi.store((new >> 32) as u32, Relaxed);
+                Ok(old)
+            })
+        }
+
+        pub fn store(&self, v: u64, _: Ordering) {
+            let _ = self.write(|_| Some(v));
+        }
+
+        pub fn swap(&self, v: u64, _: Ordering) -> u64 {
+            self.write(|_| Some(v)).unwrap()
+        }
+
+        pub fn compare_exchange(
+            &self,
+            current: u64,
+            new: u64,
+            _: Ordering,
+            _: Ordering,
+        ) -> Result<u64, u64> {
+            self.write(|v| (v == current).then_some(new))
+        }
+
+        pub fn compare_exchange_weak(
+            &self,
+            current: u64,
+            new: u64,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<u64, u64> {
+            self.compare_exchange(current, new, success, failure)
+        }
+
+        pub fn fetch_update(
+            &self,
+            _: Ordering,
+            _: Ordering,
+            f: impl FnMut(u64) -> Option<u64>,
+        ) -> Result<u64, u64> {
+            self.write(f)
+        }
+
+        pub fn fetch_add(&self, v: u64, _: Ordering) -> u64 {
+            self.write(|x| Some(x.wrapping_add(v))).unwrap()
+        }
+
+        pub fn fetch_sub(&self, v: u64, _: Ordering) -> u64 {
+            self.write(|x| Some(x.wrapping_sub(v))).unwrap()
+        }
+
+        pub fn fetch_max(&self, v: u64, _: Ordering) -> u64 {
+            self.write(|x| Some(x.max(v))).unwrap()
+        }
+
+        pub fn fetch_min(&self, v: u64, _: Ordering) -> u64 {
+            self.write(|x| Some(x.min(v))).unwrap()
+        }
+    }
+
+    impl Default for AtomicU64 {
+        fn default() -> Self {
+            Self::new(0)
+        }
+    }
+
+    impl fmt::Debug for AtomicU64 {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(&self.load(Relaxed), f)
+        }
+    }
+
+    /// Runs `f` with other writers locked out and `seq` odd, so readers
+    /// retry, and makes it even again (and different) afterwards.
+    #[cfg(target_has_atomic = "32")]
+    mod critical {
+        use core::sync::atomic::{
+            fence, AtomicU32,
+            Ordering::{Acquire, Relaxed, Release},
+        };
+
+        pub fn with<R>(seq: &AtomicU32, f: impl FnOnce() -> R) -> R {
+            let s = loop {
+                let s = seq.load(Relaxed);
+                if s & 1 == 0
+                    && seq
+                        .compare_exchange_weak(s, s + 1, Acquire, Relaxed)
+                        .is_ok()
+                {
+                    break s;
+                }
+                core::hint::spin_loop();
+            };
+            // Readers that see what `f` writes must see the odd number too.
+            fence(Release);
+            let r = f();
+            seq.store(s.wrapping_add(2), Release);
+            r
+        }
+    }
+
+    #[cfg(not(any(target_has_atomic = "32", target_arch = "arm")))]
+    compile_error!("no compare-and-swap, and no critical section for this target");
+
+    /// Same, with interrupts disabled instead of a compare-and-swap.
+    #[cfg(all(target_arch = "arm", not(target_has_atomic = "32")))]
+    mod critical {
+        use core::sync::atomic::{
+            fence, AtomicU32,
+            Ordering::{Relaxed, Release},
+        };
+
+        pub fn with<R>(seq: &AtomicU32, f: impl FnOnce() -> R) -> R {
+            let primask: u32;
+            // SAFETY: saves PRIMASK and masks interrupts, nothing else.
+            unsafe {
+                core::arch::asm!(
+                    "mrs {}, PRIMASK",
+                    "cpsid i",
+                    out(reg) primask,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+            let s = seq.load(Relaxed);
+            seq.store(s.wrapping_add(1), Relaxed);
+            fence(Release);
+            let r = f();
+            seq.store(s.wrapping_add(2), Release);
+            if primask & 1 == 0 {
+                // SAFETY: interrupts were enabled before we came in.
+                unsafe { core::arch::asm!("cpsie i", options(nomem, nostack, preserves_flags)) };
+            }
+            r
+        }
+    }
+}
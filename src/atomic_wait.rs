@@ -0,0 +1,76 @@
+//! Blocking on an `AtomicU32`, on whatever the platform offers: a futex on
+//! Linux and OpenBSD, `WaitOnAddress` on Windows, `__ulock_wait` on macOS,
+//! `_umtx_op` on FreeBSD, and a table of parked threads anywhere else.
+//!
+//! This is all `cap_9` needs to build a mutex, a condvar and a semaphore,
+//! and all you need to build your own, without touching any syscall.
+//!
+//! The rules are the same everywhere: `atomic_wait` only goes to sleep while
+//! the atomic still holds `expected`, and may wake up for no reason at all.
+//! So change the value first, then wake; and after waking, check again.
+
+use crate::sys;
+use std::{
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+/// Blocks while `a` is `expected`, until woken by `wake_one` or `wake_all`.
+pub fn atomic_wait(a: &AtomicU32, expected: u32) {
+    sys::wait(a, expected);
+}
+
+/// Like `atomic_wait`, but gives up after `timeout`. Returns `false` if it did.
+pub fn atomic_wait_timeout(a: &AtomicU32, expected: u32, timeout: Duration) -> bool {
+    sys::wait_timeout(a, expected, timeout)
+}
+
+/// Like `atomic_wait`, but gives up at `deadline`. Returns `false` if it did.
+pub fn atomic_wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    sys::wait_until(a, expected, deadline)
+}
+
+/// Wakes one thread blocked in `atomic_wait` on `a`, if there's any.
+pub fn wake_one(a: &AtomicU32) {
+    sys::wake_one(a);
+}
+
+/// Wakes every thread blocked in `atomic_wait` on `a`.
+pub fn wake_all(a: &AtomicU32) {
+    sys::wake_all(a);
+}
+
+/// A gate that's closed until it's opened, then stays open.
+pub fn main() {
+    use std::sync::atomic::Ordering::{Acquire, Release};
+    use std::thread;
+
+    struct Gate(AtomicU32);
+
+    impl Gate {
+        fn wait(&self) {
+            while self.0.load(Acquire) == 0 {
+                atomic_wait(&self.0, 0);
+            }
+        }
+
+        fn open(&self) {
+            self.0.store(1, Release);
+            wake_all(&self.0);
+        }
+    }
+
+    let gate = Gate(AtomicU32::new(0));
+    thread::scope(|s| {
+        for i in 0..3 {
+            let gate = &gate;
+            s.spawn(move || {
+                gate.wait();
+                println!("thread {i} through the gate");
+            });
+        }
+        let timed_out = !atomic_wait_timeout(&gate.0, 0, Duration::from_millis(50));
+        println!("still closed after 50ms: {timed_out}");
+        gate.open();
+    });
+}
@@ -0,0 +1,260 @@
+//! The stop flag of chapter 2, as a type: a thread that repeats some work
+//! until told to stop.
+//!
+//! The flag is only checked between two rounds of work, so `stop` takes
+//! effect once the current round is done. `Relaxed` is enough for it: the
+//! flag carries no data, and `join` synchronizes with everything the thread did.
+//!
+//! A `BackgroundService` is the other common shape: one thread for the whole
+//! process, in a `static`, started by the first job sent to it, and stopped
+//! when the process exits, after the jobs already sent.
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{mutex::Mutex, mutex_channel::Channel, once::OnceCell, threads::spawn_named};
+
+pub struct BackgroundWorker {
+    stop: Arc<AtomicBool>,
+    /// `None` once joined.
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWorker {
+    /// Spawns a thread that calls `work` over and over, until `stop`.
+    pub fn start<F>(mut work: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let handle = thread::spawn(move || {
+            while !flag.load(Relaxed) {
+                work();
+            }
+        });
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Asks the thread to stop after its current round of work. Doesn't wait for it.
+    pub fn stop(&self) {
+        self.stop.store(true, Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Relaxed)
+    }
+
+    /// Whether the thread is gone, because it was stopped or `work` panicked.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|h| h.is_finished())
+    }
+
+    /// Stops the thread and waits for it. `Err` if `work` panicked.
+    pub fn join(mut self) -> thread::Result<()> {
+        self.stop();
+        self.handle.take().unwrap().join()
+    }
+}
+
+/// Stops and joins the thread, so it doesn't outlive its worker.
+impl Drop for BackgroundWorker {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stop();
+            // Its panic, if any, was already reported on stderr.
+            let _ = handle.join();
+        }
+    }
+}
+
+/// A thread that handles jobs sent to it, one at a time, meant for a `static`:
+///
+/// `static LOGGER: BackgroundService<String> = BackgroundService::new("logger", |line| ...);`
+///
+/// The thread is spawned by the first `submit`. On exit from `main`, or
+/// `process::exit`, every service that was started is shut down, the last
+/// started first, each one handling what was sent before it stops. (With
+/// the C library's `atexit`, on Unix and Windows; elsewhere, call
+/// `shutdown` before returning.)
+pub struct BackgroundService<T: Send + 'static> {
+    name: &'static str,
+    handler: fn(T),
+    running: OnceCell<Running<T>>,
+}
+
+struct Running<T> {
+    jobs: Channel<T>,
+    /// `None` once joined.
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<T: Send + 'static> BackgroundService<T> {
+    /// A service whose thread is called `name`, and calls `handler` with
+    /// every job. A job that panics is dropped, and the thread carries on
+    /// with the next one.
+    pub const fn new(name: &'static str, handler: fn(T)) -> Self {
+        Self {
+            name,
+            handler,
+            running: OnceCell::new(),
+        }
+    }
+
+    /// Sends `job` to the thread, starting it if it isn't yet. Gives the
+    /// job back once the service is shut down.
+    pub fn submit(&'static self, job: T) -> Result<(), T> {
+        self.running().jobs.try_send(job)
+    }
+
+    fn running(&'static self) -> &'static Running<T> {
+        self.running.get_or_init(|| {
+            let thread = spawn_named(self.name, move || self.run());
+            // After it's started, so it's shut down before the ones it may
+            // have started first, and use.
+            register(self);
+            Running {
+                jobs: Channel::new(),
+                thread: Mutex::new(Some(thread)),
+            }
+        })
+    }
+
+    fn run(&'static self) {
+        // Waits for `running` to be set, by the thread that spawned us.
+        let jobs = &self.running().jobs;
+        while let Some(job) = jobs.receive() {
+            // Already reported on stderr by the panic hook.
+            let _ = catch_unwind(AssertUnwindSafe(|| (self.handler)(job)));
+        }
+    }
+
+    /// Whether the first job has started the thread.
+    pub fn is_started(&self) -> bool {
+        self.running.get().is_some()
+    }
+
+    /// Stops taking jobs, and waits for the thread to handle the ones it
+    /// has. Nothing to do if it was never started, or is stopped already.
+    pub fn shutdown(&self) {
+        let Some(running) = self.running.get() else {
+            return;
+        };
+        running.jobs.close();
+        let thread = running.thread.lock().take();
+        if let Some(thread) = thread {
+            // Not from the service's own thread, which would wait for itself.
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Services, for shutting down at exit.
+// Only called where there's an `atexit` to call it from.
+#[cfg_attr(not(any(unix, windows)), allow(dead_code))]
+trait Service: Sync {
+    fn shutdown(&self);
+}
+
+impl<T: Send + 'static> Service for BackgroundService<T> {
+    fn shutdown(&self) {
+        BackgroundService::shutdown(self);
+    }
+}
+
+/// Every service started so far, in the order they were.
+static STARTED: Mutex<Vec<&'static dyn Service>> = Mutex::new(Vec::new());
+
+fn register(service: &'static dyn Service) {
+    let mut started = STARTED.lock();
+    #[cfg(any(unix, windows))]
+    if started.is_empty() {
+        // Safety: `shutdown_all` is an `extern "C" fn()` that doesn't unwind.
+        unsafe { atexit(shutdown_all) };
+    }
+    started.push(service);
+}
+
+#[cfg(unix)]
+use libc::atexit;
+
+#[cfg(windows)]
+extern "C" {
+    fn atexit(f: extern "C" fn()) -> i32;
+}
+
+#[cfg(any(unix, windows))]
+extern "C" fn shutdown_all() {
+    // One at a time, without holding the lock while they finish, in case
+    // a last job starts another.
+    while let Some(service) = STARTED.lock().pop() {
+        // Unwinding out of an `extern "C" fn` would abort anyway.
+        let _ = catch_unwind(AssertUnwindSafe(|| service.shutdown()));
+    }
+}
+
+pub fn main() {
+    use std::time::Duration;
+
+    fn log(line: String) {
+        thread::sleep(Duration::from_millis(10));
+        println!("[{}] {line}", thread::current().name().unwrap());
+    }
+    static LOGGER: BackgroundService<String> = BackgroundService::new("logger", log);
+
+    // Threads log without waiting for the output; what's still queued
+    // when `main` returns is written out at exit.
+    println!("started before the first line: {}", LOGGER.is_started());
+    thread::scope(|s| {
+        for t in 0..3 {
+            s.spawn(move || LOGGER.submit(format!("hello from thread {t}")).unwrap());
+        }
+    });
+    println!("3 lines sent, the logger is still writing them");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+
+    #[test]
+    fn stops_between_rounds() {
+        let rounds = Arc::new(AtomicUsize::new(0));
+        let counter = rounds.clone();
+        let worker = BackgroundWorker::start(move || {
+            counter.fetch_add(1, Relaxed);
+            thread::sleep(Duration::from_millis(1));
+        });
+        while rounds.load(Relaxed) < 3 {
+            thread::yield_now();
+        }
+        worker.stop();
+        assert!(worker.is_stopped());
+        worker.join().unwrap();
+        let after = rounds.load(Relaxed);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(rounds.load(Relaxed), after);
+    }
+
+    #[test]
+    fn join_reports_a_panic() {
+        let worker = BackgroundWorker::start(|| panic!("boom"));
+        // Joining right away could stop it before its first round.
+        while !worker.is_finished() {
+            thread::yield_now();
+        }
+        assert!(worker.join().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Waiting for another thread without any help from the OS.
+//!
+//! Spinning on the same atomic over and over keeps the cache line bouncing
+//! between cores and starves a hyperthread sibling. `Backoff` spins a little
+//! longer every round, and with the `std` feature, gives the core away with
+//! `thread::yield_now` once spinning stops paying off.
+
+use core::cell::Cell;
+
+/// Rounds of exponential spinning, up to 2^6 `spin_loop` hints.
+const SPIN_LIMIT: u32 = 6;
+/// After this many rounds, waiting longer is better done by blocking.
+const YIELD_LIMIT: u32 = 10;
+
+#[derive(Debug, Default)]
+pub struct Backoff {
+    step: Cell<u32>,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self { step: Cell::new(0) }
+    }
+
+    pub fn reset(&self) {
+        self.step.set(0);
+    }
+
+    /// For a failed compare-exchange: the other thread is making progress
+    /// right now, so it's worth trying again soon.
+    pub fn spin(&self) {
+        for _ in 0..1 << self.step.get().min(SPIN_LIMIT) {
+            core::hint::spin_loop();
+        }
+        if self.step.get() <= SPIN_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// For waiting until another thread does something, which can take a while.
+    pub fn snooze(&self) {
+        if self.step.get() <= SPIN_LIMIT {
+            for _ in 0..1 << self.step.get() {
+                core::hint::spin_loop();
+            }
+        } else {
+            #[cfg(feature = "std")]
+            std::thread::yield_now();
+            #[cfg(not(feature = "std"))]
+            for _ in 0..1 << SPIN_LIMIT {
+                core::hint::spin_loop();
+            }
+        }
+        if self.step.get() <= YIELD_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// Whether it's time to stop snoozing and block instead, if we can.
+    pub fn is_completed(&self) -> bool {
+        self.step.get() > YIELD_LIMIT
+    }
+}
+
+/// Busy-waits until `done` returns `true`, snoozing with a `Backoff` in between,
+/// so waiting doesn't take the core away from the thread we're waiting for.
+pub fn spin_until(mut done: impl FnMut() -> bool) {
+    let backoff = Backoff::new();
+    while !done() {
+        backoff.snooze();
+    }
+}
@@ -0,0 +1,120 @@
+//! A barrier for threads that meet at it over and over, like the steps of
+//! a stencil computation: one `fetch_add` to arrive, and one flag to watch.
+//!
+//! The flag is the barrier's sense, flipped by the last thread to arrive.
+//! Every thread keeps its own copy of what the sense will be once the phase
+//! is over, and flips that copy each time it arrives. So the counter can go
+//! back to zero right away, with no second counter or generation for the
+//! stragglers of the last phase to check against: they're waiting for a
+//! sense the barrier doesn't go back to until everyone has arrived again.
+//!
+//! Waiting spins for a bit first, since in a tight loop the others are
+//! usually close behind, and only then sleeps on the flag.
+
+use std::sync::atomic::{
+    AtomicU32, AtomicUsize,
+    Ordering::{AcqRel, Acquire, Relaxed, SeqCst},
+};
+
+use crate::{
+    backoff::Backoff,
+    cache_padded::CachePadded,
+    sys::{wait, wake_all},
+};
+
+pub struct SenseBarrier {
+    n: usize,
+    arrived: CachePadded<AtomicUsize>,
+    /// 0 or 1, flipped at the end of every phase.
+    sense: CachePadded<AtomicU32>,
+    /// Threads that stopped spinning, for the last one to wake.
+    sleepers: AtomicU32,
+}
+
+impl SenseBarrier {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "a barrier needs at least one party");
+        Self {
+            n,
+            arrived: CachePadded::new(AtomicUsize::new(0)),
+            sense: CachePadded::new(AtomicU32::new(0)),
+            sleepers: AtomicU32::new(0),
+        }
+    }
+
+    /// A thread's way in, with its own sense. Take it before the thread's
+    /// first `wait`, not while the others are in the middle of a phase.
+    pub fn participant(&self) -> Participant<'_> {
+        Participant {
+            barrier: self,
+            sense: self.sense.load(Relaxed),
+        }
+    }
+}
+
+pub struct Participant<'a> {
+    barrier: &'a SenseBarrier,
+    sense: u32,
+}
+
+impl Participant<'_> {
+    /// Waits for all `n` to arrive. Returns `true` for the last one of
+    /// them, and `false` for the others.
+    pub fn wait(&mut self) -> bool {
+        let barrier = self.barrier;
+        self.sense ^= 1;
+        if barrier.arrived.fetch_add(1, AcqRel) == barrier.n - 1 {
+            // No one arrives for the next phase until they see the flip.
+            barrier.arrived.store(0, Relaxed);
+            barrier.sense.store(self.sense, SeqCst);
+            if barrier.sleepers.load(SeqCst) > 0 {
+                wake_all(&barrier.sense);
+            }
+            return true;
+        }
+
+        let backoff = Backoff::new();
+        while !backoff.is_completed() {
+            if barrier.sense.load(Acquire) == self.sense {
+                return false;
+            }
+            backoff.snooze();
+        }
+        // `SeqCst`, with the flip and the check of `sleepers` above: either
+        // the last one sees us here, or we see its flip.
+        barrier.sleepers.fetch_add(1, SeqCst);
+        while barrier.sense.load(SeqCst) != self.sense {
+            wait(&barrier.sense, self.sense ^ 1);
+        }
+        barrier.sleepers.fetch_sub(1, Relaxed);
+        false
+    }
+}
+
+pub fn main() {
+    use std::thread;
+
+    // Each thread smooths its cell with its neighbours, every step.
+    const CELLS: usize = 8;
+    const STEPS: usize = 100;
+    let cells: [AtomicU32; CELLS] = std::array::from_fn(|i| AtomicU32::new(i as u32 * 100));
+    let barrier = SenseBarrier::new(CELLS);
+    thread::scope(|s| {
+        for i in 0..CELLS {
+            let (cells, mut barrier) = (&cells, barrier.participant());
+            s.spawn(move || {
+                for _ in 0..STEPS {
+                    let left = cells[i.saturating_sub(1)].load(Relaxed);
+                    let right = cells[(i + 1).min(CELLS - 1)].load(Relaxed);
+                    let me = cells[i].load(Relaxed);
+                    // Everyone has read their neighbours before anyone writes.
+                    barrier.wait();
+                    cells[i].store((left + 2 * me + right) / 4, Relaxed);
+                    barrier.wait();
+                }
+            });
+        }
+    });
+    let cells: Vec<u32> = cells.iter().map(|c| c.load(Relaxed)).collect();
+    println!("after {STEPS} steps: {cells:?}");
+}
@@ -0,0 +1,518 @@
+//! What the chapters' advice costs or saves on this machine, measured.
+//! Run with `--release`, the numbers of a debug build say little.
+
+use crate::runner::Demo;
+
+/// Operations per second, in millions.
+fn mops(n: usize, elapsed: std::time::Duration) -> f64 {
+    n as f64 / elapsed.as_secs_f64() / 1e6
+}
+
+pub(crate) mod false_sharing {
+    use super::mops;
+    use crate::runner::iterations;
+    use atomics_and_locks::{sync::CachePadded, thread::affinity::pin_spread};
+    use std::{
+        sync::atomic::{AtomicU64, Ordering::Relaxed},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Two threads, each incrementing only its own counter, on cores of their
+    /// own. With a single core there's no cache line to fight over, and no
+    /// difference to see.
+    fn run(a: &AtomicU64, b: &AtomicU64, n: usize) -> Duration {
+        let start = Instant::now();
+        thread::scope(|s| {
+            for (i, counter) in [a, b].into_iter().enumerate() {
+                s.spawn(move || {
+                    // Not being able to pin only makes the numbers noisier.
+                    let _ = pin_spread(i);
+                    for _ in 0..n {
+                        counter.fetch_add(1, Relaxed);
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    pub fn main() {
+        let n = iterations(10_000_000);
+
+        // Most likely on the same cache line.
+        let adjacent = [AtomicU64::new(0), AtomicU64::new(0)];
+        let shared = run(&adjacent[0], &adjacent[1], n);
+
+        let padded = [
+            CachePadded::new(AtomicU64::new(0)),
+            CachePadded::new(AtomicU64::new(0)),
+        ];
+        let apart = run(&padded[0], &padded[1], n);
+
+        println!(
+            "adjacent: {shared:?} ({:.0} M/s), padded: {apart:?} ({:.0} M/s), {:.1}x",
+            mops(2 * n, shared),
+            mops(2 * n, apart),
+            shared.as_secs_f64() / apart.as_secs_f64(),
+        );
+    }
+}
+
+pub(crate) mod orderings {
+    use crate::runner::{iterations, threads};
+    use std::{
+        hint::black_box,
+        sync::atomic::{
+            AtomicU64,
+            Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst},
+        },
+        thread,
+        time::Instant,
+    };
+
+    type Op = fn(&AtomicU64);
+
+    const OPS: &[(&str, Op)] = &[
+        ("load Relaxed", |a| {
+            black_box(a.load(Relaxed));
+        }),
+        ("load Acquire", |a| {
+            black_box(a.load(Acquire));
+        }),
+        ("load SeqCst", |a| {
+            black_box(a.load(SeqCst));
+        }),
+        ("store Relaxed", |a| a.store(1, Relaxed)),
+        ("store Release", |a| a.store(1, Release)),
+        ("store SeqCst", |a| a.store(1, SeqCst)),
+        ("fetch_add Relaxed", |a| {
+            a.fetch_add(1, Relaxed);
+        }),
+        ("fetch_add AcqRel", |a| {
+            a.fetch_add(1, AcqRel);
+        }),
+        ("fetch_add SeqCst", |a| {
+            a.fetch_add(1, SeqCst);
+        }),
+    ];
+
+    /// Nanoseconds per operation, with `threads` threads all on one atomic.
+    fn measure(op: Op, threads: usize, n: usize) -> f64 {
+        let a = AtomicU64::new(0);
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    for _ in 0..n {
+                        op(black_box(&a));
+                    }
+                });
+            }
+        });
+        start.elapsed().as_nanos() as f64 / n as f64
+    }
+
+    /// What each ordering costs, on one shared atomic, from 1 up to
+    /// `--threads` threads: wall time per operation of each thread, so
+    /// without contention it stays flat as threads are added.
+    ///
+    /// On x86 only `SeqCst` stores differ (an `xchg`), every read-modify-write
+    /// is a `lock`ed instruction anyway. On AArch64 `Acquire` and `Release`
+    /// are the `ldar`/`stlr` instructions, `SeqCst` the same ones.
+    pub fn main() {
+        let max = threads(thread::available_parallelism().map_or(4, |n| n.get()));
+        let n = iterations(1_000_000);
+        let mut counts: Vec<usize> = (0..).map(|i| 1 << i).take_while(|&t| t < max).collect();
+        counts.push(max);
+
+        print!("{:<20}", "ns/op, threads:");
+        for t in &counts {
+            print!("{t:>8}");
+        }
+        println!();
+        for (name, op) in OPS {
+            print!("{name:<20}");
+            for &t in &counts {
+                print!("{:>8.2}", measure(*op, t, n));
+            }
+            println!();
+        }
+    }
+}
+
+pub(crate) mod cas {
+    use super::mops;
+    use crate::runner::{iterations, threads};
+    use std::{
+        sync::atomic::{AtomicU32, Ordering::Relaxed},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// `allocate_new_id` from cap_2, without the limit, counting retries.
+    fn allocate(next: &AtomicU32, weak: bool) -> usize {
+        let mut id = next.load(Relaxed);
+        let mut retries = 0;
+        loop {
+            let result = if weak {
+                next.compare_exchange_weak(id, id.wrapping_add(1), Relaxed, Relaxed)
+            } else {
+                next.compare_exchange(id, id.wrapping_add(1), Relaxed, Relaxed)
+            };
+            match result {
+                Ok(_) => return retries,
+                Err(v) => {
+                    id = v;
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    fn run(weak: bool, threads: usize, n: usize) -> (Duration, usize) {
+        let next = AtomicU32::new(0);
+        let start = Instant::now();
+        let retries = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| s.spawn(|| (0..n).map(|_| allocate(&next, weak)).sum::<usize>()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        (start.elapsed(), retries)
+    }
+
+    /// Strong against weak compare-and-exchange in a retry loop.
+    ///
+    /// On x86 both are the same `lock cmpxchg`, and only fail when another
+    /// thread got in between. On ARM without LSE they're a load-linked/
+    /// store-conditional pair that may also fail spuriously: the strong one
+    /// loops inside to hide that, the weak one leaves it to our loop, which
+    /// then reloads and retries anyway, so it does a little less work.
+    pub fn main() {
+        let threads = threads(thread::available_parallelism().map_or(4, |n| n.get()));
+        let n = iterations(1_000_000);
+        for (name, weak) in [("strong", false), ("weak", true)] {
+            let (elapsed, retries) = run(weak, threads, n);
+            println!(
+                "{name:<6} {threads} threads: {elapsed:?} ({:.1} M ids/s), {:.3} retries per id",
+                mops(threads * n, elapsed),
+                retries as f64 / (threads * n) as f64,
+            );
+        }
+    }
+}
+
+pub(crate) mod handoff {
+    use crate::runner::iterations;
+    use atomics_and_locks::sync::spin_until;
+    use std::{
+        sync::atomic::{
+            fence, AtomicU32,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    type Take = fn(&AtomicU32) -> bool;
+
+    /// What the one-shot channel's receiver used to do on every check.
+    fn take_swap(flag: &AtomicU32) -> bool {
+        flag.swap(0, Acquire) == 1
+    }
+
+    /// What it does now: `Relaxed` checks, and a fence once it's set.
+    fn take_fence(flag: &AtomicU32) -> bool {
+        if flag.load(Relaxed) == 1 {
+            fence(Acquire);
+            flag.store(0, Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Two threads handing a turn back and forth through two flags, `n` times.
+    fn run(take: Take, n: usize) -> Duration {
+        let (ping, pong) = (AtomicU32::new(0), AtomicU32::new(0));
+        let start = Instant::now();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..n {
+                    spin_until(|| take(&ping));
+                    pong.store(1, Release);
+                }
+            });
+            for _ in 0..n {
+                ping.store(1, Release);
+                spin_until(|| take(&pong));
+            }
+        });
+        start.elapsed()
+    }
+
+    /// The receiving side of the one-shot channel, both ways. The swap
+    /// writes the flag's cache line on every check, even while it's clear,
+    /// taking it away from the other thread; the load only reads it.
+    pub fn main() {
+        let n = iterations(100_000);
+        for (name, take) in [
+            ("swap(Acquire)", take_swap as Take),
+            ("load + fence", take_fence),
+        ] {
+            let elapsed = run(take, n);
+            println!("{name:<14} {:?} per round trip", elapsed / n as u32);
+        }
+    }
+}
+
+pub(crate) mod barrier {
+    use crate::runner::iterations;
+    use atomics_and_locks::sync::SenseBarrier;
+    use std::{
+        sync::Barrier,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn run(threads: usize, phases: usize, sense: bool) -> Duration {
+        let (basic, fast) = (Barrier::new(threads), SenseBarrier::new(threads));
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..threads {
+                let (basic, mut fast) = (&basic, fast.participant());
+                s.spawn(move || {
+                    for _ in 0..phases {
+                        if sense {
+                            fast.wait();
+                        } else {
+                            basic.wait();
+                        }
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    /// `std`'s barrier, a mutex and a condition variable, against
+    /// `SenseBarrier`, for 2 up to 64 threads going through it over and
+    /// over with nothing in between: the time each phase takes.
+    ///
+    /// With more threads than cores, the spinning only gets in the way of
+    /// the threads that haven't arrived yet, and the two come closer.
+    pub fn main() {
+        let phases = iterations(10_000);
+        println!("{:<8}{:>14}{:>14}", "threads", "std", "sense");
+        for threads in [2, 4, 8, 16, 32, 64] {
+            let basic = run(threads, phases, false) / phases as u32;
+            let sense = run(threads, phases, true) / phases as u32;
+            println!(
+                "{threads:<8}{:>14}{:>14}",
+                format!("{basic:?}"),
+                format!("{sense:?}")
+            );
+        }
+    }
+}
+
+pub(crate) mod elision {
+    use super::mops;
+    use crate::runner::{iterations, threads};
+    use atomics_and_locks::sync::{CachePadded, SpinLock};
+    use std::{thread, time::Instant};
+
+    /// Threads that each only touch their own counter, all behind the one
+    /// spin lock: taking it, they can only go one at a time, but as
+    /// transactions they don't conflict, and can all go at once.
+    pub fn main() {
+        let n = iterations(1_000_000);
+        let threads = threads(4);
+        let counters = SpinLock::new(vec![CachePadded::new(0u64); threads]);
+        let start = Instant::now();
+        thread::scope(|s| {
+            for t in 0..threads {
+                let counters = &counters;
+                s.spawn(move || {
+                    for _ in 0..n {
+                        *counters.lock()[t] += 1;
+                    }
+                });
+            }
+        });
+        let elapsed = start.elapsed();
+        println!(
+            "{threads} threads: {elapsed:?} ({:.1} M locks/s)",
+            mops(n * threads, elapsed)
+        );
+        #[cfg(feature = "htm")]
+        {
+            use atomics_and_locks::metrics::{
+                SPIN_LOCK_ABORTS, SPIN_LOCK_ELIDED, SPIN_LOCK_FALLBACKS,
+            };
+            let (elided, aborts) = (SPIN_LOCK_ELIDED.get(), SPIN_LOCK_ABORTS.get());
+            if elided + aborts == 0 {
+                println!("(no transactions: this CPU doesn't do RTM)");
+                return;
+            }
+            println!(
+                "elided {elided}, aborted {aborts} ({:.1}%), took the lock {} times",
+                100.0 * aborts as f64 / (elided + aborts).max(1) as f64,
+                SPIN_LOCK_FALLBACKS.get()
+            );
+        }
+        #[cfg(not(feature = "htm"))]
+        println!("(built without `htm`: every one of them took the lock)");
+    }
+}
+
+pub(crate) mod batch {
+    use super::mops;
+    use crate::runner::iterations;
+    use atomics_and_locks::channel::Channel;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// A producer sending bursts of `burst`, one by one or with `send_all`,
+    /// to a consumer that's mostly waiting for them.
+    fn run(bursts: usize, burst: usize, all: bool) -> Duration {
+        let channel = Channel::new();
+        let start = Instant::now();
+        thread::scope(|s| {
+            s.spawn(|| while channel.receive().is_some() {});
+            for b in 0..bursts {
+                let items = b * burst..(b + 1) * burst;
+                if all {
+                    channel.send_all(items);
+                } else {
+                    items.for_each(|i| channel.send(i));
+                }
+            }
+            channel.close();
+        });
+        start.elapsed()
+    }
+
+    pub fn main() {
+        let bursts = iterations(10_000);
+        for burst in [1, 10, 100, 500] {
+            let one = run(bursts, burst, false);
+            let all = run(bursts, burst, true);
+            println!(
+                "bursts of {burst:>3}: send {:.1} M/s, send_all {:.1} M/s",
+                mops(bursts * burst, one),
+                mops(bursts * burst, all),
+            );
+        }
+    }
+}
+
+pub(crate) mod lifo_slot {
+    use crate::runner::{iterations, threads};
+    use atomics_and_locks::pool::{Scheduling, Spawner, Supervision, ThreadPool};
+    use std::{
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering::{AcqRel, Acquire},
+            },
+            Arc,
+        },
+        thread::{self, Thread},
+        time::{Duration, Instant},
+    };
+
+    /// 256 KiB a chain: together, a lot more than a core's own caches.
+    const WORDS: usize = 32 * 1024;
+    const CHAINS: usize = 16;
+
+    /// A step of a chain: goes through its buffer, and queues the next step
+    /// with it, until `steps` are done.
+    fn step(pool: Spawner, mut data: Vec<u64>, steps: usize, left: Arc<AtomicUsize>, main: Thread) {
+        for word in &mut data {
+            *word = word.wrapping_mul(31).wrapping_add(1);
+        }
+        if steps == 0 {
+            if left.fetch_sub(1, AcqRel) == 1 {
+                main.unpark();
+            }
+            return;
+        }
+        let next = pool.clone();
+        pool.execute(move || step(next, data, steps - 1, left, main));
+    }
+
+    /// `CHAINS` chains of follow-ups, all started at once: from the back of
+    /// a FIFO queue, each step waits for a step of every other chain.
+    fn run(scheduling: Scheduling, threads: usize, steps: usize) -> Duration {
+        let pool = ThreadPool::with_scheduling(threads, Supervision::Contain, scheduling);
+        let left = Arc::new(AtomicUsize::new(CHAINS));
+        let start = Instant::now();
+        for _ in 0..CHAINS {
+            let (spawner, left, main) = (pool.spawner(), left.clone(), thread::current());
+            pool.execute(move || step(spawner, vec![0; WORDS], steps, left, main));
+        }
+        while left.load(Acquire) != 0 {
+            thread::park();
+        }
+        start.elapsed()
+    }
+
+    pub fn main() {
+        let threads = threads(thread::available_parallelism().map_or(4, |n| n.get()));
+        let steps = iterations(200);
+        for _ in 0..3 {
+            println!(
+                "{CHAINS} chains of {steps} steps on {threads} threads: fifo {:?}, lifo slot {:?}",
+                run(Scheduling::Fifo, threads, steps),
+                run(Scheduling::LifoSlot, threads, steps),
+            );
+        }
+    }
+}
+
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "false_sharing",
+        about: "two threads on adjacent vs cache-padded counters",
+        run: false_sharing::main,
+    },
+    Demo {
+        name: "orderings",
+        about: "the cost of load, store and fetch_add under each ordering",
+        run: orderings::main,
+    },
+    Demo {
+        name: "cas",
+        about: "compare_exchange vs compare_exchange_weak in a retry loop",
+        run: cas::main,
+    },
+    Demo {
+        name: "handoff",
+        about: "swap(Acquire) vs a Relaxed load and a fence to take a flag",
+        run: handoff::main,
+    },
+    Demo {
+        name: "barrier",
+        about: "std's Barrier vs SenseBarrier, phase after phase",
+        run: barrier::main,
+    },
+    Demo {
+        name: "elision",
+        about: "a spin lock as hardware transactions, with the htm feature",
+        run: elision::main,
+    },
+    Demo {
+        name: "batch",
+        about: "bursts through a channel, send by send vs send_all",
+        run: batch::main,
+    },
+    Demo {
+        name: "lifo_slot",
+        about: "chains of follow-up jobs on a pool, FIFO vs with a LIFO slot",
+        run: lifo_slot::main,
+    },
+];
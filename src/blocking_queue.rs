@@ -0,0 +1,204 @@
+//! The queue of `condition_variables` in chapter 1: a `VecDeque` behind a
+//! `Mutex`, and a `Condvar` for consumers to wait on.
+//!
+//! Whether it's closed is kept behind the same lock as the items, so a
+//! consumer checks both at once, before waiting. With the flag in a mutex
+//! of its own, `close` could come between those two and never be noticed.
+//!
+//! Items from `push_urgent` go in a queue of their own, which consumers
+//! empty first: a shutdown or a flush doesn't wait behind a backlog.
+
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, Result},
+    trace,
+};
+
+pub struct BlockingQueue<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    /// From `push_urgent`, popped before anything in `items`.
+    urgent: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T> BlockingQueue<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                urgent: VecDeque::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    /// Gives the item back if the queue is closed.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        self.push_to(item, false)
+    }
+
+    /// Like `push`, but the item is popped before any from `push`, however
+    /// many of them are queued.
+    pub fn push_urgent(&self, item: T) -> Result<(), T> {
+        self.push_to(item, true)
+    }
+
+    fn push_to(&self, item: T, urgent: bool) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(item);
+        }
+        if urgent {
+            state.urgent.push_back(item);
+        } else {
+            state.items.push_back(item);
+        }
+        #[cfg(feature = "metrics")]
+        {
+            crate::metrics::QUEUE_PUSHED.increment();
+            crate::metrics::QUEUE_DEPTH.increment();
+        }
+        trace::event!(queue = ?core::ptr::from_ref(self), len = state.len(), "pushed");
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Blocks until there's an item. `None` once it's closed and empty.
+    pub fn pop(&self) -> Option<T> {
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.pop() {
+                count_popped();
+                trace::event!(queue = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "popped");
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Like `pop`, but gives up after `timeout`: `Error::Timeout`, or
+    /// `Error::Closed` once it's closed and empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.pop().ok_or(Error::Closed);
+        };
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.pop() {
+                count_popped();
+                trace::event!(queue = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "popped");
+                return Ok(item);
+            }
+            if state.closed {
+                return Err(Error::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                trace::event!(queue = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "timed out");
+                return Err(Error::Timeout);
+            }
+            state = self
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Pops items for as long as each comes within `gap` of the one
+    /// before, and ends once none does, or once it's closed and empty:
+    /// draining until the producers go quiet. `is_closed` tells which.
+    pub fn iter_timeout(&self, gap: Duration) -> IterTimeout<'_, T> {
+        IterTimeout { queue: self, gap }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let item = self.state.lock().unwrap().pop();
+        item.inspect(|_| count_popped())
+    }
+
+    /// Refuses new items from now on, and wakes every consumer.
+    /// Items already queued can still be popped.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct IterTimeout<'a, T> {
+    queue: &'a BlockingQueue<T>,
+    gap: Duration,
+}
+
+impl<T> Iterator for IterTimeout<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop_timeout(self.gap).ok()
+    }
+}
+
+impl<T> State<T> {
+    fn pop(&mut self) -> Option<T> {
+        self.urgent.pop_front().or_else(|| self.items.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.urgent.len() + self.items.len()
+    }
+}
+
+/// For `metrics`, once an item is out of the queue.
+fn count_popped() {
+    #[cfg(feature = "metrics")]
+    {
+        crate::metrics::QUEUE_POPPED.increment();
+        crate::metrics::QUEUE_DEPTH.decrement();
+    }
+}
+
+/// Whatever is still queued leaves `metrics::QUEUE_DEPTH` with it.
+#[cfg(feature = "metrics")]
+impl<T> Drop for BlockingQueue<T> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        crate::metrics::QUEUE_DEPTH.sub(state.len() as i64);
+    }
+}
+
+impl<T> Default for BlockingQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,137 @@
+//! A Bloom filter threads can share: a set that answers "maybe" or "no",
+//! in a fixed number of bits, with no way to remove anything. Inserting
+//! sets `k` bits, each with a `fetch_or` on the word it's in; checking
+//! loads them. There's nothing to lock: bits only ever go from 0 to 1, so
+//! two inserts can't undo each other.
+//!
+//! `Relaxed` throughout. An insert doesn't publish anything but its own
+//! bits; whoever needs to see it by some point has to synchronize with the
+//! inserting thread anyway, e.g. through the channel that told them about it.
+//!
+//! The `k` bit positions come from one 64-bit hash, split in two halves
+//! `a` and `b`, as `a + i * b` (Kirsch and Mitzenmacher).
+
+use std::{
+    f64::consts::LN_2,
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::atomic::Ordering::Relaxed,
+};
+
+use crate::atomic_u64::AtomicU64;
+
+pub struct AtomicBloomFilter {
+    words: Box<[AtomicU64]>,
+    hashes: u32,
+}
+
+impl AtomicBloomFilter {
+    /// Sized for `items` distinct items with about `false_positives` of
+    /// the ones never inserted mistaken for inserted, e.g. `0.01`.
+    pub fn new(items: usize, false_positives: f64) -> Self {
+        assert!(
+            false_positives > 0.0 && false_positives < 1.0,
+            "the false positive rate must be between 0 and 1"
+        );
+        let items = items.max(1) as f64;
+        let bits = (-items * false_positives.ln() / (LN_2 * LN_2)).ceil();
+        let hashes = (bits / items * LN_2).round();
+        Self::with_size(bits as usize, hashes as u32)
+    }
+
+    /// `bits` bits (rounded up to a multiple of 64), set `hashes` at a time.
+    pub fn with_size(bits: usize, hashes: u32) -> Self {
+        Self {
+            words: (0..bits.div_ceil(64).max(1))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            hashes: hashes.max(1),
+        }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.words.len() * 64
+    }
+
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// The word and the bit in it of every position of `item`.
+    fn positions(&self, item: &(impl Hash + ?Sized)) -> impl Iterator<Item = (usize, u64)> {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h = hasher.finish();
+        let (a, b) = (h & 0xffff_ffff, (h >> 32) | 1);
+        let bits = self.bits() as u64;
+        (0..self.hashes as u64).map(move |i| {
+            let bit = a.wrapping_add(i.wrapping_mul(b)) % bits;
+            ((bit / 64) as usize, 1 << (bit % 64))
+        })
+    }
+
+    /// Adds `item`. Returns `true` if it wasn't in yet, like
+    /// `HashSet::insert`, except that it's sometimes wrongly `false`. Two
+    /// threads inserting the same item at once can both get `true`.
+    pub fn insert(&self, item: &(impl Hash + ?Sized)) -> bool {
+        let mut new = false;
+        for (word, bit) in self.positions(item) {
+            new |= self.words[word].fetch_or(bit, Relaxed) & bit == 0;
+        }
+        new
+    }
+
+    /// `false` if `item` was never inserted, `true` if it probably was.
+    pub fn contains(&self, item: &(impl Hash + ?Sized)) -> bool {
+        self.positions(item)
+            .all(|(word, bit)| self.words[word].load(Relaxed) & bit != 0)
+    }
+
+    /// About how many distinct items are in, from how many bits are set
+    /// (Swamidass and Baldi). Off by a few percent, and useless once
+    /// nearly every bit is set.
+    pub fn approx_count(&self) -> f64 {
+        let set: usize = self
+            .words
+            .iter()
+            .map(|w| w.load(Relaxed).count_ones() as usize)
+            .sum();
+        let (m, k) = (self.bits() as f64, self.hashes as f64);
+        -m / k * (1.0 - set as f64 / m).ln()
+    }
+}
+
+impl fmt::Debug for AtomicBloomFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicBloomFilter")
+            .field("bits", &self.bits())
+            .field("hashes", &self.hashes)
+            .field("approx_count", &self.approx_count())
+            .finish()
+    }
+}
+
+pub fn main() {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    // Four workers on overlapping ranges, each item handled only once.
+    let seen = AtomicBloomFilter::new(10_000, 0.01);
+    let handled = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for t in 0..4 {
+            let (seen, handled) = (&seen, &handled);
+            s.spawn(move || {
+                for item in t * 1000..t * 1000 + 4000 {
+                    if seen.insert(&item) {
+                        handled.fetch_add(1, Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    println!(
+        "{} of 7000 distinct items handled, about {:.0} in the filter",
+        handled.load(Relaxed),
+        seen.approx_count(),
+    );
+}
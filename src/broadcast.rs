@@ -0,0 +1,295 @@
+//! A broadcast ring: one producer, any number of readers, and every reader
+//! gets every value, read at its own pace from one fixed ring.
+//!
+//! Unlike the `pubsub` bus, there's no queue per subscriber, and nothing
+//! is allocated after `new`: a value is written into the ring once, and
+//! each reader clones it out, moving its own cursor along. The producer
+//! never overwrites a value a reader still has ahead of it, so no one ever
+//! misses one: it waits for the slowest reader instead, once that one is a
+//! whole ring behind. The readers' cursors are in a table of fixed size,
+//! which the producer goes through only when the ring looks full, and
+//! otherwise keeps the lowest of from the last time.
+//!
+//! Both sides sleep on a counter the other side bumps, and only bump it
+//! when the other side says it's sleeping.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU32, AtomicU64,
+            Ordering::{Acquire, Relaxed, Release, SeqCst},
+        },
+        Arc,
+    },
+};
+
+use crate::{
+    cache_padded::CachePadded,
+    sys::{wait, wake_all, wake_one},
+};
+
+/// A cursor slot no reader has.
+const FREE: u64 = u64::MAX;
+
+struct Shared<T> {
+    ring: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Values broadcast so far; the next one goes at `head % ring.len()`.
+    head: CachePadded<AtomicU64>,
+    /// Per reader, the next value it reads, or `FREE`.
+    cursors: Box<[CachePadded<AtomicU64>]>,
+    /// Bumped with every broadcast, and when the producer is gone.
+    published: AtomicU32,
+    readers_sleeping: AtomicU32,
+    /// Bumped by readers moving on while the producer sleeps.
+    consumed: AtomicU32,
+    producer_sleeping: AtomicBool,
+    closed: AtomicBool,
+}
+
+// Values are moved in by the producer, and cloned out by every reader.
+unsafe impl<T: Send + Sync> Sync for Shared<T> {}
+unsafe impl<T: Send> Send for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, seq: u64) -> *mut MaybeUninit<T> {
+        self.ring[seq as usize & (self.ring.len() - 1)].get()
+    }
+
+    /// The lowest cursor, or `head` with no readers.
+    fn slowest(&self, head: u64) -> u64 {
+        self.cursors
+            .iter()
+            .map(|c| c.load(SeqCst))
+            .filter(|&c| c != FREE)
+            .fold(head, u64::min)
+    }
+
+    /// A reader moved its cursor: lets the producer know, if it's waiting.
+    fn moved(&self) {
+        if self.producer_sleeping.load(SeqCst) {
+            self.consumed.fetch_add(1, SeqCst);
+            wake_one(&self.consumed);
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        for seq in head.saturating_sub(self.ring.len() as u64)..head {
+            // Safety: the last `ring.len()` values are still there, and
+            // no one else has them anymore.
+            unsafe { (*self.slot(seq)).assume_init_drop() };
+        }
+    }
+}
+
+/// The producer's end.
+pub struct Bus<T> {
+    shared: Arc<Shared<T>>,
+    /// The lowest cursor, as of the last look.
+    slowest: u64,
+}
+
+/// A reader's end. Reads every value broadcast after it was added.
+pub struct BusReader<T> {
+    shared: Arc<Shared<T>>,
+    /// Which of `cursors` is ours.
+    index: usize,
+    cursor: u64,
+}
+
+impl<T> Bus<T> {
+    /// A ring of `capacity` values, rounded up to a power of two, for up to
+    /// `max_readers` readers at once.
+    pub fn new(capacity: usize, max_readers: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        Self {
+            shared: Arc::new(Shared {
+                ring: (0..capacity)
+                    .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                    .collect(),
+                head: CachePadded::new(AtomicU64::new(0)),
+                cursors: (0..max_readers)
+                    .map(|_| CachePadded::new(AtomicU64::new(FREE)))
+                    .collect(),
+                published: AtomicU32::new(0),
+                readers_sleeping: AtomicU32::new(0),
+                consumed: AtomicU32::new(0),
+                producer_sleeping: AtomicBool::new(false),
+                closed: AtomicBool::new(false),
+            }),
+            slowest: 0,
+        }
+    }
+
+    /// A new reader, starting with the next value broadcast. `None` if
+    /// there are `max_readers` already.
+    pub fn add_reader(&self) -> Option<BusReader<T>> {
+        let shared = &*self.shared;
+        let index = shared.cursors.iter().position(|c| {
+            c.compare_exchange(FREE, shared.head.load(SeqCst), SeqCst, Relaxed)
+                .is_ok()
+        })?;
+        // The producer may have looked at the cursors before we took one,
+        // and be up to a ring ahead of what it saw as the slowest then. What
+        // it saw as `head` then is no later than this, so from here on,
+        // nothing we read gets overwritten.
+        let cursor = shared.head.load(SeqCst);
+        shared.cursors[index].store(cursor, SeqCst);
+        shared.moved();
+        Some(BusReader {
+            shared: self.shared.clone(),
+            index,
+            cursor,
+        })
+    }
+
+    /// Whether the slowest reader is less than a ring behind `head`.
+    fn has_room(&mut self, head: u64) -> bool {
+        let len = self.shared.ring.len() as u64;
+        if head - self.slowest < len {
+            return true;
+        }
+        self.slowest = self.shared.slowest(head);
+        head - self.slowest < len
+    }
+
+    /// Sends `value` to every reader, once the slowest one is less than a
+    /// whole ring behind.
+    pub fn broadcast(&mut self, value: T) {
+        let head = self.shared.head.load(Relaxed);
+        while !self.has_room(head) {
+            // `SeqCst`, with the readers' cursors and their check of it:
+            // either we see them move, or they see us sleeping.
+            self.shared.producer_sleeping.store(true, SeqCst);
+            let consumed = self.shared.consumed.load(SeqCst);
+            if !self.has_room(head) {
+                wait(&self.shared.consumed, consumed);
+            }
+            self.shared.producer_sleeping.store(false, Relaxed);
+        }
+        self.publish(head, value);
+    }
+
+    /// `broadcast`, or the value back if the slowest reader is a whole
+    /// ring behind.
+    pub fn try_broadcast(&mut self, value: T) -> Result<(), T> {
+        let head = self.shared.head.load(Relaxed);
+        if !self.has_room(head) {
+            return Err(value);
+        }
+        self.publish(head, value);
+        Ok(())
+    }
+
+    fn publish(&mut self, head: u64, value: T) {
+        let shared = &*self.shared;
+        let slot = shared.slot(head);
+        // Safety: every reader is past the value that was there, if any.
+        unsafe {
+            if head >= shared.ring.len() as u64 {
+                (*slot).assume_init_drop();
+            }
+            (*slot).write(value);
+        }
+        // Releases the value, for whoever sees the new `head`.
+        shared.head.store(head + 1, SeqCst);
+        shared.published.fetch_add(1, SeqCst);
+        if shared.readers_sleeping.load(SeqCst) > 0 {
+            wake_all(&shared.published);
+        }
+    }
+}
+
+impl<T> Drop for Bus<T> {
+    fn drop(&mut self) {
+        let shared = &*self.shared;
+        shared.closed.store(true, Release);
+        shared.published.fetch_add(1, SeqCst);
+        if shared.readers_sleeping.load(SeqCst) > 0 {
+            wake_all(&shared.published);
+        }
+    }
+}
+
+impl<T: Clone> BusReader<T> {
+    /// The next value, or `None` if there's none yet.
+    pub fn try_recv(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        if self.cursor == shared.head.load(Acquire) {
+            return None;
+        }
+        // Safety: it's written, and won't be overwritten until we move on.
+        let value = unsafe { (*shared.slot(self.cursor)).assume_init_ref().clone() };
+        self.cursor += 1;
+        shared.cursors[self.index].store(self.cursor, SeqCst);
+        shared.moved();
+        Some(value)
+    }
+
+    /// The next value, waiting for it if need be. `None` once the `Bus`
+    /// is gone, and everything it broadcast has been read.
+    pub fn recv(&mut self) -> Option<T> {
+        loop {
+            // Before looking, so a broadcast after we look wakes us.
+            let published = self.shared.published.load(SeqCst);
+            let closed = self.shared.closed.load(Acquire);
+            if let Some(value) = self.try_recv() {
+                return Some(value);
+            }
+            // Seen closed before looking at `head`, so that was all of it.
+            if closed {
+                return None;
+            }
+            let shared = &*self.shared;
+            shared.readers_sleeping.fetch_add(1, SeqCst);
+            if shared.head.load(SeqCst) == self.cursor && !shared.closed.load(SeqCst) {
+                wait(&shared.published, published);
+            }
+            shared.readers_sleeping.fetch_sub(1, Relaxed);
+        }
+    }
+
+    /// Whether the `Bus` is gone. There may still be values to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Acquire)
+    }
+}
+
+impl<T> Drop for BusReader<T> {
+    fn drop(&mut self) {
+        self.shared.cursors[self.index].store(FREE, SeqCst);
+        self.shared.moved();
+    }
+}
+
+pub fn main() {
+    use std::{thread, time::Duration};
+
+    // A ring of 4, one quick reader and one slow one: the producer keeps
+    // pace with the slow one, and neither misses a value.
+    let mut bus = Bus::new(4, 2);
+    let readers = [bus.add_reader().unwrap(), bus.add_reader().unwrap()];
+    thread::scope(|s| {
+        for (r, mut reader) in readers.into_iter().enumerate() {
+            s.spawn(move || {
+                let mut got = Vec::new();
+                while let Some(v) = reader.recv() {
+                    got.push(v);
+                    if r == 1 {
+                        thread::sleep(Duration::from_millis(2));
+                    }
+                }
+                println!("reader {r} got {got:?}");
+            });
+        }
+        for i in 0..10 {
+            bus.broadcast(i);
+        }
+        println!("broadcast 10 through a ring of 4");
+        drop(bus);
+    });
+}
@@ -0,0 +1,80 @@
+//! A value on a cache line of its own.
+//!
+//! Two atomics next to each other share a cache line, so a thread writing
+//! one keeps taking the line away from a thread using the other, although
+//! they never touch the same data: false sharing. Padding them apart costs
+//! memory, and is worth it for what different threads write all the time,
+//! like the two indices of a ring buffer.
+//!
+//! 128 bytes where the CPU fetches cache lines in pairs (x86_64's adjacent
+//! line prefetcher) or they're that long (Apple's aarch64), 32 on small
+//! 32-bit cores, 64 elsewhere.
+
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+#[cfg_attr(
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "powerpc64",
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(
+    any(target_arch = "arm", target_arch = "riscv32", target_arch = "mips"),
+    repr(align(32))
+)]
+#[cfg_attr(
+    not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "powerpc64",
+        target_arch = "arm",
+        target_arch = "riscv32",
+        target_arch = "mips",
+    )),
+    repr(align(64))
+)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CachePadded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CachePadded").field(&self.value).finish()
+    }
+}
@@ -0,0 +1,135 @@
+//! A token to coordinate shutdown, between threads and tasks alike.
+//!
+//! Clones share the same state: cancelling any of them cancels all of them.
+//! Threads can poll `is_cancelled()` or block in `wait()`,
+//! tasks can `cancelled().await`.
+
+use crate::async_notify::Notify;
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering::{Acquire, Release},
+        },
+        Arc, Condvar, Mutex,
+    },
+    task::Poll,
+    time::Duration,
+};
+
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    // For threads blocked in `wait`.
+    lock: Mutex<()>,
+    condvar: Condvar,
+    // For tasks blocked in `cancelled().await`.
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Release) {
+            return;
+        }
+        // Taking the lock means a thread in `wait` is either before its check
+        // of the flag (and will see it) or already waiting (and gets notified).
+        drop(self.inner.lock.lock().unwrap());
+        self.inner.condvar.notify_all();
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Acquire)
+    }
+
+    /// Blocks the current thread until the token is cancelled.
+    pub fn wait(&self) {
+        let mut guard = self.inner.lock.lock().unwrap();
+        while !self.is_cancelled() {
+            guard = self.inner.condvar.wait(guard).unwrap();
+        }
+    }
+
+    /// Like `wait`, but gives up after `timeout`.
+    /// Returns whether the token was cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.inner.lock.lock().unwrap();
+        let (_guard, _) = self
+            .inner
+            .condvar
+            .wait_timeout_while(guard, timeout, |_| !self.is_cancelled())
+            .unwrap();
+        self.is_cancelled()
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut notified = pin!(self.inner.notify.notified());
+        poll_fn(|cx| {
+            // Get in the waiters queue before checking the flag, so a
+            // `cancel()` between the two can't slip by unnoticed.
+            if notified.as_mut().poll(cx).is_ready() || self.is_cancelled() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Runs `future` to completion, unless the token is cancelled first:
+    /// then the future is dropped and this returns `None`.
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        let mut cancelled = pin!(self.cancelled());
+        poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            future.as_mut().poll(cx).map(Some)
+        })
+        .await
+    }
+}
+
+pub fn main() {
+    use crate::{executor::block_on, timer::sleep};
+    use std::thread;
+
+    let token = CancellationToken::new();
+
+    thread::scope(|s| {
+        // A thread doing sync work until told to stop.
+        s.spawn(|| {
+            let mut rounds = 0;
+            while !token.wait_timeout(Duration::from_millis(50)) {
+                rounds += 1;
+            }
+            println!("sync worker stopped after {rounds} rounds");
+        });
+
+        // A task that would take way too long, cut short by the same token.
+        s.spawn(|| {
+            let result = block_on(token.run_until_cancelled(async {
+                sleep(Duration::from_secs(10)).await;
+                "finished"
+            }));
+            println!("async worker: {result:?}");
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        token.cancel();
+    });
+}
@@ -1,11 +1,17 @@
-use crate::condition_variables;
-use crate::parking;
+use crate::runner::Demo;
+use crate::{condition_variables, parking};
+use atomics_and_locks::{sync::spin_until, thread as threads};
 use std::{sync::Arc, thread};
 
 fn f() {
     println!("Hi from thread {:?}", thread::current().id());
 }
 
+/// Same as `f`, but threads from `threads::spawn_named` can tell who they are.
+fn named_f() {
+    println!("Hi from thread {}", threads::current_name());
+}
+
 fn run_without_knowing_if_completed() {
     thread::spawn(f);
     thread::spawn(f);
@@ -21,15 +27,41 @@ fn run_checking_if_completed() {
     t2.join().unwrap();
 }
 
+fn run_named() {
+    let t1 = threads::spawn_named("first", named_f);
+    let t2 = threads::spawn_named("second", named_f);
+    t1.join().unwrap();
+    t2.join().unwrap();
+}
+
+/// Both children fail: joining them one after the other unwraps the first
+/// panic and never gets to the second. `scope_ext` reports both, and stops
+/// the watcher, which would otherwise run until it's told to.
+fn run_collecting_panics() {
+    let result = threads::scope_ext::scope(|s| {
+        let token = s.token();
+        s.spawn_named("watcher", move || {
+            while !token.is_cancelled() {
+                thread::yield_now();
+            }
+        });
+        s.spawn_named("first", || panic!("first gave up"));
+        s.spawn_named("second", || panic!("second gave up"));
+    });
+    for child in result.err().unwrap_or_default() {
+        let name = child.name.as_deref().unwrap_or("unnamed");
+        println!("{name} panicked: {}", child.message().unwrap_or("?"));
+    }
+}
+
 fn better_join() {
     let t1 = thread::spawn(f);
     let t2 = thread::spawn(f);
 
     println!("Joining but not blocking in case one is not finished.");
 
-    while !(t1.is_finished() && t2.is_finished()) {
-        continue;
-    }
+    // Spinning flat out would keep a core busy, maybe the one `t1` or `t2` needs.
+    spin_until(|| t1.is_finished() && t2.is_finished());
 }
 
 const fn calc_sum(v: &[usize]) -> usize {
@@ -68,6 +100,15 @@ fn double_calculation() {
     println!("calcs: {:?}", calcs);
 }
 
+fn double_calculation_with_helpers() {
+    use atomics_and_locks::thread::{parallel_reduce, Split};
+
+    let values = vec![1, 2, 3, 4, 5];
+    let total = parallel_reduce(&values, 2, Split::Static, calc_sum, |a, b| a + b);
+    let maximum = parallel_reduce(&values, 2, Split::Static, calc_max, usize::max);
+    println!("calcs with helpers: {:?}", (total, maximum));
+}
+
 fn double_arc_calculation() {
     let values = Arc::new([1, 2, 3, 4, 5]);
     let v2 = values.clone();
@@ -96,12 +137,91 @@ fn cell_mutability() {
     let a = Cell::new(2);
     f(&a, &a);
 }
-fn main() {
-    run_without_knowing_if_completed();
-    run_checking_if_completed();
-    better_join();
-    double_calculation();
-    double_arc_calculation();
-    cell_mutability();
-    parking::example();
+
+/// `RefCell`'s rules, for threads: many readers at once, or one writer,
+/// and a conflict is an error instead of a wait.
+fn atomic_ref_cell() {
+    use atomics_and_locks::sync::AtomicRefCell;
+
+    let v = AtomicRefCell::new(vec![1, 2, 3]);
+    thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| println!("sum: {}", v.borrow().iter().sum::<i32>()));
+        }
+    });
+
+    let reader = v.borrow();
+    if let Err(e) = v.try_borrow_mut() {
+        println!("can't write while {reader:?} is read: {e}");
+    }
+    drop(reader);
+    v.borrow_mut().push(4);
+    println!("{v:?}");
 }
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "spawn",
+        about: "spawn two threads and don't wait for them",
+        run: run_without_knowing_if_completed,
+    },
+    Demo {
+        name: "join",
+        about: "spawn two threads and join them",
+        run: run_checking_if_completed,
+    },
+    Demo {
+        name: "named",
+        about: "threads that know their own name",
+        run: run_named,
+    },
+    Demo {
+        name: "scope_panics",
+        about: "a scope that reports every child's panic, not just the first",
+        run: run_collecting_panics,
+    },
+    Demo {
+        name: "busy_join",
+        about: "wait for threads with is_finished",
+        run: better_join,
+    },
+    Demo {
+        name: "scoped",
+        about: "borrow local data from scoped threads",
+        run: double_calculation,
+    },
+    Demo {
+        name: "parallel_reduce",
+        about: "the same with the parallel helpers",
+        run: double_calculation_with_helpers,
+    },
+    Demo {
+        name: "arc",
+        about: "share data with Arc instead",
+        run: double_arc_calculation,
+    },
+    Demo {
+        name: "cell",
+        about: "a Cell changing under our feet",
+        run: cell_mutability,
+    },
+    Demo {
+        name: "atomic_ref_cell",
+        about: "the same borrow rules, shared between threads",
+        run: atomic_ref_cell,
+    },
+    Demo {
+        name: "parking",
+        about: "a queue with a parked consumer",
+        run: parking::example,
+    },
+    Demo {
+        name: "condvar",
+        about: "a queue with a consumer waiting on a Condvar",
+        run: condition_variables::use_condvar,
+    },
+    Demo {
+        name: "quiet",
+        about: "drain a queue until nothing comes for a while",
+        run: condition_variables::drain_until_quiet,
+    },
+];
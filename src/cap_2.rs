@@ -1,16 +1,20 @@
+use crate::runner::Demo;
+
 mod stop_flag {
+    // The flag itself is in `BackgroundWorker` now; these are two ways to drive it.
+    use atomics_and_locks::thread::BackgroundWorker;
     use std::{
-        sync::atomic::{AtomicBool, Ordering::Relaxed},
+        sync::{
+            atomic::{AtomicUsize, Ordering::Relaxed},
+            Arc,
+        },
         thread,
+        time::Duration,
     };
-    pub(super) fn main() {
-        static STOP: AtomicBool = AtomicBool::new(false);
 
-        let background_thread = thread::spawn(|| {
-            while !STOP.load(Relaxed) {
-                some_work();
-            }
-        });
+    /// Stops on `stop`, or at the end of stdin.
+    pub(super) fn main() {
+        let background_thread = BackgroundWorker::start(some_work);
 
         for line in std::io::stdin().lines() {
             match line.unwrap().as_str() {
@@ -19,9 +23,22 @@ mod stop_flag {
                 cmd => println!("unknown command {cmd}"),
             }
         }
-        STOP.store(true, Relaxed);
         background_thread.join().unwrap();
     }
+
+    /// The same without stdin: stops after a while, for scripted runs.
+    pub(super) fn timed() {
+        let rounds = Arc::new(AtomicUsize::new(0));
+        let counter = rounds.clone();
+        let background_thread = BackgroundWorker::start(move || {
+            thread::sleep(Duration::from_millis(100));
+            counter.fetch_add(1, Relaxed);
+        });
+        thread::sleep(Duration::from_secs(1));
+        background_thread.join().unwrap();
+        println!("stopped after {} rounds of work", rounds.load(Relaxed));
+    }
+
     fn some_work() {
         std::thread::sleep(std::time::Duration::from_secs(3));
     }
@@ -86,6 +103,148 @@ mod progress_reporting {
 
         println!("done!");
     }
+
+    /// The progress of `total` items, done by any number of workers, for
+    /// the thread that made it to report on.
+    ///
+    /// `with_sync` unparks the reporter for every item. Here a worker only
+    /// does when what it adds makes the count cross a multiple of `batch`,
+    /// or another whole percent, or reach `total`: `fetch_add` says what the
+    /// count was before, and only one worker's add crosses each of those.
+    ///
+    /// A reporter can also be a part of another one, worth `weight` of the
+    /// other's `total`: it adds to the other as much of `weight` as its own
+    /// items done make up, so the reporting thread still reads one count.
+    struct ProgressReporter<'a> {
+        done: AtomicUsize,
+        total: usize,
+        batch: usize,
+        up: Up<'a>,
+        unparks: AtomicUsize,
+    }
+
+    /// Who hears of a reporter's progress.
+    enum Up<'a> {
+        Reporter(thread::Thread),
+        Parent {
+            parent: &'a ProgressReporter<'a>,
+            weight: usize,
+        },
+    }
+
+    impl<'a> ProgressReporter<'a> {
+        fn new(total: usize, batch: usize) -> Self {
+            Self {
+                done: AtomicUsize::new(0),
+                total,
+                batch: batch.max(1),
+                up: Up::Reporter(thread::current()),
+                unparks: AtomicUsize::new(0),
+            }
+        }
+
+        /// A part of this one, of `total` items, worth `weight` of ours.
+        fn part(&'a self, total: usize, weight: usize) -> Self {
+            Self {
+                done: AtomicUsize::new(0),
+                total,
+                batch: 1,
+                up: Up::Parent {
+                    parent: self,
+                    weight,
+                },
+                unparks: AtomicUsize::new(0),
+            }
+        }
+
+        /// `n` more items done.
+        fn add(&self, n: usize) {
+            let before = self.done.fetch_add(n, Relaxed);
+            let after = before + n;
+            match &self.up {
+                Up::Reporter(reporter) => {
+                    let crossed = |step: usize| before / step != after / step;
+                    let percent = |count: usize| count * 100 / self.total;
+                    if crossed(self.batch)
+                        || percent(before) != percent(after)
+                        || after == self.total
+                    {
+                        self.unparks.fetch_add(1, Relaxed);
+                        reporter.unpark();
+                    }
+                }
+                // What it's worth of `weight` before and after, rounded down:
+                // the differences add up to exactly `weight` once all of it is
+                // done, whatever order the adds come in.
+                Up::Parent { parent, weight } => {
+                    let worth = |count: usize| count * weight / self.total;
+                    let n = worth(after) - worth(before);
+                    if n > 0 {
+                        parent.add(n);
+                    }
+                }
+            }
+        }
+
+        /// Reports until all of it is done. On the thread that made it.
+        fn report(&self) {
+            loop {
+                let n = self.done.load(Relaxed);
+                if n == self.total {
+                    break;
+                }
+                println!("Working.. {n:02}/{} done", self.total);
+                thread::park_timeout(std::time::Duration::from_secs(1));
+            }
+        }
+    }
+
+    pub fn batched() {
+        // 4 workers, 10000 items, a wake-up every 250 or every percent (100):
+        // 120 of them, instead of 10000.
+        let progress = ProgressReporter::new(10_000, 250);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..2500 {
+                        thread::sleep(std::time::Duration::from_micros(100));
+                        progress.add(1);
+                    }
+                });
+            }
+            progress.report();
+        });
+        println!(
+            "done! unparked {} times for {} items",
+            progress.unparks.load(Relaxed),
+            progress.total
+        );
+    }
+
+    pub fn nested() {
+        // Three stages of very different sizes: counting their items, the
+        // 8 slow downloads would hardly show next to the 5000 lines parsed.
+        // Weighed by how long they take, they're most of it.
+        let overall = ProgressReporter::new(100, 1);
+        let stages = [
+            ("download", overall.part(8, 60), 50_000),
+            ("parse", overall.part(5000, 30), 50),
+            ("index", overall.part(500, 10), 500),
+        ];
+        thread::scope(|s| {
+            for (name, stage, micros) in &stages {
+                s.spawn(move || {
+                    for _ in 0..stage.total {
+                        thread::sleep(std::time::Duration::from_micros(*micros));
+                        stage.add(1);
+                    }
+                    println!("{name} done");
+                });
+            }
+            overall.report();
+        });
+        println!("done!");
+    }
 }
 
 mod lazy_initialization {
@@ -138,8 +297,12 @@ mod multiple_threads_reporting {
 }
 
 mod statistics {
+    use atomics_and_locks::{
+        atomic::{AtomicDuration, AtomicF64},
+        metrics::{SparseHistogram, StatsSet},
+    };
     use std::{
-        sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+        sync::atomic::Ordering::Relaxed,
         thread,
         time::{Duration, Instant},
     };
@@ -147,10 +310,22 @@ mod statistics {
     fn process_item(_i: i32) {
         thread::sleep(Duration::from_millis(50));
     }
+
+    /// How much each new time counts in the recent average.
+    const ALPHA: f64 = 0.2;
+
+    const DONE: usize = 0;
+    const TOTAL_NS: usize = 1;
+
     pub fn main() {
-        let num_done = &AtomicUsize::new(0);
-        let total_time = &AtomicU64::new(0);
-        let max_time = &AtomicU64::new(0);
+        // Read together, so the average isn't of a count and a total
+        // from different moments.
+        let done = &StatsSet::new(["done", "total_ns"]);
+        let max_time = &AtomicDuration::default();
+        // In seconds, fractions included.
+        let recent_time = &AtomicF64::new(0.0);
+        // In nanoseconds, for percentiles.
+        let times = &SparseHistogram::new("process_item.ns");
 
         thread::scope(|s| {
             for t in 0..4 {
@@ -158,30 +333,47 @@ mod statistics {
                     for i in 0..25 {
                         let start = Instant::now();
                         process_item(t * 25 + i);
-                        let time_taken = start.elapsed().as_micros() as u64;
-                        num_done.fetch_add(1, Relaxed);
-                        total_time.fetch_add(time_taken, Relaxed);
+                        let time_taken = start.elapsed();
+                        done.update(|u| {
+                            u.add(DONE, 1);
+                            u.add(TOTAL_NS, time_taken.as_nanos() as u64);
+                        });
                         max_time.fetch_max(time_taken, Relaxed);
+                        times.record(time_taken.as_nanos() as u64);
+                        let time_taken = time_taken.as_secs_f64();
+                        // An exponentially weighted moving average, starting at the first.
+                        let _ = recent_time.fetch_update(Relaxed, Relaxed, |avg| {
+                            Some(if avg == 0.0 {
+                                time_taken
+                            } else {
+                                avg + ALPHA * (time_taken - avg)
+                            })
+                        });
                     }
                 });
             }
 
             loop {
-                let total_time = Duration::from_micros(total_time.load(Relaxed));
-                let max_time = Duration::from_micros(max_time.load(Relaxed));
-                let n = num_done.load(Relaxed);
+                let stats = done.snapshot();
+                let max_time = max_time.load(Relaxed);
+                let recent_time = Duration::from_secs_f64(recent_time.load(Relaxed));
+                let n = stats.values[DONE];
                 if n == 100 {
-                    break;
-                }
-                if n == 0 {
-                    println!("Working.. nothing done yet.");
-                } else {
-                    println!(
-                        "Working.. {n:02}/100 done, {:?} average, {:?} peak",
-                        total_time / n as u32,
-                        max_time
+                    crate::runner::stats(&stats);
+                    let times = times.snapshot();
+                    break println!(
+                        "Done, {:?} median, {:?} at the 99th percentile",
+                        Duration::from_nanos(times.quantile(0.5)),
+                        Duration::from_nanos(times.quantile(0.99)),
                     );
                 }
+                match stats.values[TOTAL_NS].checked_div(n) {
+                    None => println!("Working.. nothing done yet."),
+                    Some(average) => println!(
+                        "Working.. {n:02}/100 done, {:?} average, {recent_time:?} recently, {max_time:?} peak",
+                        Duration::from_nanos(average),
+                    ),
+                }
                 thread::sleep(Duration::from_millis(100));
             }
         })
@@ -189,46 +381,116 @@ mod statistics {
 }
 
 mod id_allocation {
-    use std::sync::atomic::{AtomicU32, Ordering::Relaxed};
+    use atomics_and_locks::{sync::IdAllocator, Result};
 
+    static IDS: IdAllocator = IdAllocator::new(1000);
+
+    pub fn try_allocate_new_id() -> Result<u32> {
+        IDS.try_allocate()
+    }
+
+    /// Panics once the 1000 ids are gone.
     pub fn allocate_new_id() -> u32 {
-        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
-        let mut id = NEXT_ID.load(Relaxed);
+        IDS.allocate()
+    }
+
+    pub fn main() {
+        println!("id = {}", allocate_new_id());
+        let mut n = 1;
         loop {
-            assert!(id < 1000, "too many IDS!");
-            match NEXT_ID.compare_exchange_weak(id, id + 1, Relaxed, Relaxed) {
-                Ok(_) => return id,
-                Err(v) => id = v,
+            match try_allocate_new_id() {
+                Ok(_) => n += 1,
+                Err(e) => break println!("{e}, after {n}"),
             }
         }
+
+        // From per-thread blocks: one update of the counter per 64 ids.
+        static LOCAL: IdAllocator = IdAllocator::new(1000);
+        let firsts: Vec<u32> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let first = LOCAL.try_allocate_local().unwrap();
+                        for _ in 0..99 {
+                            LOCAL.try_allocate_local().unwrap();
+                        }
+                        first
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        println!(
+            "4 threads took 100 each, starting at {firsts:?}; the counter is at {}",
+            LOCAL.allocated()
+        );
     }
 }
 mod get_random_key {
-    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+    use atomics_and_locks::atomic::{AtomicExt, AtomicU64};
+    use std::sync::atomic::Ordering::Relaxed;
     fn generate_random_key() -> u64 {
         3
     }
     pub fn get_key() -> u64 {
         static KEY: AtomicU64 = AtomicU64::new(0);
-        let key = KEY.load(Relaxed);
-        if key == 0 {
-            let new_key = generate_random_key();
-            match KEY.compare_exchange(0, new_key, Relaxed, Relaxed) {
-                Ok(_) => new_key,
-                Err(k) => k,
-            }
-        } else {
-            key
-        }
+        KEY.get_or_init(Relaxed, generate_random_key)
     }
 }
-pub fn main() {
-    stop_flag::main();
-    progress_reporting::main();
-    progress_reporting::with_sync();
-    multiple_threads_reporting::main();
-    statistics::main();
-    lazy_initialization::get_x();
-    id_allocation::allocate_new_id();
-    get_random_key::get_key();
-}
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "stop_flag",
+        about: "stop a background thread from stdin",
+        run: stop_flag::main,
+    },
+    Demo {
+        name: "stop_flag_timed",
+        about: "the same, stopped after a second",
+        run: stop_flag::timed,
+    },
+    Demo {
+        name: "progress",
+        about: "report the progress of a worker, polling",
+        run: progress_reporting::main,
+    },
+    Demo {
+        name: "progress_park",
+        about: "the same, with the worker unparking us",
+        run: progress_reporting::with_sync,
+    },
+    Demo {
+        name: "progress_batched",
+        about: "the same, unparking us every 250 items or whole percent",
+        run: progress_reporting::batched,
+    },
+    Demo {
+        name: "progress_nested",
+        about: "stages of a pipeline, weighted, rolled up into one figure",
+        run: progress_reporting::nested,
+    },
+    Demo {
+        name: "progress_threads",
+        about: "the progress of several workers",
+        run: multiple_threads_reporting::main,
+    },
+    Demo {
+        name: "statistics",
+        about: "their average and peak time too",
+        run: statistics::main,
+    },
+    Demo {
+        name: "lazy_init",
+        about: "compute a value on first use",
+        run: || println!("x = {}", lazy_initialization::get_x()),
+    },
+    Demo {
+        name: "id_allocation",
+        about: "hand out unique ids until they run out",
+        run: id_allocation::main,
+    },
+    Demo {
+        name: "random_key",
+        about: "generate a key once, whoever asks first",
+        run: || println!("key = {}", get_random_key::get_key()),
+    },
+];
@@ -1,30 +1,41 @@
+use crate::runner::{iterations, jitter, observe, Demo};
+#[cfg(feature = "teaching")]
+use atomics_and_locks::atomic::profile::{Correct, TooStrong, TooWeak};
+
 mod relaxed_ordering {
+    use super::{iterations, jitter, observe};
     use core::sync::atomic::AtomicI32;
     use core::sync::atomic::Ordering::Relaxed;
     static X: AtomicI32 = AtomicI32::new(0);
 
     pub fn main() {
-        fn a() {
+        fn a(i: u64) {
+            jitter(2 * i);
             X.fetch_add(5, Relaxed);
             X.fetch_add(10, Relaxed);
         }
 
-        fn b() {
+        fn b(i: u64) -> String {
+            jitter(2 * i + 1);
             let a = X.load(Relaxed);
             let b = X.load(Relaxed);
             let c = X.load(Relaxed);
             let d = X.load(Relaxed);
-            println!("{a}, {b}, {c}, {d}");
+            format!("{a}, {b}, {c}, {d}")
         }
 
-        let a = std::thread::spawn(a);
-        let b = std::thread::spawn(b);
-        a.join().unwrap();
-        b.join().unwrap();
+        for i in 0..iterations(1) as u64 {
+            X.store(0, Relaxed);
+            let a = std::thread::spawn(move || a(i));
+            let b = std::thread::spawn(move || b(i));
+            a.join().unwrap();
+            observe(b.join().unwrap());
+        }
     }
 }
 
 mod out_of_thin_air {
+    use super::{iterations, jitter, observe};
     use std::{
         sync::atomic::{AtomicI32, Ordering::Relaxed},
         thread,
@@ -34,20 +45,26 @@ mod out_of_thin_air {
     static Y: AtomicI32 = AtomicI32::new(0);
 
     pub fn main() {
-        let a = thread::spawn(|| {
-            let x = X.load(Relaxed);
-            Y.store(x, Relaxed);
-        });
-        let b = thread::spawn(|| {
-            let y = Y.load(Relaxed);
-            X.store(y, Relaxed);
-        });
-
-        a.join().unwrap();
-        b.join().unwrap();
-
-        assert_eq!(X.load(Relaxed), 0);
-        assert_eq!(Y.load(Relaxed), 0);
+        for i in 0..iterations(1) as u64 {
+            let a = thread::spawn(move || {
+                jitter(2 * i);
+                let x = X.load(Relaxed);
+                Y.store(x, Relaxed);
+            });
+            let b = thread::spawn(move || {
+                jitter(2 * i + 1);
+                let y = Y.load(Relaxed);
+                X.store(y, Relaxed);
+            });
+
+            a.join().unwrap();
+            b.join().unwrap();
+
+            let (x, y) = (X.load(Relaxed), Y.load(Relaxed));
+            observe(format!("x: {x}, y: {y}"));
+            assert_eq!(x, 0);
+            assert_eq!(y, 0);
+        }
     }
 }
 
@@ -101,10 +118,11 @@ mod unsafe_ordering {
 
 mod proof_a_concept_about_same_thread_order {
     // The thing is not working. It always give me this in the right order. No matter the relaxed thing.
-    use std::sync::atomic::AtomicU64;
+    use super::{iterations, observe};
+    use atomics_and_locks::sync::spin_until;
+    use std::sync::atomic::AtomicBool;
     use std::sync::atomic::Ordering::Relaxed;
     use std::thread;
-    use std::{sync::atomic::AtomicBool, time::Duration};
 
     static V1: AtomicBool = AtomicBool::new(false);
     static V2: AtomicBool = AtomicBool::new(false);
@@ -112,28 +130,32 @@ mod proof_a_concept_about_same_thread_order {
     static DONE: AtomicBool = AtomicBool::new(false);
 
     pub fn main() {
-        thread::spawn(|| {
-            // Want to know if this can happen in different order.
-            V1.store(true, Relaxed);
-            V3.store(true, Relaxed);
-            V2.store(true, Relaxed);
-            DONE.store(true, Relaxed);
-        });
-
-        // while !READY.load(Relaxed) {
-        // thread::sleep(Duration::from_millis(100));
-        // println!("Waiting...");
-        // }
-
-        while !DONE.load(Relaxed) {
-            continue;
+        for _ in 0..iterations(1) {
+            for v in [&V1, &V2, &V3, &DONE] {
+                v.store(false, Relaxed);
+            }
+            let t = thread::spawn(|| {
+                // Want to know if this can happen in different order.
+                V1.store(true, Relaxed);
+                V3.store(true, Relaxed);
+                V2.store(true, Relaxed);
+                DONE.store(true, Relaxed);
+            });
+
+            // while !READY.load(Relaxed) {
+            // thread::sleep(Duration::from_millis(100));
+            // println!("Waiting...");
+            // }
+
+            spin_until(|| DONE.load(Relaxed));
+            observe(format!(
+                "v1: {}, v3: {}, v2: {}",
+                V1.load(Relaxed),
+                V3.load(Relaxed),
+                V2.load(Relaxed)
+            ));
+            t.join().unwrap();
         }
-        println!(
-            "v1: {}, v3: {}, v2: {}",
-            V1.load(Relaxed),
-            V3.load(Relaxed),
-            V2.load(Relaxed)
-        );
     }
 }
 
@@ -154,14 +176,15 @@ mod pattern_used_on_mutexes {
             .compare_exchange(false, true, Acquire, Relaxed)
             .is_ok()
         {
-            unsafe { DATA.push('!') };
+            let data = &raw mut DATA;
+            unsafe { (*data).push('!') };
             LOCKED.store(false, Release);
         }
     }
 
     pub fn main() {
         thread::scope(|s| {
-            for _ in 0..100 {
+            for _ in 0..crate::runner::threads(100) {
                 s.spawn(f);
             }
         })
@@ -175,9 +198,10 @@ mod lazy_initialization_with_indirection {
     use std::sync::atomic::Ordering;
     use std::thread;
 
-    struct Data;
+    // Not zero-sized, or every box would be at the same address.
+    struct Data(u64);
     fn generate_data() -> Data {
-        Data {}
+        Data(42)
     }
     fn get_data() -> &'static Data {
         static PTR: AtomicPtr<Data> = AtomicPtr::new(std::ptr::null_mut());
@@ -199,14 +223,270 @@ mod lazy_initialization_with_indirection {
 
         unsafe { &*p }
     }
+
+    atomics_and_locks::static_lazy! {
+        /// The same, without the pointer juggling: threads that come while
+        /// it's being generated wait, instead of generating their own.
+        static DATA: Data = generate_data();
+    }
+
+    pub fn main() {
+        let (by_hand, lazy): (Vec<_>, Vec<_>) = thread::scope(|s| {
+            let handles: Vec<_> = (0..4).map(|_| s.spawn(|| (get_data(), &*DATA))).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).unzip()
+        });
+        println!(
+            "one instance by hand: {}, one with static_lazy!: {}, holding {}",
+            by_hand.windows(2).all(|w| std::ptr::eq(w[0], w[1])),
+            lazy.windows(2).all(|w| std::ptr::eq(w[0], w[1])),
+            DATA.0,
+        );
+    }
+}
+
+/// Mutual exclusion for two threads with nothing but loads and stores: each
+/// raises its flag, then checks the other's, and `turn` settles a tie. It
+/// only works if no thread can see the other's flag down while its own
+/// store is still on the way, which only `SeqCst` promises: with release
+/// stores and acquire loads, a store can still wait in the store buffer
+/// while the load after it goes ahead, on x86 too, and both threads get in.
+mod dekker {
+    use super::{iterations, observe};
+    use atomics_and_locks::sync::spin_until;
+    use std::{
+        sync::atomic::{
+            AtomicBool, AtomicU64, AtomicUsize,
+            Ordering::{self, Acquire, Relaxed, Release, SeqCst},
+        },
+        thread,
+    };
+
+    struct Dekker {
+        wants: [AtomicBool; 2],
+        turn: AtomicUsize,
+        store: Ordering,
+        load: Ordering,
+    }
+
+    impl Dekker {
+        const fn new(store: Ordering, load: Ordering) -> Self {
+            Self {
+                wants: [AtomicBool::new(false), AtomicBool::new(false)],
+                turn: AtomicUsize::new(0),
+                store,
+                load,
+            }
+        }
+
+        fn lock(&self, me: usize) {
+            let other = 1 - me;
+            self.wants[me].store(true, self.store);
+            while self.wants[other].load(self.load) {
+                if self.turn.load(self.load) != me {
+                    // Back off until it's our turn, so the other can get in.
+                    self.wants[me].store(false, self.store);
+                    spin_until(|| self.turn.load(self.load) == me);
+                    self.wants[me].store(true, self.store);
+                }
+            }
+        }
+
+        fn unlock(&self, me: usize) {
+            self.turn.store(1 - me, self.store);
+            self.wants[me].store(false, self.store);
+        }
+    }
+
+    /// Two threads incrementing a counter under the lock, non-atomically:
+    /// a load and a store, so any overlap loses an increment. (The counter
+    /// is still an atomic, `Relaxed`, so a broken lock isn't a data race.)
+    fn run(lock: &Dekker) {
+        const ROUNDS: u64 = 10_000;
+        let counter = AtomicU64::new(0);
+        for _ in 0..iterations(100) {
+            counter.store(0, Relaxed);
+            thread::scope(|s| {
+                for me in 0..2 {
+                    let counter = &counter;
+                    s.spawn(move || {
+                        for _ in 0..ROUNDS {
+                            lock.lock(me);
+                            counter.store(counter.load(Relaxed) + 1, Relaxed);
+                            lock.unlock(me);
+                        }
+                    });
+                }
+            });
+            let lost = 2 * ROUNDS - counter.load(Relaxed);
+            observe(if lost == 0 {
+                "exclusive".to_string()
+            } else {
+                format!("lost {lost} increments")
+            });
+        }
+    }
+
+    pub fn seq_cst() {
+        run(&Dekker::new(SeqCst, SeqCst));
+    }
+
+    /// Needs two cores to break: on one, a thread's stores are out of the
+    /// store buffer before the other one runs.
+    pub fn release_acquire() {
+        run(&Dekker::new(Release, Acquire));
+    }
 }
 
-pub fn main() {
-    println!("Here from cap 3!");
-    // relaxed_ordering::main();
-    // out_of_thin_air::main();
-    // release_and_acquire_ordering::main();
-    // unsafe_ordering::main();
-    // proof_a_concept_about_same_thread_order::main();
-    // pattern_used_on_mutexes::main();
+/// The spin lock and the one-shot channel, compiled with each `Profile`.
+/// What they protect is in `Relaxed` atomics, so `TooWeak` shows up as
+/// stale values instead of undefined behavior. It takes a weakly ordered
+/// CPU, like AArch64, to show up at all: on x86-64 every load acquires and
+/// every store releases anyway, and only the compiler could reorder them.
+#[cfg(feature = "teaching")]
+mod profiles {
+    use super::{iterations, jitter, observe};
+    use atomics_and_locks::{atomic::profile::Profile, channel::oneshot::Channel, sync::SpinLock};
+    use std::{
+        sync::atomic::{AtomicU64, Ordering::Relaxed},
+        thread,
+    };
+
+    /// Four values written before sending, and read after receiving.
+    pub fn oneshot<P: Profile>() {
+        for i in 0..iterations(1000) as u64 {
+            let data = [const { AtomicU64::new(0) }; 4];
+            // Safety: `()` is zero-sized.
+            let mut channel = unsafe { Channel::<(), P>::with_profile() };
+            let (sender, receiver) = channel.split();
+            let stale = thread::scope(|s| {
+                s.spawn(|| {
+                    jitter(2 * i);
+                    for d in &data {
+                        d.store(1, Relaxed);
+                    }
+                    sender.send(());
+                });
+                s.spawn(|| {
+                    jitter(2 * i + 1);
+                    receiver.receive();
+                    data.iter().filter(|d| d.load(Relaxed) == 0).count()
+                })
+                .join()
+                .unwrap()
+            });
+            observe(match stale {
+                0 => format!("{}: saw all of it", P::NAME),
+                n => format!("{}: {n} of 4 stale", P::NAME),
+            });
+        }
+    }
+
+    /// Two threads incrementing a counter under the lock, with a load and a
+    /// store, like `dekker`: any overlap loses an increment.
+    pub fn spin_lock<P: Profile>() {
+        const ROUNDS: u64 = 10_000;
+        for _ in 0..iterations(100) {
+            // Safety: `()` is zero-sized.
+            let lock = unsafe { SpinLock::<(), P>::with_profile(()) };
+            let counter = AtomicU64::new(0);
+            thread::scope(|s| {
+                for _ in 0..2 {
+                    s.spawn(|| {
+                        for _ in 0..ROUNDS {
+                            let _guard = lock.lock();
+                            counter.store(counter.load(Relaxed) + 1, Relaxed);
+                        }
+                    });
+                }
+            });
+            observe(match 2 * ROUNDS - counter.load(Relaxed) {
+                0 => format!("{}: exclusive", P::NAME),
+                lost => format!("{}: lost {lost} increments", P::NAME),
+            });
+        }
+    }
 }
+
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "relaxed",
+        about: "relaxed loads still see one total order of X",
+        run: relaxed_ordering::main,
+    },
+    Demo {
+        name: "out_of_thin_air",
+        about: "relaxed cycles don't make up values",
+        run: out_of_thin_air::main,
+    },
+    Demo {
+        name: "release_acquire",
+        about: "publish data with a release store",
+        run: release_and_acquire_ordering::main,
+    },
+    Demo {
+        name: "unsafe_ordering",
+        about: "the same for a non-atomic static",
+        run: unsafe_ordering::main,
+    },
+    Demo {
+        name: "same_thread_order",
+        about: "try to see relaxed stores out of order",
+        run: proof_a_concept_about_same_thread_order::main,
+    },
+    Demo {
+        name: "mutex_pattern",
+        about: "a lock out of compare_exchange",
+        run: pattern_used_on_mutexes::main,
+    },
+    Demo {
+        name: "lazy_init",
+        about: "initialize a static once, by hand and with static_lazy!",
+        run: lazy_initialization_with_indirection::main,
+    },
+    Demo {
+        name: "dekker",
+        about: "a lock out of SeqCst loads and stores only",
+        run: dekker::seq_cst,
+    },
+    Demo {
+        name: "dekker_weak",
+        about: "the same with release and acquire, letting both threads in",
+        run: dekker::release_acquire,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "oneshot_correct",
+        about: "the one-shot channel, sending with release, receiving with acquire",
+        run: profiles::oneshot::<Correct>,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "oneshot_too_weak",
+        about: "the same, relaxed, for stale data after the message",
+        run: profiles::oneshot::<TooWeak>,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "oneshot_too_strong",
+        about: "the same, SeqCst, correct but no more so",
+        run: profiles::oneshot::<TooStrong>,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "spin_lock_correct",
+        about: "the spin lock, locking with acquire, unlocking with release",
+        run: profiles::spin_lock::<Correct>,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "spin_lock_too_weak",
+        about: "the same, relaxed, losing increments under it",
+        run: profiles::spin_lock::<TooWeak>,
+    },
+    #[cfg(feature = "teaching")]
+    Demo {
+        name: "spin_lock_too_strong",
+        about: "the same, SeqCst, correct but no more so",
+        run: profiles::spin_lock::<TooStrong>,
+    },
+];
@@ -1,14 +1,15 @@
-use std::cell::UnsafeCell;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{
-    AtomicBool,
-    Ordering::{Acquire, Release},
-};
+// The spin lock itself now lives in the library, so it builds without std too.
+use crate::runner::Demo;
+pub use atomics_and_locks::sync::SpinLock;
 use std::thread;
 
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+pub const DEMOS: &[Demo] = &[Demo {
+    name: "spin_lock",
+    about: "two threads pushing to a Vec behind a SpinLock",
+    run: spin_lock,
+}];
 
-pub fn main() {
+fn spin_lock() {
     let x = SpinLock::new(vec![]);
     thread::scope(|s| {
         s.spawn(|| x.lock().push(1));
@@ -22,56 +23,3 @@ pub fn main() {
     assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
     // println!("done!!")
 }
-
-pub struct SpinLock<T> {
-    locked: AtomicBool,
-    value: UnsafeCell<T>,
-}
-
-impl<T> SpinLock<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
-        }
-    }
-
-    pub fn lock(&self) -> Guard<T> {
-        while self.locked.swap(true, Acquire) {
-            std::hint::spin_loop();
-        }
-        Guard { lock: self }
-    }
-
-    pub fn unlock(&self) {
-        self.locked.store(false, Release);
-    }
-}
-
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
-}
-
-impl<T> Deref for Guard<'_, T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        // Safety: The very existence of this Guard
-        // guarantees we've exclusively locked the lock.
-        unsafe { &*self.lock.value.get() }
-    }
-}
-
-impl<T> DerefMut for Guard<'_, T> {
-    fn deref_mut(&mut self) -> &mut T {
-        // Safety: The very existence of this Guard
-        // guarantees we've exclusively locked the lock.
-        unsafe { &mut *self.lock.value.get() }
-    }
-}
-
-impl<T> Drop for Guard<'_, T> {
-    fn drop(&mut self) {
-        self.lock.locked.store(false, Release);
-    }
-}
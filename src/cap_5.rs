@@ -1,41 +1,25 @@
 // building our own Channels
 
-use std::thread;
+use crate::runner::Demo;
 
-mod mutex_based_channel {
-    use std::{
-        collections::VecDeque,
-        sync::{Condvar, Mutex},
-    };
-
-    pub struct Channel<T> {
-        queue: Mutex<VecDeque<T>>,
-        item_ready: Condvar,
-    }
-
-    impl<T> Channel<T> {
-        pub fn new() -> Self {
-            Self {
-                queue: Mutex::new(VecDeque::new()),
-                item_ready: Condvar::new(),
-            }
-        }
+pub(crate) mod mutex_based_channel {
+    // Used by the pools and the executor, so it lives in the library now.
+    pub use atomics_and_locks::channel::Channel;
+    use std::thread;
 
-        fn send(&self, message: T) {
-            self.queue.lock().unwrap().push_back(message);
-            self.item_ready.notify_one();
-        }
-
-        fn receive(&self) -> T {
-            ///! My comment
-            let mut b = self.queue.lock().unwrap();
-            loop {
-                if let Some(message) = b.pop_front() {
-                    return message;
+    pub fn main() {
+        let channel = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..3 {
+                    channel.send(i);
                 }
-                b = self.item_ready.wait(b).unwrap();
+                channel.close();
+            });
+            while let Some(i) = channel.receive() {
+                println!("received {i}");
             }
-        }
+        });
     }
 }
 
@@ -78,6 +62,26 @@ mod unsafe_one_shot_channel {
             (*self.message.get()).assume_init_read()
         }
     }
+
+    pub fn main() {
+        use std::thread;
+        let channel = Channel::new();
+        let t = thread::current();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                // Safety: the only send.
+                unsafe { channel.send("Hello World!") };
+                t.unpark();
+            });
+            while !channel.is_ready() {
+                thread::park();
+            }
+
+            // Safety: the only receive, and it's ready.
+            assert_eq!(unsafe { channel.receive() }, "Hello World!");
+        })
+    }
 }
 mod safety_through_runtime_checks {
     //! This is a channel who only sends one message from one thread to another.
@@ -156,22 +160,25 @@ mod safety_through_runtime_checks {
         })
     }
 }
-mod single_atomic_for_channel_state {
+pub(crate) mod single_atomic_for_channel_state {
     //! This is a channel who only sends one message from one thread to another.
-    const EMPTY: u8 = 0;
-    const WRITING: u8 = 1;
-    const READY: u8 = 2;
-    const READING: u8 = 3;
+    use atomics_and_locks::{atomic::AtomicEnum, state_machine};
+    use std::{cell::UnsafeCell, mem::MaybeUninit, sync::atomic::Ordering};
 
-    use std::{
-        cell::UnsafeCell,
-        mem::MaybeUninit,
-        sync::atomic::{AtomicU8, Ordering},
-    };
+    state_machine! {
+        #[derive(Debug)]
+        enum State {
+            Empty => [Writing],
+            Writing => [Ready],
+            Ready => [Reading],
+            Reading => [Done],
+            Done => [],
+        }
+    }
 
     pub struct Channel<T> {
         message: UnsafeCell<MaybeUninit<T>>,
-        state: AtomicU8,
+        state: AtomicEnum<State>,
     }
 
     unsafe impl<T> Sync for Channel<T> where T: Send {}
@@ -179,7 +186,7 @@ mod single_atomic_for_channel_state {
         pub const fn new() -> Self {
             Self {
                 message: UnsafeCell::new(MaybeUninit::uninit()),
-                state: AtomicU8::new(EMPTY),
+                state: AtomicEnum::from_raw(State::Empty as u8),
             }
         }
 
@@ -187,17 +194,23 @@ mod single_atomic_for_channel_state {
         pub fn send(&self, message: T) {
             if self
                 .state
-                .compare_exchange(EMPTY, WRITING, Ordering::Relaxed, Ordering::Relaxed)
+                .transition(
+                    State::Empty,
+                    State::Writing,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
                 .is_err()
             {
                 panic!("can't send more than one message!")
             }
             unsafe { (*self.message.get()).write(message) };
-            self.state.store(READY, Ordering::Release);
+            self.state
+                .advance(State::Writing, State::Ready, Ordering::Release);
         }
 
         pub fn is_ready(&self) -> bool {
-            self.state.load(Ordering::Relaxed) == READY
+            self.state.load(Ordering::Relaxed) == State::Ready
         }
 
         /// Panics if no message is available yet.
@@ -207,24 +220,84 @@ mod single_atomic_for_channel_state {
         pub fn receive(&self) -> T {
             if self
                 .state
-                .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
+                .transition(
+                    State::Ready,
+                    State::Reading,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
                 .is_err()
             {
                 panic!("No message available!");
             }
-            unsafe { (*self.message.get()).assume_init_read() }
+            let message = unsafe { (*self.message.get()).assume_init_read() };
+            self.state
+                .advance(State::Reading, State::Done, Ordering::Relaxed);
+            message
+        }
+
+        /// Like `receive`, but lends the message to `f` where it is, and
+        /// drops it there after: `Reading` lasts as long as `f`.
+        pub fn receive_with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+            if self
+                .state
+                .transition(
+                    State::Ready,
+                    State::Reading,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                panic!("No message available!");
+            }
+            /// Drops the message once `f` is done with it, or panicked.
+            struct Done<'a, T>(&'a Channel<T>);
+            impl<T> Drop for Done<'_, T> {
+                fn drop(&mut self) {
+                    unsafe { (*self.0.message.get()).assume_init_drop() };
+                    self.0
+                        .state
+                        .advance(State::Reading, State::Done, Ordering::Relaxed);
+                }
+            }
+            let done = Done(self);
+            f(unsafe { (*done.0.message.get()).assume_init_ref() })
         }
     }
 
     impl<T> Drop for Channel<T> {
         fn drop(&mut self) {
-            if *self.state.get_mut() == READY {
+            if self.state.load_mut() == State::Ready {
                 unsafe { self.message.get_mut().assume_init_drop() }
             }
         }
     }
+
+    pub fn main() {
+        use std::thread;
+        let (owned, lent) = (Channel::new(), Channel::new());
+        let t = thread::current();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                owned.send(String::from("Hello World!"));
+                lent.send(String::from("Hello again!"));
+                t.unpark();
+            });
+            while !owned.is_ready() || !lent.is_ready() {
+                thread::park();
+            }
+
+            assert_eq!(owned.receive(), "Hello World!");
+            // Looked at where it is, without moving it out.
+            assert_eq!(lent.receive_with(|m| m.len()), 12);
+        })
+    }
 }
 
+/// The library's `channel::oneshot::channel` is this, with a blocking
+/// `receive` instead of a panicking one.
 mod safety_through_types {
     use std::{
         cell::UnsafeCell,
@@ -412,7 +485,7 @@ mod blocking {
             }
         }
 
-        pub fn split(&mut self) -> (Sender<T>, Receiver<T>) {
+        pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
             *self = Self::new();
             (
                 Sender {
@@ -465,10 +538,277 @@ mod blocking {
     }
 }
 
-pub fn main() {
-    // use safety_through_types::main as m;
-    // use borrowing_to_avoid_allocations::main as m;
-    use blocking::main as m;
+/// The library's blocking one, with the channel made and split in one go,
+/// so it can't be split twice, or outlive the scope.
+mod scoped {
+    use atomics_and_locks::channel::oneshot::{scoped_channel, with_channel};
+    use std::thread;
 
-    m();
+    pub fn main() {
+        scoped_channel!(sender, receiver);
+        thread::scope(|s| {
+            s.spawn(move || sender.send("hello world!"));
+            println!("{}", receiver.receive());
+        });
+
+        let n = with_channel(|sender, receiver| {
+            thread::scope(|s| {
+                s.spawn(move || sender.send(6 * 7));
+                receiver.receive()
+            })
+        });
+        println!("{n}");
+
+        // A big message, looked at where it is instead of moved out.
+        let sum = with_channel(|sender, receiver| {
+            thread::scope(|s| {
+                s.spawn(move || sender.send([1u8; 64 * 1024]));
+                receiver.receive_with(|block| block.iter().map(|&b| u64::from(b)).sum::<u64>())
+            })
+        });
+        println!("64 KiB received in place, summing to {sum}");
+    }
+}
+
+/// Several of them at once: all of their results, or the first one.
+mod join {
+    use atomics_and_locks::channel::oneshot::{join_all, race, Channel};
+    use std::{thread, time::Duration};
+
+    pub fn main() {
+        let mut channels: [Channel<u64>; 3] = Default::default();
+        let [a, b, c] = channels.each_mut().map(Channel::split);
+        thread::scope(|s| {
+            for (i, sender) in [a.0, b.0, c.0].into_iter().enumerate() {
+                s.spawn(move || {
+                    thread::sleep(Duration::from_millis(30 - 10 * i as u64));
+                    sender.send(i as u64 * 100);
+                });
+            }
+            println!("all: {:?}", join_all([a.1, b.1, c.1]));
+        });
+
+        let mut channels: [Channel<&str>; 2] = Default::default();
+        let [fast, slow] = channels.each_mut().map(Channel::split);
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                slow.0.send("slow");
+            });
+            s.spawn(move || fast.0.send("fast"));
+            println!("first: {}", race(vec![slow.1, fast.1]));
+        });
+    }
+}
+
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "mutex_channel",
+        about: "a queue behind a Mutex, closed when done",
+        run: mutex_based_channel::main,
+    },
+    Demo {
+        name: "unsafe",
+        about: "a one-shot channel whose misuse is undefined behavior",
+        run: unsafe_one_shot_channel::main,
+    },
+    Demo {
+        name: "runtime_checks",
+        about: "a one-shot channel that panics when misused",
+        run: safety_through_runtime_checks::main,
+    },
+    Demo {
+        name: "state",
+        about: "the same, with its state in one atomic enum",
+        run: single_atomic_for_channel_state::main,
+    },
+    Demo {
+        name: "types",
+        about: "a one-shot channel that can't be misused",
+        run: safety_through_types::main,
+    },
+    Demo {
+        name: "borrowing",
+        about: "the same, borrowed instead of in an Arc",
+        run: borrowing_to_avoid_allocations::main,
+    },
+    Demo {
+        name: "blocking",
+        about: "the same, with a blocking receive",
+        run: blocking::main,
+    },
+    Demo {
+        name: "scoped",
+        about: "the library's, made and split in one step",
+        run: scoped::main,
+    },
+    Demo {
+        name: "join",
+        about: "wait for all of several one-shot channels, or the first",
+        run: join::main,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    //! Property tests: random operation sequences are run against both the
+    //! crate channels and a reference model, a plain `VecDeque` behind a `Mutex`.
+
+    use proptest::prelude::*;
+    use std::{
+        collections::VecDeque,
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicUsize, Ordering::Relaxed},
+            Arc, Mutex,
+        },
+    };
+
+    /// A message that counts how many times it was dropped.
+    #[derive(Debug)]
+    struct Tracked {
+        value: u32,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Relaxed);
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Send(u32),
+        TryReceive,
+        Close,
+        Drop,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => any::<u32>().prop_map(Op::Send),
+            4 => Just(Op::TryReceive),
+            1 => Just(Op::Close),
+            1 => Just(Op::Drop),
+        ]
+    }
+
+    #[derive(Default)]
+    struct Model {
+        queue: Mutex<VecDeque<u32>>,
+        closed: bool,
+    }
+
+    proptest! {
+        #[test]
+        fn mutex_channel_matches_model(ops in proptest::collection::vec(op(), 0..200)) {
+            use super::mutex_based_channel::Channel;
+
+            let drops = Arc::new(AtomicUsize::new(0));
+            let mut created = 0;
+            let mut channel = Channel::new();
+            let mut model = Model::default();
+
+            for op in ops {
+                match op {
+                    Op::Send(value) => {
+                        let message = Tracked { value, drops: drops.clone() };
+                        created += 1;
+                        let sent = catch_unwind(AssertUnwindSafe(|| channel.send(message)));
+                        prop_assert_eq!(sent.is_ok(), !model.closed);
+                        if !model.closed {
+                            model.queue.lock().unwrap().push_back(value);
+                        }
+                    }
+                    Op::TryReceive => {
+                        let expected = model.queue.lock().unwrap().pop_front();
+                        let got = channel.try_receive().map(|m| m.value);
+                        prop_assert_eq!(got, expected);
+                    }
+                    Op::Close => {
+                        channel.close();
+                        model.closed = true;
+                        prop_assert!(channel.is_closed());
+                    }
+                    Op::Drop => {
+                        drop(std::mem::replace(&mut channel, Channel::new()));
+                        model = Model::default();
+                        prop_assert_eq!(drops.load(Relaxed), created);
+                    }
+                }
+            }
+
+            // A closed channel must hand out every queued message before reporting `None`.
+            channel.close();
+            let remaining: Vec<u32> = model.queue.lock().unwrap().drain(..).collect();
+            for expected in remaining {
+                prop_assert_eq!(channel.receive().map(|m| m.value), Some(expected));
+            }
+            prop_assert!(channel.receive().is_none());
+            drop(channel);
+            prop_assert_eq!(drops.load(Relaxed), created);
+        }
+
+        #[test]
+        fn one_shot_channels_match_model(ops in proptest::collection::vec(op(), 0..20)) {
+            use super::safety_through_runtime_checks::Channel as RuntimeChecked;
+            use super::single_atomic_for_channel_state::Channel as SingleAtomic;
+
+            let drops = Arc::new(AtomicUsize::new(0));
+            let mut created = 0;
+            let mut runtime_checked = RuntimeChecked::new();
+            let mut single_atomic = SingleAtomic::new();
+            // A one-shot channel is a queue that accepts a single send.
+            let mut model = Model::default();
+            let mut used = false;
+
+            for op in ops {
+                match op {
+                    Op::Send(value) => {
+                        let a = Tracked { value, drops: drops.clone() };
+                        let b = Tracked { value, drops: drops.clone() };
+                        created += 2;
+                        let sent_a = catch_unwind(AssertUnwindSafe(|| runtime_checked.send(a)));
+                        let sent_b = catch_unwind(AssertUnwindSafe(|| single_atomic.send(b)));
+                        prop_assert_eq!(sent_a.is_ok(), !used);
+                        prop_assert_eq!(sent_b.is_ok(), !used);
+                        if !used {
+                            model.queue.lock().unwrap().push_back(value);
+                            used = true;
+                        }
+                    }
+                    Op::TryReceive => {
+                        let expected = model.queue.lock().unwrap().pop_front();
+                        prop_assert_eq!(runtime_checked.is_ready(), expected.is_some());
+                        prop_assert_eq!(single_atomic.is_ready(), expected.is_some());
+                        let got_a = catch_unwind(AssertUnwindSafe(|| runtime_checked.receive().value));
+                        // Every other value lent out in place instead of moved out.
+                        let in_place = expected.is_some_and(|v| v % 2 == 0);
+                        let got_b = catch_unwind(AssertUnwindSafe(|| {
+                            if in_place {
+                                single_atomic.receive_with(|m| m.value)
+                            } else {
+                                single_atomic.receive().value
+                            }
+                        }));
+                        prop_assert_eq!(got_a.ok(), expected);
+                        prop_assert_eq!(got_b.ok(), expected);
+                    }
+                    // One-shot channels have no close; closing is dropping.
+                    Op::Close | Op::Drop => {
+                        drop(std::mem::replace(&mut runtime_checked, RuntimeChecked::new()));
+                        drop(std::mem::replace(&mut single_atomic, SingleAtomic::new()));
+                        model = Model::default();
+                        used = false;
+                        prop_assert_eq!(drops.load(Relaxed), created);
+                    }
+                }
+            }
+
+            drop(runtime_checked);
+            drop(single_atomic);
+            prop_assert_eq!(drops.load(Relaxed), created);
+        }
+    }
 }
@@ -0,0 +1,246 @@
+//! Building our own locks, on top of the wait/wake functions in `sys`.
+//! The locks themselves live in the library, in `sync`; these are their demos.
+
+use crate::runner::Demo;
+
+pub(crate) mod mutex {
+    use crate::runner::{iterations, threads};
+    pub use atomics_and_locks::sync::Mutex;
+    use std::thread;
+
+    pub fn main() {
+        let m = Mutex::new(0);
+        std::hint::black_box(&m);
+        let iterations = iterations(500_000);
+        let start = std::time::Instant::now();
+        thread::scope(|s| {
+            for _ in 0..threads(4) {
+                s.spawn(|| {
+                    for _ in 0..iterations {
+                        *m.lock() += 1;
+                    }
+                });
+            }
+        });
+        let duration = start.elapsed();
+        println!("locked {} times in {:?}", *m.lock(), duration);
+    }
+}
+
+/// Threads that take the lock again right after unlocking it, and one
+/// that only wants it now and then: how long does that one wait?
+pub(crate) mod fairness {
+    use crate::runner::threads;
+    use atomics_and_locks::sync::{Fairness, Mutex};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn longest_wait(fairness: Fairness) -> (Duration, u32) {
+        let m = Mutex::with_fairness(0u64, fairness);
+        let until = Instant::now() + Duration::from_millis(300);
+        let mut longest = Duration::ZERO;
+        let mut times = 0;
+        thread::scope(|s| {
+            for _ in 0..threads(3) {
+                s.spawn(|| {
+                    while Instant::now() < until {
+                        let mut v = m.lock();
+                        // Holding it for a bit, every time.
+                        let busy = Instant::now();
+                        while busy.elapsed() < Duration::from_micros(50) {
+                            *v += 1;
+                        }
+                    }
+                });
+            }
+            while Instant::now() < until {
+                let start = Instant::now();
+                drop(m.lock());
+                longest = longest.max(start.elapsed());
+                times += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        (longest, times)
+    }
+
+    pub fn main() {
+        for fairness in [
+            Fairness::Barging,
+            Fairness::Eventual(Duration::from_millis(1)),
+        ] {
+            let (longest, times) = longest_wait(fairness);
+            println!("{fairness:?}: got it {times} times, waited {longest:?} at most");
+        }
+    }
+}
+
+/// Transfers between two accounts, in both directions at once. Locking
+/// `from` and then `to` would have the two threads each hold one account
+/// and wait forever for the other.
+pub(crate) mod transfer {
+    use crate::runner::iterations;
+    use atomics_and_locks::sync::{lock_both, Mutex};
+    use std::thread;
+
+    fn transfer(from: &Mutex<i64>, to: &Mutex<i64>, amount: i64) {
+        let (mut from, mut to) = lock_both(from, to);
+        *from -= amount;
+        *to += amount;
+    }
+
+    pub fn main() {
+        let (a, b) = (Mutex::new(1000), Mutex::new(1000));
+        let n = iterations(100_000);
+        thread::scope(|s| {
+            s.spawn(|| (0..n).for_each(|_| transfer(&a, &b, 1)));
+            s.spawn(|| (0..n).for_each(|_| transfer(&b, &a, 2)));
+        });
+        let (a, b) = (a.into_inner(), b.into_inner());
+        println!("a: {a}, b: {b}, total: {}", a + b);
+    }
+}
+
+pub(crate) mod condvar {
+    pub use atomics_and_locks::sync::Condvar;
+    use atomics_and_locks::sync::Mutex;
+    use std::{thread, time::Duration};
+
+    pub fn main() {
+        let mutex = Mutex::new(0);
+        let condvar = Condvar::new();
+        let mut wakeups = 0;
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                *mutex.lock() = 123;
+                condvar.notify_one();
+            });
+
+            let mut m = mutex.lock();
+            while *m < 100 {
+                m = condvar.wait(m);
+                wakeups += 1;
+            }
+            assert_eq!(*m, 123);
+        });
+
+        // Not a busy loop: at most a few spurious wake-ups.
+        assert!(wakeups < 10);
+
+        let (_m, timed_out) = condvar.wait_timeout(mutex.lock(), Duration::from_millis(10));
+        println!("woke up {wakeups} time(s), then timed out: {timed_out}");
+    }
+}
+
+pub(crate) mod semaphore {
+    use crate::runner::threads;
+    pub use atomics_and_locks::sync::Semaphore;
+    use std::{
+        sync::atomic::{AtomicU32, Ordering::Relaxed},
+        thread,
+    };
+
+    pub fn main() {
+        let semaphore = Semaphore::new(2);
+        let inside = AtomicU32::new(0);
+        let most_inside = AtomicU32::new(0);
+        thread::scope(|s| {
+            for _ in 0..threads(8) {
+                s.spawn(|| {
+                    semaphore.acquire();
+                    let now = inside.fetch_add(1, Relaxed) + 1;
+                    most_inside.fetch_max(now, Relaxed);
+                    thread::sleep(std::time::Duration::from_millis(5));
+                    inside.fetch_sub(1, Relaxed);
+                    semaphore.release();
+                });
+            }
+        });
+        println!("at most {} threads inside", most_inside.into_inner());
+    }
+}
+
+pub(crate) mod rwlock {
+    use crate::runner::iterations;
+    pub use atomics_and_locks::sync::RwLock;
+    use std::{
+        sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+        thread,
+    };
+
+    /// A pair a writer keeps equal: readers that see them differ saw a
+    /// torn copy.
+    pub fn main() {
+        let pair = RwLock::new((0u64, 0u64));
+        let done = AtomicBool::new(false);
+        let (optimistic, fallbacks) = (AtomicU64::new(0), AtomicU64::new(0));
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while !done.load(Relaxed) {
+                        let (a, b) = match pair.read_optimistic().validate() {
+                            Some(copy) => {
+                                optimistic.fetch_add(1, Relaxed);
+                                copy
+                            }
+                            None => {
+                                fallbacks.fetch_add(1, Relaxed);
+                                *pair.read()
+                            }
+                        };
+                        assert_eq!(a, b);
+                    }
+                });
+            }
+            for _ in 0..iterations(100_000) {
+                let mut p = pair.write();
+                p.0 += 1;
+                p.1 += 1;
+            }
+            done.store(true, Relaxed);
+        });
+        println!(
+            "{:?} after {} optimistic reads and {} that fell back to locking",
+            pair.into_inner(),
+            optimistic.into_inner(),
+            fallbacks.into_inner(),
+        );
+    }
+}
+
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "mutex",
+        about: "threads counting behind the futex Mutex",
+        run: mutex::main,
+    },
+    Demo {
+        name: "fairness",
+        about: "a thread waiting behind greedy ones, with and without handoff",
+        run: fairness::main,
+    },
+    Demo {
+        name: "transfer",
+        about: "lock two mutexes in either order without deadlocking",
+        run: transfer::main,
+    },
+    Demo {
+        name: "condvar",
+        about: "wait for a value, then time out",
+        run: condvar::main,
+    },
+    Demo {
+        name: "semaphore",
+        about: "at most two threads inside",
+        run: semaphore::main,
+    },
+    Demo {
+        name: "rwlock",
+        about: "optimistic reads that fall back to a read lock",
+        run: rwlock::main,
+    },
+];
@@ -0,0 +1,169 @@
+//! Logical clocks, for ordering events between threads (or simulated
+//! processes) by what could have caused what, instead of by wall time.
+//!
+//! A `LamportClock` is one counter: every event ticks it, and every message
+//! received pushes it past the sender's time. If one event caused another,
+//! it has the smaller time, but not the other way around. A `VectorClock`
+//! keeps a counter per process, so it can also tell when two events were
+//! concurrent: neither time is below the other in every slot.
+//!
+//! Both are shared by reference, and only ever go up, so each slot is one
+//! atomic that `fetch_max` merges into, with no lock. `Relaxed` is enough:
+//! the times are the only thing they publish, and messages that carry them
+//! are synchronized by whatever carries the messages.
+
+use core::{array, cmp::Ordering, sync::atomic::Ordering::Relaxed};
+
+use crate::atomic_u64::AtomicU64;
+
+pub struct LamportClock {
+    time: AtomicU64,
+}
+
+impl LamportClock {
+    pub const fn new() -> Self {
+        Self {
+            time: AtomicU64::new(0),
+        }
+    }
+
+    /// A local event, or a send: the time to stamp it with.
+    pub fn tick(&self) -> u64 {
+        self.time.fetch_add(1, Relaxed) + 1
+    }
+
+    /// A receive of a message stamped `remote`: the time to stamp it with,
+    /// past both that and anything seen here before.
+    pub fn observe(&self, remote: u64) -> u64 {
+        let old = self
+            .time
+            .fetch_update(Relaxed, Relaxed, |t| Some(t.max(remote) + 1));
+        old.unwrap().max(remote) + 1
+    }
+
+    /// The time of the latest event, without making a new one.
+    pub fn now(&self) -> u64 {
+        self.time.load(Relaxed)
+    }
+}
+
+impl Default for LamportClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A time on a `VectorClock` of `N` processes: how many events of each
+/// it follows. Ordered only partially: `partial_cmp` is `None` for
+/// concurrent times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VectorTime<const N: usize>(pub [u64; N]);
+
+impl<const N: usize> VectorTime<N> {
+    /// The time before any event.
+    pub const ZERO: Self = Self([0; N]);
+
+    /// The latest of both, slot by slot: everything either follows.
+    pub fn merge(&mut self, other: &Self) {
+        for (mine, theirs) in self.0.iter_mut().zip(other.0) {
+            *mine = (*mine).max(theirs);
+        }
+    }
+
+    /// Neither happened before the other.
+    pub fn concurrent_with(&self, other: &Self) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+}
+
+impl<const N: usize> PartialOrd for VectorTime<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let (mut less, mut greater) = (false, false);
+        for (a, b) in self.0.iter().zip(&other.0) {
+            less |= a < b;
+            greater |= a > b;
+        }
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+}
+
+/// One counter per process, `0..N`. Each process ticks its own slot, and
+/// merges in the times of the messages it receives.
+pub struct VectorClock<const N: usize> {
+    slots: [AtomicU64; N],
+}
+
+impl<const N: usize> VectorClock<N> {
+    pub fn new() -> Self {
+        Self {
+            slots: array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    /// A local event of process `i`, or a send: the time to stamp it with.
+    pub fn tick(&self, i: usize) -> VectorTime<N> {
+        self.slots[i].fetch_add(1, Relaxed);
+        self.now()
+    }
+
+    /// Process `i` receives a message stamped `remote`: merges it in and
+    /// ticks, and returns the time to stamp the receive with.
+    pub fn observe(&self, i: usize, remote: &VectorTime<N>) -> VectorTime<N> {
+        self.merge(remote);
+        self.tick(i)
+    }
+
+    /// Merges in `other`, without an event of its own.
+    pub fn merge(&self, other: &VectorTime<N>) {
+        for (slot, &t) in self.slots.iter().zip(&other.0) {
+            slot.fetch_max(t, Relaxed);
+        }
+    }
+
+    /// The current time, slot by slot. While others tick, a slot may have
+    /// moved on by the time the next one is read, but never back.
+    pub fn now(&self) -> VectorTime<N> {
+        VectorTime(array::from_fn(|i| self.slots[i].load(Relaxed)))
+    }
+}
+
+impl<const N: usize> Default for VectorClock<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn main() {
+    use std::{sync::mpsc, thread};
+
+    // Two processes, each with its own clocks, trading one message.
+    let (to_b, from_a) = mpsc::channel();
+    let (a_lamport, b_lamport) = (LamportClock::new(), LamportClock::new());
+    let (a_vector, b_vector) = (VectorClock::<2>::new(), VectorClock::<2>::new());
+    thread::scope(|s| {
+        s.spawn(|| {
+            let first = (a_lamport.tick(), a_vector.tick(0));
+            let send = (a_lamport.tick(), a_vector.tick(0));
+            to_b.send(send).unwrap();
+            println!("a: first {first:?}, send {send:?}");
+        });
+        let (b_lamport, b_vector) = (&b_lamport, &b_vector);
+        s.spawn(move || {
+            let own = (b_lamport.tick(), b_vector.tick(1));
+            let (lamport, vector) = from_a.recv().unwrap();
+            let receive = (b_lamport.observe(lamport), b_vector.observe(1, &vector));
+            println!("b: own {own:?}, receive {receive:?}");
+            println!(
+                "b's own event and a's send were concurrent: {}",
+                own.1.concurrent_with(&vector)
+            );
+            println!("the send came before the receive: {}", vector < receive.1);
+        });
+    });
+}
@@ -1,39 +1,47 @@
-use std::collections::VecDeque;
-use std::sync::{Condvar, Mutex};
+use atomics_and_locks::channel::BlockingQueue;
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 pub fn use_condvar() {
-    let queue = Mutex::new(VecDeque::new());
-    let finish = Mutex::new(false);
-    let not_empty = Condvar::new();
+    let queue = BlockingQueue::new();
     thread::scope(|s| {
-        s.spawn(|| 'a: loop {
-            let mut q = queue.lock().unwrap();
-            let item = loop {
-                if let Some(item) = q.pop_front() {
-                    break item;
-                } else {
-                    q = not_empty.wait(q).unwrap();
-                    if *finish.lock().unwrap() {
-                        break 'a;
-                    }
-                }
-            };
-            drop(q);
-            dbg!(item);
+        // Closing wakes it up even while it waits, and it still gets
+        // everything pushed before that.
+        s.spawn(|| {
+            while let Some(item) = queue.pop() {
+                dbg!(item);
+            }
         });
 
         let start = SystemTime::now();
         for i in 0.. {
-            queue.lock().unwrap().push_back(i);
+            queue.push(i).unwrap();
             if SystemTime::now() - Duration::from_secs(5) > start {
-                *finish.lock().unwrap() = true;
-                not_empty.notify_one();
+                queue.close();
                 break;
             }
-            not_empty.notify_one();
             thread::sleep(Duration::from_secs(1));
         }
     });
 }
+
+/// Bursts of items, and a consumer that stops once they stop coming, with
+/// no `close` to tell it.
+pub fn drain_until_quiet() {
+    let queue = BlockingQueue::new();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for burst in 0..3 {
+                for i in 0..3 {
+                    queue.push(burst * 10 + i).unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+        });
+
+        let start = Instant::now();
+        let items: Vec<_> = queue.iter_timeout(Duration::from_millis(500)).collect();
+        println!("{items:?}, quiet after {:?}", start.elapsed());
+    });
+}
@@ -0,0 +1,73 @@
+//! The condition variable of chapter 9, for the mutex next to it.
+
+use crate::mutex::MutexGuard;
+use crate::sys::{wait, wait_timeout, wake_all, wake_one};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering::Relaxed};
+use std::time::Duration;
+
+pub struct Condvar {
+    counter: AtomicU32,
+    /// Lets the notify functions skip the syscall when nobody waits.
+    num_waiters: AtomicUsize,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn notify_one(&self) {
+        if self.num_waiters.load(Relaxed) > 0 {
+            self.counter.fetch_add(1, Relaxed);
+            wake_one(&self.counter);
+        }
+    }
+
+    pub fn notify_all(&self) {
+        if self.num_waiters.load(Relaxed) > 0 {
+            self.counter.fetch_add(1, Relaxed);
+            wake_all(&self.counter);
+        }
+    }
+
+    /// May wake up spuriously, check the condition again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        // Both are done while still holding the mutex, so a notify that
+        // comes after we unlock it sees us waiting and bumps the counter.
+        self.num_waiters.fetch_add(1, Relaxed);
+        let counter_value = self.counter.load(Relaxed);
+
+        let mutex = guard.mutex;
+        drop(guard);
+        wait(&self.counter, counter_value);
+
+        self.num_waiters.fetch_sub(1, Relaxed);
+        mutex.lock()
+    }
+
+    /// Like `wait`, and also says if it gave up because of the timeout.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        self.num_waiters.fetch_add(1, Relaxed);
+        let counter_value = self.counter.load(Relaxed);
+
+        let mutex = guard.mutex;
+        drop(guard);
+        let woken = wait_timeout(&self.counter, counter_value, timeout);
+
+        self.num_waiters.fetch_sub(1, Relaxed);
+        (mutex.lock(), !woken)
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,169 @@
+//! The queue of `parking::example` in chapter 1, as a type: a thread that
+//! consumes whatever is pushed, parked while there's nothing to do.
+//!
+//! Unlike the example, the queue and whether it's shutting down are behind
+//! one lock, so the consumer can't miss the shutdown between checking the
+//! queue and parking, and what happens to the items still queued is up to
+//! the `DrainPolicy`, not to timing.
+
+use std::{
+    collections::VecDeque,
+    panic::resume_unwind,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle, Thread},
+};
+
+/// What `shutdown` does with the items still queued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrainPolicy {
+    /// The consumer handles all of them first.
+    Drain,
+    /// The consumer stops after the item it's handling, the rest is dropped.
+    Discard,
+}
+
+pub struct ConsumerQueue<T> {
+    state: Arc<Mutex<State<T>>>,
+    consumer: Thread,
+    /// `None` once shut down.
+    handle: Option<JoinHandle<()>>,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    shutdown: Option<DrainPolicy>,
+}
+
+impl<T: Send + 'static> ConsumerQueue<T> {
+    /// Spawns the consumer, which calls `f` on every item, in the order they were pushed.
+    pub fn start<F>(mut f: F) -> Self
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State {
+            items: VecDeque::new(),
+            shutdown: None,
+        }));
+        let shared = state.clone();
+        let handle = thread::spawn(move || {
+            while let Some(item) = next(&shared) {
+                f(item);
+            }
+        });
+        Self {
+            state,
+            consumer: handle.thread().clone(),
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Blocks until there's an item for the consumer, or `None` when it should stop.
+fn next<T>(state: &Mutex<State<T>>) -> Option<T> {
+    loop {
+        let mut s = state.lock().unwrap();
+        match (s.shutdown, s.items.pop_front()) {
+            (Some(DrainPolicy::Discard), item) => {
+                // Put it back, `shutdown` drops them all together.
+                if let Some(item) = item {
+                    s.items.push_front(item);
+                }
+                return None;
+            }
+            (_, Some(item)) => return Some(item),
+            (Some(DrainPolicy::Drain), None) => return None,
+            (None, None) => {}
+        }
+        drop(s);
+        // An unpark that comes before this makes it return right away.
+        thread::park();
+    }
+}
+
+impl<T> ConsumerQueue<T> {
+    pub fn push(&self, item: T) {
+        self.state.lock().unwrap().items.push_back(item);
+        self.consumer.unpark();
+    }
+
+    /// Items pushed but not taken by the consumer yet.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stops the consumer, and returns how many items were discarded.
+    ///
+    /// Panics if `f` panicked.
+    pub fn shutdown(mut self, policy: DrainPolicy) -> usize {
+        match self.stop(policy) {
+            Ok(discarded) => discarded,
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+
+    fn stop(&mut self, policy: DrainPolicy) -> thread::Result<usize> {
+        let Some(handle) = self.handle.take() else {
+            return Ok(0);
+        };
+        self.state.lock().unwrap().shutdown = Some(policy);
+        self.consumer.unpark();
+        let joined = handle.join();
+        // With `Drain` these are only left if `f` panicked.
+        let rest = std::mem::take(&mut self.state.lock().unwrap().items);
+        joined.map(|()| rest.len())
+    }
+}
+
+/// Shuts down with `Drain`, so pushed items aren't lost.
+impl<T> Drop for ConsumerQueue<T> {
+    fn drop(&mut self) {
+        // The consumer's panic, if any, was already reported on stderr.
+        let _ = self.stop(DrainPolicy::Drain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn drain_handles_everything() {
+        let (tx, rx) = mpsc::channel();
+        let queue = ConsumerQueue::start(move |i| tx.send(i).unwrap());
+        for i in 0..100 {
+            queue.push(i);
+        }
+        assert_eq!(queue.shutdown(DrainPolicy::Drain), 0);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn discard_drops_the_rest() {
+        let (tx, rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+        let queue = ConsumerQueue::start(move |i| {
+            tx.send(i).unwrap();
+            // Returns `Err` once `go_tx` is dropped.
+            let _ = go_rx.recv();
+        });
+        for i in 0..10 {
+            queue.push(i);
+        }
+        // The consumer is stuck on the first item until we let it go.
+        assert_eq!(rx.recv().unwrap(), 0);
+        let state = queue.state.clone();
+        let shutdown = thread::spawn(move || queue.shutdown(DrainPolicy::Discard));
+        // Lets the consumer go on only once `shutdown` has set the policy.
+        while state.lock().unwrap().shutdown.is_none() {
+            thread::yield_now();
+        }
+        drop(go_tx);
+        assert_eq!(shutdown.join().unwrap(), 9);
+        assert_eq!(rx.iter().count(), 0);
+    }
+}
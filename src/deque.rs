@@ -0,0 +1,179 @@
+//! A Chase-Lev work-stealing deque, with the orderings from
+//! "Correct and Efficient Work-Stealing for Weak Memory Models" (Lê et al., 2013).
+//!
+//! The owning thread pushes and pops at the bottom, like a stack,
+//! other threads steal from the top, like a queue.
+//!
+//! Two simplifications compared to the paper:
+//! - The buffer has a fixed capacity, so it never has to be swapped out and
+//!   freed while a stealer may still be reading it. `push` fails when full.
+//! - Slots hold `AtomicPtr`s to boxed items. A stealer may read a slot that
+//!   the owner is overwriting at the same time (it then loses the race on `top`
+//!   and throws the value away); with atomics that read is merely stale, not UB.
+
+use crate::cache_padded::CachePadded;
+use std::{
+    marker::PhantomData,
+    ptr,
+    sync::{
+        atomic::{
+            fence, AtomicIsize, AtomicPtr,
+            Ordering::{Acquire, Relaxed, Release, SeqCst},
+        },
+        Arc,
+    },
+};
+
+struct Inner<T> {
+    /// Written by the stealers.
+    top: CachePadded<AtomicIsize>,
+    /// Written by the owner only.
+    bottom: CachePadded<AtomicIsize>,
+    slots: Box<[AtomicPtr<T>]>,
+    // We own the boxed items behind the pointers.
+    _items: PhantomData<Box<T>>,
+}
+
+impl<T> Inner<T> {
+    fn slot(&self, i: isize) -> &AtomicPtr<T> {
+        &self.slots[i as usize % self.slots.len()]
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        for i in *self.top.get_mut()..*self.bottom.get_mut() {
+            drop(unsafe { Box::from_raw(self.slot(i).load(Relaxed)) });
+        }
+    }
+}
+
+/// The owner's end. There's only one, so it's `Send` but not `Sync`.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Send> Send for Worker<T> {}
+
+/// The thieves' end, clone it for every other thread.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+unsafe impl<T: Send> Send for Stealer<T> {}
+unsafe impl<T: Send> Sync for Stealer<T> {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    /// Lost a race with another thread, worth trying again.
+    Retry,
+    Success(T),
+}
+
+pub fn deque<T>(capacity: usize) -> (Worker<T>, Stealer<T>) {
+    assert!(capacity > 0, "capacity must be at least 1");
+    let inner = Arc::new(Inner {
+        top: CachePadded::new(AtomicIsize::new(0)),
+        bottom: CachePadded::new(AtomicIsize::new(0)),
+        slots: (0..capacity)
+            .map(|_| AtomicPtr::new(ptr::null_mut()))
+            .collect(),
+        _items: PhantomData,
+    });
+    (
+        Worker {
+            inner: inner.clone(),
+            _not_sync: PhantomData,
+        },
+        Stealer { inner },
+    )
+}
+
+impl<T> Worker<T> {
+    /// Gives the item back if the deque is full.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let inner = &*self.inner;
+        let b = inner.bottom.load(Relaxed);
+        let t = inner.top.load(Acquire);
+        if b - t >= inner.slots.len() as isize {
+            return Err(item);
+        }
+        inner.slot(b).store(Box::into_raw(Box::new(item)), Relaxed);
+        fence(Release);
+        inner.bottom.store(b + 1, Relaxed);
+        Ok(())
+    }
+
+    /// Takes the most recently pushed item.
+    pub fn pop(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let b = inner.bottom.load(Relaxed) - 1;
+        inner.bottom.store(b, Relaxed);
+        fence(SeqCst);
+        let t = inner.top.load(Relaxed);
+        if t > b {
+            // Empty.
+            inner.bottom.store(b + 1, Relaxed);
+            return None;
+        }
+        let p = inner.slot(b).load(Relaxed);
+        if t == b {
+            // The last item, a stealer may be going for it too.
+            let won = inner
+                .top
+                .compare_exchange(t, t + 1, SeqCst, Relaxed)
+                .is_ok();
+            inner.bottom.store(b + 1, Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        Some(*unsafe { Box::from_raw(p) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.bottom.load(Relaxed) <= self.inner.top.load(Relaxed)
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Stealer<T> {
+    /// Takes the least recently pushed item.
+    pub fn steal(&self) -> Steal<T> {
+        let inner = &*self.inner;
+        let t = inner.top.load(Acquire);
+        fence(SeqCst);
+        let b = inner.bottom.load(Acquire);
+        if t >= b {
+            return Steal::Empty;
+        }
+        let p = inner.slot(t).load(Relaxed);
+        if inner
+            .top
+            .compare_exchange(t, t + 1, SeqCst, Relaxed)
+            .is_err()
+        {
+            return Steal::Retry;
+        }
+        Steal::Success(*unsafe { Box::from_raw(p) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.bottom.load(Acquire) <= self.inner.top.load(Acquire)
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
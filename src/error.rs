@@ -0,0 +1,40 @@
+//! The one error type of the crate, for the calls that can fail without it
+//! being a bug. Each of them also has a panicking version, for the chapters,
+//! where an `unwrap` on every line would bury what the example is about.
+
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The channel or queue is closed, and nothing is left in it.
+    Closed,
+    /// A `pubsub` subscriber was this many values behind, and they were
+    /// dropped. The next `recv` goes on with the oldest one still there.
+    Lagged(u64),
+    /// Gave up waiting.
+    Timeout,
+    /// An id allocator ran out of ids.
+    TooManyIds,
+    /// An `AtomicRefCell` is borrowed in a way that rules this borrow out.
+    Borrowed,
+    /// A `TokenBucket` had no token left.
+    RateLimited,
+}
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "closed, and nothing left in it",
+            Self::Lagged(n) => return write!(f, "lagged behind by {n} values"),
+            Self::Timeout => "timed out",
+            Self::TooManyIds => "too many ids",
+            Self::Borrowed => "already borrowed",
+            Self::RateLimited => "over the rate limit",
+        })
+    }
+}
+
+impl core::error::Error for Error {}
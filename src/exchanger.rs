@@ -0,0 +1,182 @@
+//! A meeting point for two threads to swap values: the first to arrive
+//! leaves its value in a slot and waits, the second takes it out and leaves
+//! its own in return. Elimination stacks are built on these: a push and a
+//! pop that meet cancel out, without touching the stack.
+//!
+//! The slot is a pointer, set with a compare-and-swap from null by the one
+//! who waits, and taken back to null by whoever gets there next: the
+//! partner, or the waiter itself when it gives up. Exactly one of the two
+//! wins, so a value is either exchanged or handed back, never both.
+
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ptr,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicPtr,
+            Ordering::{AcqRel, Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+/// A waiter's offer. One `Arc` count belongs to the slot while it's in it,
+/// and goes to whoever takes it out, so the partner can still unpark the
+/// waiter after handing over its value.
+struct Offer<T> {
+    /// Only the one who takes the offer out of the slot touches these.
+    item: UnsafeCell<Option<T>>,
+    response: UnsafeCell<Option<T>>,
+    matched: AtomicBool,
+    waiter: Thread,
+}
+
+pub struct Exchanger<T> {
+    slot: AtomicPtr<Offer<T>>,
+    // Values go from one thread to another, and nothing more.
+    _values: PhantomData<*mut T>,
+}
+
+unsafe impl<T: Send> Send for Exchanger<T> {}
+unsafe impl<T: Send> Sync for Exchanger<T> {}
+
+impl<T> Exchanger<T> {
+    pub const fn new() -> Self {
+        Self {
+            slot: AtomicPtr::new(ptr::null_mut()),
+            _values: PhantomData,
+        }
+    }
+
+    /// Waits for another thread to exchange with, and returns its value.
+    pub fn exchange(&self, value: T) -> T {
+        self.exchange_until(value, None)
+            .unwrap_or_else(|_| unreachable!("gave up without a deadline"))
+    }
+
+    /// Gives up after `timeout`, and hands `value` back. A `timeout` too
+    /// long to add to now waits forever.
+    pub fn exchange_timeout(&self, value: T, timeout: Duration) -> Result<T, T> {
+        self.exchange_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn exchange_until(&self, value: T, deadline: Option<Instant>) -> Result<T, T> {
+        let mut value = value;
+        loop {
+            let other = self.slot.load(Acquire);
+            if !other.is_null() {
+                match self.take(other, value) {
+                    Ok(v) => return Ok(v),
+                    Err(v) => value = v,
+                }
+                continue;
+            }
+            let offer = Arc::new(Offer {
+                item: UnsafeCell::new(Some(value)),
+                response: UnsafeCell::new(None),
+                matched: AtomicBool::new(false),
+                waiter: thread::current(),
+            });
+            let raw = Arc::into_raw(offer.clone()).cast_mut();
+            if self
+                .slot
+                .compare_exchange(ptr::null_mut(), raw, Release, Relaxed)
+                .is_err()
+            {
+                // Someone got there first: take our value back, and try
+                // exchanging with theirs.
+                // Safety: never published, we're the only ones with it.
+                unsafe { drop(Arc::from_raw(raw)) };
+                value = Arc::into_inner(offer).unwrap().item.into_inner().unwrap();
+                continue;
+            }
+            return self.wait(offer, raw, deadline);
+        }
+    }
+
+    /// Takes `other` out of the slot and swaps `value` for its item, or
+    /// hands `value` back if someone else took it first.
+    fn take(&self, other: *mut Offer<T>, value: T) -> Result<T, T> {
+        if self
+            .slot
+            .compare_exchange(other, ptr::null_mut(), AcqRel, Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        // Safety: taking it out of the slot gave us the slot's count.
+        let other = unsafe { Arc::from_raw(other) };
+        // Safety: the waiter doesn't touch these until `matched`.
+        let item = unsafe {
+            *other.response.get() = Some(value);
+            (*other.item.get()).take().unwrap()
+        };
+        other.matched.store(true, Release);
+        other.waiter.unpark();
+        Ok(item)
+    }
+
+    fn wait(
+        &self,
+        offer: Arc<Offer<T>>,
+        raw: *mut Offer<T>,
+        deadline: Option<Instant>,
+    ) -> Result<T, T> {
+        while !offer.matched.load(Acquire) {
+            match deadline.map(|d| d.saturating_duration_since(Instant::now())) {
+                None => thread::park(),
+                Some(left) if !left.is_zero() => thread::park_timeout(left),
+                Some(_) => {
+                    if self
+                        .slot
+                        .compare_exchange(raw, ptr::null_mut(), Acquire, Relaxed)
+                        .is_ok()
+                    {
+                        // Safety: it's out of the slot, and the slot's count is ours.
+                        unsafe { drop(Arc::from_raw(raw)) };
+                        // Safety: no partner took it, no one else touches it.
+                        return Err(unsafe { (*offer.item.get()).take().unwrap() });
+                    }
+                    // A partner took it just now, and is about to answer.
+                    while !offer.matched.load(Acquire) {
+                        thread::park();
+                    }
+                }
+            }
+        }
+        // Safety: the partner is done with it.
+        Ok(unsafe { (*offer.response.get()).take().unwrap() })
+    }
+}
+
+impl<T> Default for Exchanger<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The classic use: a producer filling one buffer while the consumer empties
+/// the other, swapping them when both are done.
+pub fn main() {
+    let exchanger = Exchanger::new();
+    thread::scope(|s| {
+        s.spawn(|| {
+            let mut buffer = Vec::new();
+            for round in 0..3 {
+                buffer.extend(round * 4..round * 4 + 4);
+                buffer = exchanger.exchange(buffer);
+            }
+        });
+        let mut buffer = Vec::new();
+        for _ in 0..3 {
+            buffer = exchanger.exchange(buffer);
+            println!("consumer got {buffer:?}");
+            buffer.clear();
+        }
+    });
+    let alone = exchanger.exchange_timeout(vec![42], Duration::from_millis(10));
+    println!("with no one to exchange with: {alone:?}");
+}
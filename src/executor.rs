@@ -0,0 +1,257 @@
+//! A tiny executor, to run the async primitives without pulling in a runtime.
+//!
+//! A `Future` is only a state machine: `poll` makes as much progress as it can
+//! and returns `Pending` when it has to wait. Before returning `Pending`, it
+//! stores the `Waker` from the `Context` somewhere the event it waits for can
+//! find it (see the waker lists in `async_channel` and `async_notify`).
+//! Calling `wake()` is the promise "poll me again, something changed".
+//!
+//! So an executor only has to answer one question: what does `wake()` do?
+//! - `block_on`: the waker unparks the thread that's blocked on the future.
+//! - `Executor`: the waker pushes the task back on the run queue.
+
+use crate::mutex_channel::Channel;
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{
+            AtomicBool, AtomicUsize,
+            Ordering::{Acquire, Release},
+        },
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Release);
+        self.thread.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread,
+/// parking it whenever the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        woken: AtomicBool::new(false),
+    });
+    let waker = Waker::from(thread_waker.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // `park` can return spuriously, the flag tells us if we were really woken.
+        while !thread_waker.woken.swap(false, Acquire) {
+            thread::park();
+        }
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Task {
+    future: Mutex<Option<BoxFuture>>,
+    done: AtomicBool,
+    executor: Executor,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        // Waking a finished task is harmless, as is waking one twice:
+        // the worst case is a poll that returns `Pending` again.
+        if !self.done.load(Acquire) {
+            // Fails only once everything is done, so nobody is left to poll us anyway.
+            let _ = self.executor.queue.try_send(self.clone());
+        }
+    }
+}
+
+impl Task {
+    fn poll(self: Arc<Self>) {
+        let mut slot = self.future.lock().unwrap();
+        let Some(future) = slot.as_mut() else { return };
+        let waker = Waker::from(self.clone());
+        if future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready()
+        {
+            *slot = None;
+            self.done.store(true, Release);
+            if self.executor.pending.fetch_sub(1, Release) == 1 {
+                self.executor.queue.close();
+            }
+        }
+    }
+}
+
+/// Runs spawned tasks until all of them are done,
+/// on the calling thread (`run`) or on a few worker threads (`run_on`).
+///
+/// It's a handle: clone it to spawn more tasks from inside a task.
+#[derive(Clone)]
+pub struct Executor {
+    queue: Arc<Channel<Arc<Task>>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            queue: Arc::new(Channel::new()),
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Panics if the executor already ran all its tasks to completion.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let result = Arc::new(Mutex::new(JoinState {
+            output: None,
+            waker: None,
+        }));
+        let r = result.clone();
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(async move {
+                let output = future.await;
+                let mut state = r.lock().unwrap();
+                state.output = Some(output);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }))),
+            done: AtomicBool::new(false),
+            executor: self.clone(),
+        });
+        self.pending.fetch_add(1, Release);
+        if self.queue.try_send(task).is_err() {
+            panic!("can't spawn on an executor that already finished!");
+        }
+        JoinHandle { result }
+    }
+
+    /// Runs tasks on the current thread until every spawned task is done.
+    pub fn run(&self) {
+        if self.pending.load(Acquire) == 0 {
+            return;
+        }
+        while let Some(task) = self.queue.receive() {
+            task.poll();
+        }
+    }
+
+    /// Like `run`, but with `threads` worker threads sharing the run queue.
+    pub fn run_on(&self, threads: usize) {
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| self.run());
+            }
+        });
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a spawned task.
+pub struct JoinHandle<T> {
+    result: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// The output, if the task already finished.
+    pub fn try_take(&self) -> Option<T> {
+        self.result.lock().unwrap().output.take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.result.lock().unwrap();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+pub fn main() {
+    use crate::{
+        async_channel::{self, Watermark},
+        async_notify::Notify,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    // A producer filling a small bounded channel, so it has to wait for the consumer.
+    let executor = Executor::new();
+    let (tx, mut rx) = async_channel::channel(2);
+    // How often the producers got close to having to wait.
+    let full = Arc::new(AtomicUsize::new(0));
+    let f = full.clone();
+    tx.set_watermarks(0, 2, move |mark| {
+        if mark == Watermark::High {
+            f.fetch_add(1, Relaxed);
+        }
+    });
+    for p in 0..3 {
+        let tx = tx.clone();
+        executor.spawn(async move {
+            for i in 0..5 {
+                tx.send(p * 10 + i).await.unwrap();
+            }
+        });
+    }
+    drop(tx);
+    let total = executor.spawn(async move {
+        let mut total = 0;
+        while let Some(v) = rx.recv().await {
+            total += v;
+        }
+        total
+    });
+    executor.run_on(2);
+    println!(
+        "received a total of {}, the channel filled up {} times",
+        total.try_take().unwrap(),
+        full.load(Relaxed),
+    );
+
+    // `block_on` parks this thread until another thread notifies it.
+    let notify = Arc::new(Notify::new());
+    let n = notify.clone();
+    let t = thread::spawn(move || n.notify_one());
+    block_on(notify.notified());
+    t.join().unwrap();
+    println!("notified!");
+}
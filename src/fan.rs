@@ -0,0 +1,131 @@
+//! Wiring channels together: many into one, and one into many, each with
+//! a thread or a few in between that move the messages along.
+//!
+//! The channels are the `Channel` of chapter 5, shared in an `Arc`. Closing
+//! is what ends a forwarding thread: when an input is closed and drained,
+//! its thread closes the outputs it feeds, once no other thread feeds them.
+//! So closing the inputs is all it takes to shut a whole topology down.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering::AcqRel},
+    Arc,
+};
+
+use crate::{mutex_channel::Channel, threads::spawn_named};
+
+/// How `fan_out` hands out messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FanOut {
+    /// Each message to the next output, in turn.
+    RoundRobin,
+    /// A clone of every message to every output.
+    Broadcast,
+}
+
+/// One channel with everything sent to any of `inputs`, in the order it's
+/// received from each. Closed once all of them are.
+pub fn fan_in<T: Send + 'static>(inputs: Vec<Arc<Channel<T>>>) -> Arc<Channel<T>> {
+    let output = Arc::new(Channel::new());
+    if inputs.is_empty() {
+        output.close();
+        return output;
+    }
+    let feeding = Arc::new(AtomicUsize::new(inputs.len()));
+    for (i, input) in inputs.into_iter().enumerate() {
+        let (output, feeding) = (output.clone(), feeding.clone());
+        spawn_named(format!("fan_in-{i}"), move || {
+            while let Some(message) = input.receive() {
+                // Closed from the other end: no one wants the rest.
+                if output.try_send(message).is_err() {
+                    break;
+                }
+            }
+            if feeding.fetch_sub(1, AcqRel) == 1 {
+                output.close();
+            }
+        });
+    }
+    output
+}
+
+/// `n` channels that share what's sent to `input`, the way `strategy`
+/// says. Closed once `input` is. A closed output is skipped from then on.
+pub fn fan_out<T: Clone + Send + 'static>(
+    input: Arc<Channel<T>>,
+    n: usize,
+    strategy: FanOut,
+) -> Vec<Arc<Channel<T>>> {
+    assert!(n > 0, "fan_out needs at least one output");
+    let outputs: Vec<_> = (0..n).map(|_| Arc::new(Channel::new())).collect();
+    let feeding = outputs.clone();
+    spawn_named("fan_out", move || {
+        let mut next = 0;
+        while let Some(message) = input.receive() {
+            match strategy {
+                FanOut::RoundRobin => {
+                    // The first output still open, from `next` on.
+                    let mut message = Some(message);
+                    for _ in 0..n {
+                        let output = &feeding[next];
+                        next = (next + 1) % n;
+                        match output.try_send(message.take().unwrap()) {
+                            Ok(()) => break,
+                            Err(m) => message = Some(m),
+                        }
+                    }
+                    if message.is_some() {
+                        break;
+                    }
+                }
+                FanOut::Broadcast => {
+                    let mut open = 0;
+                    for output in &feeding {
+                        open += usize::from(output.try_send(message.clone()).is_ok());
+                    }
+                    if open == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        for output in &feeding {
+            output.close();
+        }
+    });
+    outputs
+}
+
+pub fn main() {
+    use std::thread;
+
+    // Three producers, fanned into one channel, fanned out to two workers.
+    let inputs: Vec<_> = (0..3).map(|_| Arc::new(Channel::new())).collect();
+    let workers = fan_out(fan_in(inputs.clone()), 2, FanOut::RoundRobin);
+    thread::scope(|s| {
+        for (p, input) in inputs.iter().enumerate() {
+            s.spawn(move || {
+                for i in 0..4 {
+                    input.send(p * 10 + i);
+                }
+                input.close();
+            });
+        }
+        for (w, worker) in workers.iter().enumerate() {
+            s.spawn(move || {
+                let mut got = Vec::new();
+                while let Some(v) = worker.receive() {
+                    got.push(v);
+                }
+                println!("worker {w} got {got:?}");
+            });
+        }
+    });
+
+    let input = Arc::new(Channel::new());
+    let outputs = fan_out(input.clone(), 3, FanOut::Broadcast);
+    input.send("hello");
+    input.close();
+    for (i, output) in outputs.iter().enumerate() {
+        println!("output {i}: {:?}", output.receive());
+    }
+}
@@ -0,0 +1,175 @@
+//! The spin lock, the futex mutex and a byte-sized SPSC ring, for C, so the
+//! same primitives can be run against C11 `<stdatomic.h>` versions of them.
+//! The declarations are in `include/atomics_and_locks.h`, which build.rs
+//! writes from this file with cbindgen: the doc comments here are the
+//! header's.
+//!
+//! Everything is an opaque pointer from a `_new` function, freed by the
+//! matching `_free`. C has no guards, so the locks get a `_lock` and an
+//! `_unlock` instead, and it's up to the caller to pair them.
+//!
+//! Build a library to link against with
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! (it's not in `Cargo.toml`, a `staticlib` would need a panic handler of
+//! its own in `no_std` builds).
+
+use core::{mem, ptr, slice};
+
+use crate::{mutex::Mutex, spin_lock::SpinLock, spsc::Ring};
+
+/// How many bytes an `AalSpsc` holds. `AAL_SPSC_CAPACITY` in C.
+pub const SPSC_CAPACITY: usize = 4096;
+
+pub struct AalSpinLock(SpinLock<()>);
+
+pub struct AalMutex(Mutex<()>);
+
+pub struct AalSpsc(Ring<u8, SPSC_CAPACITY>);
+
+/// Spins until it gets the lock. Unlock from the thread that holds it.
+#[no_mangle]
+pub extern "C" fn aal_spin_lock_new() -> *mut AalSpinLock {
+    Box::into_raw(Box::new(AalSpinLock(SpinLock::new(()))))
+}
+
+/// # Safety
+///
+/// `lock` is from `aal_spin_lock_new`, unlocked, and not used after this.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spin_lock_free(lock: *mut AalSpinLock) {
+    if !lock.is_null() {
+        drop(Box::from_raw(lock));
+    }
+}
+
+/// # Safety
+///
+/// `lock` is from `aal_spin_lock_new`, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spin_lock_lock(lock: *const AalSpinLock) {
+    mem::forget((*lock).0.lock_for_real());
+}
+
+/// # Safety
+///
+/// As for `aal_spin_lock_lock`.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spin_lock_try_lock(lock: *const AalSpinLock) -> bool {
+    (*lock).0.try_lock().map(mem::forget).is_some()
+}
+
+/// # Safety
+///
+/// As for `aal_spin_lock_lock`, and the lock is held, by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spin_lock_unlock(lock: *const AalSpinLock) {
+    (*lock).0.force_unlock();
+}
+
+/// Sleeps in the OS (a futex on Linux) until it gets the lock.
+#[no_mangle]
+pub extern "C" fn aal_mutex_new() -> *mut AalMutex {
+    Box::into_raw(Box::new(AalMutex(Mutex::new(()))))
+}
+
+/// # Safety
+///
+/// `mutex` is from `aal_mutex_new`, unlocked, and not used after this.
+#[no_mangle]
+pub unsafe extern "C" fn aal_mutex_free(mutex: *mut AalMutex) {
+    if !mutex.is_null() {
+        drop(Box::from_raw(mutex));
+    }
+}
+
+/// # Safety
+///
+/// `mutex` is from `aal_mutex_new`, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aal_mutex_lock(mutex: *const AalMutex) {
+    mem::forget((*mutex).0.lock());
+}
+
+/// # Safety
+///
+/// As for `aal_mutex_lock`.
+#[no_mangle]
+pub unsafe extern "C" fn aal_mutex_try_lock(mutex: *const AalMutex) -> bool {
+    (*mutex).0.try_lock().map(mem::forget).is_some()
+}
+
+/// # Safety
+///
+/// As for `aal_mutex_lock`, and the lock is held, by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn aal_mutex_unlock(mutex: *const AalMutex) {
+    (*mutex).0.force_unlock();
+}
+
+/// One thread writes, one thread reads. Neither blocks: they return how
+/// many bytes they wrote or read, which may be fewer than asked for, or none.
+#[no_mangle]
+pub extern "C" fn aal_spsc_new() -> *mut AalSpsc {
+    Box::into_raw(Box::new(AalSpsc(Ring::new())))
+}
+
+/// # Safety
+///
+/// `spsc` is from `aal_spsc_new`, and not used after this.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spsc_free(spsc: *mut AalSpsc) {
+    if !spsc.is_null() {
+        drop(Box::from_raw(spsc));
+    }
+}
+
+/// Writes as much of `buf` as fits, and returns how many bytes that was.
+///
+/// # Safety
+///
+/// `spsc` is from `aal_spsc_new`, and not freed yet. `buf` points to `len`
+/// bytes. Only one thread at a time writes.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spsc_write(spsc: *const AalSpsc, buf: *const u8, len: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let mut producer = (*spsc).0.producer();
+    slice::from_raw_parts(buf, len)
+        .iter()
+        .take_while(|&&b| producer.push(b).is_ok())
+        .count()
+}
+
+/// Reads up to `len` bytes into `buf`, and returns how many there were.
+///
+/// # Safety
+///
+/// `spsc` is from `aal_spsc_new`, and not freed yet. `buf` points to room
+/// for `len` bytes. Only one thread at a time reads.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spsc_read(spsc: *const AalSpsc, buf: *mut u8, len: usize) -> usize {
+    let mut consumer = (*spsc).0.consumer();
+    let mut n = 0;
+    while n < len {
+        let Some(b) = consumer.pop() else { break };
+        ptr::write(buf.add(n), b);
+        n += 1;
+    }
+    n
+}
+
+/// How many bytes are waiting to be read.
+///
+/// # Safety
+///
+/// `spsc` is from `aal_spsc_new`, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aal_spsc_len(spsc: *const AalSpsc) -> usize {
+    // Not through a `Consumer`: the reader may hold the only one.
+    (*spsc).0.len()
+}
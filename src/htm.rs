@@ -0,0 +1,139 @@
+//! Lock elision, as an experiment: running a spin lock's critical section
+//! as a hardware transaction instead of taking the lock.
+//!
+//! The transaction only reads the lock, to see that it's free, which puts
+//! it in the transaction's read set: threads that elide the same lock don't
+//! get in each other's way unless they touch the same data, and a thread
+//! that takes the lock for real aborts them all. An abort throws away what
+//! the transaction did and goes back to where it began, where we wait for
+//! the lock to be free and try again, a few times, before taking it.
+//!
+//! That's RTM, on x86_64, where the CPU has it, which most no longer do,
+//! or have it abort every time. Arm's TME never shipped in a core and LLVM
+//! has dropped it, so everywhere else nothing is elided. Counts of the
+//! commits, aborts and fallbacks are in `metrics`.
+
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+use crate::metrics::{SPIN_LOCK_ABORTS, SPIN_LOCK_ELIDED, SPIN_LOCK_FALLBACKS};
+
+/// Transactions to try before taking the lock.
+const ATTEMPTS: usize = 3;
+
+/// Starts a transaction that sees `locked` free, for the critical section
+/// to run in. `false` if it couldn't, and the lock has to be taken.
+pub(crate) fn elide(locked: &AtomicBool) -> bool {
+    if !imp::available() {
+        return false;
+    }
+    for _ in 0..ATTEMPTS {
+        // Safety: a transaction begun here ends in `commit`, or aborts.
+        match unsafe { imp::begin() } {
+            Ok(()) => {
+                if !locked.load(Relaxed) {
+                    return true;
+                }
+                // Safety: we're in the transaction.
+                unsafe { imp::abort() }
+            }
+            // Out of the transaction again, nested or not.
+            Err(retry) => {
+                SPIN_LOCK_ABORTS.increment();
+                if !retry {
+                    break;
+                }
+                while locked.load(Relaxed) {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+    SPIN_LOCK_FALLBACKS.increment();
+    false
+}
+
+/// Ends a transaction `elide` began.
+///
+/// # Safety
+///
+/// We're in it.
+pub(crate) unsafe fn commit() {
+    unsafe { imp::end() };
+    // Counting one nested in another would put the counter in the outer
+    // one, and have every elided section conflict on it.
+    if !imp::in_transaction() {
+        SPIN_LOCK_ELIDED.increment();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use core::arch::asm;
+
+    /// What `xbegin` leaves in eax when the transaction has begun.
+    const STARTED: u32 = u32::MAX;
+    const EXPLICIT: u32 = 1 << 0;
+    const RETRY: u32 = 1 << 1;
+    /// The code our own aborts carry, for a lock that wasn't free.
+    const LOCKED: u32 = 0xff;
+
+    pub fn available() -> bool {
+        std::arch::is_x86_feature_detected!("rtm")
+    }
+
+    /// `Ok` in the transaction, or `Err` after an abort, with whether it's
+    /// worth trying again. Aborting comes back here, with the registers as
+    /// they were, so to the code around it, it's just another return.
+    #[inline(always)]
+    pub unsafe fn begin() -> Result<(), bool> {
+        let status: u32;
+        unsafe { asm!("xbegin 2f", "2:", inout("eax") STARTED => status, options(nostack)) };
+        if status == STARTED {
+            Ok(())
+        } else {
+            let ours = status & EXPLICIT != 0 && status >> 24 == LOCKED;
+            Err(status & RETRY != 0 || ours)
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn end() {
+        unsafe { asm!("xend", options(nostack)) };
+    }
+
+    #[inline(always)]
+    pub unsafe fn abort() -> ! {
+        unsafe { asm!("xabort 0xff", options(nostack, noreturn)) }
+    }
+
+    #[inline(always)]
+    pub fn in_transaction() -> bool {
+        let inside: u8;
+        // Safety: `xtest` only sets a flag, on a CPU with RTM.
+        unsafe { asm!("xtest", "setnz {}", out(reg_byte) inside, options(nostack, nomem)) };
+        inside != 0
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod imp {
+    pub fn available() -> bool {
+        false
+    }
+
+    pub unsafe fn begin() -> Result<(), bool> {
+        Err(false)
+    }
+
+    pub unsafe fn end() {
+        unreachable!("no transaction to end");
+    }
+
+    pub unsafe fn abort() -> ! {
+        unreachable!("no transaction to abort");
+    }
+
+    pub fn in_transaction() -> bool {
+        false
+    }
+}
@@ -0,0 +1,125 @@
+//! The id allocator of chapter 2, as a type: hands out `0, 1, 2, ...` up to
+//! a maximum, from any thread.
+//!
+//! It checks before incrementing, with a compare-and-swap loop, so the
+//! counter never goes past the maximum, and running out stays an error
+//! instead of wrapping around to ids that are already in use.
+//!
+//! With many threads allocating, that one counter is where they all meet.
+//! An `IdCache` takes a block of ids at a time instead, in one update of the
+//! counter, and hands them out from there with no atomics at all; with `std`,
+//! `try_allocate_local` keeps one for each thread. Ids are then no longer
+//! handed out in order across threads, and the ones still cached when a
+//! cache is dropped, or its thread exits, are never handed out.
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU32, Ordering::Relaxed},
+};
+
+use crate::error::{Error, Result};
+
+pub struct IdAllocator {
+    next: AtomicU32,
+    max: u32,
+}
+
+impl IdAllocator {
+    /// Hands out the ids below `max`.
+    pub const fn new(max: u32) -> Self {
+        Self {
+            next: AtomicU32::new(0),
+            max,
+        }
+    }
+
+    pub fn try_allocate(&self) -> Result<u32> {
+        self.next
+            .fetch_update(Relaxed, Relaxed, |id| (id < self.max).then_some(id + 1))
+            .map_err(|_| Error::TooManyIds)
+    }
+
+    /// Panics once they're all gone.
+    pub fn allocate(&self) -> u32 {
+        match self.try_allocate() {
+            Ok(id) => id,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Up to `n` ids in one go, fewer if there aren't that many left.
+    pub fn try_allocate_block(&self, n: u32) -> Result<Range<u32>> {
+        let len = |id: u32| n.max(1).min(self.max - id);
+        let start = self
+            .next
+            .fetch_update(Relaxed, Relaxed, |id| (id < self.max).then(|| id + len(id)))
+            .map_err(|_| Error::TooManyIds)?;
+        Ok(start..start + len(start))
+    }
+
+    /// A cache that takes `block` ids at a time from this allocator.
+    pub fn cache(&self, block: u32) -> IdCache<'_> {
+        IdCache {
+            allocator: self,
+            ids: 0..0,
+            block,
+        }
+    }
+
+    /// Like `try_allocate`, from a block cached for the calling thread, of
+    /// `LOCAL_BLOCK` ids. For allocators in a `static`, which every thread
+    /// can keep a cache of.
+    #[cfg(feature = "std")]
+    pub fn try_allocate_local(&'static self) -> Result<u32> {
+        use std::{cell::RefCell, vec::Vec};
+
+        std::thread_local! {
+            static CACHES: RefCell<Vec<IdCache<'static>>> = const { RefCell::new(Vec::new()) };
+        }
+        // One cache per allocator this thread has used; there are few.
+        CACHES.with_borrow_mut(|caches| {
+            let i = match caches.iter().position(|c| core::ptr::eq(c.allocator, self)) {
+                Some(i) => i,
+                None => {
+                    caches.push(self.cache(LOCAL_BLOCK));
+                    caches.len() - 1
+                }
+            };
+            caches[i].try_allocate()
+        })
+    }
+
+    /// How many were handed out, counting the ones still in caches.
+    pub fn allocated(&self) -> u32 {
+        self.next.load(Relaxed)
+    }
+}
+
+/// How many ids `try_allocate_local` takes at a time.
+#[cfg(feature = "std")]
+pub const LOCAL_BLOCK: u32 = 64;
+
+/// Ids taken from an `IdAllocator` a block at a time, for one thread to
+/// hand out.
+pub struct IdCache<'a> {
+    allocator: &'a IdAllocator,
+    ids: Range<u32>,
+    block: u32,
+}
+
+impl IdCache<'_> {
+    pub fn try_allocate(&mut self) -> Result<u32> {
+        if self.ids.is_empty() {
+            self.ids = self.allocator.try_allocate_block(self.block)?;
+        }
+        Ok(self.ids.next().unwrap())
+    }
+
+    /// Panics once they're all gone.
+    pub fn allocate(&mut self) -> u32 {
+        match self.try_allocate() {
+            Ok(id) => id,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
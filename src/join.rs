@@ -0,0 +1,179 @@
+//! Spawn a batch of closures and wait for all of them, keeping every panic.
+//!
+//! `t1.join().unwrap(); t2.join().unwrap();` stops at the first panic: `t2`'s
+//! result (or panic) is never looked at. These collect one `Result` per closure.
+//! `spawn_collect` instead hands the results over as they come, in whatever
+//! order the closures finish.
+
+use crate::{cancellation::CancellationToken, mutex_channel::Channel};
+use std::{
+    any::Any,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering::AcqRel},
+        Arc,
+    },
+    thread,
+};
+
+/// What a panicking thread left behind, as returned by `JoinHandle::join`.
+pub type Panic = Box<dyn Any + Send + 'static>;
+
+/// Runs every closure on its own thread and waits for all of them.
+/// Results are in the same order as the closures.
+pub fn join_all<T, F, I>(tasks: I) -> Vec<Result<T, Panic>>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handles: Vec<_> = tasks.into_iter().map(thread::spawn).collect();
+    handles.into_iter().map(|h| h.join()).collect()
+}
+
+/// Like `join_all`, but on scoped threads, so the closures can borrow.
+pub fn join_all_scoped<'env, T, F, I>(tasks: I) -> Vec<Result<T, Panic>>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce() -> T + Send + 'env,
+    T: Send + 'env,
+{
+    thread::scope(|s| {
+        let handles: Vec<_> = tasks.into_iter().map(|f| s.spawn(f)).collect();
+        handles.into_iter().map(|h| h.join()).collect()
+    })
+}
+
+/// Like `join_all_scoped`, but the first panic cancels `token`, which every
+/// closure gets to check so the others can give up early.
+pub fn join_all_or_cancel<'env, T, F, I>(
+    token: &CancellationToken,
+    tasks: I,
+) -> Vec<Result<T, Panic>>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce(&CancellationToken) -> T + Send + 'env,
+    T: Send + 'env,
+{
+    join_all_scoped(tasks.into_iter().map(|f| {
+        move || match catch_unwind(AssertUnwindSafe(|| f(token))) {
+            Ok(result) => result,
+            Err(e) => {
+                token.cancel();
+                resume_unwind(e)
+            }
+        }
+    }))
+}
+
+/// Spawns every closure on `scope`, and sends each result, with the
+/// closure's index, as soon as it's done. The channel is closed once all
+/// of them are, so receiving until `None` gets every result. A closure
+/// that panics sends nothing; `scope` passes the panic on when it ends.
+pub fn spawn_collect<'scope, 'env, T, F, I>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    tasks: I,
+) -> Arc<Channel<(usize, T)>>
+where
+    I: IntoIterator<Item = F>,
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope,
+{
+    /// Closes the channel after the last one, panicking or not.
+    struct Done<T> {
+        results: Arc<Channel<T>>,
+        running: Arc<AtomicUsize>,
+    }
+    impl<T> Drop for Done<T> {
+        fn drop(&mut self) {
+            if self.running.fetch_sub(1, AcqRel) == 1 {
+                self.results.close();
+            }
+        }
+    }
+
+    let results = Arc::new(Channel::new());
+    // One more than are spawned until they all are, so none of them closes
+    // it before the last one is spawned.
+    let running = Arc::new(AtomicUsize::new(1));
+    for (i, f) in tasks.into_iter().enumerate() {
+        running.fetch_add(1, AcqRel);
+        let done = Done {
+            results: results.clone(),
+            running: running.clone(),
+        };
+        scope.spawn(move || {
+            let result = f();
+            done.results.send((i, result));
+        });
+    }
+    drop(Done {
+        results: results.clone(),
+        running,
+    });
+    results
+}
+
+/// The message of a panic, if it was made with a string like `panic!` does.
+pub fn panic_message(panic: &Panic) -> Option<&str> {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+}
+
+pub fn main() {
+    use std::time::Duration;
+
+    // Both failures are reported, not just the first one.
+    let results = join_all((0..4).map(|i| {
+        move || {
+            if i % 2 == 1 {
+                panic!("task {i} failed");
+            }
+            i * 10
+        }
+    }));
+    for (i, result) in results.iter().enumerate() {
+        match result {
+            Ok(v) => println!("task {i}: {v}"),
+            Err(e) => println!("task {i} panicked: {:?}", panic_message(e)),
+        }
+    }
+
+    // Results as they come, the quickest first.
+    thread::scope(|s| {
+        let results = spawn_collect(
+            s,
+            [30, 10, 20].map(|ms| {
+                move || {
+                    thread::sleep(Duration::from_millis(ms));
+                    ms
+                }
+            }),
+        );
+        while let Some((i, ms)) = results.receive() {
+            println!("task {i} done after {ms}ms");
+        }
+    });
+
+    // One failure stops the long-running siblings.
+    let token = CancellationToken::new();
+    type Task = Box<dyn FnOnce(&CancellationToken) -> u32 + Send>;
+    let tasks: Vec<Task> = vec![
+        Box::new(|_| panic!("bad input")),
+        Box::new(|token| {
+            let mut rounds = 0;
+            while !token.wait_timeout(Duration::from_millis(10)) {
+                rounds += 1;
+            }
+            rounds
+        }),
+    ];
+    let results = join_all_or_cancel(&token, tasks);
+    println!(
+        "cancelled: {}, survivor result: {:?}",
+        token.is_cancelled(),
+        results[1].as_ref().ok()
+    );
+}
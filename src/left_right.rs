@@ -0,0 +1,183 @@
+//! Left-right: two copies of the data, one for readers and one for the
+//! writer, swapped after every write. Reads never wait, not even for a
+//! writer, and work on any `T`, not only the ones a seqlock can copy out.
+//! Writes pay for it: each one runs twice, once on each copy, and waits
+//! for the readers still on the old one in between.
+//!
+//! Readers announce themselves on one of two counters before looking at
+//! which copy is theirs, so the writer, after pointing them at the new
+//! copy, knows who could still be on the old one. Which counter is in use
+//! flips too, or a steady stream of readers could keep one from draining.
+//! (Ramalhete and Correia, 2015.)
+//!
+//! The switch and the counters are `SeqCst`: a reader's announcement and
+//! its look at the switch must not be reordered with the writer's flip of
+//! the switch and its look at the counters.
+
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering::{Relaxed, SeqCst},
+    },
+};
+
+use crate::{backoff::spin_until, cache_padded::CachePadded, mutex::Mutex};
+
+pub struct LeftRight<T> {
+    copies: [UnsafeCell<T>; 2],
+    /// Which copy readers use.
+    readers_use: AtomicUsize,
+    /// Which of `readers` new readers count themselves on.
+    version: AtomicUsize,
+    readers: [CachePadded<AtomicUsize>; 2],
+    /// One writer at a time.
+    writer: Mutex<()>,
+}
+
+// Readers share `&T` on any thread, writes move through any thread.
+unsafe impl<T: Send + Sync> Sync for LeftRight<T> {}
+
+impl<T: Clone> LeftRight<T> {
+    pub fn new(value: T) -> Self {
+        Self::from_copies(value.clone(), value)
+    }
+}
+
+impl<T> LeftRight<T> {
+    /// For a `T` that isn't `Clone`: two values that are the same.
+    pub fn from_copies(a: T, b: T) -> Self {
+        Self {
+            copies: [UnsafeCell::new(a), UnsafeCell::new(b)],
+            readers_use: AtomicUsize::new(0),
+            version: AtomicUsize::new(0),
+            readers: [
+                CachePadded::new(AtomicUsize::new(0)),
+                CachePadded::new(AtomicUsize::new(0)),
+            ],
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Runs `f` on the current value. Wait-free: four atomic operations
+    /// around `f`, whatever the writer is doing.
+    ///
+    /// Don't `write` from inside `f`, the writer would wait for `f` to end.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let version = self.version.load(SeqCst);
+        self.readers[version].fetch_add(1, SeqCst);
+        // Decrement even if `f` panics, or the writer waits forever.
+        struct Leave<'a>(&'a AtomicUsize);
+        impl Drop for Leave<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, SeqCst);
+            }
+        }
+        let _leave = Leave(&self.readers[version]);
+        let side = self.readers_use.load(SeqCst);
+        // Safety: the writer doesn't touch this copy until we've left.
+        f(unsafe { &*self.copies[side].get() })
+    }
+
+    /// Applies `op` to both copies, one after the other, and returns what
+    /// it returned the first time. It has to do the same to both: it can't
+    /// depend on anything but the value it's given, and mustn't panic
+    /// halfway, or the copies drift apart.
+    pub fn write<R>(&self, mut op: impl FnMut(&mut T) -> R) -> R {
+        let _writer = self.writer.lock();
+        let side = self.readers_use.load(Relaxed);
+        // Safety: readers are all on the other copy, and we're the only writer.
+        let r = op(unsafe { &mut *self.copies[1 - side].get() });
+        self.readers_use.store(1 - side, SeqCst);
+
+        // Readers that came before that store could still be on `side`.
+        // Wait out the ones on the counter not in use, then send new ones
+        // there, and wait out the rest.
+        let version = self.version.load(Relaxed);
+        spin_until(|| self.readers[1 - version].load(SeqCst) == 0);
+        self.version.store(1 - version, SeqCst);
+        spin_until(|| self.readers[version].load(SeqCst) == 0);
+
+        // Safety: every reader that could have seen `side` is gone, and new
+        // ones go to the other copy.
+        op(unsafe { &mut *self.copies[side].get() });
+        r
+    }
+
+    /// Like `write`, with no readers to wait for. `f` runs on both copies,
+    /// so the same rules apply.
+    pub fn update_both(&mut self, mut f: impl FnMut(&mut T)) {
+        for copy in &mut self.copies {
+            f(copy.get_mut());
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        let [a, b] = self.copies;
+        if self.readers_use.into_inner() == 0 {
+            a.into_inner()
+        } else {
+            b.into_inner()
+        }
+    }
+}
+
+pub fn main() {
+    use std::{collections::HashMap, thread, time::Duration};
+
+    let prices = LeftRight::new(HashMap::from([("apple", 3), ("pear", 4)]));
+    thread::scope(|s| {
+        for name in ["apple", "pear"] {
+            let prices = &prices;
+            s.spawn(move || {
+                for _ in 0..3 {
+                    prices.read(|p| println!("{name}: {}", p[name]));
+                    thread::sleep(Duration::from_millis(10));
+                }
+            });
+        }
+        for price in 5..7 {
+            thread::sleep(Duration::from_millis(15));
+            prices.write(|p| p.insert("apple", price));
+        }
+    });
+    println!("{:?}", prices.into_inner());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LeftRight;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering::Relaxed},
+        thread,
+    };
+
+    #[test]
+    fn read_while_writing() {
+        // Both halves are always written together: a reader that sees them
+        // differ saw a copy mid-write.
+        let pair = LeftRight::new((0u64, 0u64));
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Relaxed) {
+                        let (a, b) = pair.read(|&p| p);
+                        assert_eq!(a, b);
+                        assert!(a >= last, "went back from {last} to {a}");
+                        last = a;
+                    }
+                });
+            }
+            for _ in 0..10_000 {
+                pair.write(|p| {
+                    p.0 += 1;
+                    p.1 += 1;
+                });
+            }
+            done.store(true, Relaxed);
+        });
+        assert_eq!(pair.into_inner(), (10_000, 10_000));
+    }
+}
@@ -0,0 +1,377 @@
+//! The reusable primitives from the chapters, under stable paths: locks and
+//! once/lazy in `sync`, channels in `channel`, atomics and blocking on them
+//! in `atomic`, threads in `thread`, pools in `pool`, async in `task`. The
+//! modules they're implemented in are private and may move around. The
+//! ones most code wants are all in `prelude`, for a single `use`.
+//!
+//! Where the chapters' code went: chapter 1's parked queue is
+//! `thread::ConsumerQueue`, and its queue with a condvar
+//! `channel::BlockingQueue`; chapter 4's spin lock is `sync::SpinLock`;
+//! chapter 5's channels are `channel::Channel`, and the one-shot ones in
+//! `channel::oneshot`, owned (`channel`) or borrowing (`Channel::split`);
+//! chapter 9's mutex and condvar are in `sync`, and what they block with in
+//! `atomic`. The chapters themselves, in the demo binary, keep the steps
+//! that lead up to these.
+//!
+//! Without the default `std` feature it's `#![no_std]`, on nothing but
+//! `core::sync::atomic`, e.g. on a Cortex-M: what's left is `atomic`, most of
+//! `sync`, and the `oneshot` and `spsc` channels, which block by spinning
+//! (with `Backoff`). With `std` they sleep in the OS instead; the
+//! `backend-*` features pick how, see `wait`. With `alloc` but not `std`,
+//! there's the owned one-shot `channel` too, in an `alloc::sync::Arc`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// The wait/notify intrinsics are still unstable; wasm threads need nightly anyway.
+#![cfg_attr(
+    all(target_arch = "wasm32", target_feature = "atomics"),
+    feature(stdarch_wasm_atomic_wait)
+)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+// Everything that takes turns with a compare-and-swap needs 32-bit ones,
+// which e.g. thumbv6m doesn't have.
+#[cfg(target_has_atomic = "32")]
+mod atomic_cell;
+#[cfg(target_has_atomic = "32")]
+mod atomic_flags;
+#[cfg(target_has_atomic = "32")]
+mod atomic_float;
+#[cfg(target_has_atomic = "32")]
+mod atomic_refcell;
+mod atomic_time;
+#[cfg(target_has_atomic = "32")]
+mod atomic_u128;
+mod atomic_u64;
+mod backoff;
+mod cache_padded;
+mod clock;
+mod error;
+#[cfg(target_has_atomic = "32")]
+mod id_allocator;
+#[cfg(target_has_atomic = "32")]
+mod once;
+#[cfg(target_has_atomic = "32")]
+mod oneshot;
+#[cfg(target_has_atomic = "32")]
+mod profile;
+#[cfg(target_has_atomic = "32")]
+mod ring_log;
+#[cfg(target_has_atomic = "32")]
+mod spin_lock;
+mod spsc;
+#[cfg(target_has_atomic = "32")]
+mod trace;
+#[cfg(target_has_atomic = "32")]
+mod wait;
+
+#[cfg(feature = "std")]
+mod actor;
+#[cfg(feature = "std")]
+mod affinity;
+#[cfg(feature = "std")]
+mod append_log;
+#[cfg(feature = "std")]
+mod async_barrier;
+#[cfg(feature = "std")]
+mod async_channel;
+#[cfg(feature = "std")]
+mod async_notify;
+#[cfg(feature = "std")]
+mod atomic_enum;
+#[cfg(feature = "std")]
+mod atomic_ext;
+#[cfg(feature = "std")]
+mod atomic_wait;
+#[cfg(feature = "std")]
+mod background;
+#[cfg(feature = "std")]
+mod barrier;
+#[cfg(feature = "std")]
+mod blocking_queue;
+#[cfg(feature = "std")]
+mod bloom;
+#[cfg(feature = "std")]
+mod broadcast;
+#[cfg(feature = "std")]
+mod cancellation;
+#[cfg(feature = "std")]
+mod condvar;
+#[cfg(feature = "std")]
+mod consumer_queue;
+#[cfg(feature = "std")]
+mod deque;
+#[cfg(feature = "std")]
+mod exchanger;
+#[cfg(feature = "std")]
+mod executor;
+#[cfg(feature = "std")]
+mod fan;
+#[cfg(feature = "htm")]
+mod htm;
+#[cfg(feature = "std")]
+mod join;
+#[cfg(feature = "std")]
+mod left_right;
+#[cfg(feature = "std")]
+mod lifo_queue;
+#[cfg(feature = "std")]
+mod mutex;
+#[cfg(feature = "std")]
+mod mutex_channel;
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+mod per_cpu;
+#[cfg(feature = "std")]
+mod pipe;
+#[cfg(feature = "std")]
+mod pubsub;
+#[cfg(feature = "std")]
+mod rate_limit;
+#[cfg(feature = "std")]
+mod rng;
+#[cfg(feature = "std")]
+mod rwlock;
+#[cfg(feature = "std")]
+mod scope_ext;
+#[cfg(feature = "std")]
+mod semaphore;
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+mod shm_mutex;
+#[cfg(feature = "std")]
+mod sys;
+#[cfg(feature = "std")]
+mod thread_pool;
+#[cfg(feature = "std")]
+mod threads;
+#[cfg(feature = "std")]
+mod timer;
+#[cfg(feature = "std")]
+mod work_stealing_pool;
+
+pub use error::{Error, Result};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod metrics;
+
+/// Atomics `core` doesn't have (everywhere), and blocking on them.
+pub mod atomic {
+    #[cfg(target_has_atomic = "32")]
+    pub use crate::{
+        atomic_cell::{AtomicCell, NoPadding},
+        atomic_flags::AtomicFlags,
+        atomic_float::{AtomicF32, AtomicF64},
+        atomic_u128::AtomicU128,
+    };
+    #[cfg(feature = "std")]
+    pub use crate::{
+        atomic_enum::{AtomicEnum, Transitions},
+        atomic_ext::AtomicExt,
+        atomic_time::AtomicInstant,
+        atomic_wait::{atomic_wait, atomic_wait_timeout, atomic_wait_until, wake_all, wake_one},
+        state_machine,
+    };
+    pub use crate::{atomic_time::AtomicDuration, atomic_u64::AtomicU64};
+
+    /// The same wait/wake, on a table of parked threads instead of the OS,
+    /// which is what `atomic_wait` falls back to where there's no such call.
+    #[cfg(feature = "std")]
+    pub mod parking {
+        pub use crate::sys::parking::{wait, wait_until, wake_all, wake_one};
+    }
+
+    /// The orderings the spin lock and the one-shot channel are compiled
+    /// with, the wrong ones included, to see them go wrong.
+    #[cfg(all(feature = "teaching", target_has_atomic = "32"))]
+    pub mod profile {
+        pub use crate::profile::{Correct, Profile, TooStrong, TooWeak};
+    }
+}
+
+/// Locks, and things that happen once.
+pub mod sync {
+    pub use crate::backoff::{spin_until, Backoff};
+    pub use crate::cache_padded::CachePadded;
+    pub use crate::clock::{LamportClock, VectorClock, VectorTime};
+    #[cfg(target_has_atomic = "32")]
+    pub use crate::{
+        atomic_refcell::{AtomicRef, AtomicRefCell, AtomicRefMut},
+        id_allocator::{IdAllocator, IdCache},
+        once::{Lazy, Once, OnceCell, OnceLite},
+        spin_lock::{Guard as SpinLockGuard, SpinLock},
+        static_lazy,
+    };
+    #[cfg(feature = "std")]
+    pub use crate::{
+        barrier::{Participant as BarrierParticipant, SenseBarrier},
+        bloom::AtomicBloomFilter,
+        cancellation::CancellationToken,
+        condvar::Condvar,
+        exchanger::Exchanger,
+        left_right::LeftRight,
+        mutex::{lock_all, lock_both, Fairness, Mutex, MutexGuard},
+        per_cpu::{PerCpu, PerThread},
+        rate_limit::TokenBucket,
+        rwlock::{
+            Optimistic, ReadGuard as RwLockReadGuard, RwLock, WriteGuard as RwLockWriteGuard,
+        },
+        semaphore::Semaphore,
+    };
+
+    /// A mutex in memory shared between processes.
+    #[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+    pub mod shm {
+        pub use crate::shm_mutex::{LockResult, OwnerDied, ShmGuard, ShmMutex};
+    }
+}
+
+/// Channels: blocking, one-shot, fixed-size, publish/subscribe.
+pub mod channel {
+    #[cfg(feature = "std")]
+    pub use crate::{
+        blocking_queue::{BlockingQueue, IterTimeout},
+        fan::{fan_in, fan_out, FanOut},
+        mutex_channel::Channel,
+        pipe::{pipe, PipeReader, PipeWriter},
+        rate_limit::{Throttle, Throttled},
+    };
+
+    #[cfg(target_has_atomic = "32")]
+    pub mod oneshot {
+        #[cfg(feature = "alloc")]
+        pub use crate::oneshot::{channel, race, OwnedReceiver, OwnedSender};
+        pub use crate::oneshot::{join_all, with_channel, Channel, Receiver, Sender};
+        pub use crate::scoped_channel;
+    }
+
+    pub mod spsc {
+        pub use crate::spsc::{Consumer, Producer, Ring};
+    }
+
+    /// Logs that never block whoever writes to them: a fixed-size ring,
+    /// signal handlers included, and one that only grows.
+    #[cfg(target_has_atomic = "32")]
+    pub mod log {
+        #[cfg(feature = "std")]
+        pub use crate::append_log::{AppendLog, Iter, Snapshot};
+        pub use crate::ring_log::RingLog;
+    }
+
+    /// One producer, every value to every reader, through one fixed ring.
+    #[cfg(feature = "std")]
+    pub mod broadcast {
+        pub use crate::broadcast::{Bus, BusReader};
+    }
+
+    #[cfg(feature = "std")]
+    pub mod pubsub {
+        pub use crate::pubsub::{Bus, IterTimeout, Subscription};
+    }
+}
+
+/// Spawning, naming, pinning and joining threads.
+#[cfg(feature = "std")]
+pub mod thread {
+    pub use crate::background::{BackgroundService, BackgroundWorker};
+    pub use crate::consumer_queue::{ConsumerQueue, DrainPolicy};
+    pub use crate::join::{
+        join_all, join_all_or_cancel, join_all_scoped, panic_message, spawn_collect, Panic,
+    };
+    pub use crate::parallel::{parallel_for_each, parallel_map, parallel_reduce, Split};
+    pub use crate::threads::{current_name, name_of, registered, spawn_named, ThreadBuilder};
+
+    pub mod actor {
+        pub use crate::actor::{spawn, supervise, Actor, Addr};
+    }
+
+    pub mod affinity {
+        pub use crate::affinity::{current_cpu, physical_cores, pin_spread, pin_to_core};
+    }
+
+    /// A random number generator per thread, from one seed for them all.
+    pub mod rng {
+        pub use crate::rng::{below, seed, seed_thread, set_seed, stream, with, SplitMix64};
+    }
+
+    /// `std::thread::scope`, reporting every child's panic, with named
+    /// children and a token cancelled when the scope is done.
+    pub mod scope_ext {
+        pub use crate::scope_ext::{scope, Child, ChildPanic, Scope};
+    }
+}
+
+/// Thread pools.
+#[cfg(feature = "std")]
+pub mod pool {
+    pub use crate::thread_pool::{
+        Scheduling, Scope, Shutdown, Spawner, Supervision, TaskError, TaskHandle, ThreadPool,
+    };
+
+    /// A job queue whose last job is taken first, see `Scheduling::LifoSlot`.
+    pub mod lifo {
+        pub use crate::lifo_queue::{Consumer, LifoQueue};
+    }
+
+    pub mod work_stealing {
+        pub use crate::deque::{deque, Steal, Stealer, Worker};
+        pub use crate::work_stealing_pool::{Spawner, WorkStealingPool};
+    }
+}
+
+/// A small async runtime: an executor, timers, and things to await.
+#[cfg(feature = "std")]
+pub mod task {
+    pub use crate::async_barrier::{Barrier, Wait, WaitResult};
+    pub use crate::async_notify::{Notified, Notify};
+    pub use crate::executor::{block_on, Executor, JoinHandle};
+    pub use crate::timer::{sleep, sleep_until, timeout, Elapsed, Sleep, Timeout};
+
+    /// A bounded channel to await on.
+    pub mod channel {
+        pub use crate::async_channel::{channel, Receiver, Recv, Send, Sender, Watermark};
+    }
+}
+
+/// The locks, and the one-shot channel, owned: `channel()` and its
+/// `Sender` and `Receiver`, which are `oneshot::OwnedSender` and
+/// `OwnedReceiver`. `Channel` is the one-shot one, to borrow halves from;
+/// chapter 5's queue stays `channel::Channel`.
+pub mod prelude {
+    #[cfg(all(feature = "alloc", target_has_atomic = "32"))]
+    pub use crate::oneshot::{channel, OwnedReceiver as Receiver, OwnedSender as Sender};
+    #[cfg(feature = "std")]
+    pub use crate::{
+        condvar::Condvar,
+        mutex::{Mutex, MutexGuard},
+        rwlock::{ReadGuard as RwLockReadGuard, RwLock, WriteGuard as RwLockWriteGuard},
+        semaphore::Semaphore,
+    };
+    #[cfg(target_has_atomic = "32")]
+    pub use crate::{
+        oneshot::Channel,
+        spin_lock::{Guard as SpinLockGuard, SpinLock},
+    };
+}
+
+/// The demo of each module, for the binary to run.
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub mod demos {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub use crate::shm_mutex::main as shm_mutex;
+    pub use crate::{
+        actor::main as actor, affinity::main as affinity, append_log::main as append_log,
+        async_barrier::main as async_barrier, atomic_wait::main as atomic_wait,
+        background::main as background, barrier::main as barrier, bloom::main as bloom,
+        broadcast::main as broadcast, cancellation::main as cancellation, clock::main as clock,
+        exchanger::main as exchanger, executor::main as executor, fan::main as fan,
+        join::main as join, left_right::main as left_right, parallel::main as parallel,
+        per_cpu::main as per_cpu, pipe::main as pipe, pubsub::main as pubsub,
+        rate_limit::main as rate_limit, ring_log::main as ring_log,
+        thread_pool::main as thread_pool, threads::main as threads, timer::main as timer,
+        work_stealing_pool::main as work_stealing_pool,
+    };
+}
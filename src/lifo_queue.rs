@@ -0,0 +1,235 @@
+//! A job queue where the job pushed last skips the line: it waits in a slot
+//! of its own, and the next consumer takes it from there before looking at
+//! the others, queued in order behind a lock.
+//!
+//! That's the LIFO slot of schedulers like Go's and Tokio's. A job that
+//! pushes a follow-up, and is done, leaves the data the follow-up works on
+//! in its core's cache: the worker that ran it, free again right away, most
+//! likely takes the follow-up from the slot, and finds the data still
+//! there. From the back of a queue, it would run after everything queued
+//! before it, and by then the data is long gone. Taking from the slot is a
+//! `swap`, without the lock.
+//!
+//! Pushing swaps the new job into the slot, and the one it pushes out, if
+//! any, goes to the back of the queue. That's meant for one producer, but a
+//! `swap` works from any number of them, and the pool pushes from wherever
+//! jobs are submitted. Jobs that keep pushing follow-ups would keep the
+//! slot busy while the queue waits: a consumer that took from the slot
+//! `LIFO_LIMIT` times in a row takes from the queue first, next time.
+//!
+//! Consumers sleep on a counter bumped by every push, which only wakes one
+//! when some say they're sleeping.
+//!
+//! Pushes take the lock too, to look at `closed` and put the job in as one
+//! step: `close` sets it under the lock, so a push is either in before it,
+//! where a consumer that sees `closed` finds it, or refused.
+
+use std::{
+    collections::VecDeque,
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicU32,
+        Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst},
+    },
+};
+
+use crate::{
+    mutex::Mutex,
+    sys::{wait, wake_all, wake_one},
+};
+
+/// Times in a row a consumer takes from the slot before the queue's turn.
+const LIFO_LIMIT: u32 = 3;
+
+pub struct LifoQueue<T> {
+    /// The job pushed last, boxed, or null.
+    slot: AtomicPtr<T>,
+    queue: Mutex<VecDeque<T>>,
+    /// Bumped by every push, and by `close`.
+    pushed: AtomicU32,
+    sleepers: AtomicU32,
+    closed: AtomicBool,
+    // We own the boxed job behind the slot.
+    _slot: PhantomData<Box<T>>,
+}
+
+unsafe impl<T: Send> Send for LifoQueue<T> {}
+unsafe impl<T: Send> Sync for LifoQueue<T> {}
+
+/// A consumer's end, one for each thread taking from the queue: it counts
+/// its own takes from the slot.
+pub struct Consumer<'a, T> {
+    queue: &'a LifoQueue<T>,
+    streak: u32,
+}
+
+impl<T> LifoQueue<T> {
+    pub const fn new() -> Self {
+        Self {
+            slot: AtomicPtr::new(ptr::null_mut()),
+            queue: Mutex::new(VecDeque::new()),
+            pushed: AtomicU32::new(0),
+            sleepers: AtomicU32::new(0),
+            closed: AtomicBool::new(false),
+            _slot: PhantomData,
+        }
+    }
+
+    /// Puts `item` in the slot, and the one that was there at the back of
+    /// the queue. Panics if the queue is closed.
+    pub fn push(&self, item: T) {
+        if self.try_push(item).is_err() {
+            panic!("push to a closed queue")
+        }
+    }
+
+    /// Like `push`, but gives the item back instead of panicking.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let mut queue = self.queue.lock();
+        if self.closed.load(Relaxed) {
+            return Err(item);
+        }
+        let new = Box::into_raw(Box::new(item));
+        // Releases ours to whoever takes it, and acquires the one before.
+        let old = self.slot.swap(new, AcqRel);
+        if !old.is_null() {
+            // Safety: swapped out, it's ours alone.
+            queue.push_back(unsafe { *Box::from_raw(old) });
+        }
+        drop(queue);
+        // `SeqCst`, with the consumers' count of sleepers: either we see
+        // them sleeping, or they see the new count.
+        self.pushed.fetch_add(1, SeqCst);
+        if self.sleepers.load(SeqCst) > 0 {
+            wake_one(&self.pushed);
+        }
+        Ok(())
+    }
+
+    /// Wakes every consumer, to take what's left and get `None` after that.
+    pub fn close(&self) {
+        // Under the lock, so no push is halfway in.
+        let queue = self.queue.lock();
+        self.closed.store(true, Release);
+        drop(queue);
+        self.pushed.fetch_add(1, SeqCst);
+        wake_all(&self.pushed);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Relaxed)
+    }
+
+    pub fn consumer(&self) -> Consumer<'_, T> {
+        Consumer {
+            queue: self,
+            streak: 0,
+        }
+    }
+
+    fn take_slot(&self) -> Option<T> {
+        // A load first, to leave the cache line alone while it's empty.
+        if self.slot.load(Relaxed).is_null() {
+            return None;
+        }
+        let p = self.slot.swap(ptr::null_mut(), Acquire);
+        // Safety: swapped out, it's ours alone.
+        (!p.is_null()).then(|| unsafe { *Box::from_raw(p) })
+    }
+}
+
+impl<T> Default for LifoQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for LifoQueue<T> {
+    fn drop(&mut self) {
+        let p = *self.slot.get_mut();
+        if !p.is_null() {
+            drop(unsafe { Box::from_raw(p) });
+        }
+    }
+}
+
+impl<T> Consumer<'_, T> {
+    /// The job in the slot, or the one first in the queue, if there's any.
+    pub fn try_receive(&mut self) -> Option<T> {
+        let queue = self.queue;
+        if self.streak < LIFO_LIMIT {
+            if let Some(item) = queue.take_slot() {
+                self.streak += 1;
+                return Some(item);
+            }
+        }
+        self.streak = 0;
+        let first = queue.queue.lock().pop_front();
+        first.or_else(|| queue.take_slot())
+    }
+
+    /// `try_receive`, waiting for a job if there's none. `None` once the
+    /// queue is closed, and empty.
+    pub fn receive(&mut self) -> Option<T> {
+        let queue = self.queue;
+        loop {
+            // Before looking, so a push after we look wakes us.
+            let pushed = queue.pushed.load(SeqCst);
+            let closed = queue.closed.load(Acquire);
+            if let Some(item) = self.try_receive() {
+                return Some(item);
+            }
+            // Seen closed before looking, and every push either got in
+            // before that or was refused, so that was all of it.
+            if closed {
+                return None;
+            }
+            queue.sleepers.fetch_add(1, SeqCst);
+            wait(&queue.pushed, pushed);
+            queue.sleepers.fetch_sub(1, Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LifoQueue;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        thread,
+    };
+
+    #[test]
+    fn push_while_closing() {
+        for _ in 0..200 {
+            let queue = LifoQueue::new();
+            let received = AtomicUsize::new(0);
+            let pushed = thread::scope(|s| {
+                for _ in 0..2 {
+                    s.spawn(|| {
+                        let mut consumer = queue.consumer();
+                        while consumer.receive().is_some() {
+                            received.fetch_add(1, Relaxed);
+                        }
+                    });
+                }
+                let pushers: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut pushed = 0;
+                            while queue.try_push(pushed).is_ok() {
+                                pushed += 1;
+                            }
+                            pushed
+                        })
+                    })
+                    .collect();
+                thread::yield_now();
+                queue.close();
+                pushers.into_iter().map(|p| p.join().unwrap()).sum::<usize>()
+            });
+            assert_eq!(received.into_inner(), pushed);
+        }
+    }
+}
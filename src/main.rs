@@ -1,14 +1,205 @@
+mod bench;
+#[cfg(feature = "chapter1")]
 mod cap_1;
+#[cfg(feature = "chapter2")]
 mod cap_2;
+#[cfg(feature = "chapter3")]
 mod cap_3;
+#[cfg(feature = "chapter4")]
 mod cap_4;
+#[cfg(feature = "chapter5")]
 mod cap_5;
+mod cap_9;
+#[cfg(feature = "chapter1")]
 mod condition_variables;
+#[cfg(feature = "chapter1")]
 mod parking;
+mod runner;
+#[cfg(all(kani, feature = "verification"))]
+mod verification;
+use runner::{Chapter, Demo};
+
+/// The demos of the modules in the library.
+const LIB: &[Demo] = {
+    use atomics_and_locks::demos;
+    &[
+        Demo {
+            name: "actor",
+            about: "a counter actor, and one that's restarted",
+            run: demos::actor,
+        },
+        Demo {
+            name: "affinity",
+            about: "pin threads to physical cores",
+            run: demos::affinity,
+        },
+        Demo {
+            name: "append_log",
+            about: "writers appending to a log while a reader takes snapshots",
+            run: demos::append_log,
+        },
+        Demo {
+            name: "async_barrier",
+            about: "tasks meeting at a barrier",
+            run: demos::async_barrier,
+        },
+        Demo {
+            name: "atomic_wait",
+            about: "a gate built on atomic_wait",
+            run: demos::atomic_wait,
+        },
+        Demo {
+            name: "background",
+            about: "threads logging through a static service, flushed at exit",
+            run: demos::background,
+        },
+        Demo {
+            name: "barrier",
+            about: "threads smoothing an array in steps, meeting at a barrier",
+            run: demos::barrier,
+        },
+        Demo {
+            name: "bloom",
+            about: "workers skipping items another one already handled",
+            run: demos::bloom,
+        },
+        Demo {
+            name: "broadcast",
+            about: "one producer, a quick and a slow reader, through a ring of 4",
+            run: demos::broadcast,
+        },
+        Demo {
+            name: "cancellation",
+            about: "cancel a thread and a task",
+            run: demos::cancellation,
+        },
+        Demo {
+            name: "clock",
+            about: "Lamport and vector clocks across a message",
+            run: demos::clock,
+        },
+        Demo {
+            name: "exchanger",
+            about: "a producer and a consumer swapping buffers",
+            run: demos::exchanger,
+        },
+        Demo {
+            name: "executor",
+            about: "tasks on the executor, with a channel",
+            run: demos::executor,
+        },
+        Demo {
+            name: "fan",
+            about: "producers fanned into one channel, then out to workers",
+            run: demos::fan,
+        },
+        Demo {
+            name: "join",
+            about: "join threads, and cancel the rest on a panic",
+            run: demos::join,
+        },
+        Demo {
+            name: "left_right",
+            about: "wait-free reads of a map while it's written",
+            run: demos::left_right,
+        },
+        Demo {
+            name: "parallel",
+            about: "parallel map and reduce",
+            run: demos::parallel,
+        },
+        Demo {
+            name: "per_cpu",
+            about: "threads counting into one counter, one per core, and one per thread",
+            run: demos::per_cpu,
+        },
+        Demo {
+            name: "pipe",
+            about: "stream lines through a pipe smaller than them",
+            run: demos::pipe,
+        },
+        Demo {
+            name: "pubsub",
+            about: "topics with several subscribers",
+            run: demos::pubsub,
+        },
+        Demo {
+            name: "rate_limit",
+            about: "producers sharing a budget of messages a second",
+            run: demos::rate_limit,
+        },
+        Demo {
+            name: "ring_log",
+            about: "threads and a signal handler logging without println!",
+            run: demos::ring_log,
+        },
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Demo {
+            name: "shm_mutex",
+            about: "recover a lock from a process that died holding it",
+            run: demos::shm_mutex,
+        },
+        Demo {
+            name: "thread_pool",
+            about: "jobs, scopes and shutdown on the thread pool",
+            run: demos::thread_pool,
+        },
+        Demo {
+            name: "threads",
+            about: "named threads in a registry",
+            run: demos::threads,
+        },
+        Demo {
+            name: "timer",
+            about: "sleep and timeout in async code",
+            run: demos::timer,
+        },
+        Demo {
+            name: "work_stealing_pool",
+            about: "the work-stealing pool next to the plain one",
+            run: demos::work_stealing_pool,
+        },
+    ]
+};
+
 fn main() {
-    // cap_1::main();
-    // cap_2::main();
-    // cap_3::main();
-    // cap_4::main();
-    cap_5::main();
+    runner::run(&[
+        #[cfg(feature = "chapter1")]
+        Chapter {
+            name: "cap_1",
+            demos: cap_1::DEMOS,
+        },
+        #[cfg(feature = "chapter2")]
+        Chapter {
+            name: "cap_2",
+            demos: cap_2::DEMOS,
+        },
+        #[cfg(feature = "chapter3")]
+        Chapter {
+            name: "cap_3",
+            demos: cap_3::DEMOS,
+        },
+        #[cfg(feature = "chapter4")]
+        Chapter {
+            name: "cap_4",
+            demos: cap_4::DEMOS,
+        },
+        #[cfg(feature = "chapter5")]
+        Chapter {
+            name: "cap_5",
+            demos: cap_5::DEMOS,
+        },
+        Chapter {
+            name: "cap_9",
+            demos: cap_9::DEMOS,
+        },
+        Chapter {
+            name: "bench",
+            demos: bench::DEMOS,
+        },
+        Chapter {
+            name: "lib",
+            demos: LIB,
+        },
+    ]);
 }
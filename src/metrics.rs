@@ -0,0 +1,708 @@
+//! Counters, gauges and histograms on the crate's own atomics, and the ones
+//! the locks, channels and pools report into, with the `metrics` feature:
+//! contention, queue depths, jobs run. Without the feature the built-in
+//! ones stay at zero, and cost nothing.
+//!
+//! Nothing is exported anywhere by itself. `report` hands every built-in
+//! metric to a `Sink`, to bridge to whatever collects them; `PrometheusText`
+//! is one that writes the Prometheus text format.
+//!
+//! All of it is `Relaxed`: each metric is a number on its own, and a report
+//! taken while other threads update them is a bit behind either way. Except
+//! for a `StatsSet`, whose counters are read together, as of one moment.
+
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::atomic_u64::AtomicU64;
+#[cfg(feature = "std")]
+use std::fmt::Write;
+
+/// Goes up, and only up.
+pub struct Counter {
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Relaxed)
+    }
+
+    pub fn report(&self, sink: &mut dyn Sink) {
+        sink.counter(self.name, self.get());
+    }
+}
+
+/// Goes up and down, like the number of items in a queue.
+pub struct Gauge {
+    name: &'static str,
+    /// An `i64`, wrapping adds work the same on its bits.
+    value: AtomicU64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn set(&self, v: i64) {
+        self.value.store(v as u64, Relaxed);
+    }
+
+    pub fn add(&self, v: i64) {
+        self.value.fetch_add(v as u64, Relaxed);
+    }
+
+    pub fn sub(&self, v: i64) {
+        self.value.fetch_sub(v as u64, Relaxed);
+    }
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn decrement(&self) {
+        self.sub(1);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Relaxed) as i64
+    }
+
+    pub fn report(&self, sink: &mut dyn Sink) {
+        sink.gauge(self.name, self.get());
+    }
+}
+
+/// How many buckets a `Histogram` has: one for 0, and one per power of two.
+pub const BUCKETS: usize = 65;
+
+/// Counts values in power-of-two buckets: bucket `i > 0` holds the values
+/// from `2^(i-1)` up to `2^i - 1`. Coarse, but recording is one `fetch_add`
+/// on a bucket, plus the count and the sum.
+pub struct Histogram {
+    name: &'static str,
+    count: AtomicU64,
+    sum: AtomicU64,
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Histogram {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn record(&self, v: u64) {
+        let bucket = (u64::BITS - v.leading_zeros()) as usize;
+        self.buckets[bucket].fetch_add(1, Relaxed);
+        self.sum.fetch_add(v, Relaxed);
+        self.count.fetch_add(1, Relaxed);
+    }
+
+    /// The counts as of now. Recorded while this runs, a value may be in
+    /// the count but not in its bucket yet, or the other way around.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count.load(Relaxed),
+            sum: self.sum.load(Relaxed),
+            buckets: core::array::from_fn(|i| self.buckets[i].load(Relaxed)),
+        }
+    }
+
+    pub fn report(&self, sink: &mut dyn Sink) {
+        sink.histogram(self.name, &self.snapshot());
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    /// Wraps around, if the values add up to more than `u64::MAX`.
+    pub sum: u64,
+    pub buckets: [u64; BUCKETS],
+}
+
+impl HistogramSnapshot {
+    /// The largest value that goes in bucket `i`.
+    pub fn upper_bound(i: usize) -> u64 {
+        match i {
+            0 => 0,
+            64 => u64::MAX,
+            i => (1 << i) - 1,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use sparse::{SparseHistogram, SparseHistogramSnapshot, SUB_BUCKETS};
+
+/// A histogram fine enough for percentiles, over all of `u64`, that only
+/// allocates the ranges that values actually fall in.
+#[cfg(feature = "std")]
+mod sparse {
+    use super::{HistogramSnapshot, Sink, BUCKETS};
+    use crate::atomic_u64::AtomicU64;
+    use core::{
+        ptr,
+        sync::atomic::{
+            AtomicPtr,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+    };
+
+    /// How many buckets each power of two is split into, for a
+    /// `SparseHistogram`: a value is off by less than 1/128 of it.
+    pub const SUB_BUCKETS: usize = 128;
+    const SUB_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+    /// Values below `SUB_BUCKETS` have a bucket each, in group 0; the ones
+    /// with `SUB_BITS + g` significant bits go in group `g`.
+    const GROUPS: usize = 64 - SUB_BITS as usize + 1;
+
+    type Group = [AtomicU64; SUB_BUCKETS];
+
+    /// Like `Histogram`, with each power of two split into `SUB_BUCKETS`
+    /// linear buckets. That's thousands of them in all, so they come in
+    /// groups of `SUB_BUCKETS`, one per power of two, allocated the first
+    /// time a value falls in it and swapped into place with a
+    /// compare-and-swap. After that, recording is a load of the group's
+    /// pointer and the same `fetch_add`s as `Histogram`: wait-free, unless
+    /// it's the first value in its group.
+    pub struct SparseHistogram {
+        name: &'static str,
+        count: AtomicU64,
+        sum: AtomicU64,
+        groups: [AtomicPtr<Group>; GROUPS],
+    }
+
+    impl SparseHistogram {
+        pub const fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                count: AtomicU64::new(0),
+                sum: AtomicU64::new(0),
+                groups: [const { AtomicPtr::new(ptr::null_mut()) }; GROUPS],
+            }
+        }
+
+        pub fn name(&self) -> &'static str {
+            self.name
+        }
+
+        /// The group and the bucket in it that `v` goes in.
+        fn bucket(v: u64) -> (usize, usize) {
+            let bits = u64::BITS - v.leading_zeros();
+            if bits <= SUB_BITS {
+                (0, v as usize)
+            } else {
+                let group = bits - SUB_BITS;
+                // The `SUB_BITS` bits after the leading one.
+                let i = (v >> (group - 1)) as usize - SUB_BUCKETS;
+                (group as usize, i)
+            }
+        }
+
+        fn group(&self, g: usize) -> &Group {
+            let p = self.groups[g].load(Acquire);
+            if !p.is_null() {
+                // Safety: once in place, a group stays until `drop`.
+                return unsafe { &*p };
+            }
+            let new = Box::into_raw(Box::new([const { AtomicU64::new(0) }; SUB_BUCKETS]));
+            match self.groups[g].compare_exchange(ptr::null_mut(), new, Release, Acquire) {
+                // Safety: as above.
+                Ok(_) => unsafe { &*new },
+                Err(p) => {
+                    // Someone else's got there first. Ours was never shared.
+                    drop(unsafe { Box::from_raw(new) });
+                    unsafe { &*p }
+                }
+            }
+        }
+
+        pub fn record(&self, v: u64) {
+            let (g, i) = Self::bucket(v);
+            self.group(g)[i].fetch_add(1, Relaxed);
+            self.sum.fetch_add(v, Relaxed);
+            self.count.fetch_add(1, Relaxed);
+        }
+
+        /// How many groups have been allocated so far.
+        pub fn allocated_groups(&self) -> usize {
+            self.groups
+                .iter()
+                .filter(|g| !g.load(Relaxed).is_null())
+                .count()
+        }
+
+        /// The buckets that have values in them, as of now. The same goes
+        /// as for `Histogram::snapshot`, about values recorded meanwhile.
+        pub fn snapshot(&self) -> SparseHistogramSnapshot {
+            let mut buckets = Vec::new();
+            for (g, group) in self.groups.iter().enumerate() {
+                let p = group.load(Acquire);
+                if p.is_null() {
+                    continue;
+                }
+                // Safety: see `group`.
+                for (i, n) in unsafe { &*p }.iter().enumerate() {
+                    let n = n.load(Relaxed);
+                    if n > 0 {
+                        buckets.push((upper_bound(g, i), n));
+                    }
+                }
+            }
+            SparseHistogramSnapshot {
+                count: self.count.load(Relaxed),
+                sum: self.sum.load(Relaxed),
+                buckets,
+            }
+        }
+
+        pub fn report(&self, sink: &mut dyn Sink) {
+            sink.sparse_histogram(self.name, &self.snapshot());
+        }
+    }
+
+    impl Drop for SparseHistogram {
+        fn drop(&mut self) {
+            for group in &mut self.groups {
+                let p = *group.get_mut();
+                if !p.is_null() {
+                    // Safety: we have the only reference left.
+                    drop(unsafe { Box::from_raw(p) });
+                }
+            }
+        }
+    }
+
+    /// The largest value that goes in bucket `i` of group `g`.
+    fn upper_bound(g: usize, i: usize) -> u64 {
+        if g == 0 {
+            return i as u64;
+        }
+        let lowest = ((SUB_BUCKETS + i) as u64) << (g - 1);
+        lowest + ((1 << (g - 1)) - 1)
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct SparseHistogramSnapshot {
+        pub count: u64,
+        /// Wraps around, like `HistogramSnapshot::sum`.
+        pub sum: u64,
+        /// The largest value of every bucket that isn't empty, and how many
+        /// values it has, smallest values first.
+        pub buckets: Vec<(u64, u64)>,
+    }
+
+    impl SparseHistogramSnapshot {
+        /// The value that a fraction `q` of the values are at most, e.g.
+        /// `0.99` for the 99th percentile, rounded up to its bucket's
+        /// largest value. 0 with no values at all.
+        pub fn quantile(&self, q: f64) -> u64 {
+            let total: u64 = self.buckets.iter().map(|&(_, n)| n).sum();
+            let rank = (q.clamp(0.0, 1.0) * total as f64).ceil().max(1.0) as u64;
+            let mut seen = 0;
+            for &(upper, n) in &self.buckets {
+                seen += n;
+                if seen >= rank {
+                    return upper;
+                }
+            }
+            0
+        }
+
+        /// The same counts in `Histogram`'s power-of-two buckets.
+        pub fn coarse(&self) -> HistogramSnapshot {
+            let mut buckets = [0; BUCKETS];
+            for &(upper, n) in &self.buckets {
+                buckets[(u64::BITS - upper.leading_zeros()) as usize] += n;
+            }
+            HistogramSnapshot {
+                count: self.count,
+                sum: self.sum,
+                buckets,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use stats::{StatsSet, StatsSnapshot, Updates};
+
+/// Counters that are read together, for numbers that only make sense
+/// together, like items done and the time they took.
+#[cfg(feature = "std")]
+mod stats {
+    use super::Sink;
+    use crate::{atomic_u64::AtomicU64, backoff::spin_until, cache_padded::CachePadded};
+    use core::{
+        array,
+        sync::atomic::{
+            AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release, SeqCst},
+        },
+    };
+    use std::sync::Mutex;
+
+    /// `N` named counters, updated by any number of threads, one or several
+    /// at once, and read all at once by a `snapshot` that's as of one
+    /// moment: every update from before it is in, and none from after.
+    ///
+    /// There are two banks of counters, and `epoch` says which one updates
+    /// go to. A snapshot flips it to the other bank, waits for the updates
+    /// that had started on the old one, and then has that one to itself:
+    /// it moves what's in it into the totals, and leaves it at zero for the
+    /// next flip. An update is a few more atomics than a `Counter`: it
+    /// counts itself in on its bank, and out again.
+    pub struct StatsSet<const N: usize> {
+        names: [&'static str; N],
+        epoch: AtomicUsize,
+        banks: [[AtomicU64; N]; 2],
+        /// Updates going on, per bank.
+        updating: [CachePadded<AtomicUsize>; 2],
+        /// Every bank a snapshot has flipped away from, added up. Also
+        /// lets only one snapshot flip at a time.
+        totals: Mutex<[u64; N]>,
+    }
+
+    impl<const N: usize> StatsSet<N> {
+        pub const fn new(names: [&'static str; N]) -> Self {
+            Self {
+                names,
+                epoch: AtomicUsize::new(0),
+                banks: [const { [const { AtomicU64::new(0) }; N] }; 2],
+                updating: [const { CachePadded::new(AtomicUsize::new(0)) }; 2],
+                totals: Mutex::new([0; N]),
+            }
+        }
+
+        pub fn names(&self) -> &[&'static str; N] {
+            &self.names
+        }
+
+        /// Adds `n` to counter `i`.
+        pub fn add(&self, i: usize, n: u64) {
+            self.update(|u| u.add(i, n));
+        }
+
+        /// Makes the updates `f` makes as one: a snapshot has all of them
+        /// or none.
+        pub fn update<R>(&self, f: impl FnOnce(&Updates<'_, N>) -> R) -> R {
+            let mut epoch = self.epoch.load(Relaxed);
+            loop {
+                let bank = epoch & 1;
+                // `SeqCst`, with the flip and the snapshot's look at
+                // `updating`: either it sees us, or we see the flip.
+                self.updating[bank].fetch_add(1, SeqCst);
+                let now = self.epoch.load(SeqCst);
+                if now == epoch {
+                    let result = f(&Updates {
+                        bank: &self.banks[bank],
+                    });
+                    // Releases the updates to the snapshot that waits for us.
+                    self.updating[bank].fetch_sub(1, Release);
+                    return result;
+                }
+                self.updating[bank].fetch_sub(1, Release);
+                epoch = now;
+            }
+        }
+
+        /// Every counter, as of now.
+        pub fn snapshot(&self) -> StatsSnapshot<N> {
+            let mut totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+            let old = self.epoch.fetch_add(1, SeqCst) & 1;
+            spin_until(|| self.updating[old].load(Acquire) == 0);
+            for (total, counter) in totals.iter_mut().zip(&self.banks[old]) {
+                *total += counter.swap(0, Relaxed);
+            }
+            StatsSnapshot {
+                names: self.names,
+                values: *totals,
+            }
+        }
+
+        /// Hands a snapshot to `sink`, as counters.
+        pub fn report(&self, sink: &mut dyn Sink) {
+            self.snapshot().report(sink);
+        }
+    }
+
+    /// The counters of a `StatsSet`, for one `update`.
+    pub struct Updates<'a, const N: usize> {
+        bank: &'a [AtomicU64; N],
+    }
+
+    impl<const N: usize> Updates<'_, N> {
+        pub fn add(&self, i: usize, n: u64) {
+            self.bank[i].fetch_add(n, Relaxed);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct StatsSnapshot<const N: usize> {
+        pub names: [&'static str; N],
+        pub values: [u64; N],
+    }
+
+    impl<const N: usize> StatsSnapshot<N> {
+        pub fn get(&self, name: &str) -> Option<u64> {
+            let i = self.names.iter().position(|&n| n == name)?;
+            Some(self.values[i])
+        }
+
+        /// Each counter's name and value.
+        pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+            self.names.iter().copied().zip(self.values)
+        }
+
+        /// How much each counter went up since `earlier`, a snapshot of the
+        /// same set.
+        pub fn since(&self, earlier: &Self) -> Self {
+            Self {
+                names: self.names,
+                values: array::from_fn(|i| self.values[i] - earlier.values[i]),
+            }
+        }
+
+        pub fn report(&self, sink: &mut dyn Sink) {
+            for (name, value) in self.iter() {
+                sink.counter(name, value);
+            }
+        }
+    }
+}
+
+/// Where `report` sends the metrics.
+pub trait Sink {
+    fn counter(&mut self, name: &str, value: u64);
+    fn gauge(&mut self, name: &str, value: i64);
+    fn histogram(&mut self, name: &str, histogram: &HistogramSnapshot);
+
+    /// Falls back to the power-of-two buckets, for sinks that only know those.
+    #[cfg(feature = "std")]
+    fn sparse_histogram(&mut self, name: &str, histogram: &SparseHistogramSnapshot) {
+        self.histogram(name, &histogram.coarse());
+    }
+}
+
+/// Spin lock calls that found it locked.
+pub static SPIN_LOCK_CONTENDED: Counter = Counter::new("spin_lock.contended");
+/// Spin lock critical sections run as hardware transactions, transactions
+/// that aborted, and locks taken after all, with the `htm` feature.
+#[cfg(feature = "htm")]
+pub static SPIN_LOCK_ELIDED: Counter = Counter::new("spin_lock.elided");
+#[cfg(feature = "htm")]
+pub static SPIN_LOCK_ABORTS: Counter = Counter::new("spin_lock.aborts");
+#[cfg(feature = "htm")]
+pub static SPIN_LOCK_FALLBACKS: Counter = Counter::new("spin_lock.fallbacks");
+/// `Mutex` calls that found it locked.
+pub static MUTEX_CONTENDED: Counter = Counter::new("mutex.contended");
+/// How long those waited, in nanoseconds.
+pub static MUTEX_WAIT_NS: Histogram = Histogram::new("mutex.wait_ns");
+/// Messages through every `channel::Channel`, and how many are in them.
+pub static CHANNEL_SENT: Counter = Counter::new("channel.sent");
+pub static CHANNEL_RECEIVED: Counter = Counter::new("channel.received");
+pub static CHANNEL_DEPTH: Gauge = Gauge::new("channel.depth");
+/// Items through every `BlockingQueue`, and how many are in them.
+pub static QUEUE_PUSHED: Counter = Counter::new("queue.pushed");
+pub static QUEUE_POPPED: Counter = Counter::new("queue.popped");
+pub static QUEUE_DEPTH: Gauge = Gauge::new("queue.depth");
+/// Jobs the `ThreadPool` workers ran, and how many of them panicked.
+pub static POOL_JOBS: Counter = Counter::new("pool.jobs");
+pub static POOL_PANICS: Counter = Counter::new("pool.panics");
+
+/// Hands every built-in metric to `sink`.
+pub fn report(sink: &mut dyn Sink) {
+    for counter in [
+        &SPIN_LOCK_CONTENDED,
+        &MUTEX_CONTENDED,
+        &CHANNEL_SENT,
+        &CHANNEL_RECEIVED,
+        &QUEUE_PUSHED,
+        &QUEUE_POPPED,
+        &POOL_JOBS,
+        &POOL_PANICS,
+    ] {
+        counter.report(sink);
+    }
+    #[cfg(feature = "htm")]
+    for counter in [&SPIN_LOCK_ELIDED, &SPIN_LOCK_ABORTS, &SPIN_LOCK_FALLBACKS] {
+        counter.report(sink);
+    }
+    CHANNEL_DEPTH.report(sink);
+    QUEUE_DEPTH.report(sink);
+    MUTEX_WAIT_NS.report(sink);
+}
+
+/// The Prometheus text format, with names like `atomics_and_locks_mutex_contended`.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct PrometheusText {
+    out: String,
+}
+
+#[cfg(feature = "std")]
+impl PrometheusText {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_string(self) -> String {
+        self.out
+    }
+
+    fn header(&mut self, name: &str, kind: &str) -> String {
+        let name = format!("atomics_and_locks_{}", name.replace('.', "_"));
+        writeln!(self.out, "# TYPE {name} {kind}").unwrap();
+        name
+    }
+}
+
+#[cfg(feature = "std")]
+impl Sink for PrometheusText {
+    fn counter(&mut self, name: &str, value: u64) {
+        let name = self.header(name, "counter");
+        writeln!(self.out, "{name} {value}").unwrap();
+    }
+
+    fn gauge(&mut self, name: &str, value: i64) {
+        let name = self.header(name, "gauge");
+        writeln!(self.out, "{name} {value}").unwrap();
+    }
+
+    fn histogram(&mut self, name: &str, histogram: &HistogramSnapshot) {
+        let name = self.header(name, "histogram");
+        // Prometheus buckets count everything up to their bound.
+        let mut total = 0;
+        for (i, n) in histogram.buckets.iter().enumerate() {
+            total += n;
+            if *n > 0 {
+                let le = HistogramSnapshot::upper_bound(i);
+                writeln!(self.out, "{name}_bucket{{le=\"{le}\"}} {total}").unwrap();
+            }
+        }
+        writeln!(self.out, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count).unwrap();
+        writeln!(self.out, "{name}_sum {}", histogram.sum).unwrap();
+        writeln!(self.out, "{name}_count {}", histogram.count).unwrap();
+    }
+
+    fn sparse_histogram(&mut self, name: &str, histogram: &SparseHistogramSnapshot) {
+        let name = self.header(name, "histogram");
+        let mut total = 0;
+        for (le, n) in &histogram.buckets {
+            total += n;
+            writeln!(self.out, "{name}_bucket{{le=\"{le}\"}} {total}").unwrap();
+        }
+        writeln!(self.out, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count).unwrap();
+        writeln!(self.out, "{name}_sum {}", histogram.sum).unwrap();
+        writeln!(self.out, "{name}_count {}", histogram.count).unwrap();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets() {
+        let h = Histogram::new("test");
+        for v in [0, 1, 2, 3, 4, 1000, u64::MAX] {
+            h.record(v);
+        }
+        let s = h.snapshot();
+        assert_eq!(s.count, 7);
+        assert_eq!(&s.buckets[..4], &[1, 1, 2, 1]);
+        assert_eq!(s.buckets[10], 1);
+        assert_eq!(s.buckets[64], 1);
+        assert_eq!(HistogramSnapshot::upper_bound(10), 1023);
+    }
+
+    #[test]
+    fn sparse_histogram_buckets() {
+        let h = SparseHistogram::new("test");
+        for v in [0, 1, 127, 128, 129, 1000, 1001, u64::MAX] {
+            h.record(v);
+        }
+        assert_eq!(h.allocated_groups(), 4);
+        let s = h.snapshot();
+        assert_eq!(
+            s.buckets,
+            [
+                (0, 1),
+                (1, 1),
+                (127, 1),
+                (128, 1),
+                (129, 1),
+                (1003, 2),
+                (u64::MAX, 1)
+            ]
+        );
+        assert_eq!(s.quantile(0.5), 128);
+        assert_eq!(s.quantile(1.0), u64::MAX);
+        assert_eq!(s.coarse().buckets[10], 2);
+        assert_eq!(s.coarse().buckets[64], 1);
+    }
+
+    #[test]
+    fn prometheus_text() {
+        let c = Counter::new("test.count");
+        c.add(3);
+        let g = Gauge::new("test.depth");
+        g.decrement();
+        let h = Histogram::new("test.ns");
+        h.record(5);
+        h.record(6);
+        let mut text = PrometheusText::new();
+        c.report(&mut text);
+        g.report(&mut text);
+        h.report(&mut text);
+        assert_eq!(
+            text.into_string(),
+            "# TYPE atomics_and_locks_test_count counter\n\
+             atomics_and_locks_test_count 3\n\
+             # TYPE atomics_and_locks_test_depth gauge\n\
+             atomics_and_locks_test_depth -1\n\
+             # TYPE atomics_and_locks_test_ns histogram\n\
+             atomics_and_locks_test_ns_bucket{le=\"7\"} 2\n\
+             atomics_and_locks_test_ns_bucket{le=\"+Inf\"} 2\n\
+             atomics_and_locks_test_ns_sum 11\n\
+             atomics_and_locks_test_ns_count 2\n"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! The futex-based mutex of chapter 9.
+//!
+//! Unlocking just lets go: whoever gets there first takes it next, which is
+//! usually a thread that's already running rather than the one that was
+//! woken. That's fastest, but a thread that keeps coming back for the lock
+//! can keep a sleeper from ever getting it. With `Fairness::Eventual`, a
+//! waiter that's waited too long says so, and from then on unlocking hands
+//! the lock straight to it, without letting go in between.
+
+use crate::sys::{wait, wake_all, wake_one};
+use crate::trace;
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{
+    AtomicU32,
+    Ordering::{Acquire, Relaxed, Release},
+};
+use std::time::{Duration, Instant};
+
+/// Who gets a `Mutex` when it's unlocked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fairness {
+    /// Whoever takes it first.
+    Barging,
+    /// Whoever takes it first, unless a thread has waited this long: then
+    /// that thread, and the next one unlocking hands it to the next such
+    /// thread, until none are left. A millisecond or so keeps barging from
+    /// costing much, and starving anyone for long.
+    Eventual(Duration),
+}
+
+/// The state of a lock handed to a thread that's waited too long.
+const HANDOFF: u32 = 3;
+
+pub struct Mutex<T> {
+    /// 0: unlocked
+    /// 1: locked, no other threads waiting
+    /// 2: locked, other threads (maybe) waiting
+    /// 3: unlocked, for one of the threads that waited too long
+    state: AtomicU32,
+    fairness: Fairness,
+    /// Threads that waited longer than `Fairness::Eventual` allows, and
+    /// are still waiting.
+    starving: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self::with_fairness(value, Fairness::Barging)
+    }
+
+    pub const fn with_fairness(value: T, fairness: Fairness) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            fairness,
+            starving: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Unlocks without a guard, for `ffi`, where a lock and its unlock are two calls.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held, by a guard that was forgotten.
+    pub(crate) unsafe fn force_unlock(&self) {
+        trace::event!(lock = ?core::ptr::from_ref(self), "unlocked");
+        // Whoever's starving hasn't got the lock since it said so, and we
+        // have, so this sees it.
+        if self.starving.load(Relaxed) > 0 {
+            trace::event!(lock = ?core::ptr::from_ref(self), "handed off");
+            // We can't pick which thread wakes, so all of them do, and all
+            // but the starving ones go back to sleep.
+            self.state.store(HANDOFF, Release);
+            wake_all(&self.state);
+        } else if self.state.swap(0, Release) == 2 {
+            wake_one(&self.state);
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        if self.state.compare_exchange(0, 1, Acquire, Relaxed).is_err() {
+            // Out of line, so the uncontended path stays small enough to inline.
+            #[cfg(feature = "metrics")]
+            let contended = std::time::Instant::now();
+            self.lock_contended();
+            #[cfg(feature = "metrics")]
+            {
+                crate::metrics::MUTEX_CONTENDED.increment();
+                crate::metrics::MUTEX_WAIT_NS.record(contended.elapsed().as_nanos() as u64);
+            }
+        }
+        trace::event!(lock = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "locked");
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, 1, Acquire, Relaxed)
+            .ok()
+            .map(|_| {
+                trace::event!(lock = ?core::ptr::from_ref(self), "locked");
+                MutexGuard { mutex: self }
+            })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Locks both, in the same order whatever the order of the arguments: the
+/// one at the lower address first. Two threads doing `lock_both(a, b)` and
+/// `lock_both(b, a)` can't each end up holding one and waiting for the other.
+///
+/// That only holds if every thread that takes both does it this way.
+pub fn lock_both<'a, A, B>(
+    a: &'a Mutex<A>,
+    b: &'a Mutex<B>,
+) -> (MutexGuard<'a, A>, MutexGuard<'a, B>) {
+    let (pa, pb) = (address(a), address(b));
+    assert_ne!(pa, pb, "lock_both on the same mutex twice would deadlock");
+    if pa < pb {
+        let a = a.lock();
+        (a, b.lock())
+    } else {
+        let b = b.lock();
+        (a.lock(), b)
+    }
+}
+
+/// `lock_both`, for any number of them. The guards are in the order of
+/// `mutexes`, whatever order they were locked in.
+pub fn lock_all<'a, T>(mutexes: &[&'a Mutex<T>]) -> Vec<MutexGuard<'a, T>> {
+    let mut order: Vec<usize> = (0..mutexes.len()).collect();
+    order.sort_by_key(|&i| address(mutexes[i]));
+    assert!(
+        order
+            .windows(2)
+            .all(|w| address(mutexes[w[0]]) != address(mutexes[w[1]])),
+        "lock_all on the same mutex twice would deadlock"
+    );
+    let mut guards: Vec<Option<MutexGuard<'a, T>>> = mutexes.iter().map(|_| None).collect();
+    for i in order {
+        guards[i] = Some(mutexes[i].lock());
+    }
+    guards.into_iter().map(Option::unwrap).collect()
+}
+
+fn address<T>(m: &Mutex<T>) -> usize {
+    core::ptr::from_ref(m) as usize
+}
+
+impl<T> Mutex<T> {
+    #[cold]
+    fn lock_contended(&self) {
+        let state = &self.state;
+        // Spin a little first: the lock is often held only for a moment.
+        // Not while there are waiters though, we'd only be cutting in line.
+        let mut spin_count = 0;
+        while state.load(Relaxed) == 1 && spin_count < 100 {
+            spin_count += 1;
+            std::hint::spin_loop();
+        }
+        if state.compare_exchange(0, 1, Acquire, Relaxed).is_ok() {
+            return;
+        }
+        let Fairness::Eventual(limit) = self.fairness else {
+            // Marking the state 2 makes whoever unlocks wake us up.
+            while state.swap(2, Acquire) != 0 {
+                wait(state, 2);
+            }
+            return;
+        };
+
+        // The same, but a swap would take a lock that's handed to someone
+        // else, so it's one state at a time.
+        let start = Instant::now();
+        let mut starving = false;
+        loop {
+            match state.load(Relaxed) {
+                0 => {
+                    if state.compare_exchange(0, 2, Acquire, Relaxed).is_ok() {
+                        break;
+                    }
+                }
+                HANDOFF if starving => {
+                    if state.compare_exchange(HANDOFF, 2, Acquire, Relaxed).is_ok() {
+                        break;
+                    }
+                }
+                HANDOFF => wait(state, HANDOFF),
+                s => {
+                    if s == 2 || state.compare_exchange(1, 2, Relaxed, Relaxed).is_ok() {
+                        wait(state, 2);
+                    }
+                }
+            }
+            if !starving && start.elapsed() >= limit {
+                starving = true;
+                self.starving.fetch_add(1, Relaxed);
+            }
+        }
+        if starving {
+            self.starving.fetch_sub(1, Relaxed);
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    pub(crate) mutex: &'a Mutex<T>,
+}
+
+// As for the spin lock's guard: a `&Mutex<T>` alone would make it `Sync`
+// whenever `T: Send`.
+unsafe impl<T> Sync for MutexGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Safety: we hold the lock, and nothing uses it after this.
+        unsafe { self.mutex.force_unlock() }
+    }
+}
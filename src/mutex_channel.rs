@@ -0,0 +1,291 @@
+//! The channel of chapter 5: a queue behind a `Mutex`, with a `Condvar`
+//! to wait for messages. Any number of senders and receivers.
+//!
+//! `send` doesn't take the lock: it pushes onto `incoming`, a stack of
+//! messages on an `AtomicPtr`, which receivers take all at once, under the
+//! lock, turned around and appended to the queue. Receivers count
+//! themselves in before they wait on the `Condvar`, so a sender that finds
+//! nobody waiting skips both the lock and the notify, which with some
+//! `Condvar`s is a call into the kernel even with no one to wake. The count
+//! and the stack are both `SeqCst`: a receiver counts itself in, then looks
+//! at the stack one last time, and a sender pushes, then looks at the count,
+//! so either the receiver sees the message, or the sender sees the receiver
+//! and wakes it. It takes the lock for that, which the receiver holds until
+//! it's waiting.
+//!
+//! Closing swaps a marker in for the stack, `closed()`, so a send can't get
+//! in after it.
+//!
+//! `send_urgent` is for control messages, like a shutdown or a flush, that
+//! shouldn't wait behind a backlog: they go in a second queue, under the
+//! lock, which receivers empty first, in the order they were sent.
+
+use std::{
+    collections::VecDeque,
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{
+            AtomicPtr, AtomicUsize,
+            Ordering::{Acquire, Relaxed, SeqCst},
+        },
+        Condvar, Mutex,
+    },
+};
+
+use crate::trace;
+
+pub struct Channel<T> {
+    state: Mutex<State<T>>,
+    item_ready: Condvar,
+    /// Messages sent since a receiver last looked, newest first, or
+    /// `closed()`.
+    incoming: AtomicPtr<Node<T>>,
+    /// Receivers waiting on `item_ready`.
+    waiting: AtomicUsize,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    /// From `send_urgent`, received before anything in `queue`.
+    urgent: VecDeque<T>,
+}
+
+struct Node<T> {
+    message: T,
+    next: *mut Node<T>,
+}
+
+/// `incoming` once the channel is closed. Never a real node: those are
+/// allocated, and never at a dangling address.
+fn closed<T>() -> *mut Node<T> {
+    NonNull::dangling().as_ptr()
+}
+
+impl<T> Channel<T> {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                urgent: VecDeque::new(),
+            }),
+            item_ready: Condvar::new(),
+            incoming: AtomicPtr::new(ptr::null_mut()),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Panics if the channel was already closed.
+    pub fn send(&self, message: T) {
+        if self.try_send(message).is_err() {
+            panic!("can't send on a closed channel")
+        }
+    }
+
+    /// Like `send`, but gives the message back instead of panicking.
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        let node = Box::into_raw(Box::new(Node {
+            message,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.incoming.load(Relaxed);
+        loop {
+            if head == closed() {
+                // Safety: never shared.
+                return Err(unsafe { Box::from_raw(node) }.message);
+            }
+            // Safety: not shared until the swap below succeeds.
+            unsafe { (*node).next = head };
+            match self
+                .incoming
+                .compare_exchange_weak(head, node, SeqCst, Relaxed)
+            {
+                Ok(_) => break,
+                Err(h) => head = h,
+            }
+        }
+        count_sent(1);
+        trace::event!(channel = ?core::ptr::from_ref(self), "sent");
+        if self.waiting.load(SeqCst) > 0 {
+            drop(self.state.lock().unwrap());
+            self.item_ready.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Like `send`, but the message is received before any sent with
+    /// `send`, however many of them are queued.
+    pub fn send_urgent(&self, message: T) {
+        let mut state = self.state.lock().unwrap();
+        if self.is_closed() {
+            drop(state);
+            panic!("can't send on a closed channel")
+        }
+        state.urgent.push_back(message);
+        count_sent(1);
+        trace::event!(channel = ?core::ptr::from_ref(self), len = state.len(), "sent");
+        let wake = self.waiting.load(Relaxed) > 0;
+        drop(state);
+        if wake {
+            self.item_ready.notify_one();
+        }
+    }
+
+    /// Sends every message, under one lock, and with one notify for all of
+    /// them, instead of one of each per message. The iterator runs with the
+    /// lock held. Panics if the channel was already closed.
+    pub fn send_all(&self, messages: impl IntoIterator<Item = T>) {
+        let mut state = self.state.lock().unwrap();
+        if self.is_closed() {
+            drop(state);
+            panic!("can't send on a closed channel")
+        }
+        // After what was sent before them.
+        self.take_incoming(&mut state);
+        let before = state.queue.len();
+        state.queue.extend(messages);
+        let sent = state.queue.len() - before;
+        count_sent(sent);
+        trace::event!(channel = ?core::ptr::from_ref(self), sent = sent, len = state.len(), "sent");
+        let wake = self.waiting.load(Relaxed) > 0 && sent > 0;
+        drop(state);
+        if wake {
+            self.item_ready.notify_all();
+        }
+    }
+
+    /// Blocks until a message is available.
+    /// Returns `None` once the channel is closed and drained.
+    pub fn receive(&self) -> Option<T> {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        let mut b = self.state.lock().unwrap();
+        loop {
+            self.take_incoming(&mut b);
+            if let Some(message) = b.pop() {
+                count_received();
+                trace::event!(channel = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "received");
+                return Some(message);
+            }
+            if self.is_closed() {
+                return None;
+            }
+            self.waiting.fetch_add(1, SeqCst);
+            // A send that didn't see us counted in has pushed by now.
+            if self.incoming.load(SeqCst).is_null() {
+                b = self.item_ready.wait(b).unwrap();
+            }
+            self.waiting.fetch_sub(1, Relaxed);
+        }
+    }
+
+    pub fn try_receive(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        self.take_incoming(&mut state);
+        let message = state.pop();
+        message.inspect(|_| count_received())
+    }
+
+    /// Messages already sent can still be received after closing.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        // Swapped, not stored: a send may push until the very last moment.
+        let head = self.incoming.swap(closed(), Acquire);
+        if head != closed() {
+            state.append(head);
+        }
+        let wake = self.waiting.load(Relaxed) > 0;
+        drop(state);
+        if wake {
+            self.item_ready.notify_all();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.incoming.load(Acquire) == closed()
+    }
+
+    /// Receivers blocked in `receive` right now. Already out of date by the
+    /// time it's looked at, but good enough to size batches by.
+    pub fn waiters(&self) -> usize {
+        self.waiting.load(Relaxed)
+    }
+
+    pub fn has_waiters(&self) -> bool {
+        self.waiters() > 0
+    }
+
+    /// Moves what's on `incoming` to the back of the queue, oldest first.
+    /// Only with the lock, which `state` is from.
+    fn take_incoming(&self, state: &mut State<T>) {
+        // Closing takes the lock too, so it can't happen in between.
+        if !self.is_closed() {
+            state.append(self.incoming.swap(ptr::null_mut(), Acquire));
+        }
+    }
+}
+
+impl<T> State<T> {
+    /// Appends a stack taken off `incoming`, turned around.
+    fn append(&mut self, mut head: *mut Node<T>) {
+        let queue = &mut self.queue;
+        let at = queue.len();
+        while !head.is_null() {
+            // Safety: taken off the stack, so ours alone.
+            let node = unsafe { Box::from_raw(head) };
+            head = node.next;
+            queue.push_back(node.message);
+        }
+        queue.make_contiguous()[at..].reverse();
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.urgent.pop_front().or_else(|| self.queue.pop_front())
+    }
+
+    #[cfg(feature = "tracing")]
+    fn len(&self) -> usize {
+        self.urgent.len() + self.queue.len()
+    }
+}
+
+/// For `metrics`, once messages are in.
+fn count_sent(_n: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        crate::metrics::CHANNEL_SENT.add(_n as u64);
+        crate::metrics::CHANNEL_DEPTH.add(_n as i64);
+    }
+}
+
+/// For `metrics`, once a message is out of the queue.
+fn count_received() {
+    #[cfg(feature = "metrics")]
+    {
+        crate::metrics::CHANNEL_RECEIVED.increment();
+        crate::metrics::CHANNEL_DEPTH.decrement();
+    }
+}
+
+/// Frees what's still on `incoming`. Whatever is still queued leaves
+/// `metrics::CHANNEL_DEPTH` with it.
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        let mut head = *self.incoming.get_mut();
+        let mut _left = state.urgent.len() + state.queue.len();
+        while !head.is_null() && head != closed() {
+            // Safety: no one else can have it anymore.
+            let node = unsafe { Box::from_raw(head) };
+            head = node.next;
+            _left += 1;
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::CHANNEL_DEPTH.sub(_left as i64);
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,280 @@
+//! One-time initialization without the standard library.
+//!
+//! The first caller runs the initializer, everyone else who shows up
+//! meanwhile waits for it, the way the `backend-*` features say.
+//!
+//! `OnceLite` is the same in one byte, for tables with one per entry. A
+//! byte can't be waited on, so its waiters sleep on one of a few shared
+//! counters instead, picked by address, after spinning for a while; the
+//! initializer only bumps it if they said they're there.
+
+use crate::{
+    backoff::Backoff,
+    wait::{wait, wake_all},
+};
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+    ops::Deref,
+    ptr,
+    sync::atomic::{
+        AtomicU32, AtomicU8,
+        Ordering::{AcqRel, Acquire, Relaxed, Release},
+    },
+};
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Acquire) == COMPLETE
+    }
+
+    /// Runs `f` if no call before did, and returns once it has run.
+    ///
+    /// If `f` panics, the `Once` stays incomplete, and the next caller tries again.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        loop {
+            match self
+                .state
+                .compare_exchange(INCOMPLETE, RUNNING, Acquire, Acquire)
+            {
+                Ok(_) => {
+                    let reset = ResetOnUnwind(&self.state);
+                    f();
+                    core::mem::forget(reset);
+                    self.state.store(COMPLETE, Release);
+                    wake_all(&self.state);
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(_) => wait(&self.state, RUNNING),
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lets the waiters try again if the initializer panics.
+struct ResetOnUnwind<'a>(&'a AtomicU32);
+
+impl Drop for ResetOnUnwind<'_> {
+    fn drop(&mut self) {
+        self.0.store(INCOMPLETE, Relaxed);
+        wake_all(self.0);
+    }
+}
+
+/// `Once` in a byte, for when there are thousands of them.
+pub struct OnceLite {
+    state: AtomicU8,
+}
+
+/// Counters for `OnceLite` waiters to sleep on.
+static SLEEPERS: [AtomicU32; 16] = [const { AtomicU32::new(0) }; 16];
+
+impl OnceLite {
+    const INCOMPLETE: u8 = INCOMPLETE as u8;
+    const RUNNING: u8 = RUNNING as u8;
+    const COMPLETE: u8 = COMPLETE as u8;
+    /// Running, with someone sleeping on the counter until it's done.
+    const WAITED: u8 = 3;
+
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(Self::INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Acquire) == Self::COMPLETE
+    }
+
+    /// Like `Once::call_once`, panics included.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        loop {
+            match self
+                .state
+                .compare_exchange(Self::INCOMPLETE, Self::RUNNING, Acquire, Acquire)
+            {
+                Ok(_) => {
+                    let reset = ResetLiteOnUnwind(self);
+                    f();
+                    core::mem::forget(reset);
+                    self.finish(Self::COMPLETE);
+                    return;
+                }
+                Err(Self::COMPLETE) => return,
+                Err(_) => self.wait(),
+            }
+        }
+    }
+
+    fn sleepers(&self) -> &'static AtomicU32 {
+        &SLEEPERS[(ptr::from_ref(self) as usize >> 4) % SLEEPERS.len()]
+    }
+
+    /// Returns once it's no longer running, or maybe before.
+    fn wait(&self) {
+        let backoff = Backoff::new();
+        while !backoff.is_completed() {
+            if !matches!(self.state.load(Relaxed), Self::RUNNING | Self::WAITED) {
+                return;
+            }
+            backoff.snooze();
+        }
+        let sleepers = self.sleepers();
+        // Before saying we're there, so a bump after that wakes us.
+        // `Release`, so the bump is after the load, for whoever sees it.
+        let seen = sleepers.load(Acquire);
+        match self
+            .state
+            .compare_exchange(Self::RUNNING, Self::WAITED, Release, Relaxed)
+        {
+            Ok(_) | Err(Self::WAITED) => wait(sleepers, seen),
+            Err(_) => {}
+        }
+    }
+
+    /// Leaves `state`, waking the waiters if any said they're there.
+    fn finish(&self, state: u8) {
+        if self.state.swap(state, AcqRel) == Self::WAITED {
+            let sleepers = self.sleepers();
+            sleepers.fetch_add(1, Release);
+            wake_all(sleepers);
+        }
+    }
+}
+
+impl Default for OnceLite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct ResetLiteOnUnwind<'a>(&'a OnceLite);
+
+impl Drop for ResetLiteOnUnwind<'_> {
+    fn drop(&mut self) {
+        self.0.finish(OnceLite::INCOMPLETE);
+    }
+}
+
+/// A value that's set at most once.
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        // Safety: complete means written, and never written again.
+        self.once
+            .is_completed()
+            .then(|| unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| unsafe {
+            (*self.value.get()).write(f());
+        });
+        // Safety: `call_once` only returns once the value is written.
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Gives the value back if the cell was set already.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        value.map_or(Ok(()), Err)
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.once.state.get_mut() == COMPLETE {
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+/// A value that's computed on first use, e.g. in a `static`.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+// The initializer is only ever taken by the one thread that runs it.
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+/// Declares `static`s that are computed on first use, with whatever
+/// non-`const` code it takes: `Lazy`s with the initializer written in place.
+///
+/// `static_lazy! { pub static NAME: Type = expr; }`, any number of them, with
+/// attributes and doc comments. Use them through `Deref`, like `*NAME`.
+#[macro_export]
+macro_rules! static_lazy {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::sync::Lazy<$t> = $crate::sync::Lazy::new(|| $init);
+        )*
+    };
+}
@@ -0,0 +1,309 @@
+//! The borrowing one-shot channel of chapter 5, without the standard library.
+//!
+//! The channel lives wherever the caller puts it (a local, a `static`), and
+//! `split` borrows it, so nothing is allocated. `receive` blocks the way the
+//! `backend-*` features say, see `wait`.
+//!
+//! The receiver checks `ready` with `Relaxed` loads, and only once it's set
+//! pays for an `Acquire` fence, instead of a `swap(0, Acquire)` every time:
+//! on AArch64 that's an exclusive load/store pair (or an LSE `swpal`) per
+//! check, against a plain `ldr`. There's a single receiver, so resetting
+//! the flag needs no read-modify-write either.
+//! `cargo run --release -- bench handoff` compares the two.
+//!
+//! The sender does swap the flag in, though, to see in the same step
+//! whether `join_all` or `race` is waiting for it along with others: those
+//! leave the number of a counter in the flag while it's 0, and sleep on
+//! that counter, which every sender that finds it bumps. The counters are
+//! in a `static`, so one is still there for the sender to bump after the
+//! channel is gone; two waiters that get the same one wake each other now
+//! and then, for nothing.
+//!
+//! With `alloc`, `channel` puts one in an `Arc` instead, shared by its two
+//! halves, which can then go anywhere, like the channel of chapter 5 before
+//! it borrows.
+//!
+//! The orderings the message is published with are a `Profile`, see
+//! `profile`.
+
+use crate::{
+    profile::{Correct, Profile},
+    wait::{wait, wake_all, wake_one},
+};
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+pub struct Channel<T, P: Profile = Correct> {
+    message: UnsafeCell<MaybeUninit<T>>,
+    /// 1 once sent, 0 before, or which of `WATCHERS` to bump, shifted left
+    /// by one. 32 bits so it can be waited on.
+    ready: AtomicU32,
+    profile: PhantomData<P>,
+}
+
+/// Counters for `join_all` and `race` to sleep on.
+static WATCHERS: [AtomicU32; 16] = [const { AtomicU32::new(0) }; 16];
+
+unsafe impl<T, P: Profile> Sync for Channel<T, P> where T: Send {}
+
+pub struct Sender<'a, T, P: Profile = Correct> {
+    channel: &'a Channel<T, P>,
+}
+
+pub struct Receiver<'a, T, P: Profile = Correct> {
+    channel: &'a Channel<T, P>,
+}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        // Safety: `Correct` is.
+        unsafe { Self::with_profile() }
+    }
+}
+
+impl<T, P: Profile> Channel<T, P> {
+    /// `new`, for a channel with the orderings of `P`.
+    ///
+    /// # Safety
+    ///
+    /// `P` acquires and releases at least as strongly as `Correct`, or `T`
+    /// is zero-sized, like `()`: receiving with anything weaker may not see
+    /// the message that was written.
+    pub const unsafe fn with_profile() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            ready: AtomicU32::new(0),
+            profile: PhantomData,
+        }
+    }
+
+    pub fn split(&mut self) -> (Sender<'_, T, P>, Receiver<'_, T, P>) {
+        // Safety: whoever made `self` already vouched for `P`.
+        *self = unsafe { Self::with_profile() };
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+
+    /// The sending half, for a channel shared some other way than by
+    /// `split`, like in an `Arc`.
+    ///
+    /// # Safety
+    ///
+    /// It's the only one there ever is for this channel.
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn sender(&self) -> Sender<'_, T, P> {
+        Sender { channel: self }
+    }
+
+    /// The receiving half, the same way as `sender`.
+    ///
+    /// # Safety
+    ///
+    /// It's the only one there ever is for this channel.
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn receiver(&self) -> Receiver<'_, T, P> {
+        Receiver { channel: self }
+    }
+}
+
+/// Calls `f` with the two halves of a channel on the stack, which can't
+/// outlive the call: spawn whoever uses them in a `thread::scope` inside `f`.
+pub fn with_channel<T, R>(f: impl FnOnce(Sender<'_, T>, Receiver<'_, T>) -> R) -> R {
+    let mut channel = Channel::new();
+    let (sender, receiver) = channel.split();
+    f(sender, receiver)
+}
+
+/// `scoped_channel!(sender, receiver);` declares the two halves of a
+/// channel on the stack, hidden in the current block, like `with_channel`
+/// without the closure: they can't leave the block either.
+#[macro_export]
+macro_rules! scoped_channel {
+    ($sender:pat, $receiver:pat $(,)?) => {
+        let mut channel = $crate::channel::oneshot::Channel::new();
+        let ($sender, $receiver) = channel.split();
+    };
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, P: Profile> Sender<'_, T, P> {
+    pub fn send(self, message: T) {
+        unsafe { (*self.channel.message.get()).write(message) };
+        // After this, the channel may be gone: only its address is used.
+        let watcher = self.channel.ready.swap(1, P::RELEASE) >> 1;
+        wake_one(&self.channel.ready);
+        if watcher != 0 {
+            let watcher = &WATCHERS[watcher as usize - 1];
+            watcher.fetch_add(1, Release);
+            wake_all(watcher);
+        }
+    }
+}
+
+impl<T, P: Profile> Receiver<'_, T, P> {
+    pub fn is_ready(&self) -> bool {
+        self.channel.ready.load(Relaxed) == 1
+    }
+
+    /// The message, if it's there already.
+    pub fn try_receive(self) -> Result<T, Self> {
+        if self.channel.ready.load(Relaxed) == 1 {
+            Ok(self.take())
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn receive(self) -> T {
+        while self.channel.ready.load(Relaxed) == 0 {
+            wait(&self.channel.ready, 0);
+        }
+        self.take()
+    }
+
+    /// Waits for the message like `receive`, and hands `f` a reference to
+    /// it where it is, instead of moving it out: for a big message, that's
+    /// a copy of it saved. It's dropped in place after `f`, panic or not.
+    pub fn receive_with<R>(self, f: impl FnOnce(&T) -> R) -> R {
+        while self.channel.ready.load(Relaxed) == 0 {
+            wait(&self.channel.ready, 0);
+        }
+        P::acquire_fence();
+        /// Drops the message, and clears `ready` so `Drop` doesn't again.
+        struct Release<'a, T, P: Profile>(&'a Channel<T, P>);
+        impl<T, P: Profile> Drop for Release<'_, T, P> {
+            fn drop(&mut self) {
+                unsafe { (*self.0.message.get()).assume_init_drop() };
+                self.0.ready.store(0, Relaxed);
+            }
+        }
+        let release = Release(self.channel);
+        // Safety: it's sent, and ours alone until `release` drops it.
+        f(unsafe { (*release.0.message.get()).assume_init_ref() })
+    }
+
+    /// Once `ready` was seen set.
+    fn take(self) -> T {
+        // Pairs with the sender's `Release` store, which we've seen.
+        P::acquire_fence();
+        // The sender is done with the flag, no one else can race us for it.
+        // Cleared so `Drop` knows the message is gone.
+        self.channel.ready.store(0, Relaxed);
+        unsafe { (*self.channel.message.get()).assume_init_read() }
+    }
+}
+
+/// Waits for all of them, sleeping until the last one is sent instead of
+/// once per receiver.
+pub fn join_all<T, P: Profile, const N: usize>(receivers: [Receiver<'_, T, P>; N]) -> [T; N] {
+    wait_for(&receivers, |ready| ready == N);
+    receivers.map(Receiver::take)
+}
+
+/// Waits for the first of them, and drops the others.
+#[cfg(feature = "alloc")]
+pub fn race<T, P: Profile>(mut receivers: alloc::vec::Vec<Receiver<'_, T, P>>) -> T {
+    assert!(!receivers.is_empty(), "a race needs at least one receiver");
+    wait_for(&receivers, |ready| ready > 0);
+    let first = receivers.iter().position(Receiver::is_ready).unwrap();
+    receivers.swap_remove(first).take()
+}
+
+/// Sleeps until `done` says yes to how many of `receivers` are ready.
+fn wait_for<T, P: Profile>(receivers: &[Receiver<'_, T, P>], done: impl Fn(usize) -> bool) {
+    let ready = || receivers.iter().filter(|r| r.is_ready()).count();
+    if done(ready()) {
+        return;
+    }
+    let slot = (ptr::from_ref(receivers[0].channel) as usize >> 4) % WATCHERS.len();
+    let mark = (slot as u32 + 1) << 1;
+    let watcher = &WATCHERS[slot];
+    // A channel that's no longer 0 is sent, and `ready` counts it.
+    for r in receivers {
+        let _ = r.channel.ready.compare_exchange(0, mark, Relaxed, Relaxed);
+    }
+    loop {
+        // Before looking, so a send after we look wakes us.
+        let seen = watcher.load(Acquire);
+        if done(ready()) {
+            break;
+        }
+        wait(watcher, seen);
+    }
+    // Saves the ones still to come a wake-up no one's waiting for.
+    for r in receivers {
+        let _ = r.channel.ready.compare_exchange(mark, 0, Relaxed, Relaxed);
+    }
+}
+
+/// A channel in an `Arc`, and its two halves, each with a clone of it.
+#[cfg(feature = "alloc")]
+pub fn channel<T>() -> (OwnedSender<T>, OwnedReceiver<T>) {
+    let channel = alloc::sync::Arc::new(Channel::new());
+    (
+        OwnedSender {
+            channel: channel.clone(),
+        },
+        OwnedReceiver { channel },
+    )
+}
+
+#[cfg(feature = "alloc")]
+pub struct OwnedSender<T> {
+    channel: alloc::sync::Arc<Channel<T>>,
+}
+
+#[cfg(feature = "alloc")]
+pub struct OwnedReceiver<T> {
+    channel: alloc::sync::Arc<Channel<T>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T> OwnedSender<T> {
+    pub fn send(self, message: T) {
+        // Safety: `channel` made one of each, and this is the sender.
+        unsafe { self.channel.sender() }.send(message);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> OwnedReceiver<T> {
+    pub fn is_ready(&self) -> bool {
+        // Safety: `channel` made one of each, and this is the receiver.
+        unsafe { self.channel.receiver() }.is_ready()
+    }
+
+    /// The message, if it's there already.
+    pub fn try_receive(self) -> Result<T, Self> {
+        if self.is_ready() {
+            Ok(self.receive())
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn receive(self) -> T {
+        // Safety: as for `is_ready`.
+        unsafe { self.channel.receiver() }.receive()
+    }
+}
+
+impl<T, P: Profile> Drop for Channel<T, P> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() == 1 {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Data-parallel helpers over slices, on scoped threads.
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        Mutex,
+    },
+    thread,
+};
+
+/// How a slice is divided between the threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    /// One contiguous piece per thread, decided up front.
+    /// No coordination at all, but the slowest piece sets the pace.
+    Static,
+    /// Threads keep claiming small chunks with a `fetch_add` on a shared index
+    /// until there are none left, so fast threads pick up the slack.
+    Dynamic,
+}
+
+/// Small enough that fast threads can pick up the slack of slow ones,
+/// big enough that the shared index isn't hammered for every item.
+fn chunk_size(len: usize, n_threads: usize) -> usize {
+    (len / (n_threads * 8)).max(1)
+}
+
+/// Runs `f` on consecutive chunks of `items` and returns the results in
+/// chunk order, whichever thread computed them.
+///
+/// A panic in `f` stops the other threads after their current chunk
+/// and is resumed on the calling thread.
+fn map_chunks<T, U, F>(items: &[T], n_threads: usize, split: Split, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&[T]) -> U + Sync,
+{
+    assert!(n_threads > 0, "need at least one thread");
+    let chunk = match split {
+        Split::Static => items.len().div_ceil(n_threads).max(1),
+        Split::Dynamic => chunk_size(items.len(), n_threads),
+    };
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let panic: Mutex<Option<Box<dyn Any + Send>>> = Mutex::new(None);
+
+    let run = |i: usize| {
+        let start = i * chunk;
+        let end = (start + chunk).min(items.len());
+        match catch_unwind(AssertUnwindSafe(|| f(&items[start..end]))) {
+            Ok(result) => Some((i, result)),
+            Err(e) => {
+                stop.store(true, Relaxed);
+                panic.lock().unwrap().get_or_insert(e);
+                None
+            }
+        }
+    };
+
+    let mut results: Vec<(usize, U)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..n_threads)
+            .map(|t| {
+                let run = &run;
+                let (next, stop) = (&next, &stop);
+                s.spawn(move || match split {
+                    Split::Static if t * chunk < items.len() => run(t).into_iter().collect(),
+                    Split::Static => Vec::new(),
+                    Split::Dynamic => {
+                        let mut mine = Vec::new();
+                        while !stop.load(Relaxed) {
+                            let i = next.fetch_add(1, Relaxed);
+                            if i * chunk >= items.len() {
+                                break;
+                            }
+                            mine.extend(run(i));
+                        }
+                        mine
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+
+    if let Some(e) = panic.into_inner().unwrap() {
+        resume_unwind(e);
+    }
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Calls `f` on every item, on `n_threads` threads.
+///
+/// Threads claim the next chunk of items with a `fetch_add` on a shared index,
+/// so the work balances itself. If `f` panics, the other threads stop after
+/// their current chunk and the panic is resumed on the calling thread.
+pub fn parallel_for_each<T, F>(items: &[T], n_threads: usize, f: F)
+where
+    T: Sync,
+    F: Fn(&T) + Sync,
+{
+    map_chunks(items, n_threads, Split::Dynamic, |chunk| {
+        chunk.iter().for_each(&f)
+    });
+}
+
+/// Like `items.iter().map(f).collect()`, on `n_threads` threads.
+/// The output keeps the order of the input.
+pub fn parallel_map<T, U, F>(items: &[T], n_threads: usize, split: Split, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    map_chunks(items, n_threads, split, |chunk| {
+        chunk.iter().map(&f).collect::<Vec<U>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Reduces every chunk with `f`, then folds the partial results with
+/// `combine`, in order. `None` if `items` is empty.
+///
+/// `parallel_reduce(&v, 4, Split::Static, |c| c.iter().sum::<usize>(), |a, b| a + b)`
+pub fn parallel_reduce<T, U, F, C>(
+    items: &[T],
+    n_threads: usize,
+    split: Split,
+    f: F,
+    combine: C,
+) -> Option<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&[T]) -> U + Sync,
+    C: Fn(U, U) -> U,
+{
+    if items.is_empty() {
+        return None;
+    }
+    map_chunks(items, n_threads, split, f)
+        .into_iter()
+        .reduce(combine)
+}
+
+pub fn main() {
+    let counted = AtomicUsize::new(0);
+    let values: Vec<usize> = (1..=1000).collect();
+    parallel_for_each(&values, 4, |v| {
+        counted.fetch_add(*v, Relaxed);
+    });
+    println!("sum of 1..=1000: {}", counted.into_inner());
+
+    for split in [Split::Static, Split::Dynamic] {
+        let squares = parallel_map(&values, 4, split, |v| v * v);
+        let sum = parallel_reduce(&values, 4, split, |c| c.iter().sum::<usize>(), |a, b| a + b);
+        let max = parallel_reduce(&values, 4, split, |c| c.iter().max().copied(), Option::max);
+        println!(
+            "{split:?}: squares ..{:?}, sum {sum:?}, max {:?}",
+            &squares[997..],
+            max.flatten()
+        );
+    }
+}
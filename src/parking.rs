@@ -1,37 +1,14 @@
-use std::{
-    collections::VecDeque,
-    ops::BitAndAssign,
-    sync::RwLock,
-    thread,
-    time::{Duration, SystemTime},
-};
+use atomics_and_locks::thread::{ConsumerQueue, DrainPolicy};
+use std::{thread, time::Duration};
 
 pub fn example() {
-    let queue = RwLock::new(VecDeque::new());
-    let timeout = RwLock::new(false);
-    thread::scope(|s| {
-        let t2 = s.spawn(|| loop {
-            if *timeout.read().unwrap() {
-                break;
-            }
-            let v = queue.write().unwrap().pop_front();
-            if let Some(v) = v {
-                println!("Consuming {v}");
-            } else {
-                thread::park();
-            }
-        });
-        let start = SystemTime::now();
-        let loop_duration = Duration::from_secs(6);
-        loop {
-            if (SystemTime::now() - loop_duration) > start {
-                *timeout.write().unwrap() = true;
-                t2.thread().unpark();
-                break;
-            }
-            queue.write().unwrap().push_back(4);
-            t2.thread().unpark();
-            thread::sleep(Duration::from_secs(1));
-        }
-    })
+    // The consumer parks while the queue is empty, `push` unparks it.
+    let queue = ConsumerQueue::start(|v| println!("Consuming {v}"));
+    for _ in 0..6 {
+        queue.push(4);
+        thread::sleep(Duration::from_secs(1));
+    }
+    queue.push(5);
+    // Everything pushed is consumed before this returns.
+    queue.shutdown(DrainPolicy::Drain);
 }
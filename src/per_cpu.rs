@@ -0,0 +1,183 @@
+//! Data split into one slot per core, or per thread, each on its own cache
+//! line, for threads to add into without all of them hitting the same one:
+//! counters, histograms, whatever adds up. Readers go through the slots
+//! and add them up themselves.
+//!
+//! A slot is made by `init` the first time a thread uses it, and stays
+//! until the whole thing is dropped.
+//!
+//! `PerCpu` has one per logical core, picked by the core the thread is on
+//! when it asks. The thread may move, or share the core, so a slot is still
+//! shared, and what's in it has to take read-modify-writes from several
+//! threads: but it's mostly one core's, and stays in that core's cache.
+//! Where the core can't be told, it's the thread's index, as in `PerThread`.
+//!
+//! `PerThread` has one per thread, up to a number of them. A thread gets an
+//! index no running thread has on first use, and gives it back when it
+//! exits: the next thread to take it over gets its slot, and what's in it.
+//! The indices are the whole process's, though, not one `PerThread`'s, and
+//! a slot is the index modulo the number of them, so two threads can share
+//! one even with fewer threads using it than slots. Add to a slot with a
+//! read-modify-write, like `fetch_add`, as for `PerCpu`: a load and then a
+//! store can lose what another thread added in between.
+
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    thread,
+};
+
+use crate::{affinity, cache_padded::CachePadded, mutex::Mutex, once::OnceCell};
+
+struct Slots<T, F> {
+    slots: Box<[CachePadded<OnceCell<T>>]>,
+    init: F,
+}
+
+impl<T, F: Fn() -> T> Slots<T, F> {
+    fn new(n: usize, init: F) -> Self {
+        Self {
+            slots: (0..n.max(1))
+                .map(|_| CachePadded::new(OnceCell::new()))
+                .collect(),
+            init,
+        }
+    }
+
+    fn get(&self, index: usize) -> &T {
+        self.slots[index % self.slots.len()].get_or_init(&self.init)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.get())
+    }
+}
+
+/// Indices given back by threads that exited, to hand out again.
+static FREE: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+struct ThreadIndex(Cell<Option<usize>>);
+
+impl Drop for ThreadIndex {
+    fn drop(&mut self) {
+        if let Some(index) = self.0.get() {
+            FREE.lock().push(index);
+        }
+    }
+}
+
+thread_local! {
+    static INDEX: ThreadIndex = const { ThreadIndex(Cell::new(None)) };
+}
+
+/// This thread's index, taken on first use, and unique among running
+/// threads. 0 from another thread-local's destructor, once ours is gone.
+fn thread_index() -> usize {
+    INDEX
+        .try_with(|index| match index.0.get() {
+            Some(i) => i,
+            None => {
+                let i = FREE
+                    .lock()
+                    .pop()
+                    .unwrap_or_else(|| NEXT.fetch_add(1, Relaxed));
+                index.0.set(Some(i));
+                i
+            }
+        })
+        .unwrap_or(0)
+}
+
+pub struct PerCpu<T, F = fn() -> T> {
+    slots: Slots<T, F>,
+}
+
+impl<T, F: Fn() -> T> PerCpu<T, F> {
+    /// A slot for each logical core we may run on, made by `init`.
+    pub fn new(init: F) -> Self {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            slots: Slots::new(cpus, init),
+        }
+    }
+
+    /// Runs `f` with the slot of the core we're on.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let index = affinity::current_cpu().unwrap_or_else(thread_index);
+        f(self.slots.get(index))
+    }
+
+    /// The slots made so far, to add up. What's added meanwhile may or may
+    /// not be in it.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter()
+    }
+}
+
+pub struct PerThread<T, F = fn() -> T> {
+    slots: Slots<T, F>,
+}
+
+impl<T, F: Fn() -> T> PerThread<T, F> {
+    /// A slot for each of up to `threads` threads at once, made by `init`.
+    pub fn new(threads: usize, init: F) -> Self {
+        Self {
+            slots: Slots::new(threads, init),
+        }
+    }
+
+    /// Runs `f` with this thread's slot.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(self.slots.get(thread_index()))
+    }
+
+    /// See `PerCpu::iter`.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter()
+    }
+}
+
+pub fn main() {
+    use std::{sync::atomic::AtomicU64, time::Instant};
+
+    // Four threads counting to a million each: into one counter, one per
+    // core, and one per thread. With one core, there's no contention to
+    // save, only the work of finding the slot.
+    const THREADS: usize = 4;
+    const N: u64 = 1_000_000;
+    let time = |name: &str, add: &(dyn Fn() + Sync), total: &dyn Fn() -> u64| {
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| (0..N).for_each(|_| add()));
+            }
+        });
+        println!("{name:<10} {} in {:?}", total(), start.elapsed());
+    };
+
+    let shared = AtomicU64::new(0);
+    time(
+        "shared",
+        &|| {
+            shared.fetch_add(1, Relaxed);
+        },
+        &|| shared.load(Relaxed),
+    );
+    let per_cpu = PerCpu::new(|| AtomicU64::new(0));
+    time(
+        "per cpu",
+        &|| {
+            per_cpu.with(|c| c.fetch_add(1, Relaxed));
+        },
+        &|| per_cpu.iter().map(|c| c.load(Relaxed)).sum(),
+    );
+    let per_thread = PerThread::new(THREADS, || AtomicU64::new(0));
+    time(
+        "per thread",
+        &|| {
+            per_thread.with(|c| c.fetch_add(1, Relaxed));
+        },
+        &|| per_thread.iter().map(|c| c.load(Relaxed)).sum(),
+    );
+}
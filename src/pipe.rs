@@ -0,0 +1,214 @@
+//! An in-memory pipe: a `Write` end and a `Read` end for two threads to
+//! stream bytes through, like serialized messages, where the channels
+//! would take one value at a time.
+//!
+//! It's the SPSC ring's scheme over bytes, with the capacity picked at run
+//! time: each end owns one index and only reads the other's, and copies as
+//! many bytes as there's room or data for at once. An end that can't make
+//! progress sleeps on a counter the other end bumps whenever it does.
+//!
+//! Dropping the writer is the end of the stream: once the reader has read
+//! everything before it, `read` returns 0. Dropping the reader makes
+//! `write` fail with `BrokenPipe`, since no one would ever read it.
+
+use std::{
+    cell::UnsafeCell,
+    io::{self, Read, Write},
+    ptr,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU32, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+};
+
+use crate::{
+    cache_padded::CachePadded,
+    sys::{wait, wake_all, wake_one},
+};
+
+struct Pipe {
+    buf: Box<[UnsafeCell<u8>]>,
+    /// Next byte to read. Only the reader writes it.
+    head: CachePadded<AtomicUsize>,
+    /// Next byte to write. Only the writer writes it.
+    tail: CachePadded<AtomicUsize>,
+    writer_alive: AtomicBool,
+    reader_alive: AtomicBool,
+    /// Bumped by the writer when there's more to read, or it's gone.
+    readable: AtomicU32,
+    /// Bumped by the reader when there's more room, or it's gone.
+    writable: AtomicU32,
+}
+
+// A byte is only touched by the end whose side of the indices it's on.
+unsafe impl Sync for Pipe {}
+
+impl Pipe {
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The byte at `index`, which only ever grows (wrapping).
+    fn at(&self, index: usize) -> *mut u8 {
+        UnsafeCell::raw_get(&self.buf[index & (self.capacity() - 1)])
+    }
+
+    /// Copies `len` bytes between `bytes` and the ring from `index` on, in
+    /// at most two pieces, where the ring wraps around.
+    ///
+    /// # Safety
+    ///
+    /// Those bytes of the ring are the caller's, see `head` and `tail`.
+    unsafe fn copy(&self, index: usize, len: usize, mut copy: impl FnMut(*mut u8, usize, usize)) {
+        let offset = index & (self.capacity() - 1);
+        let first = len.min(self.capacity() - offset);
+        copy(self.at(index), 0, first);
+        if first < len {
+            copy(self.at(0), first, len - first);
+        }
+    }
+}
+
+pub struct PipeWriter {
+    pipe: Arc<Pipe>,
+}
+
+pub struct PipeReader {
+    pipe: Arc<Pipe>,
+}
+
+/// A pipe that holds up to `capacity` bytes, rounded up to a power of two.
+pub fn pipe(capacity: usize) -> (PipeWriter, PipeReader) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let pipe = Arc::new(Pipe {
+        buf: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+        writer_alive: AtomicBool::new(true),
+        reader_alive: AtomicBool::new(true),
+        readable: AtomicU32::new(0),
+        writable: AtomicU32::new(0),
+    });
+    (PipeWriter { pipe: pipe.clone() }, PipeReader { pipe })
+}
+
+impl Write for PipeWriter {
+    /// Blocks until there's room for at least one byte.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let pipe = &*self.pipe;
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            // Before looking, so a bump after we look wakes us.
+            let seq = pipe.writable.load(Acquire);
+            if !pipe.reader_alive.load(Relaxed) {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            let tail = pipe.tail.load(Relaxed);
+            // Acquire: the reader must be done with the bytes we reuse.
+            let free = pipe.capacity() - tail.wrapping_sub(pipe.head.load(Acquire));
+            if free > 0 {
+                let n = free.min(bytes.len());
+                // Safety: from `tail` up to `head + capacity` is ours.
+                unsafe {
+                    pipe.copy(tail, n, |to, from, len| {
+                        ptr::copy_nonoverlapping(bytes[from..].as_ptr(), to, len)
+                    });
+                }
+                pipe.tail.store(tail.wrapping_add(n), Release);
+                pipe.readable.fetch_add(1, Release);
+                wake_one(&pipe.readable);
+                return Ok(n);
+            }
+            wait(&pipe.writable, seq);
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        // Release: everything written before it is there to read.
+        self.pipe.writer_alive.store(false, Release);
+        self.pipe.readable.fetch_add(1, Release);
+        wake_all(&self.pipe.readable);
+    }
+}
+
+impl Read for PipeReader {
+    /// Blocks until there's at least one byte, or returns 0 once the
+    /// writer is gone and everything it wrote has been read.
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        let pipe = &*self.pipe;
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let seq = pipe.readable.load(Acquire);
+            let closed = !pipe.writer_alive.load(Acquire);
+            let head = pipe.head.load(Relaxed);
+            // Acquire: the writer's bytes up to there are written.
+            let len = pipe.tail.load(Acquire).wrapping_sub(head);
+            if len > 0 {
+                let n = len.min(bytes.len());
+                // Safety: from `head` up to `tail` is ours.
+                unsafe {
+                    pipe.copy(head, n, |from, to, len| {
+                        ptr::copy_nonoverlapping(from, bytes[to..].as_mut_ptr(), len)
+                    });
+                }
+                pipe.head.store(head.wrapping_add(n), Release);
+                pipe.writable.fetch_add(1, Release);
+                wake_one(&pipe.writable);
+                return Ok(n);
+            }
+            // Seen closed before looking at `tail`, so that was all of it.
+            if closed {
+                return Ok(0);
+            }
+            wait(&pipe.readable, seq);
+        }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.reader_alive.store(false, Relaxed);
+        self.pipe.writable.fetch_add(1, Release);
+        wake_all(&self.pipe.writable);
+    }
+}
+
+pub fn main() {
+    use std::{
+        io::{BufRead, BufReader},
+        thread,
+    };
+
+    // Far smaller than what goes through, so the writer has to wait.
+    let (mut writer, reader) = pipe(16);
+    let t = thread::spawn(move || {
+        for i in 0..5 {
+            writeln!(writer, "line {i}: {}", "=".repeat(i * 4)).unwrap();
+        }
+        // Dropping the writer ends the stream.
+    });
+    for line in BufReader::new(reader).lines() {
+        println!("{}", line.unwrap());
+    }
+    t.join().unwrap();
+
+    let (mut writer, reader) = pipe(16);
+    drop(reader);
+    println!(
+        "with no reader: {:?}",
+        writer.write(b"hello").unwrap_err().kind()
+    );
+}
@@ -0,0 +1,68 @@
+//! The orderings the spin lock and the one-shot channel use, as a type
+//! parameter, so they can be compiled with the wrong ones on purpose.
+//!
+//! Both take a `Profile`, `Correct` unless named: `Acquire` to take the
+//! lock or the message, `Release` to give it up or send it. With the
+//! `teaching` feature there are two more. `TooWeak` makes both `Relaxed`,
+//! and nothing is published anymore: a thread can take the lock, or the
+//! message, and still see what was there before. That's a data race on
+//! whatever the lock or channel holds, undefined behavior, so their
+//! `with_profile` is `unsafe`, and the demos keep their own data in
+//! `Relaxed` atomics, to see what goes wrong without it. `TooStrong` makes
+//! both `SeqCst`, which is just as correct, and only costs more, on some
+//! targets.
+
+use core::sync::atomic::{
+    fence,
+    Ordering::{self, Acquire, Relaxed, Release},
+};
+
+/// The orderings to take and to give up with.
+pub trait Profile {
+    const NAME: &'static str;
+    /// For loads that see what the other side released.
+    const ACQUIRE: Ordering;
+    /// For stores that publish what this side wrote.
+    const RELEASE: Ordering;
+
+    /// `fence(ACQUIRE)`, or nothing for `Relaxed`, which a fence can't be.
+    fn acquire_fence() {
+        if Self::ACQUIRE != Relaxed {
+            fence(Self::ACQUIRE);
+        }
+    }
+}
+
+/// `Acquire` and `Release`, just enough.
+#[derive(Debug)]
+pub struct Correct;
+
+impl Profile for Correct {
+    const NAME: &'static str = "correct";
+    const ACQUIRE: Ordering = Acquire;
+    const RELEASE: Ordering = Release;
+}
+
+/// `Relaxed` both ways: broken.
+#[cfg(feature = "teaching")]
+#[derive(Debug)]
+pub struct TooWeak;
+
+#[cfg(feature = "teaching")]
+impl Profile for TooWeak {
+    const NAME: &'static str = "too weak";
+    const ACQUIRE: Ordering = Relaxed;
+    const RELEASE: Ordering = Relaxed;
+}
+
+/// `SeqCst` both ways: correct, but more than needed.
+#[cfg(feature = "teaching")]
+#[derive(Debug)]
+pub struct TooStrong;
+
+#[cfg(feature = "teaching")]
+impl Profile for TooStrong {
+    const NAME: &'static str = "too strong";
+    const ACQUIRE: Ordering = Ordering::SeqCst;
+    const RELEASE: Ordering = Ordering::SeqCst;
+}
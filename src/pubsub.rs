@@ -0,0 +1,255 @@
+//! An in-process, topic-based message bus.
+//!
+//! Every subscriber has its own bounded buffer. A publisher never waits for
+//! slow subscribers: when a buffer is full its oldest value is dropped,
+//! and the subscriber finds out how many it missed on its next `recv`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::Duration,
+};
+
+use crate::error::{Error, Result};
+
+pub struct Bus<T> {
+    topics: Mutex<HashMap<String, Vec<Subscriber<T>>>>,
+}
+
+enum Subscriber<T> {
+    Queue(Weak<Mailbox<T>>),
+    Callback(Arc<dyn Fn(&T) + Send + Sync>),
+}
+
+impl<T> Clone for Subscriber<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Subscriber::Queue(m) => Subscriber::Queue(m.clone()),
+            Subscriber::Callback(f) => Subscriber::Callback(f.clone()),
+        }
+    }
+}
+
+struct Mailbox<T> {
+    state: Mutex<MailboxState<T>>,
+    item_ready: Condvar,
+}
+
+struct MailboxState<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    /// Dropped since the last `recv`.
+    lagged: u64,
+    closed: bool,
+}
+
+impl<T> Mailbox<T> {
+    fn push(&self, value: T) {
+        let mut state = self.state.lock().unwrap();
+        if state.queue.len() == state.capacity {
+            state.queue.pop_front();
+            state.lagged += 1;
+        }
+        state.queue.push_back(value);
+        drop(state);
+        self.item_ready.notify_one();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.item_ready.notify_all();
+    }
+}
+
+impl<T: Clone> Bus<T> {
+    pub fn new() -> Self {
+        Self {
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Buffers up to `capacity` values published on `topic`.
+    /// Dropping the subscription unsubscribes.
+    pub fn subscribe(&self, topic: &str, capacity: usize) -> Subscription<T> {
+        assert!(capacity > 0, "capacity must be at least 1");
+        let mailbox = Arc::new(Mailbox {
+            state: Mutex::new(MailboxState {
+                queue: VecDeque::with_capacity(capacity),
+                capacity,
+                lagged: 0,
+                closed: false,
+            }),
+            item_ready: Condvar::new(),
+        });
+        self.add(topic, Subscriber::Queue(Arc::downgrade(&mailbox)));
+        Subscription { mailbox }
+    }
+
+    /// Calls `f` on the publishing thread for every value on `topic`.
+    /// Callbacks stay subscribed as long as the bus lives.
+    pub fn subscribe_fn<F>(&self, topic: &str, f: F)
+    where
+        F: Fn(&T) + Send + Sync + 'static,
+    {
+        self.add(topic, Subscriber::Callback(Arc::new(f)));
+    }
+
+    fn add(&self, topic: &str, subscriber: Subscriber<T>) {
+        self.topics
+            .lock()
+            .unwrap()
+            .entry(topic.to_owned())
+            .or_default()
+            .push(subscriber);
+    }
+
+    /// Returns how many subscribers got the value.
+    pub fn publish(&self, topic: &str, value: T) -> usize {
+        // Deliver outside the lock, so callbacks may publish or subscribe too.
+        let subscribers = {
+            let mut topics = self.topics.lock().unwrap();
+            let Some(subscribers) = topics.get_mut(topic) else {
+                return 0;
+            };
+            subscribers.retain(|s| match s {
+                Subscriber::Queue(m) => m.strong_count() > 0,
+                Subscriber::Callback(_) => true,
+            });
+            subscribers.clone()
+        };
+        let mut delivered = 0;
+        for subscriber in subscribers {
+            match subscriber {
+                Subscriber::Queue(mailbox) => {
+                    if let Some(mailbox) = mailbox.upgrade() {
+                        mailbox.push(value.clone());
+                        delivered += 1;
+                    }
+                }
+                Subscriber::Callback(f) => {
+                    f(&value);
+                    delivered += 1;
+                }
+            }
+        }
+        delivered
+    }
+}
+
+impl<T: Clone> Default for Bus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Bus<T> {
+    fn drop(&mut self) {
+        for subscribers in self.topics.get_mut().unwrap().values() {
+            for subscriber in subscribers {
+                if let Subscriber::Queue(mailbox) = subscriber {
+                    if let Some(mailbox) = mailbox.upgrade() {
+                        mailbox.close();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct Subscription<T> {
+    mailbox: Arc<Mailbox<T>>,
+}
+
+impl<T> Subscription<T> {
+    /// Blocks until a value arrives. `Error::Lagged` if values were
+    /// dropped because the buffer was full, `Error::Closed` once the bus is
+    /// gone and the buffer empty.
+    pub fn recv(&self) -> Result<T> {
+        let mut state = self.mailbox.state.lock().unwrap();
+        loop {
+            if let Some(result) = Self::take(&mut state) {
+                return result;
+            }
+            state = self.mailbox.item_ready.wait(state).unwrap();
+        }
+    }
+
+    /// Like `recv`, or `Error::Timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T> {
+        let state = self.mailbox.state.lock().unwrap();
+        let (mut state, _) = self
+            .mailbox
+            .item_ready
+            .wait_timeout_while(state, timeout, |s| {
+                s.queue.is_empty() && s.lagged == 0 && !s.closed
+            })
+            .unwrap();
+        Self::take(&mut state).unwrap_or(Err(Error::Timeout))
+    }
+
+    /// Receives for as long as each value comes within `gap` of the one
+    /// before. Ends on `Error::Timeout` or `Closed`, and yields
+    /// `Lagged`, which doesn't end anything.
+    pub fn iter_timeout(&self, gap: Duration) -> IterTimeout<'_, T> {
+        IterTimeout {
+            subscription: self,
+            gap,
+        }
+    }
+
+    /// `None` if there's nothing to receive right now.
+    pub fn try_recv(&self) -> Option<Result<T>> {
+        Self::take(&mut self.mailbox.state.lock().unwrap())
+    }
+
+    fn take(state: &mut MailboxState<T>) -> Option<Result<T>> {
+        if state.lagged > 0 {
+            return Some(Err(Error::Lagged(std::mem::take(&mut state.lagged))));
+        }
+        if let Some(value) = state.queue.pop_front() {
+            return Some(Ok(value));
+        }
+        state.closed.then_some(Err(Error::Closed))
+    }
+}
+
+pub struct IterTimeout<'a, T> {
+    subscription: &'a Subscription<T>,
+    gap: Duration,
+}
+
+impl<T> Iterator for IterTimeout<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.subscription.recv_timeout(self.gap) {
+            Err(Error::Timeout | Error::Closed) => None,
+            result => Some(result),
+        }
+    }
+}
+
+pub fn main() {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    let bus = Bus::new();
+    let fast = bus.subscribe("ticks", 100);
+    let slow = bus.subscribe("ticks", 3);
+    let logged = Arc::new(AtomicUsize::new(0));
+    let l = logged.clone();
+    bus.subscribe_fn("ticks", move |_| {
+        l.fetch_add(1, Relaxed);
+    });
+
+    for i in 0..10 {
+        bus.publish("ticks", i);
+    }
+    bus.publish("nobody-listens", 99);
+
+    let fast: Vec<_> = fast.try_recv().into_iter().collect();
+    println!("fast subscriber starts with {fast:?}");
+    while let Some(result) = slow.try_recv() {
+        println!("slow subscriber: {result:?}");
+    }
+    println!("callback saw {} values", logged.load(Relaxed));
+}
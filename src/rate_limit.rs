@@ -0,0 +1,159 @@
+//! A token bucket that threads share without a lock, and a sender that
+//! takes a token from one before every message.
+//!
+//! The bucket isn't a count of tokens refilled by a timer, but the time at
+//! which it would be full again if nothing else were taken (the "generic
+//! cell rate algorithm"): taking a token pushes that time one interval
+//! later, and it may be at most `burst` intervals ahead of now. That's one
+//! number, so taking a token is one compare-and-swap, and nothing has to
+//! run in between to refill it.
+
+use std::{
+    ops::Deref,
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{atomic_u64::AtomicU64, error::Error, mutex_channel::Channel};
+
+pub struct TokenBucket {
+    start: Instant,
+    /// Nanoseconds from `start` to when the bucket is full again.
+    full_at: AtomicU64,
+    /// Nanoseconds a token takes to come back.
+    interval: u64,
+    /// How far `full_at` may be ahead of now: `burst` intervals.
+    tolerance: u64,
+}
+
+impl TokenBucket {
+    /// `per_second` tokens a second, and up to `burst` at once after a
+    /// quiet spell. Starts full.
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        assert!(per_second > 0, "the rate must be more than 0 a second");
+        let interval = 1_000_000_000 / u64::from(per_second);
+        Self {
+            start: Instant::now(),
+            full_at: AtomicU64::new(0),
+            interval,
+            tolerance: interval * u64::from(burst.max(1)),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// Takes a token, or says how long until there's one.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let now = self.now();
+        // `Relaxed`: the only thing shared is the time itself.
+        let mut full_at = self.full_at.load(Relaxed);
+        loop {
+            let new = full_at.max(now) + self.interval;
+            if new > now + self.tolerance {
+                return Err(Duration::from_nanos(new - self.tolerance - now));
+            }
+            match self
+                .full_at
+                .compare_exchange_weak(full_at, new, Relaxed, Relaxed)
+            {
+                Ok(_) => return Ok(()),
+                Err(f) => full_at = f,
+            }
+        }
+    }
+
+    /// Sleeps until there's a token, and takes it.
+    pub fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// What a `Throttled` sender does when the bucket is empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Throttle {
+    /// Sleeps until there's a token.
+    Block,
+    /// Gives the message back, with `Error::RateLimited`.
+    Fail,
+}
+
+/// A `Channel`, or a reference to one, that only sends as fast as a
+/// `TokenBucket` lets it. Share the bucket between producers to hold all
+/// of them to one budget.
+pub struct Throttled<C> {
+    channel: C,
+    bucket: Arc<TokenBucket>,
+    policy: Throttle,
+}
+
+impl<T, C: Deref<Target = Channel<T>>> Throttled<C> {
+    pub fn new(channel: C, bucket: Arc<TokenBucket>, policy: Throttle) -> Self {
+        Self {
+            channel,
+            bucket,
+            policy,
+        }
+    }
+
+    /// Takes a token, then sends. Gives the message back with
+    /// `Error::RateLimited` if there's no token and the policy is `Fail`,
+    /// or with `Error::Closed` if the channel is closed. The token is
+    /// spent either way.
+    pub fn send(&self, message: T) -> Result<(), (T, Error)> {
+        match self.policy {
+            Throttle::Block => self.bucket.acquire(),
+            Throttle::Fail => {
+                if self.bucket.try_acquire().is_err() {
+                    return Err((message, Error::RateLimited));
+                }
+            }
+        }
+        self.channel
+            .try_send(message)
+            .map_err(|message| (message, Error::Closed))
+    }
+
+    pub fn into_inner(self) -> C {
+        self.channel
+    }
+}
+
+impl<C> Deref for Throttled<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.channel
+    }
+}
+
+pub fn main() {
+    // Two producers, ten messages each, twenty a second between them.
+    let bucket = Arc::new(TokenBucket::new(20, 5));
+    let channel = Channel::new();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for p in 0..2 {
+            let sender = Throttled::new(&channel, bucket.clone(), Throttle::Block);
+            s.spawn(move || {
+                for i in 0..10 {
+                    sender.send(p * 10 + i).unwrap();
+                }
+            });
+        }
+    });
+    channel.close();
+    let n = std::iter::from_fn(|| channel.receive()).count();
+    println!("{n} sent in {:?}, the first 5 at once", start.elapsed());
+
+    let channel = Channel::new();
+    let sender = Throttled::new(&channel, Arc::new(TokenBucket::new(20, 5)), Throttle::Fail);
+    let refused = (0..10)
+        .filter(|&i| matches!(sender.send(i), Err((_, Error::RateLimited))))
+        .count();
+    println!("without waiting, {refused} of 10 refused");
+}
@@ -0,0 +1,252 @@
+//! A log that any thread can write to, and a signal handler too: records
+//! are copied into a fixed ring of fixed-size slots with nothing but atomic
+//! operations. No allocation, no lock, no waiting: when the ring is full the
+//! record is dropped, and counted. Some other thread drains it, and does the
+//! actual writing out, in one piece per record.
+//!
+//! The ring is Dmitry Vyukov's bounded queue: every slot has a sequence
+//! number that says whose turn it is, a writer's or a reader's, for which
+//! lap around the ring. Claiming a slot is a compare-and-swap on the tail,
+//! then the record is copied in, then the sequence number published. A
+//! handler that interrupts a writer between the two just takes the next
+//! slot; the drain stops at the interrupted one until it's finished.
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Write},
+    sync::atomic::{
+        AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::cache_padded::CachePadded;
+
+struct Slot<const BYTES: usize> {
+    /// The position it's for, minus the slot's index, so that it starts at
+    /// 0 for all of them and a ring can be a `static`: `lap * SLOTS` while
+    /// empty for that lap, one more once written.
+    seq: AtomicUsize,
+    len: UnsafeCell<usize>,
+    bytes: UnsafeCell<[u8; BYTES]>,
+}
+
+impl<const BYTES: usize> Slot<BYTES> {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            len: UnsafeCell::new(0),
+            bytes: UnsafeCell::new([0; BYTES]),
+        }
+    }
+}
+
+/// `SLOTS` records of up to `BYTES` bytes each; longer ones are cut short.
+pub struct RingLog<const SLOTS: usize, const BYTES: usize = 128> {
+    slots: [Slot<BYTES>; SLOTS],
+    /// Where the next record goes.
+    tail: CachePadded<AtomicUsize>,
+    /// Where the next one is drained from.
+    head: CachePadded<AtomicUsize>,
+    dropped: AtomicUsize,
+}
+
+// A slot's bytes are only touched by whoever claimed it, see `seq`.
+unsafe impl<const SLOTS: usize, const BYTES: usize> Sync for RingLog<SLOTS, BYTES> {}
+
+impl<const SLOTS: usize, const BYTES: usize> RingLog<SLOTS, BYTES> {
+    pub const fn new() -> Self {
+        // So positions can wrap around `usize` and still land on the same slot.
+        assert!(SLOTS.is_power_of_two(), "SLOTS must be a power of two");
+        Self {
+            slots: [const { Slot::new() }; SLOTS],
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// The slot of `pos`, and the `seq` it has while empty for that lap.
+    fn slot(&self, pos: usize) -> (&Slot<BYTES>, usize) {
+        let i = pos % SLOTS;
+        (&self.slots[i], pos.wrapping_sub(i))
+    }
+
+    /// Claims a slot, lets `write` fill it in, and publishes it. `false`
+    /// if the ring was full, and nothing was written.
+    fn push(&self, write: impl FnOnce(&mut [u8; BYTES]) -> usize) -> bool {
+        let mut pos = self.tail.load(Relaxed);
+        let (slot, lap) = loop {
+            let (slot, lap) = self.slot(pos);
+            let seq = slot.seq.load(Acquire);
+            match seq.wrapping_sub(lap) as isize {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                ) {
+                    Ok(_) => break (slot, lap),
+                    Err(p) => pos = p,
+                },
+                // Not drained yet, from the lap before.
+                d if d < 0 => {
+                    self.dropped.fetch_add(1, Relaxed);
+                    return false;
+                }
+                // Someone else took it, and may have written it already.
+                _ => pos = self.tail.load(Relaxed),
+            }
+        };
+        // Safety: we claimed it, and no one reads it until `seq` says so.
+        unsafe { *slot.len.get() = write(&mut *slot.bytes.get()) };
+        slot.seq.store(lap.wrapping_add(1), Release);
+        true
+    }
+
+    /// Copies `record` in, as much of it as fits. Async-signal-safe.
+    pub fn append(&self, record: &[u8]) -> bool {
+        self.push(|bytes| {
+            let len = record.len().min(BYTES);
+            bytes[..len].copy_from_slice(&record[..len]);
+            len
+        })
+    }
+
+    /// Formats straight into a slot, e.g. with `format_args!`. Doesn't
+    /// allocate, so it's as safe in a signal handler as the `Display` impls
+    /// it calls.
+    pub fn append_fmt(&self, args: fmt::Arguments<'_>) -> bool {
+        struct Cursor<'a>(&'a mut [u8], usize);
+        impl Write for Cursor<'_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                let n = s.len().min(self.0.len() - self.1);
+                self.0[self.1..self.1 + n].copy_from_slice(&s.as_bytes()[..n]);
+                self.1 += n;
+                Ok(())
+            }
+        }
+        self.push(|bytes| {
+            let mut cursor = Cursor(bytes, 0);
+            let _ = cursor.write_fmt(args);
+            cursor.1
+        })
+    }
+
+    /// Hands the oldest record to `f`, if there's one written.
+    pub fn pop(&self, f: impl FnOnce(&[u8])) -> bool {
+        let mut pos = self.head.load(Relaxed);
+        let (slot, lap) = loop {
+            let (slot, lap) = self.slot(pos);
+            let seq = slot.seq.load(Acquire);
+            match seq.wrapping_sub(lap.wrapping_add(1)) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                ) {
+                    Ok(_) => break (slot, lap),
+                    Err(p) => pos = p,
+                },
+                // Empty, or claimed but not written yet.
+                d if d < 0 => return false,
+                _ => pos = self.head.load(Relaxed),
+            }
+        };
+        // Safety: we claimed it, and no writer gets it until `seq` says so.
+        unsafe {
+            let bytes = &*slot.bytes.get();
+            f(&bytes[..*slot.len.get()]);
+        }
+        slot.seq.store(lap.wrapping_add(SLOTS), Release);
+        true
+    }
+
+    /// Pops records until there are none, and returns how many there were.
+    pub fn drain(&self, mut f: impl FnMut(&[u8])) -> usize {
+        let mut n = 0;
+        while self.pop(&mut f) {
+            n += 1;
+        }
+        n
+    }
+
+    /// Records that didn't fit, since the start.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Relaxed)
+    }
+
+    /// Drains into `out`, a line per record.
+    #[cfg(feature = "std")]
+    pub fn drain_to(&self, out: &mut impl std::io::Write) -> std::io::Result<usize> {
+        let mut result = Ok(());
+        let n = self.drain(|record| {
+            if result.is_ok() {
+                result = out.write_all(record).and_then(|()| out.write_all(b"\n"));
+            }
+        });
+        result.map(|()| n)
+    }
+}
+
+impl<const SLOTS: usize, const BYTES: usize> Default for RingLog<SLOTS, BYTES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SLOTS: usize, const BYTES: usize> fmt::Debug for RingLog<SLOTS, BYTES> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingLog")
+            .field("slots", &SLOTS)
+            .field("bytes", &BYTES)
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn main() {
+    use std::{io, sync::atomic::AtomicBool, thread, time::Duration};
+
+    static LOG: RingLog<64> = RingLog::new();
+
+    #[cfg(unix)]
+    extern "C" fn on_signal(_: libc::c_int) {
+        LOG.append(b"handler: got SIGUSR1");
+    }
+    #[cfg(unix)]
+    // Safety: the handler only appends to the log.
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_signal as *const () as libc::sighandler_t);
+    }
+
+    let done = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| {
+            let mut out = io::stdout().lock();
+            while !done.load(Acquire) {
+                LOG.drain_to(&mut out).unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+            LOG.drain_to(&mut out).unwrap();
+        });
+        thread::scope(|s| {
+            for t in 0..3 {
+                s.spawn(move || {
+                    for i in 0..3 {
+                        LOG.append_fmt(format_args!("thread {t}: step {i} of 3"));
+                    }
+                    #[cfg(unix)]
+                    if t == 1 {
+                        // Safety: raising a signal we handle.
+                        unsafe { libc::raise(libc::SIGUSR1) };
+                    }
+                });
+            }
+        });
+        done.store(true, Release);
+    });
+    println!("{LOG:?}");
+}
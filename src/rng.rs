@@ -0,0 +1,120 @@
+//! Random numbers for tests and demos that want to shake up scheduling,
+//! reproducibly: one process-wide seed, and a generator per thread derived
+//! from it.
+//!
+//! The generator is SplitMix64: one `u64` of state, a constant added per
+//! number and the result mixed. Not for anything that needs to be hard to
+//! guess, but fast, and each seed gives its own sequence. A thread's
+//! generator is made on first use, from the seed and the next number of a
+//! global counter, its stream. Which thread gets which stream depends on
+//! which asks first, so a test that needs the same numbers in the same
+//! threads every run picks the streams itself, with `seed_thread`.
+
+use std::{
+    cell::Cell,
+    sync::atomic::{
+        AtomicU64, AtomicU8,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Added to the state for every number: 2^64 over the golden ratio.
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, near enough evenly for an `n` far below 2^64.
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    /// `true` one time in `n`.
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+const UNSET: u8 = 0;
+const PICKING: u8 = 1;
+const SET: u8 = 2;
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+/// Whether `SEED` was set, or still has to be picked.
+static STATE: AtomicU8 = AtomicU8::new(UNSET);
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD: Cell<Option<SplitMix64>> = const { Cell::new(None) };
+}
+
+/// Sets the seed every thread's generator is derived from, and starts
+/// the streams over. Threads that have one already keep it.
+pub fn set_seed(seed: u64) {
+    SEED.store(seed, Relaxed);
+    STATE.store(SET, Release);
+    NEXT_STREAM.store(0, Relaxed);
+}
+
+/// The seed, picked from the clock the first time if no one set it.
+pub fn seed() -> u64 {
+    // Whoever picks one first, everyone uses that one. `Acquire` and
+    // `Release`: `SET` publishes `SEED`.
+    if STATE
+        .compare_exchange(UNSET, PICKING, Relaxed, Relaxed)
+        .is_ok()
+    {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        SEED.store(now, Relaxed);
+        STATE.store(SET, Release);
+    }
+    while STATE.load(Acquire) != SET {
+        std::hint::spin_loop();
+    }
+    SEED.load(Relaxed)
+}
+
+/// The generator of stream `stream` of the seed.
+pub fn stream(stream: u64) -> SplitMix64 {
+    // Mixed, so streams 0 and 1 don't start one number apart.
+    let mut mix = SplitMix64::new(seed() ^ stream.wrapping_mul(GAMMA));
+    SplitMix64::new(mix.next_u64())
+}
+
+/// Makes this thread's generator stream `stream`, from its start.
+pub fn seed_thread(stream: u64) {
+    THREAD.set(Some(self::stream(stream)));
+}
+
+/// Runs `f` with this thread's generator.
+pub fn with<R>(f: impl FnOnce(&mut SplitMix64) -> R) -> R {
+    let mut rng = THREAD
+        .take()
+        .unwrap_or_else(|| stream(NEXT_STREAM.fetch_add(1, Relaxed)));
+    let result = f(&mut rng);
+    THREAD.set(Some(rng));
+    result
+}
+
+/// A number in `0..n`, from this thread's generator.
+pub fn below(n: u64) -> u64 {
+    with(|rng| rng.below(n))
+}
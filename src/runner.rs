@@ -0,0 +1,374 @@
+//! Picks the demos to run from the command line, instead of editing `main`:
+//!
+//! ```text
+//! cargo run -- list
+//! cargo run -- cap_3 release_acquire relaxed
+//! cargo run -- cap_9 --threads 8 --iterations 100000
+//! cargo run -- cap_3 relaxed --iterations 10000 --output json
+//! cargo run -- cap_3 relaxed --iterations 10000 --seed 42
+//! ```
+//!
+//! Names match with or without their `_`, or with `-` for it: `cap3
+//! release-acquire` is `cap_3 release_acquire`.
+//!
+//! Chapters 1 to 5 are only there with their features, `chapter1` to
+//! `chapter5`, all on by default.
+//!
+//! A chapter on its own runs all of its demos. Demos that spawn threads or
+//! loop ask `threads` and `iterations`, the others ignore the flags.
+//!
+//! Litmus tests run `iterations` times and `observe` what they saw each
+//! time; the runner counts the outcomes. With `--output json`, the last line
+//! of stdout is a summary of every demo, its timing, counts and outcomes,
+//! to compare machines with. Demos that keep a `StatsSet` report its
+//! last snapshot with `stats`, into both.
+//!
+//! Their threads `jitter` first, a random little while, so they don't
+//! always start in the same order. The randomness is `thread::rng`, from
+//! the seed `--seed` gives, or one picked and printed, to run with again.
+
+use atomics_and_locks::{metrics::StatsSnapshot, thread::rng};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write,
+    process::exit,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub struct Demo {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub run: fn(),
+}
+
+pub struct Chapter {
+    pub name: &'static str,
+    pub demos: &'static [Demo],
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Output {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Default)]
+struct Flags {
+    iterations: Option<usize>,
+    threads: Option<usize>,
+    seed: Option<u64>,
+    output: Output,
+}
+
+static FLAGS: OnceLock<Flags> = OnceLock::new();
+
+/// What the demo that's running used and saw.
+#[derive(Default)]
+struct Record {
+    iterations: Option<usize>,
+    threads: Option<usize>,
+    outcomes: BTreeMap<String, u64>,
+    stats: Vec<(&'static str, u64)>,
+}
+
+static CURRENT: Mutex<Record> = Mutex::new(Record {
+    iterations: None,
+    threads: None,
+    outcomes: BTreeMap::new(),
+    stats: Vec::new(),
+});
+
+/// `--iterations`, or `default` when it wasn't given.
+pub fn iterations(default: usize) -> usize {
+    let n = FLAGS.get().and_then(|f| f.iterations).unwrap_or(default);
+    CURRENT.lock().unwrap().iterations = Some(n);
+    n
+}
+
+/// `--threads`, or `default` when it wasn't given.
+pub fn threads(default: usize) -> usize {
+    let n = FLAGS.get().and_then(|f| f.threads).unwrap_or(default);
+    CURRENT.lock().unwrap().threads = Some(n);
+    n
+}
+
+/// Reports the counters of a `StatsSet`, for the running demo.
+// Only chapter 2 has one.
+#[cfg_attr(not(feature = "chapter2"), allow(dead_code))]
+pub fn stats<const N: usize>(snapshot: &StatsSnapshot<N>) {
+    CURRENT.lock().unwrap().stats = snapshot.iter().collect();
+}
+
+/// Spins for a random while of up to a few microseconds, from stream
+/// `stream` of the seed: give each thread of each iteration its own, for
+/// the same run with the same seed.
+// The litmus tests are all in chapter 3.
+#[cfg_attr(not(feature = "chapter3"), allow(dead_code))]
+pub fn jitter(stream: u64) {
+    rng::seed_thread(stream);
+    for _ in 0..rng::below(1000) {
+        std::hint::spin_loop();
+    }
+}
+
+/// Counts one more time that the running demo saw `outcome`.
+#[cfg_attr(not(feature = "chapter3"), allow(dead_code))]
+pub fn observe(outcome: impl Into<String>) {
+    *CURRENT
+        .lock()
+        .unwrap()
+        .outcomes
+        .entry(outcome.into())
+        .or_default() += 1;
+}
+
+struct DemoResult {
+    chapter: &'static str,
+    demo: &'static str,
+    elapsed: Duration,
+    record: Record,
+}
+
+enum Command {
+    List,
+    Run { chapter: String, demos: Vec<String> },
+}
+
+fn parse(args: impl IntoIterator<Item = String>) -> Result<(Command, Flags), String> {
+    let mut flags = Flags::default();
+    let mut words = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "--iterations" | "-n" => &mut flags.iterations,
+            "--threads" | "-t" => &mut flags.threads,
+            "--output" | "-o" => {
+                flags.output = match args.next().as_deref() {
+                    Some("text") => Output::Text,
+                    Some("json") => Output::Json,
+                    _ => return Err(format!("{arg} needs text or json")),
+                };
+                continue;
+            }
+            "--seed" | "-s" => {
+                let value = args.next().ok_or_else(|| format!("{arg} needs a number"))?;
+                match value.parse() {
+                    Ok(seed) => flags.seed = Some(seed),
+                    Err(_) => return Err(format!("{arg} needs a number, not {value}")),
+                }
+                continue;
+            }
+            "--list" | "-l" => {
+                words.insert(0, "list".to_string());
+                continue;
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown flag {flag}")),
+            _ => {
+                words.push(arg);
+                continue;
+            }
+        };
+        let value = args.next().ok_or_else(|| format!("{arg} needs a number"))?;
+        match value.parse() {
+            Ok(n) if n > 0 => *slot = Some(n),
+            _ => return Err(format!("{arg} needs a number above 0, not {value}")),
+        }
+    }
+    let mut words = words.into_iter();
+    let command = match words.next() {
+        None => return Err("which demos?".to_string()),
+        Some(word) if word == "list" => Command::List,
+        Some(chapter) => Command::Run {
+            chapter,
+            demos: words.collect(),
+        },
+    };
+    Ok((command, flags))
+}
+
+fn usage(chapters: &[Chapter]) {
+    eprintln!(
+        "usage: atomics_and_locks <chapter> [demo...] [--iterations N] [--threads N] [--seed N] [--output json]"
+    );
+    eprintln!("       atomics_and_locks list");
+    eprintln!();
+    eprintln!(
+        "chapters: {}",
+        chapters
+            .iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+fn list(chapters: &[Chapter]) {
+    for chapter in chapters {
+        println!("{}", chapter.name);
+        for demo in chapter.demos {
+            println!("    {:<20} {}", demo.name, demo.about);
+        }
+    }
+}
+
+fn run_one(chapter: &Chapter, demo: &Demo, output: &Output) -> DemoResult {
+    if *output == Output::Text {
+        println!("== {} {}", chapter.name, demo.name);
+    }
+    *CURRENT.lock().unwrap() = Record::default();
+    let start = Instant::now();
+    (demo.run)();
+    let elapsed = start.elapsed();
+    let record = std::mem::take(&mut *CURRENT.lock().unwrap());
+    if *output == Output::Text {
+        for (outcome, count) in &record.outcomes {
+            println!("   {count:>8}x {outcome}");
+        }
+        for (name, value) in &record.stats {
+            println!("   {name} = {value}");
+        }
+        println!("== {} {} took {elapsed:?}", chapter.name, demo.name);
+    }
+    DemoResult {
+        chapter: chapter.name,
+        demo: demo.name,
+        elapsed,
+        record,
+    }
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_number(out: &mut String, n: Option<usize>) {
+    match n {
+        Some(n) => write!(out, "{n}").unwrap(),
+        None => out.push_str("null"),
+    }
+}
+
+/// One line of JSON, by hand: it's not worth a dependency.
+fn to_json(results: &[DemoResult]) -> String {
+    let mut out = String::new();
+    out.push_str("{\"arch\":");
+    json_string(&mut out, env::consts::ARCH);
+    out.push_str(",\"os\":");
+    json_string(&mut out, env::consts::OS);
+    out.push_str(",\"cpus\":");
+    json_number(
+        &mut out,
+        thread::available_parallelism().ok().map(|n| n.get()),
+    );
+    write!(out, ",\"seed\":{}", rng::seed()).unwrap();
+    out.push_str(",\"demos\":[");
+    for (i, result) in results.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"chapter\":");
+        json_string(&mut out, result.chapter);
+        out.push_str(",\"demo\":");
+        json_string(&mut out, result.demo);
+        write!(out, ",\"seconds\":{}", result.elapsed.as_secs_f64()).unwrap();
+        out.push_str(",\"iterations\":");
+        json_number(&mut out, result.record.iterations);
+        out.push_str(",\"threads\":");
+        json_number(&mut out, result.record.threads);
+        out.push_str(",\"outcomes\":{");
+        for (j, (outcome, count)) in result.record.outcomes.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            json_string(&mut out, outcome);
+            write!(out, ":{count}").unwrap();
+        }
+        out.push_str("},\"stats\":{");
+        for (j, (name, value)) in result.record.stats.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            json_string(&mut out, name);
+            write!(out, ":{value}").unwrap();
+        }
+        out.push_str("}}");
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Whether `arg` names `name`, leaving out the `_` and `-` in both.
+fn same_name(name: &str, arg: &str) -> bool {
+    let letters = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, '_' | '-'))
+            .collect::<String>()
+    };
+    letters(name) == letters(arg)
+}
+
+/// Parses the command line and runs what it asks for. Exits on bad arguments.
+pub fn run(chapters: &[Chapter]) {
+    let (command, flags) = match parse(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}");
+            usage(chapters);
+            exit(2);
+        }
+    };
+    FLAGS.set(flags).unwrap();
+
+    let (chapter, names) = match command {
+        Command::List => return list(chapters),
+        Command::Run { chapter, demos } => (chapter, demos),
+    };
+    let Some(chapter) = chapters.iter().find(|c| same_name(c.name, &chapter)) else {
+        eprintln!("no chapter {chapter}");
+        usage(chapters);
+        exit(2);
+    };
+    let demos: Vec<&Demo> = if names.is_empty() {
+        chapter.demos.iter().collect()
+    } else {
+        let mut demos = Vec::new();
+        for name in &names {
+            match chapter.demos.iter().find(|d| same_name(d.name, name)) {
+                Some(demo) => demos.push(demo),
+                None => {
+                    eprintln!("no demo {name} in {}", chapter.name);
+                    usage(chapters);
+                    exit(2);
+                }
+            }
+        }
+        demos
+    };
+    let flags = FLAGS.get().unwrap();
+    let output = &flags.output;
+    match flags.seed {
+        Some(seed) => rng::set_seed(seed),
+        None if *output == Output::Text => println!("== seed {}", rng::seed()),
+        None => {}
+    }
+    let results: Vec<DemoResult> = demos
+        .into_iter()
+        .map(|demo| run_one(chapter, demo, output))
+        .collect();
+    if *output == Output::Json {
+        println!("{}", to_json(&results));
+    }
+}
@@ -0,0 +1,213 @@
+//! The reader-writer lock of chapter 9, that doesn't let a stream of
+//! readers starve a writer, and a seqlock-style way to read without
+//! taking it at all.
+//!
+//! A writer bumps `version` to odd when it locks, and back to even when it
+//! unlocks. An optimistic reader copies the value with no lock, then checks
+//! the version is the same even number it was before: if so, no writer
+//! touched the value in between, and the copy is good. If not, it throws
+//! the copy away, and takes a read lock after all. Readers that don't lock
+//! don't write to anything shared either, so they don't slow each other
+//! down bouncing the state's cache line around.
+
+use crate::sys::{wait, wake_all, wake_one};
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{
+        fence, AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+pub struct RwLock<T> {
+    /// The number of read locks times two, plus one if there's a writer
+    /// waiting. `u32::MAX` if write locked.
+    state: AtomicU32,
+    /// Incremented to wake up writers.
+    writer_wake_counter: AtomicU32,
+    /// Odd while write locked, and goes up on every write.
+    version: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            writer_wake_counter: AtomicU32::new(0),
+            version: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let mut s = self.state.load(Relaxed);
+        loop {
+            if s.is_multiple_of(2) {
+                assert!(s < u32::MAX - 2, "too many readers");
+                match self.state.compare_exchange_weak(s, s + 2, Acquire, Relaxed) {
+                    Ok(_) => return ReadGuard { rwlock: self },
+                    Err(e) => s = e,
+                }
+            }
+            if s % 2 == 1 {
+                wait(&self.state, s);
+                s = self.state.load(Relaxed);
+            }
+        }
+    }
+
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        let mut s = self.state.load(Relaxed);
+        loop {
+            // Try to lock if unlocked.
+            if s <= 1 {
+                match self.state.compare_exchange(s, u32::MAX, Acquire, Relaxed) {
+                    Ok(_) => break,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+            // Block new readers, by making sure the state is odd.
+            if s.is_multiple_of(2) {
+                if let Err(e) = self.state.compare_exchange(s, s + 1, Relaxed, Relaxed) {
+                    s = e;
+                    continue;
+                }
+            }
+            // Wait, if it's still locked.
+            let w = self.writer_wake_counter.load(Acquire);
+            s = self.state.load(Relaxed);
+            if s >= 2 {
+                wait(&self.writer_wake_counter, w);
+                s = self.state.load(Relaxed);
+            }
+        }
+        self.version.fetch_add(1, Relaxed);
+        // Keeps the writes to the value from moving above the increment,
+        // where an optimistic reader could see them with the old version.
+        fence(Release);
+        WriteGuard { rwlock: self }
+    }
+
+    /// Copies the value without locking, for `Optimistic::validate` to
+    /// check afterwards. The copy races with writers, which the Rust memory
+    /// model calls undefined even for a copy that's thrown away; like
+    /// every seqlock in Rust, it's a volatile read and the checks that keep
+    /// a torn one from ever being used.
+    pub fn read_optimistic(&self) -> Optimistic<'_, T>
+    where
+        T: Copy,
+    {
+        let version = self.version.load(Acquire);
+        // Safety: see above. It's only a `T` once validated.
+        let copy = unsafe { ptr::read_volatile(self.value.get().cast::<MaybeUninit<T>>()) };
+        Optimistic {
+            rwlock: self,
+            version,
+            copy,
+        }
+    }
+
+    /// The value, without locking if no writer gets in the way.
+    pub fn read_copy(&self) -> T
+    where
+        T: Copy,
+    {
+        self.read_optimistic().or_read()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct ReadGuard<'a, T> {
+    rwlock: &'a RwLock<T>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees no one is writing.
+        unsafe { &*self.rwlock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // Decrement the state by 2 to remove one read-lock.
+        if self.rwlock.state.fetch_sub(2, Release) == 3 {
+            // From 3 to 1: unlocked now, and a writer is waiting. Wake it up.
+            self.rwlock.writer_wake_counter.fetch_add(1, Release);
+            wake_one(&self.rwlock.writer_wake_counter);
+        }
+    }
+}
+
+pub struct WriteGuard<'a, T> {
+    rwlock: &'a RwLock<T>,
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.rwlock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.rwlock.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // Even again, after the writes.
+        self.rwlock.version.fetch_add(1, Release);
+        self.rwlock.state.store(0, Release);
+        self.rwlock.writer_wake_counter.fetch_add(1, Release);
+        wake_one(&self.rwlock.writer_wake_counter);
+        wake_all(&self.rwlock.state);
+    }
+}
+
+/// A copy of the value taken without the lock, and the version it was
+/// taken at: the token to check it against.
+pub struct Optimistic<'a, T> {
+    rwlock: &'a RwLock<T>,
+    version: u32,
+    copy: MaybeUninit<T>,
+}
+
+impl<T: Copy> Optimistic<'_, T> {
+    /// The copy, if no writer held the lock when it was taken, or took it
+    /// since.
+    pub fn validate(self) -> Option<T> {
+        // Keeps the copy from moving below the second load.
+        fence(Acquire);
+        let now = self.rwlock.version.load(Relaxed);
+        // Safety: no writer in between, so it's a whole `T`.
+        (self.version.is_multiple_of(2) && now == self.version)
+            .then(|| unsafe { self.copy.assume_init() })
+    }
+
+    /// The copy if it's good, or the value under a read lock if not.
+    pub fn or_read(self) -> T {
+        let rwlock = self.rwlock;
+        self.validate().unwrap_or_else(|| *rwlock.read())
+    }
+}
@@ -0,0 +1,149 @@
+//! `thread::scope`, with what it leaves out: every child's panic instead
+//! of a panic of its own for the first, names for the children, and a
+//! token that tells them when to stop.
+//!
+//! Each child runs under `catch_unwind`, so `thread::scope` never sees a
+//! panic; the panics are collected, with the name of the child, and
+//! `scope` returns them all. The token is cancelled by the first panic,
+//! and when the closure given to `scope` is done, so children that loop
+//! until cancelled don't keep the scope from ending.
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread::{self, ScopedJoinHandle},
+};
+
+use crate::{
+    cancellation::CancellationToken,
+    join::{panic_message, Panic},
+    threads::ThreadBuilder,
+};
+
+/// A child's panic, with its name if it had one.
+#[derive(Debug)]
+pub struct ChildPanic {
+    pub name: Option<String>,
+    pub panic: Panic,
+}
+
+impl ChildPanic {
+    /// See `thread::panic_message`.
+    pub fn message(&self) -> Option<&str> {
+        panic_message(&self.panic)
+    }
+}
+
+struct State {
+    token: CancellationToken,
+    panics: Mutex<Vec<ChildPanic>>,
+}
+
+pub struct Scope<'scope, 'env: 'scope> {
+    inner: &'scope thread::Scope<'scope, 'env>,
+    state: Arc<State>,
+}
+
+/// A child of a `Scope`, to join for its result.
+pub struct Child<'scope, T>(ScopedJoinHandle<'scope, Option<T>>);
+
+/// Runs `f` with a `Scope` to spawn children on, and waits for all of them.
+/// `Err` with every child that panicked, in the order they did, if any
+/// did; `f`'s own panic is passed on, once they're all done.
+pub fn scope<'env, F, R>(f: F) -> Result<R, Vec<ChildPanic>>
+where
+    F: for<'scope> FnOnce(&Scope<'scope, 'env>) -> R,
+{
+    let state = Arc::new(State {
+        token: CancellationToken::new(),
+        panics: Mutex::new(Vec::new()),
+    });
+    let result = thread::scope(|s| {
+        let scope = Scope {
+            inner: s,
+            state: state.clone(),
+        };
+        /// Cancels the token once `f` is done, panic or not, before the
+        /// children are waited for.
+        struct Exit<'a>(&'a CancellationToken);
+        impl Drop for Exit<'_> {
+            fn drop(&mut self) {
+                self.0.cancel();
+            }
+        }
+        let _exit = Exit(&state.token);
+        f(&scope)
+    });
+    let panics = std::mem::take(&mut *state.panics.lock().unwrap());
+    if panics.is_empty() {
+        Ok(result)
+    } else {
+        Err(panics)
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    pub fn spawn<F, T>(&self, f: F) -> Child<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        self.spawn_with(ThreadBuilder::new(), None, f)
+    }
+
+    /// `spawn`, with the thread named, and registered under its name.
+    pub fn spawn_named<F, T>(&self, name: impl Into<String>, f: F) -> Child<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let name = name.into();
+        self.spawn_with(ThreadBuilder::new().name(name.clone()), Some(name), f)
+    }
+
+    fn spawn_with<F, T>(
+        &self,
+        builder: ThreadBuilder,
+        name: Option<String>,
+        f: F,
+    ) -> Child<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let state = self.state.clone();
+        let handle = builder
+            .spawn_scoped(self.inner, move || {
+                match catch_unwind(AssertUnwindSafe(f)) {
+                    Ok(result) => Some(result),
+                    Err(panic) => {
+                        state.token.cancel();
+                        let mut panics = state.panics.lock().unwrap_or_else(|e| e.into_inner());
+                        panics.push(ChildPanic { name, panic });
+                        None
+                    }
+                }
+            })
+            .expect("failed to spawn thread");
+        Child(handle)
+    }
+
+    /// The token that's cancelled when a child panics, or `scope`'s closure
+    /// is done. For the children to check, or wait on.
+    pub fn token(&self) -> CancellationToken {
+        self.state.token.clone()
+    }
+}
+
+impl<T> Child<'_, T> {
+    /// Waits for it. `None` if it panicked: the panic is in what `scope`
+    /// returns.
+    pub fn join(self) -> Option<T> {
+        // Its panic was caught, so the thread itself can't have panicked.
+        self.0.join().unwrap()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
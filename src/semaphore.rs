@@ -0,0 +1,59 @@
+//! A counting semaphore, the same way as the locks of chapter 9.
+
+use crate::sys::{wait, wake_one};
+use std::sync::atomic::{
+    AtomicU32,
+    Ordering::{Acquire, Relaxed, SeqCst},
+};
+
+/// Lets at most `permits` threads in at the same time.
+pub struct Semaphore {
+    permits: AtomicU32,
+    waiters: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Self {
+            permits: AtomicU32::new(permits),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Acquire, Relaxed, |p| p.checked_sub(1))
+            .is_ok()
+    }
+
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            // SeqCst, paired with `release`: either it sees us waiting,
+            // or the kernel sees its permit and doesn't put us to sleep.
+            self.waiters.fetch_add(1, SeqCst);
+            wait(&self.permits, 0);
+            self.waiters.fetch_sub(1, Relaxed);
+        }
+    }
+
+    pub fn release(&self) {
+        self.permits.fetch_add(1, SeqCst);
+        if self.waiters.load(SeqCst) > 0 {
+            wake_one(&self.permits);
+        }
+    }
+
+    pub fn available(&self) -> u32 {
+        self.permits.load(Relaxed)
+    }
+
+    /// Threads waiting in `acquire` right now, give or take one on its way
+    /// in or out.
+    pub fn waiters(&self) -> u32 {
+        self.waiters.load(Relaxed)
+    }
+
+    pub fn has_waiters(&self) -> bool {
+        self.waiters() > 0
+    }
+}
@@ -0,0 +1,270 @@
+//! A mutex for memory shared between processes, that survives its owner dying.
+//!
+//! The lock word holds the thread id of the owner, like a robust pthread mutex.
+//! A waiter that finds the owner gone takes the lock over, and learns about
+//! it through `OwnerDied`, like `EOWNERDEAD`: whatever the dead owner was
+//! doing with the data may be half done.
+//!
+//! Liveness is checked by asking the kernel whether the owner's thread id
+//! still exists. That's only safe while ids aren't reused, which on Linux takes
+//! going through all of `/proc/sys/kernel/pid_max` first.
+
+use crate::sys::futex::{wait_shared_until, wake_one_shared};
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+    time::{Duration, Instant},
+};
+
+/// Set while other threads (maybe) wait.
+const WAITERS: u32 = 1 << 31;
+const TID_MASK: u32 = !WAITERS;
+
+/// How often a waiter checks on the owner. A dead thread can't wake anyone.
+const POLL: Duration = Duration::from_millis(100);
+
+/// `#[repr(C)]` and no pointers inside, so every process sees the same thing
+/// at whatever address it mapped the memory.
+#[repr(C)]
+pub struct ShmMutex<T: Copy> {
+    /// 0 when unlocked, else the owner's thread id, plus the `WAITERS` bit.
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for ShmMutex<T> {}
+
+/// The lock was taken over from a thread that died holding it.
+pub struct OwnerDied<G>(G);
+
+impl<G> OwnerDied<G> {
+    /// The guard, to check the data and fix it up.
+    pub fn into_inner(self) -> G {
+        self.0
+    }
+}
+
+impl<G> fmt::Debug for OwnerDied<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OwnerDied")
+    }
+}
+
+impl<G> fmt::Display for OwnerDied<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the previous owner died holding the lock")
+    }
+}
+
+impl<G> std::error::Error for OwnerDied<G> {}
+
+pub type LockResult<'a, T> = Result<ShmGuard<'a, T>, OwnerDied<ShmGuard<'a, T>>>;
+
+fn current_tid() -> u32 {
+    // SAFETY: gettid can't fail.
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+fn is_alive(tid: u32) -> bool {
+    // Signal 0 only checks whether we could send one.
+    // SAFETY: no signal is sent.
+    let r = unsafe { libc::kill(tid as libc::pid_t, 0) };
+    r == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+impl<T: Copy> ShmMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Sets up a new mutex at `ptr`, typically in a fresh shared mapping,
+    /// and returns it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid and aligned for a `ShmMutex<T>` for `'a`, and
+    /// nobody may use that memory as anything else meanwhile.
+    pub unsafe fn init<'a>(ptr: *mut Self, value: T) -> &'a Self {
+        ptr.write(Self::new(value));
+        &*ptr
+    }
+
+    /// A mutex another process set up with `init`.
+    ///
+    /// # Safety
+    ///
+    /// As for `init`, and the mutex there must have been initialized.
+    pub unsafe fn from_ptr<'a>(ptr: *const Self) -> &'a Self {
+        &*ptr
+    }
+
+    pub fn lock(&self) -> LockResult<'_, T> {
+        let me = current_tid();
+        if self.state.compare_exchange(0, me, Acquire, Relaxed).is_ok() {
+            return Ok(ShmGuard {
+                mutex: self,
+                _not_send: PhantomData,
+            });
+        }
+        self.lock_contended(me)
+    }
+
+    pub fn try_lock(&self) -> Option<LockResult<'_, T>> {
+        let me = current_tid();
+        let state = self.state.load(Relaxed);
+        if state != 0 && is_alive(state & TID_MASK) {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, me | (state & WAITERS), Acquire, Relaxed)
+            .ok()
+            .map(|_| self.guard(state))
+    }
+
+    fn guard(&self, previous: u32) -> LockResult<'_, T> {
+        let guard = ShmGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        };
+        if previous == 0 {
+            Ok(guard)
+        } else {
+            Err(OwnerDied(guard))
+        }
+    }
+
+    #[cold]
+    fn lock_contended(&self, me: u32) -> LockResult<'_, T> {
+        loop {
+            let state = self.state.load(Relaxed);
+            // Free, or its owner is gone: take it, and keep the `WAITERS`
+            // bit, since we can't know we were the only one waiting.
+            if state == 0 || !is_alive(state & TID_MASK) {
+                let new = me | WAITERS;
+                if self
+                    .state
+                    .compare_exchange(state, new, Acquire, Relaxed)
+                    .is_ok()
+                {
+                    return self.guard(state);
+                }
+                continue;
+            }
+            if state & WAITERS == 0
+                && self
+                    .state
+                    .compare_exchange(state, state | WAITERS, Relaxed, Relaxed)
+                    .is_err()
+            {
+                continue;
+            }
+            wait_shared_until(&self.state, state | WAITERS, Instant::now() + POLL);
+        }
+    }
+}
+
+pub struct ShmGuard<'a, T: Copy> {
+    mutex: &'a ShmMutex<T>,
+    /// Not `Send`: the lock word holds the id of the thread that locked it,
+    /// and once that thread exits, waiters would take the lock over from
+    /// whoever the guard was sent to.
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Copy + Sync> Sync for ShmGuard<'_, T> {}
+
+impl<T: Copy> Deref for ShmGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: Copy> DerefMut for ShmGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: Copy> Drop for ShmGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.mutex.state.swap(0, Release) & WAITERS != 0 {
+            wake_one_shared(&self.mutex.state);
+        }
+    }
+}
+
+/// A child process dies holding the lock; the parent recovers it.
+pub fn main() {
+    use std::{mem::size_of, ptr};
+
+    #[derive(Clone, Copy)]
+    struct Account {
+        balance: i64,
+        /// Set while a transfer is half done.
+        in_transfer: bool,
+    }
+
+    // SAFETY: a fresh anonymous shared mapping, big enough and page aligned.
+    let mutex = unsafe {
+        let ptr = libc::mmap(
+            ptr::null_mut(),
+            size_of::<ShmMutex<Account>>(),
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(ptr, libc::MAP_FAILED);
+        let account = Account {
+            balance: 100,
+            in_transfer: false,
+        };
+        ShmMutex::init(ptr.cast(), account)
+    };
+
+    // SAFETY: the child only touches the shared mapping, then exits.
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed"),
+        0 => {
+            let mut account = mutex.lock().unwrap_or_else(|e| e.into_inner());
+            account.in_transfer = true;
+            account.balance -= 30;
+            // Crash halfway through, without unlocking.
+            // SAFETY: exits right away, running nothing of the parent's.
+            unsafe { libc::_exit(0) };
+        }
+        child => {
+            // SAFETY: `child` is our child process.
+            unsafe { libc::waitpid(child, ptr::null_mut(), 0) };
+        }
+    }
+
+    match mutex.lock() {
+        Ok(account) => println!("balance {}", account.balance),
+        Err(e) => {
+            println!("{e}");
+            let mut account = e.into_inner();
+            if account.in_transfer {
+                account.balance += 30;
+                account.in_transfer = false;
+            }
+            println!("rolled back, balance {}", account.balance);
+        }
+    }
+    println!("locks normally again: {}", mutex.lock().is_ok());
+}
@@ -0,0 +1,140 @@
+//! The spin lock of chapter 4, usable without the standard library.
+//!
+//! With the `htm` feature, `lock` tries to elide it first, see `htm`. Its
+//! orderings are a `Profile`, see `profile`.
+
+use crate::profile::{Correct, Profile};
+use crate::trace;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::AtomicBool;
+
+unsafe impl<T, P: Profile> Sync for SpinLock<T, P> where T: Send {}
+
+pub struct SpinLock<T, P: Profile = Correct> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+    profile: PhantomData<P>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        // Safety: `Correct` is.
+        unsafe { Self::with_profile(value) }
+    }
+}
+
+impl<T, P: Profile> SpinLock<T, P> {
+    /// `new`, for a lock with the orderings of `P`.
+    ///
+    /// # Safety
+    ///
+    /// `P` acquires and releases at least as strongly as `Correct`, or `T`
+    /// is zero-sized, like `()`: locking with anything weaker may not see
+    /// what the last holder wrote.
+    pub const unsafe fn with_profile(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+            profile: PhantomData,
+        }
+    }
+
+    pub fn lock(&self) -> Guard<'_, T, P> {
+        #[cfg(feature = "htm")]
+        if crate::htm::elide(&self.locked) {
+            return Guard {
+                lock: self,
+                elided: true,
+            };
+        }
+        self.lock_for_real()
+    }
+
+    /// `lock`, never elided: for `ffi`, where the lock is held across
+    /// calls that a transaction couldn't last through.
+    pub(crate) fn lock_for_real(&self) -> Guard<'_, T, P> {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        if self.locked.swap(true, P::ACQUIRE) {
+            #[cfg(feature = "metrics")]
+            crate::metrics::SPIN_LOCK_CONTENDED.increment();
+            while self.locked.swap(true, P::ACQUIRE) {
+                core::hint::spin_loop();
+            }
+        }
+        trace::event!(lock = ?core::ptr::from_ref(self), waited = ?start.elapsed(), "locked");
+        self.guard()
+    }
+
+    fn guard(&self) -> Guard<'_, T, P> {
+        Guard {
+            lock: self,
+            #[cfg(feature = "htm")]
+            elided: false,
+        }
+    }
+
+    /// Takes the lock only if it is free right now, without spinning.
+    pub fn try_lock(&self) -> Option<Guard<'_, T, P>> {
+        if self.locked.swap(true, P::ACQUIRE) {
+            None
+        } else {
+            trace::event!(lock = ?core::ptr::from_ref(self), "locked");
+            Some(self.guard())
+        }
+    }
+
+    /// Unlocks without a guard, for `ffi`, where a lock and its unlock are two calls.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held, by a guard that was forgotten.
+    pub(crate) unsafe fn force_unlock(&self) {
+        trace::event!(lock = ?core::ptr::from_ref(self), "unlocked");
+        self.locked.store(false, P::RELEASE);
+    }
+}
+
+pub struct Guard<'a, T, P: Profile = Correct> {
+    lock: &'a SpinLock<T, P>,
+    /// In a transaction, without the lock.
+    #[cfg(feature = "htm")]
+    elided: bool,
+}
+
+// Only a `&SpinLock<T>` inside, which would make it `Sync` for any `T: Send`,
+// and hand out a `&T` to several threads at once through a shared `&Guard`.
+unsafe impl<T, P: Profile> Sync for Guard<'_, T, P> where T: Sync {}
+
+impl<T, P: Profile> Deref for Guard<'_, T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T, P: Profile> DerefMut for Guard<'_, T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T, P: Profile> Drop for Guard<'_, T, P> {
+    fn drop(&mut self) {
+        #[cfg(feature = "htm")]
+        if self.elided {
+            // Safety: the transaction `lock` began is still going, or this
+            // wouldn't run: an abort goes back to before there was a guard.
+            return unsafe { crate::htm::commit() };
+        }
+        // Safety: we hold the lock, and nothing uses it after this.
+        unsafe { self.lock.force_unlock() }
+    }
+}
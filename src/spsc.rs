@@ -0,0 +1,166 @@
+//! A fixed-size ring buffer for exactly one producer and one consumer.
+//!
+//! Each side owns one index and only reads the other's, so there's no
+//! compare-exchange anywhere: a `Release` store publishes a slot, the
+//! `Acquire` load on the other side picks it up. Like the one-shot channel,
+//! `split` borrows the ring, so it can live in a `static` with no allocator.
+
+use crate::cache_padded::CachePadded;
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+/// `N` must be a power of two.
+pub struct Ring<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Next slot to read. Only the consumer writes it.
+    head: CachePadded<AtomicUsize>,
+    /// Next slot to write. Only the producer writes it.
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Ring<T, N> {}
+
+pub struct Producer<'a, T, const N: usize> {
+    ring: &'a Ring<T, N>,
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    ring: &'a Ring<T, N>,
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    pub const fn new() -> Self {
+        // So the slot of an index doesn't jump when the index wraps around,
+        // which on 32-bit targets takes only 2^32 pushes.
+        assert!(N.is_power_of_two(), "the capacity must be a power of two");
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Empties the ring and hands out its two ends.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        *self = Self::new();
+        (Producer { ring: self }, Consumer { ring: self })
+    }
+
+    /// The producer's end, without `split`'s `&mut`, for `ffi`, where the
+    /// ring is shared and each end is a function rather than a value.
+    ///
+    /// # Safety
+    ///
+    /// No other `Producer` of this ring may be in use at the same time.
+    #[cfg(feature = "ffi")]
+    pub(crate) unsafe fn producer(&self) -> Producer<'_, T, N> {
+        Producer { ring: self }
+    }
+
+    /// The consumer's end, like `producer`.
+    ///
+    /// # Safety
+    ///
+    /// No other `Consumer` of this ring may be in use at the same time.
+    #[cfg(feature = "ffi")]
+    pub(crate) unsafe fn consumer(&self) -> Consumer<'_, T, N> {
+        Consumer { ring: self }
+    }
+
+    /// How many values are waiting, without a `Consumer`, for `ffi`, where
+    /// the reader may hold the only one. `head` is loaded first, so if the
+    /// producer moves on in between, it's too high rather than below zero:
+    /// it's capped at `N`.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn len(&self) -> usize {
+        let head = self.head.load(Acquire);
+        self.tail.load(Acquire).wrapping_sub(head).min(N)
+    }
+
+    /// The indices only ever grow (wrapping), the slot is the index mod `N`.
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % N].get()
+    }
+}
+
+impl<T, const N: usize> Default for Ring<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Gives the value back if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.ring.tail.load(Relaxed);
+        // Acquire: the consumer must be done reading the slot we reuse.
+        if tail.wrapping_sub(self.ring.head.load(Acquire)) == N {
+            return Err(value);
+        }
+        unsafe { (*self.ring.slot(tail)).write(value) };
+        self.ring.tail.store(tail.wrapping_add(1), Release);
+        Ok(())
+    }
+
+    /// Pushes as many of `values` as there's room for, taking no more than
+    /// that from the iterator, and publishes them all with one store.
+    /// Returns how many it took: pass `iter.by_ref()` to keep the rest.
+    pub fn push_bulk(&mut self, values: impl IntoIterator<Item = T>) -> usize {
+        let tail = self.ring.tail.load(Relaxed);
+        // Acquire, like `push`, for every slot up to `head` at once.
+        let free = N - tail.wrapping_sub(self.ring.head.load(Acquire));
+        let mut pushed = 0;
+        for value in values.into_iter().take(free) {
+            unsafe { (*self.ring.slot(tail.wrapping_add(pushed))).write(value) };
+            pushed += 1;
+        }
+        if pushed > 0 {
+            self.ring.tail.store(tail.wrapping_add(pushed), Release);
+        }
+        pushed
+    }
+
+    pub fn is_full(&self) -> bool {
+        let tail = self.ring.tail.load(Relaxed);
+        tail.wrapping_sub(self.ring.head.load(Relaxed)) == N
+    }
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.ring.head.load(Relaxed);
+        if head == self.ring.tail.load(Acquire) {
+            return None;
+        }
+        let value = unsafe { (*self.ring.slot(head)).assume_init_read() };
+        // Release: we're done with the slot, the producer may reuse it.
+        self.ring.head.store(head.wrapping_add(1), Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.ring.head.load(Relaxed);
+        self.ring.tail.load(Acquire).wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Drop for Ring<T, N> {
+    fn drop(&mut self) {
+        let tail = *self.tail.get_mut();
+        let mut head = *self.head.get_mut();
+        while head != tail {
+            unsafe { self.slots[head % N].get_mut().assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! FreeBSD: `_umtx_op` with `UMTX_OP_WAIT_UINT_PRIVATE` and `UMTX_OP_WAKE_PRIVATE`.
+
+use std::{
+    ffi::c_void,
+    io, mem, ptr,
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+fn umtx_op(a: &AtomicU32, op: i32, val: u32, timeout: Option<&libc::_umtx_time>) -> i32 {
+    // The size of the timeout goes where a pointer would.
+    let (size, timeout) = match timeout {
+        Some(t) => (
+            mem::size_of_val(t) as *mut c_void,
+            t as *const _ as *mut c_void,
+        ),
+        None => (ptr::null_mut(), ptr::null_mut()),
+    };
+    // SAFETY: `a` is a valid u32 for the whole call, and `timeout` is null
+    // or a valid _umtx_time of the given size.
+    unsafe { libc::_umtx_op(a.as_ptr().cast(), op, val.into(), size, timeout) }
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    umtx_op(a, libc::UMTX_OP_WAIT_UINT_PRIVATE, expected, None);
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // An absolute CLOCK_MONOTONIC time, like `Instant` uses.
+    let deadline = monotonic_now().saturating_add(remaining);
+    let timeout = libc::_umtx_time {
+        _timeout: libc::timespec {
+            tv_sec: deadline.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: deadline.subsec_nanos().into(),
+        },
+        _flags: libc::UMTX_ABSTIME,
+        _clockid: libc::CLOCK_MONOTONIC as u32,
+    };
+    let r = umtx_op(a, libc::UMTX_OP_WAIT_UINT_PRIVATE, expected, Some(&timeout));
+    !(r == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ETIMEDOUT))
+}
+
+fn monotonic_now() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    umtx_op(a, libc::UMTX_OP_WAKE_PRIVATE, 1, None);
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    umtx_op(a, libc::UMTX_OP_WAKE_PRIVATE, i32::MAX as u32, None);
+}
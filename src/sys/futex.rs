@@ -0,0 +1,148 @@
+//! Linux futexes: wait until a 32-bit atomic changes, wake who's waiting on it.
+//!
+//! `wait` only sleeps while the atomic still holds `expected`, checked by the
+//! kernel under the same lock `wake_*` takes, so a wake-up that comes right
+//! after the caller's last look at the value can't get lost.
+//! All of them may also return for no reason at all: always check the value again.
+
+use std::{
+    io, ptr,
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+/// Only ever shared between threads of this process, which is cheaper.
+const WAIT: libc::c_int = libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG;
+const WAIT_BITSET: libc::c_int = libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG;
+const WAKE: libc::c_int = libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG;
+
+fn futex(
+    a: &AtomicU32,
+    op: libc::c_int,
+    val: u32,
+    timeout: *const libc::timespec,
+    bitset: u32,
+) -> libc::c_long {
+    // SAFETY: `a` is a valid, aligned u32 for as long as the call lasts,
+    // and `timeout` is either null or points to a timespec on our stack.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            a.as_ptr(),
+            op,
+            val,
+            timeout,
+            ptr::null::<u32>(),
+            bitset,
+        )
+    }
+}
+
+/// Sleeps until woken, if `a` is still `expected`.
+pub fn wait(a: &AtomicU32, expected: u32) {
+    futex(a, WAIT, expected, ptr::null(), 0);
+}
+
+/// Like `wait`, but gives up at `deadline`.
+/// Returns `false` if it did, `true` for every other reason to return.
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // FUTEX_WAIT_BITSET takes an absolute CLOCK_MONOTONIC time, the clock
+    // `Instant` uses too, so retries after a spurious wake-up don't drift.
+    let deadline = monotonic_now().saturating_add(remaining);
+    let deadline = libc::timespec {
+        tv_sec: deadline.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+        tv_nsec: deadline.subsec_nanos().into(),
+    };
+    let r = futex(
+        a,
+        WAIT_BITSET,
+        expected,
+        &deadline,
+        libc::FUTEX_BITSET_MATCH_ANY as u32,
+    );
+    !(r == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ETIMEDOUT))
+}
+
+fn monotonic_now() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+/// Like `wait_until`, for an atomic in memory shared with other processes,
+/// where the cheaper process-private futexes can't see each other.
+pub fn wait_shared_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // Relative this time: FUTEX_WAIT measures it on CLOCK_MONOTONIC anyway.
+    let timeout = libc::timespec {
+        tv_sec: remaining.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+        tv_nsec: remaining.subsec_nanos().into(),
+    };
+    let r = futex(a, libc::FUTEX_WAIT, expected, &timeout, 0);
+    !(r == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ETIMEDOUT))
+}
+
+/// Like `wake_one`, for an atomic in memory shared with other processes.
+pub fn wake_one_shared(a: &AtomicU32) -> bool {
+    futex(a, libc::FUTEX_WAKE, 1, ptr::null(), 0) > 0
+}
+
+/// Wakes one thread waiting on `a`. Returns whether there was one.
+pub fn wake_one(a: &AtomicU32) -> bool {
+    futex(a, WAKE, 1, ptr::null(), 0) > 0
+}
+
+/// Wakes every thread waiting on `a`. Returns how many there were.
+pub fn wake_all(a: &AtomicU32) -> usize {
+    futex(a, WAKE, i32::MAX as u32, ptr::null(), 0).max(0) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::wait_timeout;
+    use std::{sync::atomic::Ordering::SeqCst, thread};
+
+    #[test]
+    fn wait_returns_right_away_on_a_changed_value() {
+        let a = AtomicU32::new(1);
+        wait(&a, 0);
+        assert!(wait_until(&a, 0, Instant::now() + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn wait_until_times_out() {
+        let a = AtomicU32::new(0);
+        let start = Instant::now();
+        assert!(!wait_timeout(&a, 0, Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(!wait_until(&a, 0, start));
+    }
+
+    #[test]
+    fn wake_reaches_waiters() {
+        let a = AtomicU32::new(0);
+        assert!(!wake_one(&a));
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while a.load(SeqCst) == 0 {
+                        wait(&a, 0);
+                    }
+                });
+            }
+            thread::sleep(Duration::from_millis(20));
+            a.store(1, SeqCst);
+            wake_all(&a);
+        });
+    }
+}
@@ -0,0 +1,104 @@
+//! macOS and the other Apple platforms: `__ulock_wait` and `__ulock_wake`.
+//!
+//! They're what libc++'s `std::atomic::wait` uses, but they're not public API,
+//! so they're looked up at runtime. Without them we fall back to the table of
+//! parked threads in `sys::parking`.
+
+use super::parking;
+use std::{
+    ffi::{c_int, c_void},
+    mem,
+    sync::{atomic::AtomicU32, OnceLock},
+    time::Instant,
+};
+
+const UL_COMPARE_AND_WAIT: u32 = 1;
+const ULF_WAKE_ALL: u32 = 0x100;
+/// Return `-errno` instead of setting `errno`.
+const ULF_NO_ERRNO: u32 = 0x0100_0000;
+
+type UlockWait = unsafe extern "C" fn(u32, *mut c_void, u64, u32) -> c_int;
+type UlockWake = unsafe extern "C" fn(u32, *mut c_void, u64) -> c_int;
+
+struct Ulock {
+    wait: UlockWait,
+    wake: UlockWake,
+}
+
+fn ulock() -> Option<&'static Ulock> {
+    static ULOCK: OnceLock<Option<Ulock>> = OnceLock::new();
+    ULOCK
+        .get_or_init(|| {
+            // SAFETY: the names are nul-terminated, and if found, the
+            // symbols have had these signatures since macOS 10.12.
+            unsafe {
+                let wait = libc::dlsym(libc::RTLD_DEFAULT, c"__ulock_wait".as_ptr());
+                let wake = libc::dlsym(libc::RTLD_DEFAULT, c"__ulock_wake".as_ptr());
+                (!wait.is_null() && !wake.is_null()).then(|| Ulock {
+                    wait: mem::transmute::<*mut c_void, UlockWait>(wait),
+                    wake: mem::transmute::<*mut c_void, UlockWake>(wake),
+                })
+            }
+        })
+        .as_ref()
+}
+
+/// 0 means no timeout at all. Returns `false` on timeout.
+fn wait_us(ulock: &Ulock, a: &AtomicU32, expected: u32, timeout_us: u32) -> bool {
+    // SAFETY: `a` is a valid u32 for the whole call.
+    let r = unsafe {
+        (ulock.wait)(
+            UL_COMPARE_AND_WAIT | ULF_NO_ERRNO,
+            a.as_ptr().cast(),
+            expected.into(),
+            timeout_us,
+        )
+    };
+    r != -libc::ETIMEDOUT
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    match ulock() {
+        Some(ulock) => {
+            wait_us(ulock, a, expected, 0);
+        }
+        None => parking::wait(a, expected),
+    }
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(ulock) = ulock() else {
+        return parking::wait_until(a, expected, deadline);
+    };
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // At least 1, since 0 would mean forever. Longer than u32::MAX
+    // microseconds (71 minutes) we wake up early, which callers allow for.
+    let us = remaining.as_micros().clamp(1, u32::MAX.into()) as u32;
+    wait_us(ulock, a, expected, us)
+}
+
+fn wake(a: &AtomicU32, flags: u32) {
+    match ulock() {
+        // SAFETY: any address is fine, it's only used as a key.
+        // -ENOENT just means nobody was waiting.
+        Some(ulock) => unsafe {
+            (ulock.wake)(
+                UL_COMPARE_AND_WAIT | ULF_NO_ERRNO | flags,
+                a.as_ptr().cast(),
+                0,
+            );
+        },
+        None if flags & ULF_WAKE_ALL != 0 => parking::wake_all(a),
+        None => parking::wake_one(a),
+    }
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    wake(a, 0);
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    wake(a, ULF_WAKE_ALL);
+}
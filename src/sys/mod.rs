@@ -0,0 +1,97 @@
+//! The operating system calls the crate's own locks are built on.
+//!
+//! Most platforms offer some way to sleep until a 32-bit atomic changes
+//! and to wake up whoever sleeps on one. The functions at the top level
+//! hide which one it is; the platform modules are there for the details.
+//! Targets without such a call get `parking`, picked at compile time.
+
+use crate::trace;
+use std::{
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod futex;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use futex as imp;
+
+#[cfg(windows)]
+pub mod windows;
+#[cfg(windows)]
+use windows as imp;
+
+#[cfg(target_vendor = "apple")]
+pub mod macos;
+#[cfg(target_vendor = "apple")]
+use macos as imp;
+
+#[cfg(target_os = "freebsd")]
+pub mod freebsd;
+#[cfg(target_os = "freebsd")]
+use freebsd as imp;
+
+#[cfg(target_os = "openbsd")]
+pub mod openbsd;
+#[cfg(target_os = "openbsd")]
+use openbsd as imp;
+
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+pub mod wasm;
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+use wasm as imp;
+
+/// Everywhere else, and on macOS if `__ulock_*` is missing.
+pub mod parking;
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    windows,
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    all(target_arch = "wasm32", target_feature = "atomics"),
+)))]
+use parking as imp;
+
+/// Sleeps until woken, if `a` is still `expected`.
+/// May also return spuriously, so check the value again.
+pub fn wait(a: &AtomicU32, expected: u32) {
+    #[cfg(feature = "tracing")]
+    let start = Instant::now();
+    imp::wait(a, expected);
+    trace::event!(atomic = ?a.as_ptr(), waited = ?start.elapsed(), "woke up");
+}
+
+/// Like `wait`, but gives up at `deadline`.
+/// Returns `false` if it did, `true` for every other reason to return.
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    #[cfg(feature = "tracing")]
+    let start = Instant::now();
+    let woken = imp::wait_until(a, expected, deadline);
+    trace::event!(atomic = ?a.as_ptr(), waited = ?start.elapsed(), timed_out = !woken, "woke up");
+    woken
+}
+
+/// Like `wait`, but gives up after `timeout`. Returns `false` if it did.
+pub fn wait_timeout(a: &AtomicU32, expected: u32, timeout: Duration) -> bool {
+    match Instant::now().checked_add(timeout) {
+        Some(deadline) => wait_until(a, expected, deadline),
+        None => {
+            wait(a, expected);
+            true
+        }
+    }
+}
+
+/// Wakes one thread waiting on `a`, if there's any.
+pub fn wake_one(a: &AtomicU32) {
+    trace::event!(atomic = ?a.as_ptr(), "waking one");
+    imp::wake_one(a);
+}
+
+/// Wakes every thread waiting on `a`.
+pub fn wake_all(a: &AtomicU32) {
+    trace::event!(atomic = ?a.as_ptr(), "waking all");
+    imp::wake_all(a);
+}
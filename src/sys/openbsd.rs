@@ -0,0 +1,37 @@
+//! OpenBSD: `futex(2)`, a subset of the Linux one.
+
+use std::{io, ptr, sync::atomic::AtomicU32, time::Instant};
+
+const WAIT: i32 = libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG;
+const WAKE: i32 = libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG;
+
+fn futex(a: &AtomicU32, op: i32, val: u32, timeout: *const libc::timespec) -> i32 {
+    // SAFETY: `a` is a valid u32 for the whole call, and `timeout` is null
+    // or points to a timespec on our caller's stack.
+    unsafe { libc::futex(a.as_ptr(), op, val as i32, timeout, ptr::null_mut()) }
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    futex(a, WAIT, expected, ptr::null());
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // Only relative timeouts here.
+    let timeout = libc::timespec {
+        tv_sec: remaining.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+        tv_nsec: remaining.subsec_nanos().into(),
+    };
+    let r = futex(a, WAIT, expected, &timeout);
+    !(r == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ETIMEDOUT))
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    futex(a, WAKE, 1, ptr::null());
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    futex(a, WAKE, i32::MAX as u32, ptr::null());
+}
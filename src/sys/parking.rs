@@ -0,0 +1,195 @@
+//! Wait/wake without help from the OS: a fixed table of wait queues,
+//! picked by hashing the atomic's address, holding the parked threads.
+//!
+//! Checking the value and joining the queue happen under the queue's lock,
+//! which `wake_*` takes too, so a wake-up can't slip in between.
+
+use crate::cache_padded::CachePadded;
+use std::{
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU32,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+    thread::{self, Thread},
+    time::Instant,
+};
+
+const BUCKETS: usize = 64;
+
+struct Waiter {
+    address: usize,
+    thread: Thread,
+    woken: Arc<AtomicBool>,
+}
+
+type Queue = lock::Lock<Vec<Waiter>>;
+
+/// Padded, so threads on different buckets don't slow each other down.
+static TABLE: [CachePadded<Queue>; BUCKETS] =
+    [const { CachePadded::new(Queue::new(Vec::new())) }; BUCKETS];
+
+fn bucket(a: &AtomicU32) -> (usize, &'static Queue) {
+    let address = a.as_ptr() as usize;
+    // Atomics are at least 4-byte aligned, the low bits say nothing.
+    (address, &TABLE[(address >> 2) % BUCKETS])
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    wait_inner(a, expected, None);
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    wait_inner(a, expected, Some(deadline))
+}
+
+fn wait_inner(a: &AtomicU32, expected: u32, deadline: Option<Instant>) -> bool {
+    let (address, bucket) = bucket(a);
+    let woken = Arc::new(AtomicBool::new(false));
+    let queued = bucket.with(|waiters| {
+        if a.load(Relaxed) != expected {
+            return false;
+        }
+        waiters.push(Waiter {
+            address,
+            thread: thread::current(),
+            woken: woken.clone(),
+        });
+        true
+    });
+    if !queued {
+        return true;
+    }
+    while !woken.load(Acquire) {
+        match deadline.map(|d| d.checked_duration_since(Instant::now())) {
+            None => thread::park(),
+            Some(Some(remaining)) => thread::park_timeout(remaining),
+            Some(None) => {
+                // Still in the queue means nobody woke us, and now nobody will.
+                return !bucket.with(|waiters| {
+                    let before = waiters.len();
+                    waiters.retain(|w| !Arc::ptr_eq(&w.woken, &woken));
+                    waiters.len() < before
+                });
+            }
+        }
+    }
+    true
+}
+
+fn wake(a: &AtomicU32, mut n: usize) {
+    let (address, bucket) = bucket(a);
+    bucket.with(|waiters| {
+        waiters.retain(|w| {
+            if n == 0 || w.address != address {
+                return true;
+            }
+            n -= 1;
+            w.woken.store(true, Release);
+            w.thread.unpark();
+            false
+        })
+    });
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    wake(a, 1);
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    wake(a, usize::MAX);
+}
+
+#[cfg(target_vendor = "apple")]
+mod lock {
+    use std::cell::UnsafeCell;
+
+    /// Just what the table needs of a lock, on `os_unfair_lock`, which
+    /// doesn't need the `sys` layer itself and is never moved out of the table.
+    pub struct Lock<T> {
+        lock: UnsafeCell<libc::os_unfair_lock>,
+        value: UnsafeCell<T>,
+    }
+
+    unsafe impl<T: Send> Sync for Lock<T> {}
+
+    impl<T> Lock<T> {
+        pub const fn new(value: T) -> Self {
+            Self {
+                lock: UnsafeCell::new(libc::OS_UNFAIR_LOCK_INIT),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+            struct Unlock<'a>(&'a UnsafeCell<libc::os_unfair_lock>);
+            impl Drop for Unlock<'_> {
+                fn drop(&mut self) {
+                    // SAFETY: we locked it, on this thread.
+                    unsafe { libc::os_unfair_lock_unlock(self.0.get()) };
+                }
+            }
+            // SAFETY: the lock lives in a static, so it never moves.
+            unsafe { libc::os_unfair_lock_lock(self.lock.get()) };
+            let _unlock = Unlock(&self.lock);
+            // SAFETY: we hold the lock.
+            f(unsafe { &mut *self.value.get() })
+        }
+    }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+mod lock {
+    use std::sync::Mutex;
+
+    pub struct Lock<T>(Mutex<T>);
+
+    impl<T> Lock<T> {
+        pub const fn new(value: T) -> Self {
+            Self(Mutex::new(value))
+        }
+
+        pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+            f(&mut self.0.lock().unwrap_or_else(|e| e.into_inner()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::Ordering::SeqCst, time::Duration};
+
+    #[test]
+    fn wait_until_times_out_and_leaves_the_queue() {
+        let a = AtomicU32::new(0);
+        let start = Instant::now();
+        assert!(!wait_until(&a, 0, start + Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(bucket(&a)
+            .1
+            .with(|w| w.iter().all(|w| w.address != a.as_ptr() as usize)));
+    }
+
+    #[test]
+    fn wake_one_wakes_exactly_one() {
+        let a = AtomicU32::new(0);
+        let woken = AtomicU32::new(0);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    wait_until(&a, 0, Instant::now() + Duration::from_secs(1));
+                    woken.fetch_add(1, SeqCst);
+                });
+            }
+            thread::sleep(Duration::from_millis(50));
+            wake_one(&a);
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(woken.load(SeqCst), 1);
+            wake_all(&a);
+        });
+        assert_eq!(woken.load(SeqCst), 2);
+    }
+}
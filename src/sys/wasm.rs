@@ -0,0 +1,45 @@
+//! WebAssembly with shared memory: `memory.atomic.wait32` and `memory.atomic.notify`.
+//!
+//! Only for builds with `+atomics` (which need a nightly std), and only in
+//! workers: browsers don't let the main thread block, and trap instead.
+//!
+//! ```text
+//! RUSTFLAGS="-C target-feature=+atomics,+bulk-memory" \
+//!     cargo +nightly build -Z build-std=std,panic_abort --target wasm32-unknown-unknown
+//! ```
+
+use core::arch::wasm32;
+use std::{sync::atomic::AtomicU32, time::Instant};
+
+/// Returns `false` if it timed out. A negative timeout is no timeout.
+fn wait_ns(a: &AtomicU32, expected: u32, timeout_ns: i64) -> bool {
+    // SAFETY: `a` is a valid, aligned u32 in linear memory.
+    let r = unsafe { wasm32::memory_atomic_wait32(a.as_ptr().cast(), expected as i32, timeout_ns) };
+    // 0: woken, 1: not `expected`, 2: timed out.
+    r != 2
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    wait_ns(a, expected, -1);
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    wait_ns(
+        a,
+        expected,
+        remaining.as_nanos().min(i64::MAX as u128) as i64,
+    )
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    // SAFETY: any address in linear memory is fine, it's only used as a key.
+    unsafe { wasm32::memory_atomic_notify(a.as_ptr().cast(), 1) };
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    // SAFETY: any address in linear memory is fine, it's only used as a key.
+    unsafe { wasm32::memory_atomic_notify(a.as_ptr().cast(), u32::MAX) };
+}
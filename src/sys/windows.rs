@@ -0,0 +1,55 @@
+//! Windows: `WaitOnAddress` and `WakeByAddress*`, the futex of Windows 8 and later.
+
+use std::{ffi::c_void, io, sync::atomic::AtomicU32, time::Instant};
+
+const INFINITE: u32 = u32::MAX;
+const ERROR_TIMEOUT: i32 = 1460;
+
+#[link(name = "synchronization")]
+extern "system" {
+    fn WaitOnAddress(
+        address: *const c_void,
+        compare_address: *const c_void,
+        address_size: usize,
+        milliseconds: u32,
+    ) -> i32;
+    fn WakeByAddressSingle(address: *const c_void);
+    fn WakeByAddressAll(address: *const c_void);
+}
+
+fn wait_ms(a: &AtomicU32, expected: u32, milliseconds: u32) -> bool {
+    // SAFETY: both pointers are valid u32s for the whole call.
+    let r = unsafe {
+        WaitOnAddress(
+            a.as_ptr().cast(),
+            (&expected as *const u32).cast(),
+            4,
+            milliseconds,
+        )
+    };
+    r != 0 || io::Error::last_os_error().raw_os_error() != Some(ERROR_TIMEOUT)
+}
+
+pub fn wait(a: &AtomicU32, expected: u32) {
+    wait_ms(a, expected, INFINITE);
+}
+
+pub fn wait_until(a: &AtomicU32, expected: u32, deadline: Instant) -> bool {
+    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+        return false;
+    };
+    // Round up, or we'd spin on waits of 0ms right before the deadline.
+    let ms = remaining.as_nanos().div_ceil(1_000_000);
+    // Only `INFINITE` itself means forever.
+    wait_ms(a, expected, ms.min(INFINITE as u128 - 1) as u32)
+}
+
+pub fn wake_one(a: &AtomicU32) {
+    // SAFETY: any address is fine, it's only used as a key.
+    unsafe { WakeByAddressSingle(a.as_ptr().cast()) };
+}
+
+pub fn wake_all(a: &AtomicU32) {
+    // SAFETY: any address is fine, it's only used as a key.
+    unsafe { WakeByAddressAll(a.as_ptr().cast()) };
+}
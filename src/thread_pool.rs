@@ -0,0 +1,528 @@
+//! A fixed number of worker threads running jobs from one shared queue,
+//! the mutex channel from cap_5. Or, with `Scheduling::LifoSlot`, a
+//! `LifoQueue`, which hands the job submitted last to the next free worker
+//! first, for a job's follow-up to find its data still in the cache.
+//!
+//! A panicking job doesn't take its worker down with it: the panic is caught,
+//! counted, and the worker moves on to the next job. Or, with
+//! `Supervision::Restart`, it does, and a fresh thread takes the worker's
+//! place, for jobs that leave thread-locals behind in a state no later job
+//! should see. Either way the pool keeps its size, up to a limit of restarts.
+//!
+//! `execute` is fire-and-forget; `submit` hands back a `TaskHandle` to get
+//! the job's result from, through a one-shot channel in an `Arc` the two
+//! share, and to cancel it with. A job dropped without running, by an
+//! `Abort` shutdown, still sends: `Cancelled`.
+
+use crate::{
+    cancellation::CancellationToken,
+    join::{panic_message, Panic},
+    lifo_queue::{self, LifoQueue},
+    mutex_channel::Channel,
+    oneshot,
+    threads::ThreadBuilder,
+    trace,
+};
+use std::{
+    marker::PhantomData,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{
+            AtomicBool, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+    thread::{self, JoinHandle, Thread},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// What a worker does when a job panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supervision {
+    /// Catches the panic and runs the next job, on the same thread.
+    Contain,
+    /// Lets the panic end the thread, and starts a new one with the same
+    /// name in its place, at most `max_restarts` times over the pool's
+    /// life. After that, each panic leaves the pool a thread short.
+    Restart { max_restarts: usize },
+}
+
+/// Which of the queued jobs a free worker takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+    /// The one queued first.
+    Fifo,
+    /// The one queued last, while it's still in its slot, see `LifoQueue`,
+    /// or the one queued first.
+    LifoSlot,
+}
+
+/// What `shutdown` does with jobs that are still queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    /// Run them all before stopping.
+    Drain,
+    /// Drop them without running them.
+    Abort,
+}
+
+pub struct ThreadPool {
+    queue: Arc<Jobs>,
+    workers: Vec<Worker>,
+    shared: Arc<Shared>,
+}
+
+/// A worker's thread, which returns its replacement if it was restarted.
+struct Worker(JoinHandle<Option<Worker>>);
+
+struct Shared {
+    queue: Arc<Jobs>,
+    supervision: Supervision,
+    panicked: AtomicUsize,
+    restarts: AtomicUsize,
+}
+
+impl ThreadPool {
+    pub fn new(n: usize) -> Self {
+        Self::with_supervision(n, Supervision::Contain)
+    }
+
+    pub fn with_supervision(n: usize, supervision: Supervision) -> Self {
+        Self::with_scheduling(n, supervision, Scheduling::Fifo)
+    }
+
+    pub fn with_scheduling(n: usize, supervision: Supervision, scheduling: Scheduling) -> Self {
+        assert!(n > 0, "a thread pool needs at least one thread");
+        let queue = Arc::new(match scheduling {
+            Scheduling::Fifo => Jobs::Fifo(Channel::new()),
+            Scheduling::LifoSlot => Jobs::LifoSlot(LifoQueue::new()),
+        });
+        let shared = Arc::new(Shared {
+            queue: queue.clone(),
+            supervision,
+            panicked: AtomicUsize::new(0),
+            restarts: AtomicUsize::new(0),
+        });
+        let workers = (0..n)
+            .map(|i| Worker::spawn(format!("pool-worker-{i}"), shared.clone()))
+            .collect();
+        Self {
+            queue,
+            workers,
+            shared,
+        }
+    }
+
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queue.send(Box::new(f));
+    }
+
+    /// Like `execute`, for a job whose result is wanted: `f` returns it,
+    /// and the handle gets it. `f` is given the handle's token, to check
+    /// between stages of its work, and stop early once it's cancelled.
+    pub fn submit<F, T>(&self, f: F) -> TaskHandle<T>
+    where
+        F: FnOnce(&CancellationToken) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, result) = oneshot::channel();
+        let reply = Reply(Some(sender));
+        let token = CancellationToken::new();
+        let (shared, cancelled) = (self.shared.clone(), token.clone());
+        self.execute(move || {
+            let outcome = if cancelled.is_cancelled() {
+                Err(TaskError::Cancelled)
+            } else {
+                match catch_unwind(AssertUnwindSafe(|| f(&cancelled))) {
+                    Ok(_) if cancelled.is_cancelled() => Err(TaskError::Cancelled),
+                    Ok(value) => Ok(value),
+                    Err(panic) => {
+                        shared.job_panicked();
+                        Err(TaskError::Panicked(panic))
+                    }
+                }
+            };
+            reply.send(outcome);
+        });
+        TaskHandle { result, token }
+    }
+
+    /// A handle to submit jobs from anywhere, including from inside a job.
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            queue: self.queue.clone(),
+        }
+    }
+
+    /// Like `thread::scope`, but the jobs run on the pool's threads:
+    /// they can borrow from the caller's stack, because this only returns
+    /// once all of them are done.
+    ///
+    /// Panics if any of the jobs panicked.
+    /// Calling this from inside a pool job can deadlock once every worker is
+    /// waiting on a scope.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState {
+                pending: AtomicUsize::new(0),
+                panicked: AtomicBool::new(false),
+                owner: thread::current(),
+            }),
+            _scope: PhantomData,
+            _env: PhantomData,
+        };
+        let result = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        // Even if `f` panicked, jobs may still be using borrowed data.
+        while scope.state.pending.load(Acquire) != 0 {
+            thread::park();
+        }
+        match result {
+            Err(e) => resume_unwind(e),
+            Ok(_) if scope.state.panicked.load(Relaxed) => panic!("a scoped job panicked"),
+            Ok(result) => result,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// How many jobs panicked so far.
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.panicked.load(Relaxed)
+    }
+
+    /// How many workers were replaced so far, see `Supervision::Restart`.
+    pub fn restarts(&self) -> usize {
+        self.shared.restarts.load(Relaxed)
+    }
+
+    /// Stops accepting jobs and waits for the workers to finish.
+    /// Returns how many queued jobs were dropped (always 0 for `Drain`).
+    ///
+    /// Dropping the pool does a `Drain` shutdown.
+    pub fn shutdown(&mut self, mode: Shutdown) -> usize {
+        self.queue.close();
+        let mut dropped = 0;
+        if mode == Shutdown::Abort {
+            let mut jobs = self.queue.receiver();
+            while jobs.try_receive().is_some() {
+                dropped += 1;
+            }
+        }
+        for worker in self.workers.drain(..) {
+            worker.join();
+        }
+        dropped
+    }
+}
+
+impl Worker {
+    fn spawn(name: String, shared: Arc<Shared>) -> Self {
+        let handle = ThreadBuilder::new()
+            .name(name.clone())
+            .spawn(move || {
+                let contain = shared.supervision == Supervision::Contain;
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    let mut jobs = shared.queue.receiver();
+                    while let Some(job) = jobs.receive() {
+                        if !contain {
+                            job();
+                        } else if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            shared.job_panicked();
+                        }
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::POOL_JOBS.increment();
+                    }
+                }));
+                let Err(panic) = result else { return None };
+                shared.job_panicked();
+                let Supervision::Restart { max_restarts } = shared.supervision else {
+                    unreachable!("contained panics don't get here")
+                };
+                let _message = panic_message(&panic);
+                let restarted = shared
+                    .restarts
+                    .fetch_update(Relaxed, Relaxed, |r| (r < max_restarts).then_some(r + 1));
+                if restarted.is_err() {
+                    trace::event!(message = ?_message, "pool worker panicked, not restarted");
+                    return None;
+                }
+                trace::event!(message = ?_message, "pool worker panicked, restarting");
+                Some(Worker::spawn(name, shared))
+            })
+            .unwrap();
+        Self(handle)
+    }
+
+    /// Waits for this thread and every one that replaced it.
+    fn join(self) {
+        let mut worker = self;
+        while let Some(next) = worker.0.join().unwrap() {
+            worker = next;
+        }
+    }
+}
+
+/// The queue behind a pool, as `Scheduling` says.
+enum Jobs {
+    Fifo(Channel<Job>),
+    LifoSlot(LifoQueue<Job>),
+}
+
+/// A worker's end of `Jobs`.
+enum JobReceiver<'a> {
+    Fifo(&'a Channel<Job>),
+    LifoSlot(lifo_queue::Consumer<'a, Job>),
+}
+
+impl Jobs {
+    fn send(&self, job: Job) {
+        match self {
+            Jobs::Fifo(channel) => channel.send(job),
+            Jobs::LifoSlot(queue) => queue.push(job),
+        }
+    }
+
+    fn close(&self) {
+        match self {
+            Jobs::Fifo(channel) => channel.close(),
+            Jobs::LifoSlot(queue) => queue.close(),
+        }
+    }
+
+    fn receiver(&self) -> JobReceiver<'_> {
+        match self {
+            Jobs::Fifo(channel) => JobReceiver::Fifo(channel),
+            Jobs::LifoSlot(queue) => JobReceiver::LifoSlot(queue.consumer()),
+        }
+    }
+}
+
+impl JobReceiver<'_> {
+    fn receive(&mut self) -> Option<Job> {
+        match self {
+            JobReceiver::Fifo(channel) => channel.receive(),
+            JobReceiver::LifoSlot(consumer) => consumer.receive(),
+        }
+    }
+
+    fn try_receive(&mut self) -> Option<Job> {
+        match self {
+            JobReceiver::Fifo(channel) => channel.try_receive(),
+            JobReceiver::LifoSlot(consumer) => consumer.try_receive(),
+        }
+    }
+}
+
+impl Shared {
+    fn job_panicked(&self) {
+        self.panicked.fetch_add(1, Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::metrics::POOL_PANICS.increment();
+    }
+}
+
+/// Why a submitted job has no result.
+#[derive(Debug)]
+pub enum TaskError {
+    /// It panicked, with this.
+    Panicked(Panic),
+    /// It was cancelled, before it started, or before it was done.
+    Cancelled,
+}
+
+/// A job `submit` queued: its result, once it's done, or a way to cancel it.
+pub struct TaskHandle<T> {
+    result: oneshot::OwnedReceiver<Result<T, TaskError>>,
+    token: CancellationToken,
+}
+
+/// Where a submitted job's result goes. Sends `Cancelled` if the job is
+/// dropped before it sends anything.
+struct Reply<T>(Option<oneshot::OwnedSender<Result<T, TaskError>>>);
+
+impl<T> Reply<T> {
+    fn send(mut self, outcome: Result<T, TaskError>) {
+        if let Some(sender) = self.0.take() {
+            sender.send(outcome);
+        }
+    }
+}
+
+impl<T> Drop for Reply<T> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.0.take() {
+            sender.send(Err(TaskError::Cancelled));
+        }
+    }
+}
+
+impl<T> TaskHandle<T> {
+    /// Waits for the job to be done.
+    pub fn join(self) -> Result<T, TaskError> {
+        self.result.receive()
+    }
+
+    /// The result if the job is done, or the handle back if it isn't.
+    pub fn try_result(self) -> Result<Result<T, TaskError>, Self> {
+        if self.is_finished() {
+            Ok(self.join())
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.is_ready()
+    }
+
+    /// Asks the job to stop. One that hasn't started won't; one that's
+    /// running sees it in its token. Either way, `join` is `Cancelled`.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+}
+
+#[derive(Clone)]
+pub struct Spawner {
+    queue: Arc<Jobs>,
+}
+
+impl Spawner {
+    /// Panics if the pool was shut down.
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queue.send(Box::new(f));
+    }
+}
+
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // Same variance trick as `std::thread::Scope`.
+    _scope: PhantomData<&'scope mut &'scope ()>,
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeState {
+    pending: AtomicUsize,
+    panicked: AtomicBool,
+    owner: Thread,
+}
+
+impl<'scope> Scope<'scope, '_> {
+    pub fn spawn<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        let state = self.state.clone();
+        state.pending.fetch_add(1, Relaxed);
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            if catch_unwind(AssertUnwindSafe(f)).is_err() {
+                state.panicked.store(true, Relaxed);
+            }
+            if state.pending.fetch_sub(1, Release) == 1 {
+                state.owner.unpark();
+            }
+        });
+        // Safety: `ThreadPool::scope` doesn't return before `pending` is back
+        // to zero, so the job is gone before anything it borrows is.
+        let job: Job = unsafe { std::mem::transmute(job) };
+        self.pool.queue.send(job);
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown(Shutdown::Drain);
+    }
+}
+
+pub fn main() {
+    use std::time::Duration;
+
+    let mut pool = ThreadPool::new(4);
+    let done = Arc::new(AtomicUsize::new(0));
+    for i in 0..20 {
+        let done = done.clone();
+        pool.execute(move || {
+            thread::sleep(Duration::from_millis(10));
+            if i == 7 {
+                panic!("job {i} failed");
+            }
+            done.fetch_add(1, Relaxed);
+        });
+    }
+    let mut numbers = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    pool.scope(|s| {
+        for chunk in numbers.chunks_mut(2) {
+            s.spawn(move || chunk.iter_mut().for_each(|n| *n *= 10));
+        }
+    });
+    println!("scoped jobs borrowed and updated {numbers:?}");
+
+    pool.shutdown(Shutdown::Drain);
+    println!(
+        "{} jobs done, {} panicked along the way",
+        done.load(Relaxed),
+        pool.panicked_jobs()
+    );
+
+    let mut pool = ThreadPool::new(1);
+    for _ in 0..10 {
+        pool.execute(|| thread::sleep(Duration::from_millis(50)));
+    }
+    thread::sleep(Duration::from_millis(20));
+    println!("aborted {} queued jobs", pool.shutdown(Shutdown::Abort));
+
+    // Jobs with results: one done, one cancelled between its stages.
+    let pool = ThreadPool::new(2);
+    let sum = pool.submit(|_| (1..=100).sum::<u32>());
+    let long = pool.submit(|token| {
+        for stage in 0..100 {
+            if token.is_cancelled() {
+                return stage;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        100
+    });
+    thread::sleep(Duration::from_millis(35));
+    long.cancel();
+    println!(
+        "sum: {:?}, long job: {:?}",
+        sum.join().ok(),
+        long.join().err()
+    );
+    drop(pool);
+
+    // Every job starts on a thread of its own, with its thread-locals
+    // fresh, even after one before it panicked.
+    thread_local!(static DIRTY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) });
+    let mut pool = ThreadPool::with_supervision(2, Supervision::Restart { max_restarts: 5 });
+    for i in 0..4 {
+        pool.execute(move || {
+            assert!(!DIRTY.get(), "left dirty by a job before");
+            DIRTY.set(true);
+            panic!("job {i} failed halfway");
+        });
+    }
+    pool.shutdown(Shutdown::Drain);
+    println!(
+        "{} panicked, {} workers restarted",
+        pool.panicked_jobs(),
+        pool.restarts()
+    );
+}
@@ -0,0 +1,165 @@
+//! Named threads, and a registry of the live ones.
+//!
+//! A `ThreadId(7)` in a log line or a deadlock report doesn't tell much.
+//! Threads spawned through here are registered under their name until they
+//! exit, so anything can ask who a `ThreadId` belongs to.
+
+use std::{
+    io,
+    sync::Mutex,
+    thread::{self, JoinHandle, Scope, ScopedJoinHandle, ThreadId},
+};
+
+/// Every live thread spawned through this module. Short, so a `Vec` will do.
+static REGISTRY: Mutex<Vec<(ThreadId, String)>> = Mutex::new(Vec::new());
+
+/// Unregisters the current thread when dropped, even by a panic.
+struct Registration;
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let id = thread::current().id();
+        // Don't panic while unwinding just because some other thread
+        // poisoned the registry.
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|(t, _)| *t != id);
+    }
+}
+
+/// Like `thread::spawn`, but the thread gets a name and is registered.
+pub fn spawn_named<F, T>(name: impl Into<String>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    ThreadBuilder::new()
+        .name(name)
+        .spawn(f)
+        .expect("failed to spawn thread")
+}
+
+/// `thread::Builder`, plus registration.
+#[derive(Debug, Default, Clone)]
+pub struct ThreadBuilder {
+    name: Option<String>,
+    stack_size: Option<usize>,
+}
+
+impl ThreadBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// In bytes.
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    fn std_builder(&self) -> thread::Builder {
+        let mut builder = thread::Builder::new();
+        if let Some(name) = &self.name {
+            builder = builder.name(name.clone());
+        }
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+        builder
+    }
+
+    /// Registers `id` under our name, or its id if we have none.
+    fn register(&self, registry: &mut Vec<(ThreadId, String)>, id: ThreadId) {
+        let name = self.name.clone().unwrap_or_else(|| format!("{id:?}"));
+        registry.push((id, name));
+    }
+
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // Register before the lock is released, so the thread is known from
+        // the moment `spawn` returns, and it can't unregister before that.
+        let mut registry = REGISTRY.lock().unwrap();
+        let handle = self.std_builder().spawn(move || {
+            let _registration = Registration;
+            f()
+        })?;
+        self.register(&mut registry, handle.thread().id());
+        Ok(handle)
+    }
+
+    pub fn spawn_scoped<'scope, 'env, F, T>(
+        self,
+        scope: &'scope Scope<'scope, 'env>,
+        f: F,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let mut registry = REGISTRY.lock().unwrap();
+        let handle = self.std_builder().spawn_scoped(scope, move || {
+            let _registration = Registration;
+            f()
+        })?;
+        self.register(&mut registry, handle.thread().id());
+        Ok(handle)
+    }
+}
+
+/// The name `id` was registered under, if it's still running.
+pub fn name_of(id: ThreadId) -> Option<String> {
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .iter()
+        .find(|(t, _)| *t == id)
+        .map(|(_, name)| name.clone())
+}
+
+/// Something to print for the current thread: its registered name,
+/// else the name std knows it by (like "main"), else its id.
+pub fn current_name() -> String {
+    let current = thread::current();
+    name_of(current.id())
+        .or_else(|| current.name().map(str::to_owned))
+        .unwrap_or_else(|| format!("{:?}", current.id()))
+}
+
+/// All registered threads that are still running.
+pub fn registered() -> Vec<(ThreadId, String)> {
+    REGISTRY.lock().unwrap().clone()
+}
+
+pub fn main() {
+    use std::{sync::Barrier, time::Duration};
+
+    let barrier = Barrier::new(3);
+    thread::scope(|s| {
+        for i in 0..2 {
+            let barrier = &barrier;
+            ThreadBuilder::new()
+                .name(format!("worker-{i}"))
+                .stack_size(64 * 1024)
+                .spawn_scoped(s, move || {
+                    println!("hi from {}", current_name());
+                    barrier.wait();
+                    barrier.wait();
+                })
+                .unwrap();
+        }
+        barrier.wait();
+        println!("running: {:?}", registered());
+        barrier.wait();
+    });
+
+    let t = spawn_named("sleeper", || thread::sleep(Duration::from_millis(10)));
+    println!("{:?} is {:?}", t.thread().id(), name_of(t.thread().id()));
+    t.join().unwrap();
+    println!("after join: {:?}", registered());
+}
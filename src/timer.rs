@@ -0,0 +1,278 @@
+//! `sleep` and `timeout` futures, without a runtime.
+//!
+//! One background thread keeps all pending deadlines in a min-heap and parks
+//! with `park_timeout` until the earliest one. Registering an earlier deadline
+//! unparks it so it can recompute how long to sleep.
+//!
+//! A `Sleep` registers once, and shares the waker in its entry: polling it
+//! again from another task swaps the waker in place, instead of pushing a
+//! new entry for every poll. Dropping it takes the waker back out, so the
+//! task isn't kept alive until the deadline, and counts its entry as
+//! cancelled: once those are half the heap, they're swept out of it, so a
+//! loop of `timeout`s that finish early doesn't grow it without end.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+struct Timers {
+    deadlines: Mutex<Deadlines>,
+    thread: Thread,
+}
+
+struct Deadlines {
+    heap: BinaryHeap<Reverse<Entry>>,
+    /// Entries in `heap` whose `Sleep` was dropped.
+    cancelled: usize,
+}
+
+/// Taken out by whichever comes first, the timer or `Sleep::drop`, with
+/// `Deadlines` locked: `Some` while the entry is in the heap.
+type Slot = Arc<Mutex<Option<Waker>>>;
+
+struct Entry {
+    deadline: Instant,
+    waker: Slot,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+impl Eq for Entry {}
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+fn timers() -> &'static Timers {
+    static TIMERS: OnceLock<Timers> = OnceLock::new();
+    TIMERS.get_or_init(|| {
+        let thread = thread::Builder::new()
+            .name("timer".into())
+            .spawn(|| run(timers()))
+            .unwrap()
+            .thread()
+            .clone();
+        Timers {
+            deadlines: Mutex::new(Deadlines {
+                heap: BinaryHeap::new(),
+                cancelled: 0,
+            }),
+            thread,
+        }
+    })
+}
+
+fn run(timers: &Timers) {
+    loop {
+        let mut deadlines = timers.deadlines.lock().unwrap();
+        let now = Instant::now();
+        while deadlines.heap.peek().is_some_and(|e| e.0.deadline <= now) {
+            let entry = deadlines.heap.pop().unwrap().0;
+            let waker = entry.waker.lock().unwrap().take();
+            match waker {
+                Some(waker) => waker.wake(),
+                None => deadlines.cancelled -= 1,
+            }
+        }
+        let next = deadlines.heap.peek().map(|e| e.0.deadline);
+        drop(deadlines);
+        match next {
+            Some(deadline) => thread::park_timeout(deadline - now),
+            None => thread::park(),
+        }
+    }
+}
+
+fn register(deadline: Instant, waker: Slot) {
+    let timers = timers();
+    let mut deadlines = timers.deadlines.lock().unwrap();
+    let earliest = deadlines
+        .heap
+        .peek()
+        .is_none_or(|e| deadline < e.0.deadline);
+    deadlines.heap.push(Reverse(Entry { deadline, waker }));
+    drop(deadlines);
+    if earliest {
+        timers.thread.unpark();
+    }
+}
+
+/// For a `Sleep` dropped before its deadline.
+fn cancel(waker: &Slot) {
+    let mut deadlines = timers().deadlines.lock().unwrap();
+    if waker.lock().unwrap().take().is_none() {
+        // The timer got to it first.
+        return;
+    }
+    deadlines.cancelled += 1;
+    if deadlines.cancelled * 2 > deadlines.heap.len() {
+        deadlines
+            .heap
+            .retain(|e| e.0.waker.lock().unwrap().is_some());
+        deadlines.cancelled = 0;
+    }
+}
+
+/// A `duration` too long for an `Instant` never ends.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now().checked_add(duration),
+        waker: None,
+    }
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline: Some(deadline),
+        waker: None,
+    }
+}
+
+pub struct Sleep {
+    /// `None` for never.
+    deadline: Option<Instant>,
+    /// The waker in our timer entry, once registered.
+    waker: Option<Slot>,
+}
+
+impl Sleep {
+    /// `None` if it never ends.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(deadline) = self.deadline else {
+            return Poll::Pending;
+        };
+        if Instant::now() >= deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            // We may have been moved to another task since.
+            Some(waker) => match &mut *waker.lock().unwrap() {
+                Some(waker) if !waker.will_wake(cx.waker()) => waker.clone_from(cx.waker()),
+                Some(_) => {}
+                // Taken by the timer, but after we looked at the time.
+                None => return Poll::Ready(()),
+            },
+            None => {
+                let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                register(deadline, waker.clone());
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Resolves to `Err(Elapsed)` if `future` doesn't finish within `duration`.
+/// The future is dropped in that case.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep(duration),
+    }
+}
+
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut self.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(waker) = &self.waker {
+            cancel(waker);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+pub fn main() {
+    use crate::{async_notify::Notify, executor::block_on};
+
+    let start = Instant::now();
+    block_on(sleep(Duration::from_millis(200)));
+    println!("slept for {:?}", start.elapsed());
+
+    let never = Notify::new();
+    let result = block_on(timeout(Duration::from_millis(100), never.notified()));
+    println!("waiting for a notification that never comes: {result:?}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sleep, timeout, timers};
+    use std::{
+        future::{pending, Future},
+        pin::pin,
+        sync::Arc,
+        task::{Context, Wake, Waker},
+        time::Duration,
+    };
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn dropped_timeouts_leave_the_heap() {
+        let noop = Arc::new(Noop);
+        let waker = Waker::from(noop.clone());
+        let mut cx = Context::from_waker(&waker);
+        let long = pin!(sleep(Duration::from_secs(3600)));
+        assert!(long.poll(&mut cx).is_pending());
+        for _ in 0..1000 {
+            let mut t = pin!(timeout(Duration::from_secs(60), pending::<()>()));
+            assert!(t.as_mut().poll(&mut cx).is_pending());
+        }
+        // `noop`, `waker`, and the clone in `long`'s entry.
+        assert_eq!(Arc::strong_count(&noop), 3);
+        assert!(timers().deadlines.lock().unwrap().heap.len() < 100);
+    }
+}
@@ -0,0 +1,26 @@
+//! `tracing` events from the locks, channels and waits, behind the
+//! `tracing` feature, to see who waited on what, and for how long, in a
+//! tracing viewer. Without the feature the macros expand to nothing.
+//!
+//! Everything is at `TRACE` level, with the name of the thread and the
+//! address of the lock or channel, so the events of one can be told apart,
+//! and how long it took to get the lock or the message as `waited`.
+
+/// A `tracing::trace!` event, with the current thread's name added.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(thread = $crate::trace::thread_name(), $($arg)*);
+    };
+}
+
+pub(crate) use event;
+
+#[cfg(feature = "tracing")]
+pub(crate) fn thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
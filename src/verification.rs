@@ -0,0 +1,114 @@
+//! Kani proof harnesses for the atomic state machines.
+//!
+//! Kani explores every value of `kani::any()`, so the loops below cover
+//! every interleaving of operations up to `STEPS` long.
+//! Run with `cargo kani --features verification`.
+
+mod one_shot {
+    use crate::cap_5::single_atomic_for_channel_state::Channel;
+    use std::{cell::Cell, rc::Rc};
+
+    const STEPS: usize = 4;
+
+    /// A message that counts how many times it was dropped.
+    struct Tracked {
+        value: u8,
+        drops: Rc<Cell<u8>>,
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    /// Whatever the order of sends and receives, the message that comes out
+    /// is the one that went in (so it was initialized), and it is dropped
+    /// exactly once: either by the receiver or by the channel.
+    #[kani::proof]
+    #[kani::unwind(5)]
+    fn never_reads_uninit_nor_double_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let value: u8 = kani::any();
+        let channel = Channel::new();
+        let mut sent = false;
+        let mut received = false;
+
+        for _ in 0..STEPS {
+            if kani::any() {
+                if !sent {
+                    channel.send(Tracked {
+                        value,
+                        drops: drops.clone(),
+                    });
+                    sent = true;
+                }
+            } else if channel.is_ready() {
+                assert!(sent && !received);
+                assert_eq!(channel.receive().value, value);
+                received = true;
+            }
+        }
+
+        drop(channel);
+        assert_eq!(drops.get(), u8::from(sent));
+    }
+
+    #[kani::proof]
+    #[kani::should_panic]
+    fn second_send_panics() {
+        let channel = Channel::new();
+        channel.send(1u8);
+        channel.send(2u8);
+    }
+
+    #[kani::proof]
+    #[kani::should_panic]
+    fn receive_before_send_panics() {
+        let channel = Channel::<u8>::new();
+        channel.receive();
+    }
+
+    #[kani::proof]
+    #[kani::should_panic]
+    fn second_receive_panics() {
+        let channel = Channel::new();
+        channel.send(1u8);
+        channel.receive();
+        channel.receive();
+    }
+}
+
+mod spin_lock {
+    use atomics_and_locks::sync::{SpinLock, SpinLockGuard as Guard};
+
+    const THREADS: usize = 2;
+    const STEPS: usize = 6;
+
+    /// Every step, some thread either tries to take the lock or, if it
+    /// holds it, does its critical section and releases it.
+    /// No schedule lets two threads hold a guard at the same time.
+    #[kani::proof]
+    #[kani::unwind(7)]
+    fn mutual_exclusion() {
+        let lock = SpinLock::new(0u8);
+        let mut guards: [Option<Guard<u8>>; THREADS] = [None, None];
+        let mut releases = 0u8;
+
+        for _ in 0..STEPS {
+            let t: usize = kani::any();
+            kani::assume(t < THREADS);
+            match guards[t].take() {
+                None => guards[t] = lock.try_lock(),
+                Some(mut guard) => {
+                    *guard += 1;
+                    releases += 1;
+                }
+            }
+            assert!(guards.iter().filter(|g| g.is_some()).count() <= 1);
+        }
+
+        drop(guards);
+        assert_eq!(*lock.lock(), releases);
+    }
+}
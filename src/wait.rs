@@ -0,0 +1,65 @@
+//! How the blocking primitives of this library wait, picked at compile time
+//! with one of the `backend-*` features:
+//!
+//! - `backend-spin`: spin with `Backoff`. Lowest latency, burns a core,
+//!   and the only choice without `std`.
+//! - `backend-park`: park the thread in a table keyed by address, see `sys::parking`.
+//! - `backend-futex`: the OS's wait-on-address call, see `sys`.
+//!
+//! Without any, it's `backend-futex` with `std` and `backend-spin` without.
+
+use core::sync::atomic::AtomicU32;
+
+#[cfg(any(
+    all(feature = "backend-spin", feature = "backend-park"),
+    all(feature = "backend-spin", feature = "backend-futex"),
+    all(feature = "backend-park", feature = "backend-futex"),
+))]
+compile_error!(
+    "pick at most one of the `backend-spin`, `backend-park` and `backend-futex` features"
+);
+
+#[cfg(any(
+    feature = "backend-spin",
+    not(any(feature = "std", feature = "backend-park", feature = "backend-futex"))
+))]
+mod imp {
+    use crate::backoff::Backoff;
+    use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+    pub fn wait(a: &AtomicU32, expected: u32) {
+        let backoff = Backoff::new();
+        while a.load(Relaxed) == expected {
+            backoff.snooze();
+        }
+    }
+
+    /// Spinners notice by themselves.
+    pub fn wake_one(_: &AtomicU32) {}
+
+    pub fn wake_all(_: &AtomicU32) {}
+}
+
+// Each arm below steps aside for the ones above it, so two features at once
+// only give the `compile_error!`.
+#[cfg(all(feature = "backend-park", not(feature = "backend-spin")))]
+use crate::sys::parking as imp;
+
+#[cfg(all(
+    any(feature = "backend-futex", feature = "std"),
+    not(any(feature = "backend-spin", feature = "backend-park"))
+))]
+use crate::sys as imp;
+
+/// Blocks while `a` is `expected`. May return spuriously.
+pub(crate) fn wait(a: &AtomicU32, expected: u32) {
+    imp::wait(a, expected);
+}
+
+pub(crate) fn wake_one(a: &AtomicU32) {
+    imp::wake_one(a);
+}
+
+pub(crate) fn wake_all(a: &AtomicU32) {
+    imp::wake_all(a);
+}
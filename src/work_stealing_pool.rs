@@ -0,0 +1,282 @@
+//! A thread pool where every worker has its own deque.
+//!
+//! Jobs submitted from inside a job go to the submitting worker's deque,
+//! where it finds them again (newest first, still warm in cache) without
+//! touching any shared lock. Jobs from outside go through the injector queue.
+//! A worker that runs out of work steals the oldest job of one of its peers,
+//! and if there's nothing anywhere, it parks until someone submits more.
+//!
+//! `shutdown` closes the injector before it tells the workers, so a job
+//! from outside either gets in before that and is run, or is refused.
+
+use crate::{
+    deque::{self, Steal, Stealer, Worker},
+    error::{Error, Result},
+    mutex_channel::Channel,
+    threads::ThreadBuilder,
+};
+use std::{
+    cell::RefCell,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle, Thread},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+const LOCAL_CAPACITY: usize = 1024;
+
+struct Shared {
+    injector: Channel<Job>,
+    stealers: Vec<Stealer<Job>>,
+    idle: Mutex<Vec<Thread>>,
+    shutdown: AtomicBool,
+    panicked: AtomicUsize,
+}
+
+struct Local {
+    pool: *const Shared,
+    deque: Worker<Job>,
+}
+
+thread_local! {
+    /// Set on the pool's own worker threads.
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+impl Shared {
+    fn wake_one(&self) {
+        if let Some(thread) = self.idle.lock().unwrap().pop() {
+            thread.unpark();
+        }
+    }
+
+    fn find_job(&self, index: usize, local: &Worker<Job>) -> Option<Job> {
+        if let Some(job) = local.pop() {
+            return Some(job);
+        }
+        if let Some(job) = self.injector.try_receive() {
+            return Some(job);
+        }
+        let n = self.stealers.len();
+        loop {
+            let mut retry = false;
+            // Start right after ourselves, so not everyone robs worker 0.
+            for i in (1..n).map(|i| (index + i) % n) {
+                match self.stealers[i].steal() {
+                    Steal::Success(job) => return Some(job),
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
+                }
+            }
+            if !retry {
+                return None;
+            }
+        }
+    }
+
+    fn run_worker(self: &Arc<Self>, index: usize, deque: Worker<Job>) {
+        LOCAL.with(|l| {
+            *l.borrow_mut() = Some(Local {
+                pool: Arc::as_ptr(self),
+                deque,
+            })
+        });
+        let find_job = || LOCAL.with(|l| self.find_job(index, &l.borrow().as_ref().unwrap().deque));
+        loop {
+            if let Some(job) = find_job() {
+                if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    self.panicked.fetch_add(1, Relaxed);
+                }
+                continue;
+            }
+            if self.shutdown.load(Acquire) {
+                break;
+            }
+            // Register as idle *before* the last look for work: anyone who
+            // submits after that look will find us in the list and unpark us.
+            let me = thread::current();
+            self.idle.lock().unwrap().push(me.clone());
+            if let Some(job) = find_job() {
+                self.idle.lock().unwrap().retain(|t| t.id() != me.id());
+                if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    self.panicked.fetch_add(1, Relaxed);
+                }
+                continue;
+            }
+            if !self.shutdown.load(Acquire) {
+                thread::park();
+            }
+            self.idle.lock().unwrap().retain(|t| t.id() != me.id());
+        }
+        LOCAL.with(|l| l.borrow_mut().take());
+    }
+}
+
+pub struct WorkStealingPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkStealingPool {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "a thread pool needs at least one thread");
+        let (deques, stealers): (Vec<_>, Vec<_>) =
+            (0..n).map(|_| deque::deque(LOCAL_CAPACITY)).unzip();
+        let shared = Arc::new(Shared {
+            injector: Channel::new(),
+            stealers,
+            idle: Mutex::new(Vec::new()),
+            shutdown: AtomicBool::new(false),
+            panicked: AtomicUsize::new(0),
+        });
+        let workers = deques
+            .into_iter()
+            .enumerate()
+            .map(|(i, deque)| {
+                let shared = shared.clone();
+                ThreadBuilder::new()
+                    .name(format!("stealing-worker-{i}"))
+                    .spawn(move || shared.run_worker(i, deque))
+                    .unwrap()
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// `Error::Closed` after `shutdown`, see `Spawner::execute`.
+    pub fn execute<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.spawner().execute(f)
+    }
+
+    /// A handle to submit jobs from anywhere, including from inside a job.
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// How many jobs panicked so far.
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.panicked.load(Relaxed)
+    }
+
+    /// Runs every job that's left, including the ones they spawn,
+    /// then stops the workers.
+    pub fn shutdown(&mut self) {
+        self.shared.injector.close();
+        self.shared.shutdown.store(true, Release);
+        for thread in self.shared.idle.lock().unwrap().drain(..) {
+            thread.unpark();
+        }
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+impl Drop for WorkStealingPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+impl Spawner {
+    /// `Error::Closed`, and the job dropped, if it's from outside the pool
+    /// after `shutdown`. A job's own jobs are still run then, unless its
+    /// worker's deque is full and they'd have to go through the injector.
+    pub fn execute<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job: Job = Box::new(f);
+        let overflow = LOCAL.with(|l| match &*l.borrow() {
+            Some(local) if ptr::eq(local.pool, Arc::as_ptr(&self.shared)) => {
+                local.deque.push(job).err()
+            }
+            _ => Some(job),
+        });
+        if let Some(job) = overflow {
+            self.shared
+                .injector
+                .try_send(job)
+                .map_err(|_| Error::Closed)?;
+        }
+        self.shared.wake_one();
+        Ok(())
+    }
+}
+
+/// Fork-join benchmark: every job splits in two until `DEPTH` levels deep,
+/// against the same tree on the plain shared-queue `ThreadPool`.
+pub fn main() {
+    use crate::thread_pool;
+    use std::time::{Duration, Instant};
+
+    const DEPTH: u32 = 16;
+
+    trait Spawn: Clone + Send + 'static {
+        fn spawn(&self, job: impl FnOnce() + Send + 'static);
+    }
+    impl Spawn for Spawner {
+        fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+            self.execute(job).expect("the pool is running")
+        }
+    }
+    impl Spawn for thread_pool::Spawner {
+        fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+            self.execute(job)
+        }
+    }
+
+    fn fork<S: Spawn>(s: S, depth: u32, leaves: Arc<AtomicUsize>, main: Thread) {
+        if depth == 0 {
+            std::hint::black_box((0..100).sum::<u64>());
+            if leaves.fetch_sub(1, Release) == 1 {
+                main.unpark();
+            }
+            return;
+        }
+        for _ in 0..2 {
+            let (s2, leaves, main) = (s.clone(), leaves.clone(), main.clone());
+            s.spawn(move || fork(s2, depth - 1, leaves, main));
+        }
+    }
+
+    fn run<S: Spawn>(s: S) -> Duration {
+        let start = Instant::now();
+        let leaves = Arc::new(AtomicUsize::new(1 << DEPTH));
+        fork(s, DEPTH, leaves.clone(), thread::current());
+        while leaves.load(Acquire) != 0 {
+            thread::park();
+        }
+        start.elapsed()
+    }
+
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let shared_queue = thread_pool::ThreadPool::new(threads);
+    let work_stealing = WorkStealingPool::new(threads);
+    for _ in 0..3 {
+        println!(
+            "{} leaves on {threads} threads: shared queue {:?}, work stealing {:?}",
+            1 << DEPTH,
+            run(shared_queue.spawner()),
+            run(work_stealing.spawner()),
+        );
+    }
+}
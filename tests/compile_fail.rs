@@ -0,0 +1,15 @@
+//! The `Send`/`Sync` of the primitives, which their soundness depends on,
+//! and which nothing would notice going wrong otherwise: what must compile
+//! is in `ui/pass`, what must not in `ui/fail` (and `ui/linux`), with the error it gives.
+//!
+//! The expected errors are rustc's, so after a toolchain update they may
+//! need `TRYBUILD=overwrite cargo test --test compile_fail`.
+
+#[test]
+fn auto_traits() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass/*.rs");
+    t.compile_fail("tests/ui/fail/*.rs");
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    t.compile_fail("tests/ui/linux/*.rs");
+}
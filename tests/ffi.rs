@@ -0,0 +1,49 @@
+//! Builds the crate as a static library, and runs `ffi/smoke.c` against it
+//! and `include/atomics_and_locks.h`, which building it with `ffi` writes
+//! from `src/ffi.rs`. Skipped without a C compiler.
+
+#![cfg(all(feature = "ffi", unix))]
+
+use std::{env, path::Path, process::Command};
+
+#[test]
+fn smoke_test_from_c() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    if Command::new(&cc).arg("--version").output().is_err() {
+        eprintln!("no C compiler ({cc}), skipping");
+        return;
+    }
+
+    let status = Command::new(env::var("CARGO").unwrap())
+        .current_dir(root)
+        .args([
+            "rustc",
+            "--lib",
+            "--features",
+            "ffi",
+            "--crate-type",
+            "staticlib",
+        ])
+        .arg("--target-dir")
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success(), "building the static library failed");
+
+    let exe = out.join("smoke");
+    let status = Command::new(&cc)
+        .arg(root.join("tests/ffi/smoke.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(out.join("debug/libatomics_and_locks.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling smoke.c failed");
+
+    let status = Command::new(&exe).status().unwrap();
+    assert!(status.success(), "smoke.c failed: {status}");
+}
@@ -0,0 +1,90 @@
+/* The primitives from C, under contention. Exits non-zero on a mismatch. */
+
+#include <pthread.h>
+#include <stdio.h>
+
+#include "atomics_and_locks.h"
+
+#define THREADS 4
+#define ROUNDS 100000
+#define BYTES 1000000
+
+static AalSpinLock *spin;
+static AalMutex *mutex;
+static long spin_count, mutex_count;
+
+static void *lock_both(void *arg) {
+    (void)arg;
+    for (int i = 0; i < ROUNDS; i++) {
+        aal_spin_lock_lock(spin);
+        spin_count++;
+        aal_spin_lock_unlock(spin);
+        aal_mutex_lock(mutex);
+        mutex_count++;
+        aal_mutex_unlock(mutex);
+    }
+    return NULL;
+}
+
+static void *produce(void *arg) {
+    AalSpsc *spsc = arg;
+    uint8_t buf[256];
+    size_t sent = 0;
+    while (sent < BYTES) {
+        size_t n = 0;
+        for (; n < sizeof buf && sent + n < BYTES; n++) {
+            buf[n] = (uint8_t)(sent + n);
+        }
+        size_t done = 0;
+        while (done < n) {
+            done += aal_spsc_write(spsc, buf + done, n - done);
+        }
+        sent += n;
+    }
+    return NULL;
+}
+
+int main(void) {
+    spin = aal_spin_lock_new();
+    mutex = aal_mutex_new();
+    pthread_t threads[THREADS];
+    for (int i = 0; i < THREADS; i++) {
+        pthread_create(&threads[i], NULL, lock_both, NULL);
+    }
+    for (int i = 0; i < THREADS; i++) {
+        pthread_join(threads[i], NULL);
+    }
+    if (spin_count != THREADS * ROUNDS || mutex_count != THREADS * ROUNDS) {
+        fprintf(stderr, "counted %ld and %ld\n", spin_count, mutex_count);
+        return 1;
+    }
+    if (!aal_mutex_try_lock(mutex) || aal_mutex_try_lock(mutex)) {
+        fprintf(stderr, "try_lock\n");
+        return 1;
+    }
+    aal_mutex_unlock(mutex);
+    aal_spin_lock_free(spin);
+    aal_mutex_free(mutex);
+
+    AalSpsc *spsc = aal_spsc_new();
+    pthread_t producer;
+    pthread_create(&producer, NULL, produce, spsc);
+    size_t received = 0;
+    while (received < BYTES) {
+        uint8_t buf[100];
+        size_t n = aal_spsc_read(spsc, buf, sizeof buf);
+        for (size_t i = 0; i < n; i++, received++) {
+            if (buf[i] != (uint8_t)received) {
+                fprintf(stderr, "byte %zu is %u\n", received, buf[i]);
+                return 1;
+            }
+        }
+    }
+    pthread_join(producer, NULL);
+    if (aal_spsc_len(spsc) != 0) {
+        fprintf(stderr, "left over\n");
+        return 1;
+    }
+    aal_spsc_free(spsc);
+    return 0;
+}
@@ -0,0 +1,11 @@
+// Unlike a lock, any number of threads can borrow at once: two of them
+// would be calling `Cell::set` on the same value.
+
+use atomics_and_locks::sync::AtomicRefCell;
+use std::cell::Cell;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<AtomicRefCell<Cell<i32>>>();
+}
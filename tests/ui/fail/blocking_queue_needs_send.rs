@@ -0,0 +1,10 @@
+// A queue shared between threads moves its items between them.
+
+use atomics_and_locks::channel::BlockingQueue;
+use std::rc::Rc;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<BlockingQueue<Rc<i32>>>();
+}
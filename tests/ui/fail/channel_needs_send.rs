@@ -0,0 +1,10 @@
+// A channel shared between threads moves its messages between them.
+
+use atomics_and_locks::channel::Channel;
+use std::rc::Rc;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<Channel<Rc<i32>>>();
+}
@@ -0,0 +1,10 @@
+// Exchanging hands each thread a value from the other one.
+
+use atomics_and_locks::sync::Exchanger;
+use std::rc::Rc;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<Exchanger<Rc<i32>>>();
+}
@@ -0,0 +1,10 @@
+// Through a shared `&MutexGuard`, two threads would get a `&Cell` at once.
+
+use atomics_and_locks::sync::MutexGuard;
+use std::cell::Cell;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<MutexGuard<'static, Cell<i32>>>();
+}
@@ -0,0 +1,10 @@
+// Locking would hand the `Rc` to another thread, next to its other clones.
+
+use atomics_and_locks::sync::Mutex;
+use std::rc::Rc;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<Mutex<Rc<i32>>>();
+}
@@ -0,0 +1,7 @@
+// The halves borrow a channel on the stack, which is gone after the call.
+
+use atomics_and_locks::channel::oneshot::{with_channel, Sender};
+
+fn main() {
+    let _escaped: Sender<'_, i32> = with_channel(|sender, _receiver| sender);
+}
@@ -0,0 +1,10 @@
+// The sender's thread would hand the `Rc` to the receiver's.
+
+use atomics_and_locks::channel::oneshot::Sender;
+use std::rc::Rc;
+
+fn is_send<T: Send>() {}
+
+fn main() {
+    is_send::<Sender<'static, Rc<i32>>>();
+}
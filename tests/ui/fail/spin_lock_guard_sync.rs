@@ -0,0 +1,10 @@
+// Through a shared `&Guard`, two threads would get a `&Cell` at once.
+
+use atomics_and_locks::sync::SpinLockGuard;
+use std::cell::Cell;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<SpinLockGuard<'static, Cell<i32>>>();
+}
@@ -0,0 +1,10 @@
+// The producer's thread would hand the `Rc` to the consumer's.
+
+use atomics_and_locks::channel::spsc::Ring;
+use std::rc::Rc;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<Ring<Rc<i32>, 4>>();
+}
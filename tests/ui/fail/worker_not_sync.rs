@@ -0,0 +1,10 @@
+// Only the owner may push and pop at the bottom; sharing a `&Worker` would
+// let two threads do it.
+
+use atomics_and_locks::pool::work_stealing::Worker;
+
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    is_sync::<Worker<i32>>();
+}
@@ -0,0 +1,10 @@
+// The lock word holds the id of the thread that locked it: once that thread
+// exits, waiters would take the lock over from whoever has the guard now.
+
+use atomics_and_locks::sync::shm::ShmGuard;
+
+fn is_send<T: Send>() {}
+
+fn main() {
+    is_send::<ShmGuard<'static, i32>>();
+}
@@ -0,0 +1,37 @@
+// What the primitives are for: sharing them, and sending their ends.
+
+use atomics_and_locks::{
+    channel::{log, oneshot, spsc, BlockingQueue, Channel},
+    pool::work_stealing::{Stealer, Worker},
+    sync::{AtomicRefCell, Exchanger, Mutex, MutexGuard, SpinLock, SpinLockGuard},
+};
+use std::cell::Cell;
+
+fn is_send<T: Send>() {}
+fn is_sync<T: Sync>() {}
+
+fn main() {
+    // A lock makes `Send` data `Sync`.
+    is_sync::<SpinLock<Cell<i32>>>();
+    is_sync::<Mutex<Cell<i32>>>();
+    // Unlocking on another thread is fine for both.
+    is_send::<SpinLockGuard<'static, Cell<i32>>>();
+    is_send::<MutexGuard<'static, Cell<i32>>>();
+    is_sync::<SpinLockGuard<'static, i32>>();
+    is_sync::<AtomicRefCell<i32>>();
+
+    is_sync::<Channel<Cell<i32>>>();
+    is_sync::<BlockingQueue<Cell<i32>>>();
+    is_sync::<oneshot::Channel<Cell<i32>>>();
+    is_send::<oneshot::Sender<'static, Cell<i32>>>();
+    is_send::<oneshot::Receiver<'static, Cell<i32>>>();
+    is_sync::<Exchanger<Cell<i32>>>();
+    is_sync::<log::AppendLog<i32>>();
+    is_send::<log::AppendLog<Cell<i32>>>();
+    is_sync::<spsc::Ring<Cell<i32>, 4>>();
+    is_send::<spsc::Producer<'static, Cell<i32>, 4>>();
+    is_send::<spsc::Consumer<'static, Cell<i32>, 4>>();
+
+    is_send::<Worker<Cell<i32>>>();
+    is_sync::<Stealer<Cell<i32>>>();
+}